@@ -0,0 +1,59 @@
+use alloy_consensus::Receipt;
+use alloy_primitives::{Address, B256};
+use alloy_rpc_types_engine::PayloadId;
+use base_reth_flashblocks_rpc::flashblocks::{decode_flashblock, Metadata};
+use criterion::{criterion_group, criterion_main, Criterion};
+use reth_optimism_primitives::OpReceipt;
+use rollup_boost::primitives::{ExecutionPayloadFlashblockDeltaV1, FlashblocksPayloadV1};
+
+/// A flashblock with `count` receipts and balance changes, roughly the size of a busy
+/// flashblock on mainnet.
+fn frame(count: u64) -> Vec<u8> {
+    let mut metadata = Metadata {
+        receipts: Default::default(),
+        new_account_balances: Default::default(),
+        block_number: 1,
+    };
+    for i in 0..count {
+        metadata.receipts.insert(
+            B256::left_padding_from(&i.to_be_bytes()).to_string(),
+            OpReceipt::Eip1559(Receipt {
+                status: true.into(),
+                cumulative_gas_used: 21000 * (i + 1),
+                logs: vec![],
+            }),
+        );
+        metadata.new_account_balances.insert(
+            Address::left_padding_from(&i.to_be_bytes()).to_string(),
+            "0x1234".to_string(),
+        );
+    }
+
+    let payload = FlashblocksPayloadV1 {
+        payload_id: PayloadId::new([0; 8]),
+        index: 1,
+        base: None,
+        diff: ExecutionPayloadFlashblockDeltaV1::default(),
+        metadata: serde_json::to_value(metadata).unwrap(),
+    };
+    serde_json::to_vec(&payload).unwrap()
+}
+
+fn decode(c: &mut Criterion) {
+    let json = frame(500);
+    let mut group = c.benchmark_group("decode_flashblock");
+
+    // decoding through a `serde_json::Value`, as done before the metadata was read raw
+    group.bench_function("value", |b| {
+        b.iter(|| {
+            let payload: FlashblocksPayloadV1 = serde_json::from_slice(&json).unwrap();
+            let metadata: Metadata = serde_json::from_value(payload.metadata).unwrap();
+            metadata
+        })
+    });
+    group.bench_function("raw", |b| b.iter(|| decode_flashblock(&json).unwrap()));
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
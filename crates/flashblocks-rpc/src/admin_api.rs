@@ -0,0 +1,116 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::cache::Cache;
+use crate::pending::PendingViewStore;
+use alloy_primitives::U64;
+use jsonrpsee::{
+    core::{async_trait, RpcResult},
+    proc_macros::rpc,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tracing::{debug, warn};
+
+/// The flashblocks state to drop, either a single height or everything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidateTarget {
+    Block(u64),
+    All,
+}
+
+impl Serialize for InvalidateTarget {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Block(number) => U64::from(*number).serialize(serializer),
+            Self::All => serializer.serialize_str("all"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for InvalidateTarget {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(u64),
+            Tag(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(number) => Ok(Self::Block(number)),
+            Raw::Tag(tag) if tag == "all" => Ok(Self::All),
+            Raw::Tag(tag) => U64::from_str(&tag)
+                .map(|number| Self::Block(number.to()))
+                .map_err(|_| serde::de::Error::custom(format!("invalid target: {tag}"))),
+        }
+    }
+}
+
+#[cfg_attr(not(test), rpc(server, namespace = "admin"))]
+#[cfg_attr(test, rpc(server, client, namespace = "admin"))]
+pub trait AdminApi {
+    /// Drops the flashblocks state of a height, or of all heights with `"all"`, so poisoned
+    /// state can be recovered from without restarting the node. Returns the heights whose
+    /// pending view was dropped.
+    #[method(name = "flashblocksInvalidate")]
+    async fn flashblocks_invalidate(&self, target: InvalidateTarget) -> RpcResult<Vec<u64>>;
+}
+
+#[derive(Debug)]
+pub struct AdminApiExt {
+    cache: Arc<Cache>,
+    pending: Arc<PendingViewStore>,
+}
+
+impl AdminApiExt {
+    pub fn new(cache: Arc<Cache>, pending: Arc<PendingViewStore>) -> Self {
+        Self { cache, pending }
+    }
+}
+
+#[async_trait]
+impl AdminApiServer for AdminApiExt {
+    async fn flashblocks_invalidate(&self, target: InvalidateTarget) -> RpcResult<Vec<u64>> {
+        debug!("flashblocks_invalidate: {:?}", target);
+        let invalidated = match target {
+            InvalidateTarget::Block(block_number) => {
+                self.cache.remove_block(block_number);
+                if self.pending.invalidate(block_number) {
+                    vec![block_number]
+                } else {
+                    Vec::new()
+                }
+            }
+            InvalidateTarget::All => {
+                self.cache.clear();
+                self.pending.invalidate_all()
+            }
+        };
+        warn!("Invalidated flashblocks state: {:?}", invalidated);
+        Ok(invalidated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalidate_target_serde() {
+        for (json, target) in [
+            ("\"all\"", InvalidateTarget::All),
+            ("12", InvalidateTarget::Block(12)),
+            ("\"0xc\"", InvalidateTarget::Block(12)),
+        ] {
+            assert_eq!(
+                serde_json::from_str::<InvalidateTarget>(json).unwrap(),
+                target
+            );
+        }
+        assert!(serde_json::from_str::<InvalidateTarget>("\"latest\"").is_err());
+        assert_eq!(
+            serde_json::to_string(&InvalidateTarget::Block(12)).unwrap(),
+            "\"0xc\""
+        );
+    }
+}
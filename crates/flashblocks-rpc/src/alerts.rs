@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::clock::SharedClock;
+use crate::pending::PendingViewStore;
+use crate::startup::{CheckStatus, StartupReport};
+use crate::validation::BlockLimitValidator;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+use url::Url;
+
+/// Time without a new flashblock before the stream is reported down, unless configured
+/// otherwise.
+pub const DEFAULT_STREAM_DOWN_AFTER: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    StreamDown,
+    StreamRecovered,
+    ValidationFailed,
+    BalanceChanged,
+    BlockLimitExceeded,
+}
+
+impl Display for AlertKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlertKind::StreamDown => write!(f, "stream_down"),
+            AlertKind::StreamRecovered => write!(f, "stream_recovered"),
+            AlertKind::ValidationFailed => write!(f, "validation_failed"),
+            AlertKind::BalanceChanged => write!(f, "balance_changed"),
+            AlertKind::BlockLimitExceeded => write!(f, "block_limit_exceeded"),
+        }
+    }
+}
+
+/// Body posted to the webhook. `text` is what chat webhooks (e.g. Slack) display.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alert {
+    pub kind: AlertKind,
+    pub text: String,
+    /// Unix timestamp in milliseconds at which the alert was raised
+    pub timestamp: u64,
+}
+
+/// Webhook receiving the [`Alert`]s of the node.
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    url: Url,
+    client: reqwest::Client,
+    clock: SharedClock,
+}
+
+impl Webhook {
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            client: reqwest::Client::new(),
+            clock: SharedClock::default(),
+        }
+    }
+
+    /// Clock the alerts are timestamped with.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub async fn send(&self, kind: AlertKind, text: String) {
+        info!("Sending {} alert: {}", kind, text);
+        let alert = Alert {
+            kind,
+            text,
+            timestamp: self.clock.unix_millis(),
+        };
+        let result = self
+            .client
+            .post(self.url.clone())
+            .json(&alert)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            error!("Failed to post {} alert: {}", kind, e);
+        }
+    }
+}
+
+/// Posts a JSON alert to a webhook when the flashblocks stream goes down or comes back, and
+/// when a startup validation fails or a block is truncated for exceeding its limits, so
+/// operators without a metrics stack still get paged.
+/// Every incident is posted once.
+#[derive(Debug)]
+pub struct AlertNotifier {
+    webhook: Webhook,
+    pending: Arc<PendingViewStore>,
+    startup_report: Arc<StartupReport>,
+    stream_down_after: Duration,
+    /// Last time a flashblock was seen, or when monitoring started
+    last_flashblock: Instant,
+    stream_down: bool,
+    /// Failed checks already alerted, with the detail they were alerted with
+    failed_checks: HashMap<String, String>,
+    block_limits: Option<Arc<BlockLimitValidator>>,
+}
+
+impl AlertNotifier {
+    pub fn new(
+        webhook: Webhook,
+        pending: Arc<PendingViewStore>,
+        startup_report: Arc<StartupReport>,
+    ) -> Self {
+        let last_flashblock = pending.clock().now();
+        Self {
+            webhook,
+            pending,
+            startup_report,
+            stream_down_after: DEFAULT_STREAM_DOWN_AFTER,
+            last_flashblock,
+            stream_down: false,
+            failed_checks: HashMap::new(),
+            block_limits: None,
+        }
+    }
+
+    pub fn with_stream_down_after(mut self, stream_down_after: Duration) -> Self {
+        self.stream_down_after = stream_down_after;
+        self
+    }
+
+    /// Alerts on the blocks truncated by `block_limits`.
+    pub fn with_block_limits(mut self, block_limits: Arc<BlockLimitValidator>) -> Self {
+        self.block_limits = Some(block_limits);
+        self
+    }
+
+    /// Returns the incidents that started or ended since the last call.
+    fn check(&mut self, now: Instant) -> Vec<(AlertKind, String)> {
+        let mut alerts = Vec::new();
+
+        if let Some(published_at) = self.pending.load_blocks().last_published_at() {
+            self.last_flashblock = self.last_flashblock.max(published_at);
+        }
+        let silence = now.saturating_duration_since(self.last_flashblock);
+        if !self.stream_down && silence > self.stream_down_after {
+            self.stream_down = true;
+            alerts.push((
+                AlertKind::StreamDown,
+                format!("No flashblocks received for {}s", silence.as_secs()),
+            ));
+        } else if self.stream_down && silence <= self.stream_down_after {
+            self.stream_down = false;
+            alerts.push((
+                AlertKind::StreamRecovered,
+                "Flashblocks are being received again".to_string(),
+            ));
+        }
+
+        for check in self.startup_report.checks() {
+            if check.status != CheckStatus::Failed {
+                self.failed_checks.remove(&check.name);
+                continue;
+            }
+            let detail = check.detail.unwrap_or_default();
+            if self.failed_checks.get(&check.name) == Some(&detail) {
+                continue;
+            }
+            alerts.push((
+                AlertKind::ValidationFailed,
+                format!("Startup check {} failed: {}", check.name, detail),
+            ));
+            self.failed_checks.insert(check.name, detail);
+        }
+
+        if let Some(block_limits) = &self.block_limits {
+            for incident in block_limits.take_incidents() {
+                alerts.push((AlertKind::BlockLimitExceeded, incident));
+            }
+        }
+
+        alerts
+    }
+
+    pub async fn run(mut self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            let now = self.pending.clock().now();
+            for (kind, text) in self.check(now) {
+                self.webhook.send(kind, text).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flashblocks::Metadata;
+    use crate::pending::PendingView;
+    use crate::startup::CHAIN_MATCHES;
+    use crate::validation::PayloadValidator;
+    use alloy_primitives::Bytes;
+    use alloy_rpc_types_engine::PayloadId;
+    use reth_optimism_primitives::OpBlock;
+    use rollup_boost::primitives::{ExecutionPayloadFlashblockDeltaV1, FlashblocksPayloadV1};
+
+    fn kinds(alerts: &[(AlertKind, String)]) -> Vec<AlertKind> {
+        alerts.iter().map(|(kind, _)| *kind).collect()
+    }
+
+    #[test]
+    fn test_stream_down_and_recovered() {
+        let pending = Arc::new(PendingViewStore::default());
+        let mut notifier = AlertNotifier::new(
+            Webhook::new(Url::parse("http://localhost/webhook").unwrap()),
+            Arc::clone(&pending),
+            Arc::new(StartupReport::default()),
+        )
+        .with_stream_down_after(Duration::from_secs(5));
+
+        let start = Instant::now();
+        assert!(notifier.check(start).is_empty());
+
+        let later = start + Duration::from_secs(6);
+        assert_eq!(kinds(&notifier.check(later)), vec![AlertKind::StreamDown]);
+        // an incident is only reported once
+        assert!(notifier.check(later).is_empty());
+
+        pending.publish(PendingView::new(OpBlock::default(), 0, Vec::new()));
+        assert_eq!(
+            kinds(&notifier.check(Instant::now())),
+            vec![AlertKind::StreamRecovered]
+        );
+    }
+
+    #[test]
+    fn test_validation_failed() {
+        let startup_report = Arc::new(StartupReport::default());
+        let mut notifier = AlertNotifier::new(
+            Webhook::new(Url::parse("http://localhost/webhook").unwrap()),
+            Arc::new(PendingViewStore::default()),
+            Arc::clone(&startup_report),
+        );
+        let now = Instant::now();
+
+        startup_report.fail(CHAIN_MATCHES, "source serves a different chain");
+        assert_eq!(
+            kinds(&notifier.check(now)),
+            vec![AlertKind::ValidationFailed]
+        );
+        assert!(notifier.check(now).is_empty());
+
+        // a check that recovers and fails again is reported again
+        startup_report.pass(CHAIN_MATCHES, "source serves the node's chain");
+        assert!(notifier.check(now).is_empty());
+        startup_report.fail(CHAIN_MATCHES, "source serves a different chain");
+        assert_eq!(
+            kinds(&notifier.check(now)),
+            vec![AlertKind::ValidationFailed]
+        );
+    }
+
+    #[test]
+    fn test_block_limit_exceeded() {
+        let block_limits = Arc::new(BlockLimitValidator::new(0));
+        let mut notifier = AlertNotifier::new(
+            Webhook::new(Url::parse("http://localhost/webhook").unwrap()),
+            Arc::new(PendingViewStore::default()),
+            Arc::new(StartupReport::default()),
+        )
+        .with_block_limits(Arc::clone(&block_limits));
+        let now = Instant::now();
+        assert!(notifier.check(now).is_empty());
+
+        let payload = FlashblocksPayloadV1 {
+            payload_id: PayloadId::new([0; 8]),
+            index: 1,
+            base: None,
+            diff: ExecutionPayloadFlashblockDeltaV1 {
+                transactions: vec![Bytes::from_static(&[1])],
+                ..Default::default()
+            },
+            metadata: serde_json::Value::Null,
+        };
+        let metadata = Metadata {
+            receipts: Default::default(),
+            new_account_balances: Default::default(),
+            block_number: 1,
+        };
+        assert!(block_limits.validate(&payload, &metadata).is_err());
+        assert_eq!(
+            kinds(&notifier.check(now)),
+            vec![AlertKind::BlockLimitExceeded]
+        );
+        assert!(notifier.check(now).is_empty());
+    }
+}
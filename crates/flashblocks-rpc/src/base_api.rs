@@ -0,0 +1,1117 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::cache::{Cache, CacheKey};
+use crate::flashblocks::FlashblockAccountChanges;
+use crate::origins::{BlockOrigins, OriginTracker};
+use crate::pending::{
+    PendingView, PendingViewStore, DEFAULT_BLOCK_TIME_MS, DEFAULT_FLASHBLOCK_INTERVAL_MS,
+};
+use crate::reconciliation::{Reconciliation, ReconciliationHistory};
+use crate::replacements::{ReplacedTransaction, ReplacementTracker};
+use crate::startup::{StartupCheck, StartupReport};
+use crate::submissions::{SubmissionState, SubmissionTracker};
+use crate::summaries::{BlockSummary, SummaryAggregate, SummaryStore};
+use crate::upstream::{UpstreamInfo, UpstreamInfoStore};
+use alloy_consensus::{Transaction, TxReceipt};
+use alloy_eips::{BlockId, BlockNumberOrTag};
+use alloy_primitives::{Address, Log, TxHash, I256, U256};
+use jsonrpsee::{
+    core::{async_trait, RpcResult},
+    proc_macros::rpc,
+};
+use op_alloy_network::Optimism;
+use reth::rpc::server_types::eth::TransactionSource;
+use reth::transaction_pool::TransactionPool;
+use reth_rpc_eth_api::helpers::{EthBlocks, EthState, EthTransactions, FullEthApi};
+use reth_rpc_eth_api::RpcNodeCore;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// Pool transactions further back than this are reported queued, the pool isn't walked past
+/// them. They are many blocks away from inclusion.
+const MAX_POOL_POSITION: u64 = 10_000;
+
+/// Polling sooner than this is unlikely to find a new flashblock.
+const MIN_RETRY_AFTER_MS: u64 = 50;
+
+/// Accounts changed by the flashblocks of a block after a given flashblock index.
+///
+/// Only account level changes are reported, the flashblock metadata does not carry storage
+/// writes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateDiff {
+    pub block_number: u64,
+    pub from_index: u64,
+    pub to_index: u64,
+    pub accounts: BTreeMap<Address, AccountDiff>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDiff {
+    /// Latest balance set within the range, if the balance changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance: Option<U256>,
+    /// Number of transactions sent by the account within the range
+    pub nonce_increment: u64,
+}
+
+/// Nonce state of an account across the canonical chain, the flashblocks and the txpool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NonceGapReport {
+    /// Nonce of the account at the latest canonical block
+    pub latest_nonce: u64,
+    /// Number of transactions from the account preconfirmed in the blocks after the latest one
+    pub preconfirmed_count: u64,
+    /// Next nonce expected once the preconfirmed transactions are included
+    pub pending_nonce: u64,
+    /// Nonces of the account's transactions waiting in the txpool
+    pub pool_nonces: Vec<u64>,
+    /// Ranges of missing nonces that keep later pool transactions from being included
+    pub gaps: Vec<NonceGap>,
+}
+
+/// An inclusive range of missing nonces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NonceGap {
+    pub from: u64,
+    pub to: u64,
+}
+
+/// Number of transactions each account sent in the preconfirmed flashblocks of a block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockTransactionCounts {
+    pub block_number: u64,
+    /// Highest flashblock index the counts include
+    pub flashblock_index: u64,
+    pub counts: BTreeMap<Address, u64>,
+}
+
+/// Time the latest flashblock took from arriving on the websocket to being visible over RPC.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestLag {
+    pub block_number: u64,
+    pub flashblock_index: u64,
+    pub generation: u64,
+    pub lag_micros: u64,
+    /// Time since the flashblock became visible
+    pub age_millis: u64,
+}
+
+/// How the transactions of a preconfirmed block were ordered, per flashblock.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderingReport {
+    pub block_number: u64,
+    pub flashblocks: Vec<FlashblockOrdering>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlashblockOrdering {
+    pub index: u64,
+    /// Unix timestamp in milliseconds at which the node first saw the flashblock
+    pub first_seen_at: u64,
+    pub transaction_count: u64,
+    /// Number of deposits, which always come first and carry no tip
+    pub deposit_count: u64,
+    /// Whether the other transactions are ordered by descending effective tip
+    pub tip_sorted: bool,
+    /// Number of adjacent transaction pairs where a lower tip precedes a higher one
+    pub tip_inversions: u64,
+    pub distinct_senders: u64,
+    /// Sender with the most transactions in the flashblock
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_sender: Option<Address>,
+    pub top_sender_count: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfirmationStatus {
+    Canonical,
+    Preconfirmed,
+    /// Waiting in the pool, ready to be included
+    Pending,
+    /// Waiting in the pool behind a nonce gap or a too low fee, so there is no estimate
+    Queued,
+}
+
+/// Expected time until a transaction shows up in a flashblock and in a canonical block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfirmationEstimate {
+    pub status: ConfirmationStatus,
+    /// Block the transaction is, or is expected to be, included in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    /// Number of pool transactions ordered before the transaction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_position: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_to_preconfirmation_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_to_canonical_ms: Option<u64>,
+}
+
+/// A preconfirmed transaction sent by or to an account.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityTransaction {
+    pub transaction_hash: TxHash,
+    /// Flashblock the transaction was first preconfirmed in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flashblock_index: Option<u64>,
+    pub from: Address,
+    pub to: Option<Address>,
+    pub value: U256,
+    pub nonce: u64,
+    /// Whether the transaction succeeded, unknown without its receipt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success: Option<bool>,
+}
+
+/// A preconfirmed log with an account as one of its topics.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityLog {
+    pub transaction_hash: TxHash,
+    /// Index of the log in the block
+    pub log_index: u64,
+    #[serde(flatten)]
+    pub log: Log,
+}
+
+/// Everything the pending block does to an account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingActivity {
+    pub address: Address,
+    pub block_number: u64,
+    pub flashblock_index: u64,
+    pub transactions: Vec<ActivityTransaction>,
+    pub logs: Vec<ActivityLog>,
+    /// Balance once the pending block is included
+    pub balance: U256,
+    /// Change of the balance since the latest canonical block
+    pub balance_delta: I256,
+}
+
+/// When to poll again for the receipt of a transaction that is in the pool but not preconfirmed
+/// yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptPollingHint {
+    pub transaction_hash: TxHash,
+    /// Milliseconds until the next flashblock is expected
+    pub retry_after_ms: u64,
+}
+
+#[cfg_attr(not(test), rpc(server, namespace = "base"))]
+#[cfg_attr(test, rpc(server, client, namespace = "base"))]
+pub trait BaseApi {
+    /// Returns the builder (fee recipient) of the given block, resolving `pending` from the
+    /// flashblocks state.
+    #[method(name = "getBlockBuilder")]
+    async fn get_block_builder(&self, number: BlockNumberOrTag) -> RpcResult<Option<Address>>;
+
+    /// Returns the accounts changed by the flashblocks of `number` with an index greater than
+    /// `from_index`.
+    #[method(name = "getStateDiffSinceFlashblock")]
+    async fn get_state_diff_since_flashblock(
+        &self,
+        number: BlockNumberOrTag,
+        from_index: u64,
+    ) -> RpcResult<Option<StateDiff>>;
+
+    /// Reports missing nonces between the account's preconfirmed transactions and the ones
+    /// queued in the txpool, which would stall inclusion of the queued transactions.
+    #[method(name = "checkNonceGap")]
+    async fn check_nonce_gap(&self, address: Address) -> RpcResult<NonceGapReport>;
+
+    /// Returns the ingest lag of the flashblock backing the current pending view.
+    #[method(name = "getIngestLag")]
+    async fn get_ingest_lag(&self) -> RpcResult<Option<IngestLag>>;
+
+    /// Returns the furthest block height with preconfirmed flashblocks. It can be more than one
+    /// block ahead of the canonical head when the sequencer builds blocks in parallel.
+    #[method(name = "getPreconfirmedBlockNumber")]
+    async fn get_preconfirmed_block_number(&self) -> RpcResult<Option<u64>>;
+
+    /// Returns the per sender transaction counts of the preconfirmed blocks between `from` and
+    /// `to`, inclusive. Both default to the range of blocks with flashblocks in flight.
+    #[method(name = "getTransactionCountByBlockRange")]
+    async fn get_transaction_count_by_block_range(
+        &self,
+        from: Option<u64>,
+        to: Option<u64>,
+    ) -> RpcResult<Vec<BlockTransactionCounts>>;
+
+    /// Returns recent preconfirmed transactions that share a sender and nonce with a different
+    /// transaction in the txpool, newest first.
+    #[method(name = "getReplacedTransactions")]
+    async fn get_replaced_transactions(
+        &self,
+        sender: Option<Address>,
+    ) -> RpcResult<Vec<ReplacedTransaction>>;
+
+    /// Returns the checks run while the node started: whether the websocket is reachable, a
+    /// payload was parsed, the source serves this chain and the namespaces were mounted.
+    #[method(name = "getStartupReport")]
+    async fn get_startup_report(&self) -> RpcResult<Vec<StartupCheck>>;
+
+    /// Summarizes how each flashblock of a preconfirmed block ordered its transactions: whether
+    /// they are sorted by tip, how concentrated the senders are and when the flashblock was
+    /// first seen.
+    #[method(name = "getOrderingReport")]
+    async fn get_ordering_report(
+        &self,
+        number: BlockNumberOrTag,
+    ) -> RpcResult<Option<OrderingReport>>;
+
+    /// Estimates when a transaction will be preconfirmed and included in a canonical block,
+    /// from its position in the pool, the flashblock and block cadence and how full the block
+    /// being built is. Returns `null` for unknown transactions. Transactions more than 10000
+    /// positions back in the pool are reported queued.
+    #[method(name = "estimateConfirmationTime")]
+    async fn estimate_confirmation_time(
+        &self,
+        tx_hash: TxHash,
+    ) -> RpcResult<Option<ConfirmationEstimate>>;
+
+    /// Returns how the last preconfirmed view of recent blocks compared to the canonical block,
+    /// newest first: the transactions that matched, went missing or were never preconfirmed,
+    /// and the receipts that differ.
+    #[method(name = "getReconciliationHistory")]
+    async fn get_reconciliation_history(
+        &self,
+        limit: Option<usize>,
+    ) -> RpcResult<Vec<Reconciliation>>;
+
+    /// Summarizes what the pending block does to `address`: the preconfirmed transactions it
+    /// sent or received, the logs naming it as a topic and how its balance changed since the
+    /// latest canonical block. Returns `null` without a pending block.
+    #[method(name = "getPendingActivity")]
+    async fn get_pending_activity(&self, address: Address) -> RpcResult<Option<PendingActivity>>;
+
+    /// Returns how many of the preconfirmed transactions of `block_number` were waiting in the
+    /// local txpool and how many were never seen locally, in total and per flashblock. Only
+    /// the most recent blocks are kept.
+    #[method(name = "getTransactionOrigins")]
+    async fn get_transaction_origins(&self, block_number: u64) -> RpcResult<Option<BlockOrigins>>;
+
+    /// Returns what the flashblocks source announced about itself on the current connection:
+    /// the `Server` header of the websocket handshake and the software and protocol version of
+    /// its info frame, when it sends one.
+    #[method(name = "getUpstreamInfo")]
+    async fn get_upstream_info(&self) -> RpcResult<Option<UpstreamInfo>>;
+
+    /// Returns the preconfirmation summaries of the blocks from `from_block` to `to_block`, at
+    /// most 10000 of them. Blocks older than the retention period or reconciled before the
+    /// summaries were enabled are left out.
+    #[method(name = "getBlockSummaries")]
+    async fn get_block_summaries(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> RpcResult<Vec<BlockSummary>>;
+
+    /// Returns the preconfirmation summaries of the blocks from `from_block` to `to_block`
+    /// folded into buckets of `bucket_size` blocks, at most 1000 of them, so quality can be
+    /// compared week over week in one call. Buckets without any summary are left out.
+    #[method(name = "getBlockSummaryAggregates")]
+    async fn get_block_summary_aggregates(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        bucket_size: u64,
+    ) -> RpcResult<Vec<SummaryAggregate>>;
+
+    /// Suggests when to poll `eth_getTransactionReceipt` again for a transaction waiting in the
+    /// pool, based on the flashblock cadence. `null` for transactions that aren't waiting.
+    #[method(name = "getReceiptPollingHint")]
+    async fn get_receipt_polling_hint(
+        &self,
+        tx_hash: TxHash,
+    ) -> RpcResult<Option<ReceiptPollingHint>>;
+}
+
+#[derive(Debug)]
+pub struct BaseApiExt<Eth> {
+    eth_api: Eth,
+    cache: Arc<Cache>,
+    pending: Arc<PendingViewStore>,
+    replacements: Arc<ReplacementTracker>,
+    reconciliations: Arc<ReconciliationHistory>,
+    origins: Arc<OriginTracker>,
+    upstream_info: Arc<UpstreamInfoStore>,
+    summaries: Option<Arc<SummaryStore>>,
+    submissions: Arc<SubmissionTracker>,
+    startup_report: Arc<StartupReport>,
+}
+
+impl<E> BaseApiExt<E> {
+    pub fn new(
+        eth_api: E,
+        cache: Arc<Cache>,
+        pending: Arc<PendingViewStore>,
+        replacements: Arc<ReplacementTracker>,
+        reconciliations: Arc<ReconciliationHistory>,
+        origins: Arc<OriginTracker>,
+        startup_report: Arc<StartupReport>,
+    ) -> Self {
+        Self {
+            eth_api,
+            cache,
+            pending,
+            replacements,
+            reconciliations,
+            origins,
+            upstream_info: Arc::new(UpstreamInfoStore::default()),
+            summaries: None,
+            submissions: Arc::new(SubmissionTracker::default()),
+            startup_report,
+        }
+    }
+
+    /// Store the flashblocks client records what the upstream announced in.
+    pub fn with_upstream_info(mut self, upstream_info: Arc<UpstreamInfoStore>) -> Self {
+        self.upstream_info = upstream_info;
+        self
+    }
+
+    /// Store the reconciler records the block summaries in.
+    pub fn with_summaries(mut self, summaries: Arc<SummaryStore>) -> Self {
+        self.summaries = Some(summaries);
+        self
+    }
+
+    /// Tracker of the transactions sent to the node, which the sequencer may have been sent
+    /// without them ever reaching the local txpool.
+    pub fn with_submissions(mut self, submissions: Arc<SubmissionTracker>) -> Self {
+        self.submissions = submissions;
+        self
+    }
+
+    fn pending_block_number(&self) -> Option<u64> {
+        self.pending.load().map(|view| view.block_number())
+    }
+}
+
+#[async_trait]
+impl<Eth> BaseApiServer for BaseApiExt<Eth>
+where
+    Eth: FullEthApi<NetworkTypes = Optimism> + Send + Sync + 'static,
+    Eth: RpcNodeCore,
+    <Eth as RpcNodeCore>::Pool: TransactionPool,
+{
+    async fn get_block_builder(&self, number: BlockNumberOrTag) -> RpcResult<Option<Address>> {
+        debug!("get_block_builder: {:?}", number);
+        let block_number = match number {
+            BlockNumberOrTag::Pending => {
+                return Ok(self.pending.load().map(|view| view.block.beneficiary));
+            }
+            _ => {
+                let header = EthBlocks::rpc_block_header(&self.eth_api, number.into())
+                    .await
+                    .map_err(Into::into)?;
+                if let Some(header) = header {
+                    return Ok(Some(header.beneficiary));
+                }
+                match number.as_number() {
+                    Some(number) => number,
+                    None => return Ok(None),
+                }
+            }
+        };
+
+        Ok(self
+            .cache
+            .get::<Address>(&CacheKey::BlockBuilder(block_number)))
+    }
+
+    async fn get_state_diff_since_flashblock(
+        &self,
+        number: BlockNumberOrTag,
+        from_index: u64,
+    ) -> RpcResult<Option<StateDiff>> {
+        debug!(
+            "get_state_diff_since_flashblock: {:?} {}",
+            number, from_index
+        );
+        let block_number = match number {
+            BlockNumberOrTag::Pending => match self.pending_block_number() {
+                Some(number) => number,
+                None => return Ok(None),
+            },
+            BlockNumberOrTag::Number(number) => number,
+            _ => return Ok(None),
+        };
+
+        // with a known highest index missing flashblocks are skipped, otherwise the block is
+        // read until the first missing flashblock
+        let highest_index = self
+            .cache
+            .get::<u64>(&CacheKey::HighestPayloadIndex(block_number));
+
+        let mut diff = StateDiff {
+            block_number,
+            from_index,
+            to_index: from_index,
+            accounts: BTreeMap::new(),
+        };
+        let mut index = from_index + 1;
+        loop {
+            if highest_index.is_some_and(|highest| index > highest) {
+                break;
+            }
+            let Some(changes) =
+                self.cache
+                    .get::<FlashblockAccountChanges>(&CacheKey::AccountChanges {
+                        block_number,
+                        index,
+                    })
+            else {
+                if highest_index.is_some() {
+                    index += 1;
+                    continue;
+                }
+                break;
+            };
+
+            for (address, balance) in changes.balances {
+                diff.accounts.entry(address).or_default().balance = Some(balance);
+            }
+            for (address, increment) in changes.nonce_increments {
+                diff.accounts.entry(address).or_default().nonce_increment += increment;
+            }
+            diff.to_index = index;
+            index += 1;
+        }
+
+        Ok(Some(diff))
+    }
+
+    async fn check_nonce_gap(&self, address: Address) -> RpcResult<NonceGapReport> {
+        debug!("check_nonce_gap: {:?}", address);
+        let latest_nonce: u64 = EthState::transaction_count(
+            &self.eth_api,
+            address,
+            Some(BlockId::Number(BlockNumberOrTag::Latest)),
+        )
+        .await
+        .map_err(Into::into)?
+        .saturating_to();
+
+        let latest_header =
+            EthBlocks::rpc_block_header(&self.eth_api, BlockNumberOrTag::Latest.into())
+                .await
+                .map_err(Into::into)?;
+        let pending_nonce = latest_header
+            .and_then(|header| {
+                self.pending
+                    .load_blocks()
+                    .next_nonce_since(address, header.number + 1)
+            })
+            .map_or(latest_nonce, |next_nonce| next_nonce.max(latest_nonce));
+        let preconfirmed_count = pending_nonce - latest_nonce;
+
+        let mut pool_nonces: Vec<u64> = self
+            .eth_api
+            .pool()
+            .get_transactions_by_sender(address)
+            .iter()
+            .map(|tx| tx.nonce())
+            .collect();
+        pool_nonces.sort_unstable();
+        pool_nonces.dedup();
+
+        Ok(NonceGapReport {
+            latest_nonce,
+            preconfirmed_count,
+            pending_nonce,
+            gaps: find_nonce_gaps(pending_nonce, &pool_nonces),
+            pool_nonces,
+        })
+    }
+
+    async fn get_ingest_lag(&self) -> RpcResult<Option<IngestLag>> {
+        debug!("get_ingest_lag");
+        Ok(self.pending.load().map(|view| IngestLag {
+            block_number: view.block_number(),
+            flashblock_index: view.flashblock_index,
+            generation: view.generation,
+            lag_micros: view.ingest_lag().as_micros() as u64,
+            age_millis: self.pending.age(&view).as_millis() as u64,
+        }))
+    }
+
+    async fn get_preconfirmed_block_number(&self) -> RpcResult<Option<u64>> {
+        debug!("get_preconfirmed_block_number");
+        Ok(self.pending_block_number())
+    }
+
+    async fn get_transaction_count_by_block_range(
+        &self,
+        from: Option<u64>,
+        to: Option<u64>,
+    ) -> RpcResult<Vec<BlockTransactionCounts>> {
+        debug!("get_transaction_count_by_block_range: {:?} {:?}", from, to);
+        let range = from.unwrap_or(u64::MIN)..=to.unwrap_or(u64::MAX);
+        if range.is_empty() {
+            return Ok(Vec::new());
+        }
+        let blocks = self.pending.load_blocks();
+        Ok(blocks
+            .range(range)
+            .map(|view| BlockTransactionCounts {
+                block_number: view.block_number(),
+                flashblock_index: view.flashblock_index,
+                counts: view
+                    .nonces
+                    .keys()
+                    .map(|address| (*address, view.transaction_count(*address)))
+                    .collect(),
+            })
+            .collect())
+    }
+
+    async fn get_replaced_transactions(
+        &self,
+        sender: Option<Address>,
+    ) -> RpcResult<Vec<ReplacedTransaction>> {
+        debug!("get_replaced_transactions: {:?}", sender);
+        Ok(self.replacements.recent(sender))
+    }
+
+    async fn get_startup_report(&self) -> RpcResult<Vec<StartupCheck>> {
+        debug!("get_startup_report");
+        Ok(self.startup_report.checks())
+    }
+
+    async fn get_ordering_report(
+        &self,
+        number: BlockNumberOrTag,
+    ) -> RpcResult<Option<OrderingReport>> {
+        debug!("get_ordering_report: {:?}", number);
+        let blocks = self.pending.load_blocks();
+        let view = match number {
+            BlockNumberOrTag::Pending => blocks.latest(),
+            BlockNumberOrTag::Number(number) => blocks.for_block(number),
+            _ => None,
+        };
+        Ok(view.map(|view| ordering_report(view)))
+    }
+
+    async fn estimate_confirmation_time(
+        &self,
+        tx_hash: TxHash,
+    ) -> RpcResult<Option<ConfirmationEstimate>> {
+        debug!("estimate_confirmation_time: {:?}", tx_hash);
+        let source = EthTransactions::transaction_by_hash(&self.eth_api, tx_hash)
+            .await
+            .map_err(Into::into)?;
+        if let Some(TransactionSource::Block { block_number, .. }) = source {
+            return Ok(Some(ConfirmationEstimate {
+                status: ConfirmationStatus::Canonical,
+                block_number: Some(block_number),
+                pool_position: None,
+                time_to_preconfirmation_ms: Some(0),
+                time_to_canonical_ms: Some(0),
+            }));
+        }
+
+        let now = self.pending.clock().unix_millis();
+        let blocks = self.pending.load_blocks();
+        if let Some(tx) = blocks.transaction(tx_hash) {
+            return Ok(Some(ConfirmationEstimate {
+                status: ConfirmationStatus::Preconfirmed,
+                block_number: Some(tx.view.block_number()),
+                pool_position: None,
+                time_to_preconfirmation_ms: Some(0),
+                time_to_canonical_ms: Some(block_end_ms(tx.view, now)),
+            }));
+        }
+        if source.is_none() {
+            return Ok(None);
+        }
+
+        // the sequencer includes pool transactions in the order the pool yields them
+        let block_time = blocks.block_time_ms().unwrap_or(DEFAULT_BLOCK_TIME_MS);
+        let mut position = 0;
+        let mut gas_ahead = 0;
+        for tx in self
+            .eth_api
+            .pool()
+            .best_transactions()
+            .take(MAX_POOL_POSITION as usize)
+        {
+            if *tx.hash() == tx_hash {
+                return Ok(Some(estimate_pool_confirmation(
+                    position,
+                    gas_ahead,
+                    tx.gas_limit(),
+                    blocks.latest().map(Arc::as_ref),
+                    block_time,
+                    now,
+                )));
+            }
+            position += 1;
+            gas_ahead += tx.gas_limit();
+        }
+
+        Ok(Some(ConfirmationEstimate {
+            status: ConfirmationStatus::Queued,
+            block_number: None,
+            pool_position: None,
+            time_to_preconfirmation_ms: None,
+            time_to_canonical_ms: None,
+        }))
+    }
+
+    async fn get_reconciliation_history(
+        &self,
+        limit: Option<usize>,
+    ) -> RpcResult<Vec<Reconciliation>> {
+        debug!("get_reconciliation_history: {:?}", limit);
+        Ok(self.reconciliations.recent(limit))
+    }
+
+    async fn get_pending_activity(&self, address: Address) -> RpcResult<Option<PendingActivity>> {
+        debug!("get_pending_activity: {:?}", address);
+        let canonical_balance = EthState::balance(
+            &self.eth_api,
+            address,
+            Some(BlockId::Number(BlockNumberOrTag::Latest)),
+        )
+        .await
+        .map_err(Into::into)?;
+
+        let blocks = self.pending.load_blocks();
+        let Some(view) = blocks.latest() else {
+            return Ok(None);
+        };
+        // blocks built ahead of the pending one may have changed the balance too
+        let balance = blocks
+            .balance_at(address, view.block_number())
+            .unwrap_or(canonical_balance);
+        let (transactions, logs) = account_activity(view, address);
+
+        Ok(Some(PendingActivity {
+            address,
+            block_number: view.block_number(),
+            flashblock_index: view.flashblock_index,
+            transactions,
+            logs,
+            balance,
+            balance_delta: I256::from_raw(balance.wrapping_sub(canonical_balance)),
+        }))
+    }
+
+    async fn get_transaction_origins(&self, block_number: u64) -> RpcResult<Option<BlockOrigins>> {
+        debug!("get_transaction_origins: {:?}", block_number);
+        Ok(self.origins.for_block(block_number))
+    }
+
+    async fn get_upstream_info(&self) -> RpcResult<Option<UpstreamInfo>> {
+        debug!("get_upstream_info");
+        Ok(self.upstream_info.get())
+    }
+
+    async fn get_block_summaries(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> RpcResult<Vec<BlockSummary>> {
+        debug!("get_block_summaries: {:?} {:?}", from_block, to_block);
+        Ok(self
+            .summaries
+            .as_ref()
+            .map(|summaries| summaries.range(from_block, to_block))
+            .unwrap_or_default())
+    }
+
+    async fn get_block_summary_aggregates(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        bucket_size: u64,
+    ) -> RpcResult<Vec<SummaryAggregate>> {
+        debug!(
+            "get_block_summary_aggregates: {:?} {:?} {:?}",
+            from_block, to_block, bucket_size
+        );
+        Ok(self
+            .summaries
+            .as_ref()
+            .map(|summaries| summaries.aggregate(from_block, to_block, bucket_size))
+            .unwrap_or_default())
+    }
+
+    async fn get_receipt_polling_hint(
+        &self,
+        tx_hash: TxHash,
+    ) -> RpcResult<Option<ReceiptPollingHint>> {
+        debug!("get_receipt_polling_hint: {:?}", tx_hash);
+        // a forwarded transaction the local txpool rejected is still on its way
+        let submitted = self
+            .submissions
+            .get(tx_hash)
+            .is_some_and(|submission| submission.state == SubmissionState::Submitted);
+        if !submitted {
+            let source = EthTransactions::transaction_by_hash(&self.eth_api, tx_hash)
+                .await
+                .map_err(Into::into)?;
+            if !matches!(source, Some(TransactionSource::Pool(_))) {
+                return Ok(None);
+            }
+        }
+        let blocks = self.pending.load_blocks();
+        if blocks.transaction(tx_hash).is_some() {
+            return Ok(None);
+        }
+        let now = self.pending.clock().unix_millis();
+        Ok(Some(ReceiptPollingHint {
+            transaction_hash: tx_hash,
+            retry_after_ms: retry_after_ms(blocks.latest().map(Arc::as_ref), now),
+        }))
+    }
+}
+
+/// Returns the transactions of `view` sent by or to `address`, and the logs naming it as a
+/// topic.
+fn account_activity(
+    view: &PendingView,
+    address: Address,
+) -> (Vec<ActivityTransaction>, Vec<ActivityLog>) {
+    let topic = address.into_word();
+    let mut transactions = Vec::new();
+    let mut logs = Vec::new();
+    let mut log_index = 0;
+    for tx in view.transactions() {
+        let signed = tx.transaction();
+        let receipt = tx.receipt();
+        if tx.sender() == address || signed.to() == Some(address) {
+            transactions.push(ActivityTransaction {
+                transaction_hash: signed.tx_hash(),
+                flashblock_index: tx
+                    .preconfirmation()
+                    .map(|preconfirmation| preconfirmation.index),
+                from: tx.sender(),
+                to: signed.to(),
+                value: signed.value(),
+                nonce: signed.nonce(),
+                success: receipt.map(|receipt| receipt.status()),
+            });
+        }
+        for log in receipt.map(|receipt| receipt.logs()).unwrap_or_default() {
+            if log.topics().contains(&topic) {
+                logs.push(ActivityLog {
+                    transaction_hash: signed.tx_hash(),
+                    log_index,
+                    log: log.clone(),
+                });
+            }
+            log_index += 1;
+        }
+    }
+    (transactions, logs)
+}
+
+/// Milliseconds until the flashblock after the latest one of `view` is expected.
+fn retry_after_ms(view: Option<&PendingView>, now: u64) -> u64 {
+    let retry_after = view.map_or(DEFAULT_FLASHBLOCK_INTERVAL_MS, |view| {
+        view.next_flashblock_in_ms(now)
+    });
+    retry_after.max(MIN_RETRY_AFTER_MS)
+}
+
+/// Milliseconds from `now` until the block of `view` is sealed, which happens at its timestamp.
+fn block_end_ms(view: &PendingView, now: u64) -> u64 {
+    (view.block.timestamp * 1000).saturating_sub(now)
+}
+
+/// Estimates the confirmation of a pool transaction using `gas_limit` gas, with `gas_ahead` gas
+/// of `position` transactions ordered before it. The sequencer is assumed to fill the block
+/// being built, then whole blocks of `block_time` milliseconds, in pool order.
+fn estimate_pool_confirmation(
+    position: u64,
+    gas_ahead: u64,
+    gas_limit: u64,
+    view: Option<&PendingView>,
+    block_time: u64,
+    now: u64,
+) -> ConfirmationEstimate {
+    let Some(view) = view else {
+        return ConfirmationEstimate {
+            status: ConfirmationStatus::Pending,
+            block_number: None,
+            pool_position: Some(position),
+            time_to_preconfirmation_ms: Some(DEFAULT_FLASHBLOCK_INTERVAL_MS),
+            time_to_canonical_ms: Some(block_time),
+        };
+    };
+
+    let header = &view.block.header;
+    let remaining_gas = header.gas_limit.saturating_sub(header.gas_used);
+    let needed_gas = gas_ahead + gas_limit;
+    let block_end = block_end_ms(view, now);
+    let blocks_later = if needed_gas <= remaining_gas {
+        0
+    } else {
+        (needed_gas - remaining_gas).div_ceil(header.gas_limit.max(1))
+    };
+    let (time_to_preconfirmation, time_to_canonical) = if blocks_later == 0 {
+        let next_flashblock = view.next_flashblock_in_ms(now);
+        (next_flashblock, block_end.max(next_flashblock))
+    } else {
+        (
+            block_end + (blocks_later - 1) * block_time + view.flashblock_interval_ms(),
+            block_end + blocks_later * block_time,
+        )
+    };
+
+    ConfirmationEstimate {
+        status: ConfirmationStatus::Pending,
+        block_number: Some(view.block_number() + blocks_later),
+        pool_position: Some(position),
+        time_to_preconfirmation_ms: Some(time_to_preconfirmation),
+        time_to_canonical_ms: Some(time_to_canonical),
+    }
+}
+
+fn ordering_report(view: &PendingView) -> OrderingReport {
+    let base_fee = view.block.header.base_fee_per_gas.unwrap_or_default();
+    let flashblocks = view
+        .audit
+        .iter()
+        .map(|audit| {
+            let transactions: Vec<_> = audit
+                .transactions
+                .iter()
+                .filter_map(|tx_hash| view.transaction(*tx_hash))
+                .collect();
+
+            let tips: Vec<u128> = transactions
+                .iter()
+                .filter(|tx| !tx.transaction().is_deposit())
+                .map(|tx| {
+                    tx.transaction()
+                        .effective_tip_per_gas(base_fee)
+                        .unwrap_or_default()
+                })
+                .collect();
+            let tip_inversions = tips.windows(2).filter(|pair| pair[0] < pair[1]).count() as u64;
+
+            let mut senders: HashMap<Address, u64> = HashMap::new();
+            for tx in &transactions {
+                *senders.entry(tx.sender()).or_default() += 1;
+            }
+            let top_sender = senders
+                .iter()
+                .max_by_key(|(address, count)| (**count, std::cmp::Reverse(**address)))
+                .map(|(address, count)| (*address, *count));
+
+            FlashblockOrdering {
+                index: audit.index,
+                first_seen_at: audit.received_at,
+                transaction_count: transactions.len() as u64,
+                deposit_count: (transactions.len() - tips.len()) as u64,
+                tip_sorted: tip_inversions == 0,
+                tip_inversions,
+                distinct_senders: senders.len() as u64,
+                top_sender: top_sender.map(|(address, _)| address),
+                top_sender_count: top_sender.map_or(0, |(_, count)| count),
+            }
+        })
+        .collect();
+
+    OrderingReport {
+        block_number: view.block_number(),
+        flashblocks,
+    }
+}
+
+/// Returns the missing nonces between `next_nonce` and the sorted `pool_nonces`. Pool nonces
+/// below `next_nonce` are already used and ignored.
+fn find_nonce_gaps(next_nonce: u64, pool_nonces: &[u64]) -> Vec<NonceGap> {
+    let mut gaps = Vec::new();
+    let mut expected = next_nonce;
+    for &nonce in pool_nonces.iter().filter(|&&nonce| nonce >= next_nonce) {
+        if nonce > expected {
+            gaps.push(NonceGap {
+                from: expected,
+                to: nonce - 1,
+            });
+        }
+        expected = nonce + 1;
+    }
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pending::FlashblockAudit;
+    use crate::test_utils::{eip1559_tx, flashblock_audit};
+    use alloy_consensus::transaction::SignerRecoverable;
+    use alloy_consensus::Receipt;
+    use alloy_primitives::{Bytes, LogData, B256};
+    use reth_optimism_primitives::{OpBlock, OpReceipt};
+
+    #[test]
+    fn test_ordering_report() {
+        let tx = eip1559_tx();
+        let sender = tx.recover_signer().unwrap();
+        let tx_hash = tx.tx_hash();
+
+        let mut block = OpBlock::default();
+        block.header.number = 1;
+        block.body.transactions.push(tx);
+        let mut view = PendingView::new(block, 0, vec![sender]);
+        for (index, transactions) in [(0, vec![tx_hash]), (1, Vec::new())] {
+            view.audit.push(FlashblockAudit {
+                transactions,
+                ..flashblock_audit(index, 100 + index)
+            });
+        }
+
+        let report = ordering_report(&view);
+        assert_eq!(report.block_number, 1);
+        assert_eq!(
+            report.flashblocks[0],
+            FlashblockOrdering {
+                index: 0,
+                first_seen_at: 100,
+                transaction_count: 1,
+                deposit_count: 0,
+                tip_sorted: true,
+                tip_inversions: 0,
+                distinct_senders: 1,
+                top_sender: Some(sender),
+                top_sender_count: 1,
+            }
+        );
+        assert_eq!(report.flashblocks[1].transaction_count, 0);
+        assert_eq!(report.flashblocks[1].top_sender, None);
+    }
+
+    #[test]
+    fn test_estimate_pool_confirmation() {
+        let mut block = OpBlock::default();
+        block.header.number = 1;
+        block.header.gas_limit = 100;
+        block.header.gas_used = 60;
+        block.header.timestamp = 10;
+        let mut view = PendingView::new(block, 1, Vec::new());
+        for (index, received_at) in [(0, 9000), (1, 9200)] {
+            view.audit.push(flashblock_audit(index, received_at));
+        }
+        let now = 9300;
+
+        // fits in the next flashblock, expected at 9400, the block is sealed at 10000
+        let estimate = estimate_pool_confirmation(1, 20, 20, Some(&view), 2000, now);
+        assert_eq!(estimate.block_number, Some(1));
+        assert_eq!(estimate.time_to_preconfirmation_ms, Some(100));
+        assert_eq!(estimate.time_to_canonical_ms, Some(700));
+
+        // spills into the next block
+        let estimate = estimate_pool_confirmation(5, 100, 20, Some(&view), 2000, now);
+        assert_eq!(estimate.block_number, Some(2));
+        assert_eq!(estimate.pool_position, Some(5));
+        assert_eq!(estimate.time_to_preconfirmation_ms, Some(900));
+        assert_eq!(estimate.time_to_canonical_ms, Some(2700));
+
+        let estimate = estimate_pool_confirmation(5, 170, 20, Some(&view), 2000, now);
+        assert_eq!(estimate.block_number, Some(3));
+        assert_eq!(estimate.time_to_preconfirmation_ms, Some(2900));
+        assert_eq!(estimate.time_to_canonical_ms, Some(4700));
+
+        // on a chain with one second blocks
+        let estimate = estimate_pool_confirmation(5, 170, 20, Some(&view), 1000, now);
+        assert_eq!(estimate.time_to_preconfirmation_ms, Some(1900));
+        assert_eq!(estimate.time_to_canonical_ms, Some(2700));
+    }
+
+    #[test]
+    fn test_retry_after_ms() {
+        assert_eq!(retry_after_ms(None, 0), DEFAULT_FLASHBLOCK_INTERVAL_MS);
+
+        let mut view = PendingView::new(OpBlock::default(), 2, Vec::new());
+        for (index, received_at) in [1000, 1200, 1400].into_iter().enumerate() {
+            view.audit.push(flashblock_audit(index as u64, received_at));
+        }
+        // the next flashblock is expected at 1600
+        assert_eq!(retry_after_ms(Some(&view), 1450), 150);
+        assert_eq!(retry_after_ms(Some(&view), 1590), MIN_RETRY_AFTER_MS);
+        // overdue
+        assert_eq!(retry_after_ms(Some(&view), 1700), 200);
+    }
+
+    #[test]
+    fn test_account_activity() {
+        let tx = eip1559_tx();
+        let sender = tx.recover_signer().unwrap();
+        let recipient = tx.to().unwrap();
+        let watched = Address::repeat_byte(0x1);
+        let log = Log {
+            address: recipient,
+            data: LogData::new_unchecked(vec![B256::ZERO, watched.into_word()], Bytes::new()),
+        };
+
+        let mut block = OpBlock::default();
+        block.header.number = 1;
+        block.body.transactions.push(tx.clone());
+        let mut view = PendingView::new(block, 0, vec![sender]);
+        view.receipts.push_chunk(vec![OpReceipt::Eip1559(Receipt {
+            status: true.into(),
+            cumulative_gas_used: 21000,
+            logs: vec![log.clone()],
+        })]);
+
+        let (transactions, logs) = account_activity(&view, sender);
+        assert_eq!(
+            transactions,
+            vec![ActivityTransaction {
+                transaction_hash: tx.tx_hash(),
+                flashblock_index: None,
+                from: sender,
+                to: Some(recipient),
+                value: tx.value(),
+                nonce: 382,
+                success: Some(true),
+            }]
+        );
+        assert!(logs.is_empty());
+
+        let (transactions, _) = account_activity(&view, recipient);
+        assert_eq!(transactions.len(), 1);
+
+        let (transactions, logs) = account_activity(&view, watched);
+        assert!(transactions.is_empty());
+        assert_eq!(
+            logs,
+            vec![ActivityLog {
+                transaction_hash: tx.tx_hash(),
+                log_index: 0,
+                log,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_find_nonce_gaps() {
+        assert!(find_nonce_gaps(5, &[]).is_empty());
+        assert!(find_nonce_gaps(5, &[5, 6, 7]).is_empty());
+        assert!(find_nonce_gaps(5, &[3, 4, 5]).is_empty());
+        assert_eq!(
+            find_nonce_gaps(5, &[4, 7, 8, 11]),
+            vec![NonceGap { from: 5, to: 6 }, NonceGap { from: 9, to: 10 }]
+        );
+    }
+}
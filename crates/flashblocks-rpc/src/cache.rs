@@ -1,4 +1,7 @@
-use alloy_primitives::{Address, B256};
+use crate::clock::SharedClock;
+#[cfg(any(test, feature = "fault-injection"))]
+use crate::faults::FaultInjector;
+use alloy_primitives::Address;
 use serde::{de::DeserializeOwned, Serialize};
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
@@ -7,44 +10,43 @@ use std::time::{Duration, Instant};
 
 #[derive(Hash, Eq, PartialEq, Debug, Clone)]
 pub enum CacheKey {
-    Transaction(B256),                                        // tx_hash
-    TransactionSender(B256),                                  // tx_sender:tx_hash
-    TransactionBlockNumber(B256),                             // tx_block_number:tx_hash
-    TransactionIndex(B256),                                   // tx_idx:tx_hash
-    TransactionCount { address: Address, block_number: u64 }, // tx_count:from_address:block_number
-    Receipt(B256),                                            // receipt:tx_hash
-    ReceiptBlock(B256),                                       // receipt_block:tx_hash
-    Block(u64),                                               // block:block_number
-    Base(u64),                                                // base:block_number
-    PendingBlock,                                             // pending
-    PendingReceipts(u64),                                     // pending_receipts:block_number
-    DiffTransactions(u64),                                    // diff:transactions:block_number
-    AccountBalance(Address),                                  // address
-    HighestPayloadIndex,                                      // highest_payload_index
+    Base(u64),                                              // base:block_number
+    HighestPayloadIndex(u64),                               // highest_payload_index:block_number
+    BlockBuilder(u64),                                      // block_builder:block_number
+    AccountChanges { block_number: u64, index: u64 },       // account_changes:block_number:index
+    CanonicalNonce { block_number: u64, address: Address }, // canonical_nonce:block_number:address
+}
+
+impl CacheKey {
+    pub fn block_number(&self) -> u64 {
+        match self {
+            CacheKey::Base(number)
+            | CacheKey::HighestPayloadIndex(number)
+            | CacheKey::BlockBuilder(number) => *number,
+            CacheKey::AccountChanges { block_number, .. }
+            | CacheKey::CanonicalNonce { block_number, .. } => *block_number,
+        }
+    }
 }
 
 impl Display for CacheKey {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            CacheKey::Transaction(hash) => write!(f, "{hash:?}"),
-            CacheKey::TransactionSender(hash) => write!(f, "tx_sender:{hash:?}"),
-            CacheKey::TransactionBlockNumber(hash) => write!(f, "tx_block_number:{hash:?}"),
-            CacheKey::TransactionIndex(hash) => write!(f, "tx_idx:{hash:?}"),
-            CacheKey::TransactionCount {
-                address,
+            CacheKey::Base(number) => write!(f, "base:{number:?}"),
+            CacheKey::HighestPayloadIndex(number) => write!(f, "highest_payload_index:{number:?}"),
+            CacheKey::BlockBuilder(number) => write!(f, "block_builder:{number:?}"),
+            CacheKey::AccountChanges {
                 block_number,
+                index,
             } => {
-                write!(f, "tx_count:{address}:{block_number}")
+                write!(f, "account_changes:{block_number}:{index}")
+            }
+            CacheKey::CanonicalNonce {
+                block_number,
+                address,
+            } => {
+                write!(f, "canonical_nonce:{block_number}:{address}")
             }
-            CacheKey::Receipt(hash) => write!(f, "receipt:{hash:?}"),
-            CacheKey::ReceiptBlock(hash) => write!(f, "receipt_block:{hash:?}"),
-            CacheKey::Block(number) => write!(f, "block:{number:?}"),
-            CacheKey::Base(number) => write!(f, "base:{number:?}"),
-            CacheKey::PendingBlock => write!(f, "pending"),
-            CacheKey::PendingReceipts(number) => write!(f, "pending_receipts:{number:?}"),
-            CacheKey::DiffTransactions(number) => write!(f, "diff:transactions:{number:?}"),
-            CacheKey::AccountBalance(addr) => write!(f, "{addr:?}"),
-            CacheKey::HighestPayloadIndex => write!(f, "highest_payload_index"),
         }
     }
 }
@@ -58,17 +60,44 @@ struct CacheEntry<T> {
 #[derive(Debug, Clone)]
 pub struct Cache {
     store: Arc<RwLock<HashMap<CacheKey, CacheEntry<Vec<u8>>>>>,
+    clock: SharedClock,
+    #[cfg(any(test, feature = "fault-injection"))]
+    faults: Option<Arc<FaultInjector>>,
 }
 
 impl Default for Cache {
     fn default() -> Self {
         Self {
             store: Arc::new(RwLock::new(HashMap::new())),
+            clock: SharedClock::default(),
+            #[cfg(any(test, feature = "fault-injection"))]
+            faults: None,
         }
     }
 }
 
 impl Cache {
+    /// Clock the TTLs are measured with.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Injects `faults` into every write.
+    #[cfg(any(test, feature = "fault-injection"))]
+    pub fn with_fault_injector(mut self, faults: Arc<FaultInjector>) -> Self {
+        self.faults = Some(faults);
+        self
+    }
+
+    /// Waits out the delay injected into the writes, before those of a flashblock.
+    pub async fn before_writes(&self) {
+        #[cfg(any(test, feature = "fault-injection"))]
+        if let Some(faults) = &self.faults {
+            faults.before_writes().await;
+        }
+    }
+
     pub fn set<T: Serialize>(
         &self,
         key: CacheKey,
@@ -78,7 +107,7 @@ impl Cache {
         let serialized = serde_json::to_vec(value)?;
         let entry = CacheEntry {
             value: serialized,
-            expiry: ttl_secs.map(|secs| Instant::now() + Duration::from_secs(secs)),
+            expiry: ttl_secs.map(|secs| self.clock.now() + Duration::from_secs(secs)),
         };
 
         let mut store = self.store.write().unwrap();
@@ -86,24 +115,90 @@ impl Cache {
         Ok(())
     }
 
+    /// Replaces the value under `key` with `f(current)` while holding the write lock, so
+    /// concurrent read-modify-write updates of the same key can't interleave.
+    pub fn update<T, F>(
+        &self,
+        key: CacheKey,
+        ttl_secs: Option<u64>,
+        f: F,
+    ) -> Result<T, Box<dyn std::error::Error>>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce(Option<T>) -> T,
+    {
+        let mut store = self.store.write().unwrap();
+        let current = store.get(&key).and_then(|entry| {
+            if entry.expiry.is_some_and(|e| self.clock.now() > e) {
+                return None;
+            }
+            serde_json::from_slice(&entry.value).ok()
+        });
+
+        let value = f(current);
+        let entry = CacheEntry {
+            value: serde_json::to_vec(&value)?,
+            expiry: ttl_secs.map(|secs| self.clock.now() + Duration::from_secs(secs)),
+        };
+        store.insert(key, entry);
+        Ok(value)
+    }
+
     pub fn get<T: DeserializeOwned>(&self, key: &CacheKey) -> Option<T> {
         let store = self.store.read().unwrap();
         store.get(key).and_then(|entry| {
-            if entry.expiry.is_some_and(|e| Instant::now() > e) {
+            if entry.expiry.is_some_and(|e| self.clock.now() > e) {
                 return None;
             }
             serde_json::from_slice(&entry.value).ok()
         })
     }
 
+    /// Removes every entry of `block_number`.
+    pub fn remove_block(&self, block_number: u64) {
+        let mut store = self.store.write().unwrap();
+        store.retain(|key, _| key.block_number() != block_number);
+    }
+
+    /// Removes the canonical nonces cached for `block_number`.
+    pub fn remove_canonical_nonces(&self, block_number: u64) {
+        let mut store = self.store.write().unwrap();
+        store.retain(|key, _| {
+            !matches!(key, CacheKey::CanonicalNonce { .. }) || key.block_number() != block_number
+        });
+    }
+
+    pub fn clear(&self) {
+        self.store.write().unwrap().clear();
+    }
+
     pub fn cleanup_expired(&self) {
+        let now = self.clock.now();
         if let Ok(mut store) = self.store.write() {
-            store.retain(|_, entry| {
-                entry
-                    .expiry
-                    .map(|expiry| Instant::now() <= expiry)
-                    .unwrap_or(true)
-            });
+            store.retain(|_, entry| entry.expiry.map(|expiry| now <= expiry).unwrap_or(true));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_ttl_follows_clock() {
+        let clock = Arc::new(ManualClock::default());
+        let cache = Cache::default().with_clock(SharedClock::new(clock.clone()));
+        cache.set(CacheKey::Base(1), &1u64, Some(10)).unwrap();
+        cache.set(CacheKey::Base(2), &2u64, None).unwrap();
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cache.get::<u64>(&CacheKey::Base(1)), Some(1));
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.get::<u64>(&CacheKey::Base(1)), None);
+        cache.cleanup_expired();
+        assert_eq!(cache.store.read().unwrap().len(), 1);
+        assert_eq!(cache.get::<u64>(&CacheKey::Base(2)), Some(2));
+    }
+}
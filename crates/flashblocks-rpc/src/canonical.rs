@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::cache::{Cache, CacheKey};
+use alloy_consensus::{BlockHeader, Transaction};
+use alloy_eips::{BlockId, BlockNumberOrTag};
+use alloy_primitives::Address;
+use jsonrpsee::core::RpcResult;
+use op_alloy_consensus::DEPOSIT_TX_TYPE_ID;
+use reth::providers::{CanonStateNotification, CanonStateNotifications};
+use reth_optimism_primitives::OpPrimitives;
+use reth_rpc_eth_api::helpers::{EthBlocks, EthState, FullEthApi, LoadBlock};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, error};
+
+/// How long canonical nonces stay cached. They are keyed by block, so this only bounds memory.
+const CANONICAL_NONCE_TTL_SECS: u64 = 10;
+
+/// Nonce of `address` at canonical block `block_number`, read from the cache when known. The
+/// cache is only written from the canonical chain, the misses are read from the provider.
+pub async fn canonical_nonce<Eth>(
+    eth_api: &Eth,
+    cache: &Cache,
+    address: Address,
+    block_number: u64,
+) -> RpcResult<u64>
+where
+    Eth: FullEthApi,
+{
+    let key = CacheKey::CanonicalNonce {
+        block_number,
+        address,
+    };
+    if let Some(nonce) = cache.get::<u64>(&key) {
+        return Ok(nonce);
+    }
+
+    let nonce = EthState::transaction_count(
+        eth_api,
+        address,
+        Some(BlockId::Number(BlockNumberOrTag::Number(block_number))),
+    )
+    .await
+    .map_err(Into::into)?;
+    Ok(nonce.saturating_to())
+}
+
+/// Caches the nonces the senders of canonical block `block_number` are left with, one past
+/// the last nonce each of them used. Deposits are skipped, their nonce isn't the sender's.
+/// Returns the number of nonces cached.
+pub fn cache_block_nonces<'a, T>(
+    cache: &Cache,
+    block_number: u64,
+    transactions: impl IntoIterator<Item = (&'a Address, &'a T)>,
+) -> usize
+where
+    T: Transaction + 'a,
+{
+    let mut nonces = BTreeMap::new();
+    for (sender, transaction) in transactions {
+        if transaction.ty() == DEPOSIT_TX_TYPE_ID {
+            continue;
+        }
+        let nonce = nonces.entry(*sender).or_insert(0);
+        *nonce = (*nonce).max(transaction.nonce() + 1);
+    }
+
+    for (address, nonce) in &nonces {
+        let key = CacheKey::CanonicalNonce {
+            block_number,
+            address: *address,
+        };
+        if let Err(e) = cache.set(key, nonce, Some(CANONICAL_NONCE_TTL_SECS)) {
+            error!("Failed to set canonical nonce in cache: {}", e);
+        }
+    }
+    nonces.len()
+}
+
+/// Caches the sender nonces of the blocks committed by `notification`, dropping those of the
+/// blocks it reorged out.
+pub fn observe_canonical(cache: &Cache, notification: &CanonStateNotification<OpPrimitives>) {
+    if let CanonStateNotification::Reorg { old, .. } = notification {
+        for block_number in old.blocks().keys() {
+            cache.remove_canonical_nonces(*block_number);
+        }
+    }
+    for block in notification.committed().blocks().values() {
+        cache_block_nonces(
+            cache,
+            block.header().number(),
+            block.transactions_with_sender(),
+        );
+    }
+}
+
+/// Follows the canonical chain, caching the nonces of the senders of every new block.
+pub async fn run(cache: Arc<Cache>, mut canonical: CanonStateNotifications<OpPrimitives>) {
+    loop {
+        match canonical.recv().await {
+            Ok(notification) => observe_canonical(&cache, &notification),
+            // the nonces of the skipped blocks are read from the provider
+            Err(RecvError::Lagged(skipped)) => {
+                debug!("Canonical nonces skipped {} notifications", skipped);
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Loads the canonical head block and its receipts into the eth API's cache and the nonces of
+/// its senders into `cache`, so the first pending requests after startup don't wait on the
+/// provider. Returns the number of nonces loaded.
+pub async fn warm_up<Eth>(eth_api: &Eth, cache: &Cache) -> RpcResult<usize>
+where
+    Eth: FullEthApi,
+{
+    let Some(block) = LoadBlock::recovered_block(eth_api, BlockNumberOrTag::Latest.into())
+        .await
+        .map_err(Into::into)?
+    else {
+        return Ok(0);
+    };
+    let block_number = block.header().number();
+    EthBlocks::block_receipts(eth_api, block_number.into())
+        .await
+        .map_err(Into::into)?;
+
+    // the accounts that just transacted are the likeliest to ask for their pending nonce
+    Ok(cache_block_nonces(
+        cache,
+        block_number,
+        block.transactions_with_sender(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::signed_tx;
+    use alloy_eips::eip2718::{Decodable2718, Encodable2718};
+    use alloy_primitives::Sealed;
+    use op_alloy_consensus::{OpTxEnvelope, TxDeposit};
+    use reth::providers::{Chain, ExecutionOutcome};
+    use reth_optimism_primitives::{OpBlock, OpTransactionSigned};
+    use reth_primitives::RecoveredBlock;
+
+    fn chain(
+        block_number: u64,
+        transactions: Vec<(Address, OpTransactionSigned)>,
+    ) -> Arc<Chain<OpPrimitives>> {
+        let mut block = OpBlock::default();
+        block.header.number = block_number;
+        let (senders, transactions) = transactions.into_iter().unzip();
+        block.body.transactions = transactions;
+        let block = RecoveredBlock::new_unhashed(block, senders);
+        Arc::new(Chain::new([block], ExecutionOutcome::default(), None))
+    }
+
+    fn cached(cache: &Cache, block_number: u64, address: Address) -> Option<u64> {
+        cache.get(&CacheKey::CanonicalNonce {
+            block_number,
+            address,
+        })
+    }
+
+    #[test]
+    fn test_cache_block_nonces() {
+        let cache = Cache::default();
+        let (alice, bob, depositor) = (
+            Address::repeat_byte(0x1),
+            Address::repeat_byte(0x2),
+            Address::repeat_byte(0x3),
+        );
+        let deposit = TxDeposit {
+            from: depositor,
+            ..Default::default()
+        };
+        let deposit = OpTxEnvelope::Deposit(Sealed::new(deposit));
+        let deposit =
+            OpTransactionSigned::decode_2718(&mut deposit.encoded_2718().as_slice()).unwrap();
+        let transactions = [
+            (depositor, deposit),
+            (alice, signed_tx(4)),
+            (bob, signed_tx(0)),
+            (alice, signed_tx(5)),
+        ];
+        let cached_nonces = cache_block_nonces(
+            &cache,
+            5,
+            transactions.iter().map(|(sender, tx)| (sender, tx)),
+        );
+        assert_eq!(cached_nonces, 2);
+        assert_eq!(cached(&cache, 5, alice), Some(6));
+        assert_eq!(cached(&cache, 5, bob), Some(1));
+        assert_eq!(cached(&cache, 5, depositor), None);
+        assert_eq!(cached(&cache, 4, alice), None);
+    }
+
+    #[test]
+    fn test_observe_canonical() {
+        let cache = Cache::default();
+        let (alice, bob) = (Address::repeat_byte(0x1), Address::repeat_byte(0x2));
+        let included = chain(5, vec![(alice, signed_tx(0))]);
+        observe_canonical(
+            &cache,
+            &CanonStateNotification::Commit {
+                new: included.clone(),
+            },
+        );
+        assert_eq!(cached(&cache, 5, alice), Some(1));
+
+        // the block is reorged out for one sent by another account
+        observe_canonical(
+            &cache,
+            &CanonStateNotification::Reorg {
+                old: included,
+                new: chain(5, vec![(bob, signed_tx(2))]),
+            },
+        );
+        assert_eq!(cached(&cache, 5, alice), None);
+        assert_eq!(cached(&cache, 5, bob), Some(3));
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Source of time for the cache TTLs, the staleness of the pending views and the latencies
+/// reported by the metrics. Only differences between instants of the same clock are meaningful.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Time since the unix epoch, which the timestamps reported over RPC and to webhooks are
+    /// taken from.
+    fn unix_time(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+
+    /// Called with the timestamp of every block the flashblocks start, in seconds.
+    fn observe_block(&self, _timestamp: u64) {}
+}
+
+/// The wall clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to, so tests can expire entries deterministically.
+#[derive(Debug)]
+pub struct ManualClock {
+    start: Instant,
+    /// Unix time at `start`
+    start_unix: Duration,
+    elapsed: Mutex<Duration>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            start_unix: SystemClock.unix_time(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+}
+
+impl ManualClock {
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+
+    fn unix_time(&self) -> Duration {
+        self.start_unix + *self.elapsed.lock().unwrap()
+    }
+}
+
+/// A clock following the timestamps of the preconfirmed blocks rather than the host, so
+/// entries expire after a number of blocks even when the stream is replayed faster than real
+/// time. Between blocks it runs with the host's monotonic time, so entries still expire when no
+/// block starts and latencies within a block are measured at full resolution.
+#[derive(Debug)]
+pub struct BlockClock {
+    start: Instant,
+    /// Timestamp of the first block seen, zero until then
+    first_timestamp: AtomicU64,
+    anchor: Mutex<BlockClockAnchor>,
+}
+
+/// Reading of a [`BlockClock`] at the host instant it was taken, which it runs on from.
+#[derive(Debug, Clone, Copy)]
+struct BlockClockAnchor {
+    elapsed: Duration,
+    at: Instant,
+}
+
+impl BlockClockAnchor {
+    fn elapsed(&self) -> Duration {
+        self.elapsed + self.at.elapsed()
+    }
+}
+
+impl Default for BlockClock {
+    fn default() -> Self {
+        let start = Instant::now();
+        Self {
+            start,
+            first_timestamp: AtomicU64::new(0),
+            anchor: Mutex::new(BlockClockAnchor {
+                elapsed: Duration::ZERO,
+                at: start,
+            }),
+        }
+    }
+}
+
+impl Clock for BlockClock {
+    fn now(&self) -> Instant {
+        self.start + self.anchor.lock().unwrap().elapsed()
+    }
+
+    fn observe_block(&self, timestamp: u64) {
+        let first = self
+            .first_timestamp
+            .compare_exchange(0, timestamp, Ordering::Relaxed, Ordering::Relaxed)
+            .unwrap_or_else(|first| first);
+        let block_elapsed = Duration::from_secs(timestamp.saturating_sub(first));
+        // blocks of different heights may interleave and the host time may have run past the
+        // block time, the clock never goes back
+        let mut anchor = self.anchor.lock().unwrap();
+        if block_elapsed > anchor.elapsed() {
+            *anchor = BlockClockAnchor {
+                elapsed: block_elapsed,
+                at: Instant::now(),
+            };
+        }
+    }
+}
+
+/// A [`Clock`] shared by the cache, the pending views and the tasks watching them. Defaults to
+/// the wall clock.
+#[derive(Debug, Clone)]
+pub struct SharedClock(Arc<dyn Clock>);
+
+impl SharedClock {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self(clock)
+    }
+
+    pub fn now(&self) -> Instant {
+        self.0.now()
+    }
+
+    pub fn observe_block(&self, timestamp: u64) {
+        self.0.observe_block(timestamp);
+    }
+
+    /// Unix timestamp in milliseconds.
+    pub fn unix_millis(&self) -> u64 {
+        self.0.unix_time().as_millis() as u64
+    }
+
+    /// Time from `earlier` to now.
+    pub fn since(&self, earlier: Instant) -> Duration {
+        self.now().saturating_duration_since(earlier)
+    }
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        Self(Arc::new(SystemClock))
+    }
+}
+
+/// Clock the node expires its flashblocks state with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    #[default]
+    Wall,
+    Block,
+}
+
+impl ClockSource {
+    pub fn clock(self) -> SharedClock {
+        match self {
+            Self::Wall => SharedClock::default(),
+            Self::Block => SharedClock::new(Arc::new(BlockClock::default())),
+        }
+    }
+}
+
+impl FromStr for ClockSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wall" => Ok(Self::Wall),
+            "block" => Ok(Self::Block),
+            _ => Err(format!("invalid clock: {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for ClockSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Wall => write!(f, "wall"),
+            Self::Block => write!(f, "block"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::default();
+        let start = clock.now();
+        assert_eq!(clock.now(), start);
+
+        let start_unix = clock.unix_time();
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(clock.now() - start, Duration::from_secs(3));
+        assert_eq!(clock.unix_time() - start_unix, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_block_clock() {
+        let clock = BlockClock::default();
+        let start = clock.now();
+        clock.observe_block(1000);
+        assert!(clock.now() - start < Duration::from_secs(1));
+
+        // runs between blocks
+        let before = clock.now();
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(clock.now() - before >= Duration::from_millis(5));
+
+        // jumps ahead to blocks replayed faster than real time
+        clock.observe_block(1002);
+        let elapsed = clock.now() - start;
+        assert!(elapsed >= Duration::from_secs(2) && elapsed < Duration::from_secs(3));
+        // an older height starting late doesn't move the clock back
+        clock.observe_block(1001);
+        assert!(clock.now() - start >= elapsed);
+    }
+
+    #[test]
+    fn test_clock_source() {
+        for source in [ClockSource::Wall, ClockSource::Block] {
+            assert_eq!(source.to_string().parse::<ClockSource>(), Ok(source));
+        }
+        assert!("host".parse::<ClockSource>().is_err());
+    }
+}
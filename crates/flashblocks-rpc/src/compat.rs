@@ -0,0 +1,357 @@
+use serde_json::Value;
+
+/// Type of deposit transactions, as rendered in RPC responses.
+const DEPOSIT_TX_TYPE: &str = "0x7e";
+
+/// Transaction fields only the Optimism network types have, all of them deposit related.
+const OP_TRANSACTION_FIELDS: [&str; 5] = [
+    "sourceHash",
+    "mint",
+    "isSystemTx",
+    "depositNonce",
+    "depositReceiptVersion",
+];
+
+/// Receipt fields only the Optimism network types have: the L1 fee breakdown, the operator fee
+/// and the deposit fields.
+const OP_RECEIPT_FIELDS: [&str; 11] = [
+    "l1GasPrice",
+    "l1GasUsed",
+    "l1Fee",
+    "l1FeeScalar",
+    "l1BaseFeeScalar",
+    "l1BlobBaseFee",
+    "l1BlobBaseFeeScalar",
+    "operatorFeeScalar",
+    "operatorFeeConstant",
+    "depositNonce",
+    "depositReceiptVersion",
+];
+
+/// Fills in the fields of a rendered pending block that the RPC types omit but strict client
+/// libraries expect, with the values op-geth serves for canonical blocks:
+/// - `totalDifficulty`, part of the block schema web3.js validates responses against
+/// - `withdrawals`, always empty on OP Stack chains but expected whenever `withdrawalsRoot` is set
+/// - `v`, `r` and `s` of deposit transactions, which ethers builds a signature from
+pub fn adjust_block(block: &mut Value) {
+    let Some(block) = block.as_object_mut() else {
+        return;
+    };
+    block
+        .entry("totalDifficulty")
+        .or_insert_with(|| Value::from("0x0"));
+    if block.contains_key("withdrawalsRoot") {
+        block
+            .entry("withdrawals")
+            .or_insert_with(|| Value::Array(Vec::new()));
+    }
+
+    // blocks rendered with transaction hashes only have nothing more to adjust
+    let Some(Value::Array(transactions)) = block.get_mut("transactions") else {
+        return;
+    };
+    for tx in transactions.iter_mut().filter_map(Value::as_object_mut) {
+        if tx.get("type").and_then(Value::as_str) != Some(DEPOSIT_TX_TYPE) {
+            continue;
+        }
+        for field in ["v", "r", "s"] {
+            tx.entry(field).or_insert_with(|| Value::from("0x0"));
+        }
+    }
+}
+
+/// Drops the Optimism specific fields of the transactions of a rendered block, so it parses as
+/// the plain Ethereum block type.
+pub fn to_ethereum_block(block: &mut Value) {
+    let Some(Value::Array(transactions)) = block.get_mut("transactions") else {
+        return;
+    };
+    for tx in transactions {
+        to_ethereum_transaction(tx);
+    }
+}
+
+/// Drops the Optimism specific fields of a rendered transaction. Deposits keep their type, there
+/// is no Ethereum equivalent.
+pub fn to_ethereum_transaction(tx: &mut Value) {
+    remove_fields(tx, &OP_TRANSACTION_FIELDS);
+}
+
+/// Drops the Optimism specific fields of a rendered receipt.
+pub fn to_ethereum_receipt(receipt: &mut Value) {
+    remove_fields(receipt, &OP_RECEIPT_FIELDS);
+}
+
+fn remove_fields(value: &mut Value, fields: &[&str]) {
+    if let Some(object) = value.as_object_mut() {
+        for field in fields {
+            object.remove(*field);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::cache::Cache;
+    use crate::pending::{PendingView, PendingViewStore};
+    use crate::rpc::{EthApiExt, PendingBlock};
+    use alloy_consensus::{SignableTransaction, TxEip1559};
+    use alloy_eips::eip2718::{Decodable2718, Encodable2718};
+    use alloy_primitives::{Address, Sealed, Signature, B256};
+    use op_alloy_consensus::{OpTxEnvelope, TxDeposit};
+    use reth_optimism_chainspec::BASE_MAINNET;
+    use reth_optimism_primitives::{OpBlock, OpTransactionSigned};
+
+    /// Fields each client library reads from a block without falling back to a default.
+    const REQUIRED_BLOCK_FIELDS: &[(&str, &[&str])] = &[
+        (
+            "ethers v5",
+            &[
+                "parentHash",
+                "number",
+                "timestamp",
+                "difficulty",
+                "gasLimit",
+                "gasUsed",
+                "extraData",
+                "transactions",
+            ],
+        ),
+        (
+            "ethers v6",
+            &[
+                "parentHash",
+                "number",
+                "timestamp",
+                "nonce",
+                "difficulty",
+                "gasLimit",
+                "gasUsed",
+                "miner",
+                "extraData",
+                "baseFeePerGas",
+                "transactions",
+            ],
+        ),
+        (
+            "viem",
+            &[
+                "hash",
+                "number",
+                "timestamp",
+                "gasLimit",
+                "gasUsed",
+                "baseFeePerGas",
+                "transactions",
+                "withdrawals",
+            ],
+        ),
+        (
+            "web3.js v4",
+            &[
+                "parentHash",
+                "sha3Uncles",
+                "miner",
+                "stateRoot",
+                "transactionsRoot",
+                "receiptsRoot",
+                "logsBloom",
+                "difficulty",
+                "number",
+                "gasLimit",
+                "gasUsed",
+                "timestamp",
+                "extraData",
+                "mixHash",
+                "nonce",
+                "totalDifficulty",
+                "uncles",
+                "transactions",
+            ],
+        ),
+    ];
+
+    /// Fields each client library reads from a deposit transaction.
+    const REQUIRED_DEPOSIT_FIELDS: &[(&str, &[&str])] = &[
+        ("ethers v5", &["hash", "from", "gas", "value", "input"]),
+        (
+            "ethers v6",
+            &["hash", "from", "gas", "value", "input", "v", "r", "s"],
+        ),
+        ("viem", &["hash", "from", "gas", "value", "input", "type"]),
+    ];
+
+    /// Block fields that are quantities, hex encoded without leading zeros.
+    const BLOCK_QUANTITY_FIELDS: &[&str] = &[
+        "number",
+        "timestamp",
+        "difficulty",
+        "totalDifficulty",
+        "gasLimit",
+        "gasUsed",
+        "baseFeePerGas",
+    ];
+
+    /// Transaction fields that are quantities.
+    const TRANSACTION_QUANTITY_FIELDS: &[&str] = &["gas", "value", "nonce", "v", "r", "s"];
+
+    fn decode(envelope: OpTxEnvelope) -> OpTransactionSigned {
+        OpTransactionSigned::decode_2718(&mut envelope.encoded_2718().as_slice()).unwrap()
+    }
+
+    fn pending_block(compat: bool) -> Value {
+        let deposit = TxDeposit {
+            source_hash: B256::repeat_byte(0x1),
+            from: Address::repeat_byte(0x1),
+            gas_limit: 1000000,
+            ..Default::default()
+        };
+        let transfer = TxEip1559 {
+            chain_id: 8453,
+            gas_limit: 21000,
+            ..Default::default()
+        };
+        let mut block = OpBlock::default();
+        block.header.number = 1;
+        block.header.base_fee_per_gas = Some(1);
+        block.header.withdrawals_root = Some(B256::ZERO);
+        block.body.transactions = vec![
+            decode(OpTxEnvelope::Deposit(Sealed::new(deposit))),
+            decode(OpTxEnvelope::Eip1559(
+                transfer.into_signed(Signature::test_signature()),
+            )),
+        ];
+        let view = PendingView::new(
+            block,
+            1,
+            vec![Address::repeat_byte(0x1), Address::repeat_byte(0x2)],
+        );
+
+        let eth_api = EthApiExt::new(
+            (),
+            Arc::new(Cache::default()),
+            Arc::new(PendingViewStore::default()),
+            BASE_MAINNET.clone(),
+        );
+        let mut block = PendingBlock::from(eth_api.transform_block(&view, true));
+        block.compat = compat;
+        serde_json::to_value(&block).unwrap()
+    }
+
+    fn missing_fields(value: &Value, required: &[&str]) -> Vec<String> {
+        required
+            .iter()
+            .filter(|field| value.get(**field).is_none_or(Value::is_null))
+            .map(|field| field.to_string())
+            .collect()
+    }
+
+    fn is_quantity(value: &str) -> bool {
+        value.strip_prefix("0x").is_some_and(|digits| {
+            digits == "0"
+                || (!digits.is_empty()
+                    && !digits.starts_with('0')
+                    && digits.chars().all(|c| c.is_ascii_hexdigit()))
+        })
+    }
+
+    #[test]
+    fn test_block_conformance() {
+        let block = pending_block(true);
+        for (library, required) in REQUIRED_BLOCK_FIELDS {
+            assert!(
+                missing_fields(&block, required).is_empty(),
+                "{library} needs {:?}",
+                missing_fields(&block, required)
+            );
+        }
+
+        let deposit = &block["transactions"][0];
+        assert_eq!(deposit["type"], DEPOSIT_TX_TYPE);
+        for (library, required) in REQUIRED_DEPOSIT_FIELDS {
+            assert!(
+                missing_fields(deposit, required).is_empty(),
+                "{library} needs {:?}",
+                missing_fields(deposit, required)
+            );
+        }
+
+        let quantities = [
+            (&block, BLOCK_QUANTITY_FIELDS),
+            (deposit, TRANSACTION_QUANTITY_FIELDS),
+            (&block["transactions"][1], TRANSACTION_QUANTITY_FIELDS),
+        ];
+        for (value, fields) in quantities {
+            for field in fields {
+                if let Some(quantity) = value.get(*field).and_then(Value::as_str) {
+                    assert!(
+                        is_quantity(quantity),
+                        "{field} is not a quantity: {quantity}"
+                    );
+                }
+            }
+        }
+        // fixed size data, not quantities
+        assert_eq!(block["hash"].as_str().unwrap().len(), 2 + 64);
+        assert_eq!(block["nonce"].as_str().unwrap().len(), 2 + 16);
+        assert_eq!(block["logsBloom"].as_str().unwrap().len(), 2 + 512);
+    }
+
+    #[test]
+    fn test_adjustments_need_compat() {
+        let block = pending_block(false);
+        assert_eq!(
+            missing_fields(&block, &["totalDifficulty", "withdrawals"]),
+            vec!["totalDifficulty", "withdrawals"]
+        );
+
+        // signed transactions keep their own signature
+        let adjusted = pending_block(true);
+        assert_eq!(
+            adjusted["transactions"][1]["r"],
+            block["transactions"][1]["r"]
+        );
+    }
+
+    #[test]
+    fn test_to_ethereum_block() {
+        let mut block = pending_block(false);
+        assert!(block["transactions"][0].get("sourceHash").is_some());
+
+        to_ethereum_block(&mut block);
+        let deposit = block["transactions"][0].as_object().unwrap();
+        for field in OP_TRANSACTION_FIELDS {
+            assert!(!deposit.contains_key(field), "{field} is Optimism specific");
+        }
+        assert_eq!(deposit["type"], DEPOSIT_TX_TYPE);
+        assert_eq!(deposit["from"], Address::repeat_byte(0x1).to_string());
+    }
+
+    #[test]
+    fn test_to_ethereum_receipt() {
+        let mut receipt = serde_json::json!({
+            "status": "0x1",
+            "l1Fee": "0x1",
+            "l1GasUsed": "0x1",
+            "depositNonce": "0x1",
+        });
+        to_ethereum_receipt(&mut receipt);
+        assert_eq!(receipt, serde_json::json!({"status": "0x1"}));
+    }
+
+    #[test]
+    fn test_adjust_block_keeps_present_fields() {
+        let mut block = serde_json::json!({
+            "totalDifficulty": "0x1",
+            "transactions": [B256::ZERO],
+        });
+        adjust_block(&mut block);
+        assert_eq!(
+            block,
+            serde_json::json!({"totalDifficulty": "0x1", "transactions": [B256::ZERO]})
+        );
+    }
+}
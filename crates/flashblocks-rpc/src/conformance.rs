@@ -0,0 +1,284 @@
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::cache::Cache;
+use crate::pending::{PendingView, PendingViewStore};
+use crate::rpc::EthApiExt;
+use alloy_consensus::Receipt;
+use alloy_eips::eip2718::{Decodable2718, Encodable2718};
+use alloy_primitives::{Address, Bytes, Log, LogData, B256, U256};
+use op_alloy_consensus::OpDepositReceipt;
+use regex::Regex;
+use reth_optimism_chainspec::BASE_SEPOLIA;
+use reth_optimism_primitives::{OpBlock, OpReceipt, OpTransactionSigned};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+const SCHEMA_FILES: [&str; 4] = [
+    include_str!("../schemas/base-types.json"),
+    include_str!("../schemas/block.json"),
+    include_str!("../schemas/transaction.json"),
+    include_str!("../schemas/receipt.json"),
+];
+
+const REF_PREFIX: &str = "#/components/schemas/";
+
+/// L1 block info deposit, Ecotone encoded
+const L1_INFO_DEPOSIT: &str = "0x7ef8f8a042a8ae5ec231af3d0f90f68543ec8bca1da4f7edd712d5b51b490688355a6db794deaddeaddeaddeaddeaddeaddeaddeaddead00019442000000000000000000000000000000000000158080830f424080b8a4440a5e200000044d000a118b00000000000000040000000067cb7cb0000000000077dbd4000000000000000000000000000000000000000000000000000000000000000a00000000000000000000000000000000000000000000000000000000000000014edd27304108914dd6503b19b9eeb9956982ef197febbeeed8a9eac3dbaaabdf000000000000000000000000fc56e7272eebbba5bc6c544e159483c4a38f8ba3";
+
+/// Legacy transaction calling a contract
+const LEGACY_TRANSACTION: &str = "0xf8cd82016d8316e5708302c01c94f39635f2adf40608255779ff742afe13de31f57780b8646e530e9700000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000001bc16d674ec8000000000000000000000000000000000000000000000000000156ddc81eed2a36d68302948ba0a608703e79b22164f74523d188a11f81c25a65dd59535bab1cd1d8b30d115f3ea07f4cfbbad77a139c9209d3bded89091867ff6b548dd714109c61d1f8e7a84d14";
+
+/// The execution API schemas of `schemas/`, by name. The responses the overridden methods
+/// serve from the flashblocks state are checked against them, so a field that changes shape or
+/// goes missing is caught before it breaks strict clients.
+struct Schemas {
+    schemas: Map<String, Value>,
+}
+
+impl Schemas {
+    fn load() -> Self {
+        let mut schemas = Map::new();
+        for file in SCHEMA_FILES {
+            let Value::Object(file) = serde_json::from_str(file).unwrap() else {
+                panic!("schema files map names to schemas");
+            };
+            schemas.extend(file);
+        }
+        Self { schemas }
+    }
+
+    /// Returns the violations of the schema `name` by `response`.
+    fn check(&self, name: &str, response: impl Serialize) -> Vec<String> {
+        let response = serde_json::to_value(response).unwrap();
+        self.validate(&self.schemas[name], &response, name)
+    }
+
+    /// Validates `value` against the subset of JSON schema used by the execution APIs: `$ref`,
+    /// `type`, `pattern`, `required`, `properties`, `items` and `oneOf`.
+    fn validate(&self, schema: &Value, value: &Value, path: &str) -> Vec<String> {
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            let name = reference.strip_prefix(REF_PREFIX).unwrap();
+            return self.validate(&self.schemas[name], value, path);
+        }
+
+        let mut errors = Vec::new();
+        if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+            if type_name(value) != expected {
+                errors.push(format!("{path}: expected {expected}, got {value}"));
+                return errors;
+            }
+        }
+        if let (Some(pattern), Some(string)) = (
+            schema.get("pattern").and_then(Value::as_str),
+            value.as_str(),
+        ) {
+            if !Regex::new(pattern).unwrap().is_match(string) {
+                errors.push(format!("{path}: {string} doesn't match {pattern}"));
+            }
+        }
+        if let (Some(required), Some(object)) = (
+            schema.get("required").and_then(Value::as_array),
+            value.as_object(),
+        ) {
+            for field in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(field) {
+                    errors.push(format!("{path}.{field}: missing"));
+                }
+            }
+        }
+        if let (Some(properties), Some(object)) = (
+            schema.get("properties").and_then(Value::as_object),
+            value.as_object(),
+        ) {
+            for (field, property) in properties {
+                if let Some(value) = object.get(field) {
+                    errors.extend(self.validate(property, value, &format!("{path}.{field}")));
+                }
+            }
+        }
+        if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+            for (index, item) in array.iter().enumerate() {
+                errors.extend(self.validate(items, item, &format!("{path}[{index}]")));
+            }
+        }
+        if let Some(variants) = schema.get("oneOf").and_then(Value::as_array) {
+            let matching = variants
+                .iter()
+                .filter(|variant| self.validate(variant, value, path).is_empty())
+                .count();
+            if matching != 1 {
+                errors.push(format!("{path}: matches {matching} of its oneOf schemas"));
+            }
+        }
+        errors
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn decode(raw: &str) -> OpTransactionSigned {
+    OpTransactionSigned::decode_2718(&mut Bytes::from_str(raw).unwrap().as_ref()).unwrap()
+}
+
+/// A pending block with an L1 info deposit and a legacy transaction emitting a log, and the
+/// overrides serving it.
+fn pending_state() -> (EthApiExt<()>, Arc<PendingView>) {
+    let sender = Address::repeat_byte(0x1);
+    let mut block = OpBlock::default();
+    block.header.number = 1;
+    // Ecotone is active on Base Sepolia
+    block.header.timestamp = 1710000000;
+    block.header.base_fee_per_gas = Some(1000);
+    block.header.withdrawals_root = Some(B256::ZERO);
+    block.body.transactions = vec![decode(L1_INFO_DEPOSIT), decode(LEGACY_TRANSACTION)];
+
+    let mut view = PendingView::new(block, 1, vec![Address::repeat_byte(0xde), sender]);
+    let log = Log {
+        address: Address::repeat_byte(0x2),
+        data: LogData::new_unchecked(vec![B256::repeat_byte(0x3)], Bytes::from_static(&[1])),
+    };
+    view.receipts.push_chunk(vec![
+        OpReceipt::Deposit(OpDepositReceipt {
+            inner: Receipt {
+                status: true.into(),
+                cumulative_gas_used: 50000,
+                logs: vec![],
+            },
+            deposit_nonce: Some(1),
+            deposit_receipt_version: Some(1),
+        }),
+        OpReceipt::Legacy(Receipt {
+            status: true.into(),
+            cumulative_gas_used: 100000,
+            logs: vec![log],
+        }),
+    ]);
+    view.balances.insert(sender, U256::from(1234));
+    view.nonces.insert(sender, BTreeSet::from([365]));
+
+    let pending = Arc::new(PendingViewStore::default());
+    let view = pending.publish(view);
+    let eth_api = EthApiExt::new(
+        (),
+        Arc::new(Cache::default()),
+        pending,
+        BASE_SEPOLIA.clone(),
+    );
+    (eth_api, view)
+}
+
+#[test]
+fn test_get_block_by_number() {
+    let schemas = Schemas::load();
+    let (eth_api, view) = pending_state();
+    for full in [false, true] {
+        let block = eth_api.pending_block(&view, full);
+        assert_eq!(schemas.check("Block", &block), Vec::<String>::new());
+    }
+}
+
+#[test]
+fn test_get_transaction_by_hash() {
+    let schemas = Schemas::load();
+    let (eth_api, view) = pending_state();
+    for tx in view.transactions() {
+        let transaction = eth_api
+            .pending_transaction(tx.transaction().tx_hash())
+            .unwrap();
+        assert_eq!(
+            schemas.check("TransactionInfo", &transaction),
+            Vec::<String>::new()
+        );
+    }
+}
+
+#[test]
+fn test_get_transaction_by_block_number_and_index() {
+    let schemas = Schemas::load();
+    let (eth_api, view) = pending_state();
+    for (index, tx) in view.transactions().enumerate() {
+        let transaction = eth_api.render_transaction(view.transaction_at(index).unwrap());
+        assert_eq!(transaction.inner.transaction_index, Some(index as u64));
+        assert_eq!(
+            transaction.inner.inner.tx_hash(),
+            tx.transaction().tx_hash()
+        );
+        assert_eq!(
+            schemas.check("TransactionInfo", &transaction),
+            Vec::<String>::new()
+        );
+    }
+    assert!(view.transaction_at(2).is_none());
+}
+
+#[test]
+fn test_get_transaction_receipt() {
+    let schemas = Schemas::load();
+    let (eth_api, view) = pending_state();
+    for tx in view.transactions() {
+        let receipt = eth_api.pending_receipt(tx.transaction().tx_hash()).unwrap();
+        assert_eq!(schemas.check("ReceiptInfo", &receipt), Vec::<String>::new());
+    }
+}
+
+#[test]
+fn test_get_block_receipts() {
+    let schemas = Schemas::load();
+    let (eth_api, view) = pending_state();
+    let receipts = eth_api.pending_block_receipts(&view);
+    assert_eq!(receipts.len(), 2);
+    for (index, receipt) in receipts.iter().enumerate() {
+        assert_eq!(receipt.receipt.inner.transaction_index, Some(index as u64));
+        assert_eq!(schemas.check("ReceiptInfo", receipt), Vec::<String>::new());
+    }
+}
+
+#[test]
+fn test_get_raw_transaction_by_hash() {
+    let schemas = Schemas::load();
+    let (_, view) = pending_state();
+    for tx in view.transactions() {
+        let raw: Bytes = tx.transaction().encoded_2718().into();
+        assert_eq!(schemas.check("bytes", &raw), Vec::<String>::new());
+    }
+}
+
+#[test]
+fn test_get_balance_and_transaction_count() {
+    let schemas = Schemas::load();
+    let (_, view) = pending_state();
+    let sender = Address::repeat_byte(0x1);
+
+    let balance = view.balance(sender).unwrap();
+    assert_eq!(schemas.check("uint256", balance), Vec::<String>::new());
+    let nonce = U256::from(view.next_nonce(sender).unwrap());
+    assert_eq!(schemas.check("uint", nonce), Vec::<String>::new());
+}
+
+#[test]
+fn test_violations_are_reported() {
+    let schemas = Schemas::load();
+    let (eth_api, view) = pending_state();
+    let mut block = serde_json::to_value(eth_api.pending_block(&view, false)).unwrap();
+    block["number"] = Value::from("0x01");
+    block.as_object_mut().unwrap().remove("size");
+
+    assert_eq!(
+        schemas.check("Block", &block),
+        vec![
+            "Block.size: missing".to_string(),
+            "Block.number: 0x01 doesn't match ^0x(0|[1-9a-f][0-9a-f]*)$".to_string(),
+        ]
+    );
+}
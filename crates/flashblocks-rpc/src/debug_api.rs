@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use crate::pending::{FlashblockAudit, PendingViewStore};
+use alloy_primitives::{B256, U64};
+use alloy_rpc_types_engine::PayloadId;
+use jsonrpsee::{
+    core::{async_trait, RpcResult},
+    proc_macros::rpc,
+};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// How a preconfirmed block was put together from its flashblocks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockAudit {
+    pub block_number: u64,
+    pub block_hash: B256,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload_id: Option<PayloadId>,
+    pub flashblocks: Vec<FlashblockAudit>,
+}
+
+#[cfg_attr(not(test), rpc(server, namespace = "debug"))]
+#[cfg_attr(test, rpc(server, client, namespace = "debug"))]
+pub trait DebugApi {
+    /// Returns, per flashblock of a preconfirmed block, the transactions it added, where their
+    /// receipts came from and the checks run while applying it. Returns `null` for heights
+    /// whose view is no longer retained.
+    #[method(name = "flashblocksBlockAudit")]
+    async fn flashblocks_block_audit(&self, block_number: U64) -> RpcResult<Option<BlockAudit>>;
+}
+
+#[derive(Debug)]
+pub struct DebugApiExt {
+    pending: Arc<PendingViewStore>,
+}
+
+impl DebugApiExt {
+    pub fn new(pending: Arc<PendingViewStore>) -> Self {
+        Self { pending }
+    }
+}
+
+#[async_trait]
+impl DebugApiServer for DebugApiExt {
+    async fn flashblocks_block_audit(&self, block_number: U64) -> RpcResult<Option<BlockAudit>> {
+        debug!("flashblocks_block_audit: {}", block_number);
+        Ok(self
+            .pending
+            .load_blocks()
+            .for_block(block_number.to())
+            .map(|view| BlockAudit {
+                block_number: view.block_number(),
+                block_hash: view.block_hash,
+                payload_id: view.payload_id,
+                flashblocks: view.audit.iter().cloned().collect(),
+            }))
+    }
+}
@@ -0,0 +1,410 @@
+use std::sync::Arc;
+
+use crate::metrics::Metrics;
+use crate::pending::{PendingView, PendingViewStore};
+use crate::rpc::FallbackReason;
+use crate::simulations::cancellable;
+use crate::tags::{preconfirmed_unavailable, PendingTagMode, PreconfirmedOr};
+use alloy_consensus::ReceiptWithBloom;
+use alloy_eips::eip2718::Encodable2718;
+use alloy_eips::BlockId;
+use alloy_primitives::Bytes;
+use alloy_rpc_types_eth::TransactionRequest;
+use alloy_rpc_types_trace::geth::{GethDebugTracingCallOptions, GethTrace};
+use jsonrpsee::{
+    core::{async_trait, RpcResult},
+    proc_macros::rpc,
+};
+use reth::providers::BlockNumReader;
+use reth::rpc::api::DebugApiServer;
+use reth::rpc::server_types::result::internal_rpc_err;
+use tracing::debug;
+
+/// The node's own `debug` methods overridden here, serving the canonical chain.
+#[async_trait]
+pub trait CanonicalDebug: Send + Sync {
+    async fn trace_call(
+        &self,
+        request: TransactionRequest,
+        block_id: Option<BlockId>,
+        opts: Option<GethDebugTracingCallOptions>,
+    ) -> RpcResult<GethTrace>;
+
+    async fn raw_block(&self, block_id: BlockId) -> RpcResult<Bytes>;
+
+    async fn raw_receipts(&self, block_id: BlockId) -> RpcResult<Vec<Bytes>>;
+}
+
+#[async_trait]
+impl<T> CanonicalDebug for T
+where
+    T: DebugApiServer,
+{
+    async fn trace_call(
+        &self,
+        request: TransactionRequest,
+        block_id: Option<BlockId>,
+        opts: Option<GethDebugTracingCallOptions>,
+    ) -> RpcResult<GethTrace> {
+        DebugApiServer::debug_trace_call(self, request, block_id, opts).await
+    }
+
+    async fn raw_block(&self, block_id: BlockId) -> RpcResult<Bytes> {
+        DebugApiServer::raw_block(self, block_id).await
+    }
+
+    async fn raw_receipts(&self, block_id: BlockId) -> RpcResult<Vec<Bytes>> {
+        DebugApiServer::raw_receipts(self, block_id).await
+    }
+}
+
+/// RLP encoding of the preconfirmed block, as `debug_getRawBlock` encodes canonical blocks.
+fn raw_block(view: &PendingView) -> Bytes {
+    alloy_rlp::encode(&view.block).into()
+}
+
+/// EIP-2718 encodings of the preconfirmed receipts, as `debug_getRawReceipts` encodes canonical
+/// receipts.
+fn raw_receipts(view: &PendingView) -> Vec<Bytes> {
+    view.receipts
+        .iter()
+        .map(|receipt| {
+            ReceiptWithBloom::from(receipt.clone())
+                .encoded_2718()
+                .into()
+        })
+        .collect()
+}
+
+#[cfg_attr(not(test), rpc(server, namespace = "debug"))]
+#[cfg_attr(test, rpc(server, client, namespace = "debug"))]
+pub trait DebugOverride {
+    /// Traces a call with the requested tracer. `preconfirmed` calls run on top of the
+    /// canonical head with the flashblocks applied, like `eth_call` on the pending block.
+    #[method(name = "traceCall")]
+    async fn trace_call(
+        &self,
+        request: TransactionRequest,
+        block_id: Option<PreconfirmedOr<BlockId>>,
+        opts: Option<GethDebugTracingCallOptions>,
+    ) -> RpcResult<GethTrace>;
+
+    /// Returns the RLP encoded block. The `preconfirmed` block is encoded with its header as
+    /// complete as the flashblocks make it.
+    #[method(name = "getRawBlock")]
+    async fn raw_block(&self, block_id: PreconfirmedOr<BlockId>) -> RpcResult<Bytes>;
+
+    /// Returns the EIP-2718 encoded receipts of the block. The receipts of the `preconfirmed`
+    /// block are the ones preconfirmed so far.
+    #[method(name = "getRawReceipts")]
+    async fn raw_receipts(&self, block_id: PreconfirmedOr<BlockId>) -> RpcResult<Vec<Bytes>>;
+}
+
+pub struct DebugOverrideExt<Provider> {
+    provider: Provider,
+    pending: Arc<PendingViewStore>,
+    canonical: Arc<dyn CanonicalDebug>,
+    metrics: Metrics,
+    pending_tag_mode: PendingTagMode,
+}
+
+impl<Provider> DebugOverrideExt<Provider> {
+    pub fn new(
+        provider: Provider,
+        pending: Arc<PendingViewStore>,
+        canonical: Arc<dyn CanonicalDebug>,
+    ) -> Self {
+        Self {
+            provider,
+            pending,
+            canonical,
+            metrics: Metrics::default(),
+            pending_tag_mode: PendingTagMode::default(),
+        }
+    }
+
+    /// What the `pending` tag is served from. `preconfirmed` is always served from the
+    /// flashblocks.
+    pub fn with_pending_tag_mode(mut self, mode: PendingTagMode) -> Self {
+        self.pending_tag_mode = mode;
+        self
+    }
+}
+
+#[async_trait]
+impl<Provider> DebugOverrideServer for DebugOverrideExt<Provider>
+where
+    Provider: BlockNumReader + Send + Sync + 'static,
+{
+    async fn trace_call(
+        &self,
+        request: TransactionRequest,
+        block_id: Option<PreconfirmedOr<BlockId>>,
+        opts: Option<GethDebugTracingCallOptions>,
+    ) -> RpcResult<GethTrace> {
+        debug!("trace_call: {:?}", block_id);
+        let preconfirmed = block_id.is_some_and(|block| block.is_preconfirmed());
+        let (block, flashblocks) = block_id.unwrap_or_default().resolve(self.pending_tag_mode);
+        let block_id = Some(block);
+        if !flashblocks {
+            return self.canonical.trace_call(request, block_id, opts).await;
+        }
+
+        let head = self
+            .provider
+            .best_block_number()
+            .map_err(|e| internal_rpc_err(e.to_string()))?;
+        let blocks = self.pending.load_blocks();
+        // only a view built on top of the canonical head can be applied to its state
+        let Some(view) = blocks.for_block(head + 1) else {
+            FallbackReason::for_miss(&self.pending).record("debug_traceCall");
+            if preconfirmed {
+                return Err(preconfirmed_unavailable());
+            }
+            return self.canonical.trace_call(request, block_id, opts).await;
+        };
+        // storage writes aren't sent with the flashblocks, the node traces on its pending block
+        if blocks.writes_storage(head + 1) {
+            FallbackReason::StorageWrites.record("debug_traceCall");
+            if preconfirmed {
+                return Err(preconfirmed_unavailable());
+            }
+            return self.canonical.trace_call(request, block_id, opts).await;
+        }
+
+        self.metrics.trace_call.increment(1);
+        let mut opts = opts.unwrap_or_default();
+        opts.state_overrides = Some(blocks.merged_state_overrides(head + 1, opts.state_overrides));
+        opts.block_overrides = Some(view.block_overrides(opts.block_overrides));
+        let trace = self
+            .canonical
+            .trace_call(request, Some(BlockId::latest()), Some(opts));
+        cancellable("debug_traceCall", |_| trace).await
+    }
+
+    async fn raw_block(&self, block_id: PreconfirmedOr<BlockId>) -> RpcResult<Bytes> {
+        debug!("raw_block: {:?}", block_id);
+        let preconfirmed = block_id.is_preconfirmed();
+        let (block_id, flashblocks) = block_id.resolve(self.pending_tag_mode);
+        if flashblocks {
+            if let Some(view) = self.pending.load() {
+                self.metrics.get_raw_block.increment(1);
+                return Ok(raw_block(&view));
+            }
+            FallbackReason::for_miss(&self.pending).record("debug_getRawBlock");
+            if preconfirmed {
+                return Err(preconfirmed_unavailable());
+            }
+        }
+        self.canonical.raw_block(block_id).await
+    }
+
+    async fn raw_receipts(&self, block_id: PreconfirmedOr<BlockId>) -> RpcResult<Vec<Bytes>> {
+        debug!("raw_receipts: {:?}", block_id);
+        let preconfirmed = block_id.is_preconfirmed();
+        let (block_id, flashblocks) = block_id.resolve(self.pending_tag_mode);
+        if flashblocks {
+            if let Some(view) = self.pending.load() {
+                self.metrics.get_raw_receipts.increment(1);
+                return Ok(raw_receipts(&view));
+            }
+            FallbackReason::for_miss(&self.pending).record("debug_getRawReceipts");
+            if preconfirmed {
+                return Err(preconfirmed_unavailable());
+            }
+        }
+        self.canonical.raw_receipts(block_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::eip1559_tx;
+    use alloy_consensus::Receipt;
+    use alloy_primitives::{Address, BlockNumber, B256, U256};
+    use alloy_rlp::Decodable;
+    use reth::chainspec::ChainInfo;
+    use reth::providers::{BlockHashReader, ProviderResult};
+    use reth_optimism_primitives::{OpBlock, OpReceipt};
+    use std::sync::Mutex;
+
+    /// Canonical chain whose head is the given block.
+    struct Head(BlockNumber);
+
+    impl BlockHashReader for Head {
+        fn block_hash(&self, _number: BlockNumber) -> ProviderResult<Option<B256>> {
+            Ok(None)
+        }
+
+        fn canonical_hashes_range(
+            &self,
+            _start: BlockNumber,
+            _end: BlockNumber,
+        ) -> ProviderResult<Vec<B256>> {
+            Ok(Vec::new())
+        }
+    }
+
+    impl BlockNumReader for Head {
+        fn chain_info(&self) -> ProviderResult<ChainInfo> {
+            Ok(ChainInfo {
+                best_hash: B256::ZERO,
+                best_number: self.0,
+            })
+        }
+
+        fn best_block_number(&self) -> ProviderResult<BlockNumber> {
+            Ok(self.0)
+        }
+
+        fn last_block_number(&self) -> ProviderResult<BlockNumber> {
+            Ok(self.0)
+        }
+
+        fn block_number(&self, _hash: B256) -> ProviderResult<Option<BlockNumber>> {
+            Ok(None)
+        }
+    }
+
+    /// Records the calls traced on the canonical chain.
+    #[derive(Default)]
+    struct Canonical {
+        calls: Mutex<Vec<(Option<BlockId>, Option<GethDebugTracingCallOptions>)>>,
+    }
+
+    #[async_trait]
+    impl CanonicalDebug for Canonical {
+        async fn trace_call(
+            &self,
+            _request: TransactionRequest,
+            block_id: Option<BlockId>,
+            opts: Option<GethDebugTracingCallOptions>,
+        ) -> RpcResult<GethTrace> {
+            self.calls.lock().unwrap().push((block_id, opts));
+            Ok(GethTrace::JS(Default::default()))
+        }
+
+        async fn raw_block(&self, _block_id: BlockId) -> RpcResult<Bytes> {
+            Ok(Bytes::new())
+        }
+
+        async fn raw_receipts(&self, _block_id: BlockId) -> RpcResult<Vec<Bytes>> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn debug_ext(
+        head: BlockNumber,
+        view: Option<PendingView>,
+    ) -> (DebugOverrideExt<Head>, Arc<Canonical>) {
+        let pending = Arc::new(PendingViewStore::default());
+        if let Some(view) = view {
+            pending.publish(view);
+        }
+        let canonical = Arc::new(Canonical::default());
+        let ext = DebugOverrideExt::new(Head(head), pending, canonical.clone());
+        (ext, canonical)
+    }
+
+    /// The blocks the calls were traced on, and whether they were sent options.
+    fn traced(canonical: &Canonical) -> Vec<(Option<BlockId>, bool)> {
+        let calls = canonical.calls.lock().unwrap();
+        calls
+            .iter()
+            .map(|(block_id, opts)| (*block_id, opts.is_some()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_trace_call_on_pending() {
+        let sender = Address::repeat_byte(0x1);
+        let mut block = OpBlock::default();
+        block.header.number = 5;
+        block.header.timestamp = 1710000000;
+        let mut view = PendingView::new(block, 0, Vec::new());
+        view.balances.insert(sender, U256::from(10));
+        let (ext, canonical) = debug_ext(4, Some(view));
+
+        let pending = Some(BlockId::pending().into());
+        DebugOverrideServer::trace_call(&ext, TransactionRequest::default(), pending, None)
+            .await
+            .unwrap();
+
+        // the call runs on the canonical head with the flashblocks applied
+        let [(block_id, opts)] = std::mem::take(&mut *canonical.calls.lock().unwrap())
+            .try_into()
+            .unwrap();
+        assert_eq!(block_id, Some(BlockId::latest()));
+        let opts = opts.unwrap();
+        let state_overrides = opts.state_overrides.unwrap();
+        assert_eq!(state_overrides[&sender].balance, Some(U256::from(10)));
+        let block_overrides = opts.block_overrides.unwrap();
+        assert_eq!(block_overrides.number, Some(U256::from(5)));
+        assert_eq!(block_overrides.time, Some(1710000000));
+    }
+
+    #[tokio::test]
+    async fn test_trace_call_fallbacks() {
+        let request = TransactionRequest::default;
+        let pending = Some(BlockId::pending().into());
+
+        // no view is built on top of the canonical head
+        let (ext, canonical) = debug_ext(4, None);
+        DebugOverrideServer::trace_call(&ext, request(), pending, None)
+            .await
+            .unwrap();
+        assert_eq!(traced(&canonical), [(Some(BlockId::pending()), false)]);
+        let preconfirmed = Some(PreconfirmedOr::Preconfirmed);
+        assert!(
+            DebugOverrideServer::trace_call(&ext, request(), preconfirmed, None)
+                .await
+                .is_err()
+        );
+
+        // a transaction without a receipt may have written storage
+        let mut block = OpBlock::default();
+        block.header.number = 5;
+        block.body.transactions = vec![eip1559_tx()];
+        let view = PendingView::new(block, 0, vec![Address::ZERO]);
+        let (ext, canonical) = debug_ext(4, Some(view));
+        DebugOverrideServer::trace_call(&ext, request(), pending, None)
+            .await
+            .unwrap();
+        assert_eq!(traced(&canonical), [(Some(BlockId::pending()), false)]);
+        // the node's pending block isn't the preconfirmed one
+        assert!(
+            DebugOverrideServer::trace_call(&ext, request(), preconfirmed, None)
+                .await
+                .is_err()
+        );
+
+        // other blocks are traced by the node
+        let (ext, canonical) = debug_ext(4, None);
+        let latest = Some(BlockId::latest().into());
+        DebugOverrideServer::trace_call(&ext, request(), latest, None)
+            .await
+            .unwrap();
+        assert_eq!(traced(&canonical), [(Some(BlockId::latest()), false)]);
+    }
+
+    #[test]
+    fn test_raw_encodings() {
+        let mut block = OpBlock::default();
+        block.header.number = 3;
+        let mut view = PendingView::new(block.clone(), 0, Vec::new());
+        let receipt = OpReceipt::Eip1559(Receipt {
+            status: true.into(),
+            cumulative_gas_used: 21000,
+            logs: vec![],
+        });
+        view.receipts.push_chunk(vec![receipt]);
+
+        let raw = raw_block(&view);
+        assert_eq!(OpBlock::decode(&mut raw.as_ref()).unwrap(), block);
+
+        // typed receipts are prefixed with their type
+        let [raw] = raw_receipts(&view).try_into().unwrap();
+        assert_eq!(raw[0], 0x02);
+    }
+}
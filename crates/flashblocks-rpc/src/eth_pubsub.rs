@@ -0,0 +1,480 @@
+use std::sync::Arc;
+
+use crate::filters::{Advance, BlockFilterMode, PendingFilterKind};
+use crate::metrics::Metrics;
+use crate::pending::{PendingTransaction, PendingViewStore};
+use crate::pubsub::{forward_to_sink, SlowSubscriberPolicy, Subscribers, ViewSubscription};
+use crate::rpc::render_pending_transaction;
+use alloy_consensus::TxReceipt;
+use alloy_rpc_types::{Filter, Log};
+use alloy_rpc_types_eth::pubsub::{Params, SubscriptionKind};
+use alloy_rpc_types_eth::PendingTransactionFilterKind;
+use jsonrpsee::{
+    core::{async_trait, SubscriptionResult},
+    proc_macros::rpc,
+    types::ErrorObjectOwned,
+    PendingSubscriptionSink, SubscriptionSink,
+};
+use op_alloy_rpc_types::Transaction;
+use reth::providers::{CanonStateNotification, CanonStateNotifications, CanonStateSubscriptions};
+use reth::rpc::server_types::result::invalid_params_rpc_err;
+use reth_optimism_primitives::{OpBlock, OpPrimitives, OpReceipt};
+use reth_primitives::RecoveredBlock;
+use reth_rpc_eth_api::EthPubSubApiServer;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::debug;
+
+/// The node's own `eth_subscribe`, following the canonical chain.
+#[async_trait]
+pub trait CanonicalPubSub: Send + Sync {
+    async fn subscribe(
+        &self,
+        pending_sink: PendingSubscriptionSink,
+        kind: SubscriptionKind,
+        params: Option<Params>,
+    ) -> SubscriptionResult;
+}
+
+#[async_trait]
+impl<T> CanonicalPubSub for T
+where
+    T: EthPubSubApiServer<Transaction>,
+{
+    async fn subscribe(
+        &self,
+        pending_sink: PendingSubscriptionSink,
+        kind: SubscriptionKind,
+        params: Option<Params>,
+    ) -> SubscriptionResult {
+        EthPubSubApiServer::subscribe(self, pending_sink, kind, params).await
+    }
+}
+
+/// Parameters of a `newHeads` subscription.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+struct HeadsParams {
+    /// Which heads are pushed, the canonical ones unless asked otherwise
+    mode: BlockFilterMode,
+}
+
+/// A log pushed to the logs subscriptions following the flashblocks. Preconfirmed logs are
+/// pushed again with `pending: false` once their block is canonical.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionLog {
+    #[serde(flatten)]
+    pub log: Log,
+    /// Whether the log comes from a flashblock rather than a canonical block
+    pub pending: bool,
+}
+
+/// Parameters of a `newPendingTransactions` subscription following the flashblocks.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+struct TransactionsParams {
+    /// Whether the transactions are pushed in full rather than as hashes
+    full: bool,
+}
+
+#[cfg_attr(not(test), rpc(server, namespace = "eth"))]
+#[cfg_attr(test, rpc(server, client, namespace = "eth"))]
+pub trait EthPubSubOverride {
+    /// Subscribes to the node's notifications. `newHeads` subscriptions made with
+    /// `{"mode": "flashblock"}` receive the header of the pending block after every flashblock,
+    /// and with `{"mode": "block"}` its final header once the next block started, both with the
+    /// `flashblockIndex` they were built from. `logs` subscriptions made with
+    /// `"flashblocks": true` next to the filter receive the matching logs of every flashblock
+    /// with `pending: true`, then the logs of the canonical blocks with `pending: false`.
+    /// `newPendingTransactions` subscriptions made with `{"flashblocks": true}` receive the
+    /// transactions preconfirmed by every flashblock, in full with `"full": true`.
+    #[subscription(
+        name = "subscribe" => "subscription",
+        unsubscribe = "unsubscribe",
+        item = Value
+    )]
+    async fn subscribe(&self, kind: SubscriptionKind, params: Option<Value>) -> SubscriptionResult;
+}
+
+pub struct EthPubSubExt<Provider> {
+    provider: Provider,
+    pending: Arc<PendingViewStore>,
+    canonical: Arc<dyn CanonicalPubSub>,
+    subscribers: Arc<Subscribers>,
+    metrics: Metrics,
+}
+
+impl<Provider> EthPubSubExt<Provider> {
+    pub fn new(
+        provider: Provider,
+        pending: Arc<PendingViewStore>,
+        canonical: Arc<dyn CanonicalPubSub>,
+    ) -> Self {
+        Self {
+            provider,
+            pending,
+            canonical,
+            subscribers: Arc::new(Subscribers::default()),
+            metrics: Metrics::default(),
+        }
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Option<Value>) -> Result<Option<T>, ErrorObjectOwned> {
+    params
+        .map(serde_json::from_value)
+        .transpose()
+        .map_err(|e| invalid_params_rpc_err(e.to_string()))
+}
+
+/// Takes the `flashblocks` flag out of the params of a logs subscription, leaving the filter.
+fn take_flashblocks_flag(params: &mut Option<Value>) -> Result<bool, ErrorObjectOwned> {
+    let Some(Value::Object(fields)) = params else {
+        return Ok(false);
+    };
+    match fields.remove("flashblocks") {
+        None => Ok(false),
+        Some(Value::Bool(flashblocks)) => Ok(flashblocks),
+        Some(other) => Err(invalid_params_rpc_err(format!(
+            "invalid flashblocks flag: {other}"
+        ))),
+    }
+}
+
+/// The logs of a canonical block matching `filter`, numbered like the node's own logs.
+fn canonical_logs(
+    block: &RecoveredBlock<OpBlock>,
+    receipts: &[OpReceipt],
+    filter: &Filter,
+    removed: bool,
+) -> Vec<Log> {
+    let header = block.header();
+    let mut logs = Vec::new();
+    let mut log_index = 0;
+    let transactions = block.body().transactions.iter().zip(receipts);
+    for (index, (transaction, receipt)) in transactions.enumerate() {
+        for log in receipt.logs() {
+            if filter.matches(log) {
+                logs.push(Log {
+                    inner: log.clone(),
+                    block_hash: Some(block.hash()),
+                    block_number: Some(header.number),
+                    block_timestamp: Some(header.timestamp),
+                    transaction_hash: Some(transaction.tx_hash()),
+                    transaction_index: Some(index as u64),
+                    log_index: Some(log_index),
+                    removed,
+                });
+            }
+            log_index += 1;
+        }
+    }
+    logs
+}
+
+/// Marks `logs` as coming from flashblocks or from canonical blocks.
+fn subscription_logs(logs: impl IntoIterator<Item = Log>, pending: bool) -> Vec<SubscriptionLog> {
+    logs.into_iter()
+        .map(|log| SubscriptionLog { log, pending })
+        .collect()
+}
+
+/// Pushes the logs matching `filter` from every flashblock, then from the canonical blocks,
+/// until the subscriber goes away. The logs pushed from a block that started over are pushed
+/// again with `removed: true`. The subscription is closed when canonical notifications or
+/// flashblocks are skipped, since the subscriber can't tell which logs it missed.
+async fn forward_logs(
+    sink: SubscriptionSink,
+    filter: Filter,
+    mut subscription: ViewSubscription,
+    mut canonical: CanonStateNotifications<OpPrimitives>,
+) {
+    loop {
+        let delivered = tokio::select! {
+            _ = sink.closed() => break,
+            blocks = subscription.next() => {
+                let Some(blocks) = blocks else {
+                    break;
+                };
+                let Some(advance) = subscription.advance(&blocks) else {
+                    break;
+                };
+                let mut logs = Vec::new();
+                if let Some((view, position)) = &advance.replaced {
+                    let removed = view.logs_from(&filter, *position);
+                    logs.extend(removed.into_iter().map(|log| Log { removed: true, ..log }));
+                }
+                for (view, position) in advance.views {
+                    logs.extend(view.logs_from(&filter, position));
+                }
+                subscription.send(&sink, subscription_logs(logs, true), true).await
+            }
+            notification = canonical.recv() => match notification {
+                Ok(notification) => {
+                    let mut logs = Vec::new();
+                    if let CanonStateNotification::Reorg { old, .. } = &notification {
+                        for (block, receipts) in old.blocks_and_receipts() {
+                            logs.extend(canonical_logs(block, receipts, &filter, true));
+                        }
+                    }
+                    for (block, receipts) in notification.committed().blocks_and_receipts() {
+                        logs.extend(canonical_logs(block, receipts, &filter, false));
+                    }
+                    subscription.send(&sink, subscription_logs(logs, false), false).await
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!("Logs subscription skipped {} canonical notifications", skipped);
+                    break;
+                }
+                Err(RecvError::Closed) => break,
+            },
+        };
+        if !delivered {
+            break;
+        }
+    }
+}
+
+impl<Provider> EthPubSubExt<Provider> {
+    /// Hands the subscription to the node's own `eth_subscribe`.
+    async fn delegate(
+        &self,
+        pending_sink: PendingSubscriptionSink,
+        kind: SubscriptionKind,
+        params: Option<Value>,
+    ) -> SubscriptionResult {
+        match parse_params::<Params>(params) {
+            Ok(params) => self.canonical.subscribe(pending_sink, kind, params).await,
+            Err(e) => {
+                pending_sink.reject(e).await;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Pushes the transactions of every flashblock, as hashes or in full, until the subscriber
+/// goes away.
+async fn forward_transactions(
+    sink: SubscriptionSink,
+    kind: PendingTransactionFilterKind,
+    subscription: ViewSubscription,
+) {
+    match kind {
+        PendingTransactionFilterKind::Hashes => {
+            let hashes = |advance: Advance<'_>| {
+                transactions(advance)
+                    .map(|tx| tx.transaction().tx_hash())
+                    .collect::<Vec<_>>()
+            };
+            subscription.forward(sink, hashes).await
+        }
+        PendingTransactionFilterKind::Full => {
+            let full = |advance: Advance<'_>| {
+                transactions(advance)
+                    .map(render_pending_transaction)
+                    .collect::<Vec<_>>()
+            };
+            subscription.forward(sink, full).await
+        }
+    }
+}
+
+/// The transactions the views of `advance` added.
+fn transactions(advance: Advance<'_>) -> impl Iterator<Item = PendingTransaction<'_>> {
+    advance.views.into_iter().flat_map(|(view, position)| {
+        (position..view.block.body.transactions.len())
+            .filter_map(|index| view.transaction_at(index))
+    })
+}
+
+#[async_trait]
+impl<Provider> EthPubSubOverrideServer for EthPubSubExt<Provider>
+where
+    Provider: CanonStateSubscriptions<Primitives = OpPrimitives> + 'static,
+{
+    async fn subscribe(
+        &self,
+        pending_sink: PendingSubscriptionSink,
+        kind: SubscriptionKind,
+        params: Option<Value>,
+    ) -> SubscriptionResult {
+        debug!("subscribe: {:?} {:?}", kind, params);
+        match kind {
+            SubscriptionKind::NewHeads => {
+                let heads = match parse_params::<HeadsParams>(params) {
+                    Ok(heads) => heads.unwrap_or_default(),
+                    Err(e) => {
+                        pending_sink.reject(e).await;
+                        return Ok(());
+                    }
+                };
+                let fan_out = match heads.mode {
+                    BlockFilterMode::Canonical => {
+                        return self.delegate(pending_sink, kind, None).await
+                    }
+                    BlockFilterMode::Flashblock => self.pending.flashblock_heads(),
+                    BlockFilterMode::Block => self.pending.block_heads(),
+                };
+
+                self.metrics.subscribe.increment(1);
+                let sink = pending_sink.accept().await?;
+                let (receiver, subscriber) =
+                    self.subscribers
+                        .subscribe(fan_out, sink.connection_id().0 as u64, "newHeads");
+                let policy = SlowSubscriberPolicy::DropOldest;
+                tokio::spawn(forward_to_sink(sink, receiver, policy, subscriber));
+                Ok(())
+            }
+            SubscriptionKind::Logs => {
+                let mut params = params;
+                let filter = match take_flashblocks_flag(&mut params) {
+                    Ok(true) => parse_params::<Filter>(params),
+                    Ok(false) => return self.delegate(pending_sink, kind, params).await,
+                    Err(e) => Err(e),
+                };
+                let filter = match filter {
+                    Ok(filter) => filter.unwrap_or_default(),
+                    Err(e) => {
+                        pending_sink.reject(e).await;
+                        return Ok(());
+                    }
+                };
+
+                self.metrics.subscribe.increment(1);
+                // subscribed before accepting, so no canonical block is missed
+                let canonical = self.provider.subscribe_to_canonical_state();
+                let sink = pending_sink.accept().await?;
+                let subscription = ViewSubscription::new(
+                    Arc::clone(&self.pending),
+                    PendingFilterKind::Logs(filter.clone()),
+                    SlowSubscriberPolicy::DropOldest,
+                    &self.subscribers,
+                    sink.connection_id().0 as u64,
+                    "logs",
+                );
+                tokio::spawn(forward_logs(sink, filter, subscription, canonical));
+                Ok(())
+            }
+            SubscriptionKind::NewPendingTransactions => {
+                let mut params = params;
+                let transactions = match take_flashblocks_flag(&mut params) {
+                    Ok(true) => parse_params::<TransactionsParams>(params),
+                    Ok(false) => return self.delegate(pending_sink, kind, params).await,
+                    Err(e) => Err(e),
+                };
+                let transactions = match transactions {
+                    Ok(transactions) => transactions.unwrap_or_default(),
+                    Err(e) => {
+                        pending_sink.reject(e).await;
+                        return Ok(());
+                    }
+                };
+                let kind = if transactions.full {
+                    PendingTransactionFilterKind::Full
+                } else {
+                    PendingTransactionFilterKind::Hashes
+                };
+
+                self.metrics.subscribe.increment(1);
+                let sink = pending_sink.accept().await?;
+                let subscription = ViewSubscription::new(
+                    Arc::clone(&self.pending),
+                    PendingFilterKind::Transactions(kind),
+                    SlowSubscriberPolicy::DropOldest,
+                    &self.subscribers,
+                    sink.connection_id().0 as u64,
+                    "newPendingTransactions",
+                );
+                tokio::spawn(forward_transactions(sink, kind, subscription));
+                Ok(())
+            }
+            _ => self.delegate(pending_sink, kind, params).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::signed_tx;
+    use alloy_consensus::Receipt;
+    use alloy_primitives::{Address, Bytes, LogData, B256};
+
+    fn receipt(addresses: &[u8]) -> OpReceipt {
+        let logs = addresses
+            .iter()
+            .map(|&address| alloy_primitives::Log {
+                address: Address::repeat_byte(address),
+                data: LogData::new_unchecked(vec![B256::ZERO], Bytes::new()),
+            })
+            .collect();
+        OpReceipt::Eip1559(Receipt {
+            status: true.into(),
+            cumulative_gas_used: 21000,
+            logs,
+        })
+    }
+
+    #[test]
+    fn test_flashblocks_flag() {
+        let mut params = Some(serde_json::json!({"address": "0x01", "flashblocks": true}));
+        assert!(matches!(take_flashblocks_flag(&mut params), Ok(true)));
+        assert_eq!(params, Some(serde_json::json!({"address": "0x01"})));
+
+        assert!(matches!(take_flashblocks_flag(&mut None), Ok(false)));
+        let mut params = Some(serde_json::json!({"flashblocks": "yes"}));
+        assert!(take_flashblocks_flag(&mut params).is_err());
+    }
+
+    #[test]
+    fn test_transactions_params() {
+        let mut params = Some(serde_json::json!({"flashblocks": true, "full": true}));
+        assert!(matches!(take_flashblocks_flag(&mut params), Ok(true)));
+        let transactions = parse_params::<TransactionsParams>(params).unwrap().unwrap();
+        assert!(transactions.full);
+        assert!(!TransactionsParams::default().full);
+    }
+
+    #[test]
+    fn test_canonical_logs() {
+        let mut block = OpBlock::default();
+        block.header.number = 7;
+        block.body.transactions = vec![signed_tx(0), signed_tx(1)];
+        let block = RecoveredBlock::new_unhashed(block, vec![Address::ZERO; 2]);
+        let receipts = vec![receipt(&[0x1, 0x2]), receipt(&[0x2])];
+
+        let filter = Filter::new().address(Address::repeat_byte(0x2));
+        let logs = canonical_logs(&block, &receipts, &filter, true);
+        let positions: Vec<_> = logs
+            .iter()
+            .map(|log| (log.transaction_index.unwrap(), log.log_index.unwrap()))
+            .collect();
+        // log indexes count the logs of the whole block, matching or not
+        assert_eq!(positions, vec![(0, 1), (1, 2)]);
+        assert_eq!(logs[1].transaction_hash, Some(signed_tx(1).tx_hash()));
+        assert!(logs
+            .iter()
+            .all(|log| log.removed && log.block_number == Some(7)));
+    }
+
+    #[test]
+    fn test_heads_params() {
+        let parse = |params| {
+            parse_params::<HeadsParams>(params)
+                .unwrap()
+                .unwrap_or_default()
+        };
+        assert_eq!(parse(None).mode, BlockFilterMode::Canonical);
+        assert_eq!(
+            parse(Some(serde_json::json!({}))).mode,
+            BlockFilterMode::Canonical
+        );
+        let flashblock = serde_json::json!({"mode": "flashblock"});
+        assert_eq!(parse(Some(flashblock)).mode, BlockFilterMode::Flashblock);
+        assert!(parse_params::<HeadsParams>(Some(serde_json::json!({"mode": "head"}))).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::flashblocks::Metadata;
+
+/// Faults injected into the flashblocks client and cache, to check that the pending state
+/// degrades gracefully when the upstream or the node misbehave. Only built for tests and with
+/// the `fault-injection` feature.
+#[derive(Debug, Default)]
+pub struct FaultInjector {
+    drop_every: Option<u64>,
+    corrupt_every: Option<u64>,
+    write_delay: Option<Duration>,
+    frames: AtomicU64,
+    decoded: AtomicU64,
+}
+
+impl FaultInjector {
+    /// Drops every `n`th websocket frame before it is parsed.
+    pub fn with_drop_every(mut self, n: u64) -> Self {
+        self.drop_every = Some(n.max(1));
+        self
+    }
+
+    /// Corrupts the block number of every `n`th decoded flashblock.
+    pub fn with_corrupt_every(mut self, n: u64) -> Self {
+        self.corrupt_every = Some(n.max(1));
+        self
+    }
+
+    /// Delays the cache writes of every flashblock by `delay`, like a node starved of cpu.
+    pub fn with_write_delay(mut self, delay: Duration) -> Self {
+        self.write_delay = Some(delay);
+        self
+    }
+
+    /// Counts a websocket frame, returning whether it must be dropped.
+    pub fn drop_frame(&self) -> bool {
+        let frame = self.frames.fetch_add(1, Ordering::Relaxed) + 1;
+        self.drop_every.is_some_and(|n| frame % n == 0)
+    }
+
+    /// Counts a decoded flashblock, moving it to the next block when it must be corrupted.
+    pub fn corrupt(&self, mut metadata: Metadata) -> Metadata {
+        let decoded = self.decoded.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.corrupt_every.is_some_and(|n| decoded % n == 0) {
+            metadata.block_number = metadata.block_number.wrapping_add(1);
+        }
+        metadata
+    }
+
+    /// Called before the cache writes of every flashblock.
+    pub async fn before_writes(&self) {
+        if let Some(delay) = self.write_delay {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(block_number: u64) -> Metadata {
+        Metadata {
+            block_number,
+            receipts: Default::default(),
+            new_account_balances: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_drop_every() {
+        let faults = FaultInjector::default().with_drop_every(3);
+        let dropped: Vec<_> = (0..6).map(|_| faults.drop_frame()).collect();
+        assert_eq!(dropped, vec![false, false, true, false, false, true]);
+
+        // nothing is dropped unless asked to
+        assert!(!FaultInjector::default().drop_frame());
+    }
+
+    #[test]
+    fn test_corrupt_every() {
+        let faults = FaultInjector::default().with_corrupt_every(2);
+        assert_eq!(faults.corrupt(metadata(10)).block_number, 10);
+        assert_eq!(faults.corrupt(metadata(10)).block_number, 11);
+        assert_eq!(faults.corrupt(metadata(10)).block_number, 10);
+    }
+}
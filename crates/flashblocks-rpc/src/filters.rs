@@ -0,0 +1,635 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::clock::SharedClock;
+use crate::pending::{PendingBlocks, PendingTransaction, PendingView};
+use alloy_rpc_types::{Filter, FilterChanges, FilterId};
+use alloy_rpc_types_eth::PendingTransactionFilterKind;
+use jsonrpsee::types::ErrorObject;
+use op_alloy_rpc_types::Transaction;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Filters that aren't polled for this long are dropped, like the node's own filters.
+pub const FILTER_TTL: Duration = Duration::from_secs(300);
+
+/// Filters installed at once, beyond which installing another one fails until some expire or
+/// are uninstalled.
+pub const MAX_FILTERS: usize = 10_000;
+
+/// Error code of filter polls that missed flashblocks, the EIP-1474 "resource unavailable".
+pub const FLASHBLOCKS_MISSED_CODE: i32 = -32002;
+
+/// Error of filter polls that missed flashblocks dropped or expired before the filter was
+/// polled. The filter goes on from the latest flashblock, so the next poll succeeds.
+pub fn flashblocks_missed() -> ErrorObject<'static> {
+    ErrorObject::owned(
+        FLASHBLOCKS_MISSED_CODE,
+        "flashblocks were dropped before the filter was polled",
+        None::<()>,
+    )
+}
+
+/// Error code of filters installed while [`MAX_FILTERS`] are, the EIP-1474 "limit exceeded".
+pub const FILTER_LIMIT_CODE: i32 = -32005;
+
+/// Error of filters installed while the maximum number of filters is.
+pub fn filter_limit_reached() -> ErrorObject<'static> {
+    ErrorObject::owned(
+        FILTER_LIMIT_CODE,
+        "too many filters installed, uninstall some first",
+        None::<()>,
+    )
+}
+
+/// What `eth_newBlockFilter` and `newHeads` subscriptions report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockFilterMode {
+    /// The hashes of canonical blocks, as served by the node's own filters
+    #[default]
+    Canonical,
+    /// The block hash sent with every flashblock, so polling clients can react to each of them
+    Flashblock,
+    /// The final hash of every preconfirmed block, once the next block has started
+    Block,
+}
+
+impl FromStr for BlockFilterMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "canonical" => Ok(Self::Canonical),
+            "flashblock" => Ok(Self::Flashblock),
+            "block" => Ok(Self::Block),
+            _ => Err(format!("invalid block filter mode: {s}")),
+        }
+    }
+}
+
+impl Display for BlockFilterMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Canonical => write!(f, "canonical"),
+            Self::Flashblock => write!(f, "flashblock"),
+            Self::Block => write!(f, "block"),
+        }
+    }
+}
+
+/// What an installed filter reports.
+#[derive(Debug, Clone)]
+pub enum PendingFilterKind {
+    /// Logs matching the filter, from every flashblock as it arrives
+    Logs(Filter),
+    /// Transactions preconfirmed by the flashblocks, as hashes or in full
+    Transactions(PendingTransactionFilterKind),
+    /// Pseudo-heads of the preconfirmed blocks, never [`BlockFilterMode::Canonical`]
+    Blocks(BlockFilterMode),
+}
+
+impl PendingFilterKind {
+    /// Number of entries of `view` the filter reads, the next flashblock starts after them.
+    fn position(&self, view: &PendingView) -> usize {
+        match self {
+            Self::Logs(_) => view.receipts.len(),
+            Self::Transactions(_) => view.block.body.transactions.len(),
+            Self::Blocks(_) => view.audit.len(),
+        }
+    }
+}
+
+/// Position in the flashblocks a filter has reported up to.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Cursor {
+    /// Last view read, all of its entries were reported
+    view: Option<Arc<PendingView>>,
+    /// Position the entries of the block of the last view were reported from, `None` for the
+    /// view the cursor started at, whose entries were there before
+    reported_from: Option<usize>,
+    /// Generation of the blocks read up to
+    generation: u64,
+}
+
+/// What a [`Cursor`] moved past.
+#[derive(Debug, Default)]
+pub(crate) struct Advance<'a> {
+    /// Views published since the last read, each with the position to report it from
+    pub(crate) views: Vec<(&'a PendingView, usize)>,
+    /// View read last, if its block started over from a new first flashblock since, with the
+    /// position its entries were reported from. What was reported from it no longer holds.
+    pub(crate) replaced: Option<(Arc<PendingView>, usize)>,
+    /// Whether flashblocks published since the last read were dropped or expired before they
+    /// could be read
+    pub(crate) missed: bool,
+}
+
+impl Cursor {
+    pub(crate) fn latest(blocks: &PendingBlocks) -> Self {
+        Self {
+            view: blocks.latest().cloned(),
+            reported_from: None,
+            generation: blocks.generation(),
+        }
+    }
+
+    /// Moves past the views published since the last read.
+    pub(crate) fn advance<'a>(
+        &mut self,
+        blocks: &'a PendingBlocks,
+        kind: &PendingFilterKind,
+    ) -> Advance<'a> {
+        let mut advance = Advance {
+            missed: blocks.missed_since(self.generation),
+            ..Default::default()
+        };
+        let from = self.view.as_ref().map_or(0, |view| view.block_number());
+        for view in blocks.range(from..=u64::MAX) {
+            let position = match &self.view {
+                Some(last) if view.extends(last) => kind.position(last),
+                Some(last) if last.block_number() == view.block_number() => {
+                    let reported_from = self.reported_from.unwrap_or_else(|| kind.position(last));
+                    advance.replaced = Some((last.clone(), reported_from));
+                    self.reported_from = Some(0);
+                    0
+                }
+                // a new block starts from its first entry
+                _ => {
+                    self.reported_from = Some(0);
+                    0
+                }
+            };
+            advance.views.push((view.as_ref(), position));
+            self.view = Some(view.clone());
+        }
+        self.generation = blocks.generation();
+        advance
+    }
+}
+
+#[derive(Debug)]
+struct PendingFilter {
+    kind: PendingFilterKind,
+    cursor: Cursor,
+    polled_at: Instant,
+}
+
+/// Filters installed on the flashblocks state. Each one reports what the flashblocks added
+/// since it was last polled, starting from the flashblocks received when it was installed.
+#[derive(Debug)]
+pub struct PendingFilters {
+    filters: Mutex<HashMap<FilterId, PendingFilter>>,
+    max_filters: usize,
+    clock: SharedClock,
+}
+
+impl Default for PendingFilters {
+    fn default() -> Self {
+        Self {
+            filters: Mutex::new(HashMap::new()),
+            max_filters: MAX_FILTERS,
+            clock: SharedClock::default(),
+        }
+    }
+}
+
+impl PendingFilters {
+    /// Clock the filters expire with, the one of the pending views they read.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Number of filters installed at once, [`MAX_FILTERS`] by default.
+    pub fn with_max_filters(mut self, max_filters: usize) -> Self {
+        self.max_filters = max_filters;
+        self
+    }
+
+    /// Installs a filter reporting the logs matching `filter` from now on.
+    pub fn install_logs(
+        &self,
+        filter: Filter,
+        blocks: &PendingBlocks,
+    ) -> Result<FilterId, ErrorObject<'static>> {
+        self.install(PendingFilterKind::Logs(filter), blocks)
+    }
+
+    /// Installs a filter reporting the transactions preconfirmed from now on.
+    pub fn install_transactions(
+        &self,
+        kind: PendingTransactionFilterKind,
+        blocks: &PendingBlocks,
+    ) -> Result<FilterId, ErrorObject<'static>> {
+        self.install(PendingFilterKind::Transactions(kind), blocks)
+    }
+
+    /// Installs a filter reporting the pseudo-heads of `mode` from now on.
+    pub fn install_blocks(
+        &self,
+        mode: BlockFilterMode,
+        blocks: &PendingBlocks,
+    ) -> Result<FilterId, ErrorObject<'static>> {
+        self.install(PendingFilterKind::Blocks(mode), blocks)
+    }
+
+    /// Installs a filter, failing while [`Self::with_max_filters`] others are.
+    fn install(
+        &self,
+        kind: PendingFilterKind,
+        blocks: &PendingBlocks,
+    ) -> Result<FilterId, ErrorObject<'static>> {
+        let id = FilterId::Str(format!("0x{}", Uuid::new_v4().simple()));
+        let now = self.clock.now();
+        let mut filters = self.filters.lock().unwrap();
+        sweep(&mut filters, now);
+        if filters.len() >= self.max_filters {
+            return Err(filter_limit_reached());
+        }
+        filters.insert(
+            id.clone(),
+            PendingFilter {
+                cursor: Cursor::latest(blocks),
+                kind,
+                polled_at: now,
+            },
+        );
+        Ok(id)
+    }
+
+    /// What the flashblocks added since the filter was last polled, or `None` if the filter
+    /// wasn't installed here. Full transactions are rendered with `render`. Fails when some of
+    /// the flashblocks were dropped before they could be read.
+    pub fn changes(
+        &self,
+        id: &FilterId,
+        blocks: &PendingBlocks,
+        render: impl Fn(PendingTransaction<'_>) -> Transaction,
+    ) -> Option<Result<FilterChanges<Transaction>, ErrorObject<'static>>> {
+        let now = self.clock.now();
+        let mut filters = self.filters.lock().unwrap();
+        sweep(&mut filters, now);
+        let filter = filters.get_mut(id)?;
+        filter.polled_at = now;
+
+        let advance = filter.cursor.advance(blocks, &filter.kind);
+        if advance.missed {
+            return Some(Err(flashblocks_missed()));
+        }
+        // a block that started over is reported again from its first entry
+        let views = advance.views;
+        let transactions = || {
+            views.iter().flat_map(|(view, position)| {
+                (*position..view.block.body.transactions.len())
+                    .filter_map(|index| view.transaction_at(index))
+            })
+        };
+        let changes = match &filter.kind {
+            PendingFilterKind::Logs(log_filter) => FilterChanges::Logs(
+                views
+                    .iter()
+                    .flat_map(|(view, position)| view.logs_from(log_filter, *position))
+                    .collect(),
+            ),
+            PendingFilterKind::Transactions(PendingTransactionFilterKind::Hashes) => {
+                FilterChanges::Hashes(
+                    transactions()
+                        .map(|tx| tx.transaction().tx_hash())
+                        .collect(),
+                )
+            }
+            PendingFilterKind::Transactions(PendingTransactionFilterKind::Full) => {
+                FilterChanges::Transactions(transactions().map(&render).collect())
+            }
+            PendingFilterKind::Blocks(BlockFilterMode::Flashblock) => FilterChanges::Hashes(
+                views
+                    .iter()
+                    .flat_map(|(view, position)| view.audit.iter().skip(*position))
+                    .map(|flashblock| flashblock.block_hash)
+                    .collect(),
+            ),
+            // the last view is the block still being built
+            PendingFilterKind::Blocks(_) => FilterChanges::Hashes(
+                views[..views.len().saturating_sub(1)]
+                    .iter()
+                    .map(|(view, _)| view.block_hash)
+                    .collect(),
+            ),
+        };
+        Some(Ok(changes))
+    }
+
+    /// The filter of an installed log filter.
+    pub fn log_filter(&self, id: &FilterId) -> Option<Filter> {
+        match &self.filters.lock().unwrap().get(id)?.kind {
+            PendingFilterKind::Logs(filter) => Some(filter.clone()),
+            PendingFilterKind::Transactions(_) | PendingFilterKind::Blocks(_) => None,
+        }
+    }
+
+    /// Removes a filter, returning whether it was installed here.
+    pub fn uninstall(&self, id: &FilterId) -> bool {
+        self.filters.lock().unwrap().remove(id).is_some()
+    }
+}
+
+/// Drops the filters that weren't polled for [`FILTER_TTL`].
+fn sweep(filters: &mut HashMap<FilterId, PendingFilter>, now: Instant) {
+    filters.retain(|_, filter| now.duration_since(filter.polled_at) < FILTER_TTL);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::pending::{FlashblockAudit, PendingView, PendingViewStore, RETAINED_BLOCKS};
+    use crate::test_utils::flashblock_audit;
+    use alloy_consensus::{SignableTransaction, TxEip1559};
+    use alloy_eips::eip2718::{Decodable2718, Encodable2718};
+    use alloy_primitives::{Address, Bytes, Signature, TxHash, B256};
+    use alloy_rpc_types_engine::PayloadId;
+    use op_alloy_consensus::OpTxEnvelope;
+    use reth_optimism_primitives::{OpBlock, OpReceipt, OpTransactionSigned};
+
+    fn view(block_number: u64, logs_per_receipt: &[usize]) -> PendingView {
+        let mut block = OpBlock::default();
+        block.header.number = block_number;
+        let mut view = PendingView::new(block, 0, Vec::new());
+        let log = alloy_primitives::Log {
+            address: Address::repeat_byte(0x1),
+            data: alloy_primitives::LogData::new_unchecked(vec![B256::ZERO], Bytes::new()),
+        };
+        view.receipts.push_chunk(
+            logs_per_receipt
+                .iter()
+                .map(|&logs| {
+                    OpReceipt::Eip1559(alloy_consensus::Receipt {
+                        status: true.into(),
+                        cumulative_gas_used: 21000,
+                        logs: vec![log.clone(); logs],
+                    })
+                })
+                .collect(),
+        );
+        view
+    }
+
+    /// A view of `block_number` with `count` transactions, the nth one with nonce n.
+    fn transactions_view(block_number: u64, count: u64) -> PendingView {
+        let mut block = OpBlock::default();
+        block.header.number = block_number;
+        block.body.transactions = (0..count)
+            .map(|nonce| {
+                let tx = TxEip1559 {
+                    chain_id: 8453,
+                    nonce,
+                    gas_limit: 21000,
+                    to: Address::repeat_byte(block_number as u8).into(),
+                    ..Default::default()
+                };
+                let envelope = OpTxEnvelope::Eip1559(tx.into_signed(Signature::test_signature()));
+                OpTransactionSigned::decode_2718(&mut envelope.encoded_2718().as_slice()).unwrap()
+            })
+            .collect();
+        let senders = vec![Address::ZERO; count as usize];
+        PendingView::new(block, 0, senders)
+    }
+
+    /// A view of `block_number` after `flashblocks` flashblocks, each sent with its own hash.
+    fn flashblocks_view(block_number: u64, flashblocks: u64) -> PendingView {
+        let mut block = OpBlock::default();
+        block.header.number = block_number;
+        let mut view = PendingView::new(block, flashblocks - 1, Vec::new());
+        for index in 0..flashblocks {
+            view.block_hash = B256::with_last_byte((block_number * 16 + index) as u8);
+            view.audit.push(FlashblockAudit {
+                block_hash: view.block_hash,
+                ..flashblock_audit(index, 0)
+            });
+        }
+        view
+    }
+
+    fn no_render(_: PendingTransaction<'_>) -> Transaction {
+        unreachable!("log and hash filters don't render transactions")
+    }
+
+    type Changes = Option<Result<FilterChanges<Transaction>, ErrorObject<'static>>>;
+
+    fn logs(changes: Changes) -> Vec<(u64, u64)> {
+        match changes {
+            Some(Ok(FilterChanges::Logs(logs))) => logs
+                .iter()
+                .map(|log| (log.block_number.unwrap(), log.log_index.unwrap()))
+                .collect(),
+            other => panic!("unexpected changes: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_log_filter_follows_flashblocks() {
+        let store = PendingViewStore::default();
+        let filters = PendingFilters::default();
+        store.publish(view(1, &[1]));
+        let id = filters
+            .install_logs(Filter::new(), &store.load_blocks())
+            .unwrap();
+
+        // logs received before the filter was installed aren't reported
+        assert!(logs(filters.changes(&id, &store.load_blocks(), no_render)).is_empty());
+
+        store.publish(view(1, &[1, 2]));
+        assert_eq!(
+            logs(filters.changes(&id, &store.load_blocks(), no_render)),
+            vec![(1, 1), (1, 2)]
+        );
+        assert!(logs(filters.changes(&id, &store.load_blocks(), no_render)).is_empty());
+
+        // the rest of a block and the start of the next one are reported together
+        store.publish(view(1, &[1, 2, 1]));
+        store.publish(view(2, &[1]));
+        assert_eq!(
+            logs(filters.changes(&id, &store.load_blocks(), no_render)),
+            vec![(1, 3), (2, 0)]
+        );
+
+        assert!(filters.log_filter(&id).is_some());
+        assert!(filters.uninstall(&id));
+        assert!(filters
+            .changes(&id, &store.load_blocks(), no_render)
+            .is_none());
+        assert!(!filters.uninstall(&id));
+    }
+
+    #[test]
+    fn test_log_filter_restarts_with_the_block() {
+        let store = PendingViewStore::default();
+        let filters = PendingFilters::default();
+        let mut first = view(1, &[1, 1]);
+        first.payload_id = Some(PayloadId::new([1; 8]));
+        store.publish(first);
+        let id = filters
+            .install_logs(Filter::new(), &store.load_blocks())
+            .unwrap();
+
+        // the sequencer restarted the block with another payload, with fewer receipts so far
+        let mut restarted = view(1, &[1]);
+        restarted.payload_id = Some(PayloadId::new([2; 8]));
+        store.publish(restarted);
+        assert_eq!(
+            logs(filters.changes(&id, &store.load_blocks(), no_render)),
+            vec![(1, 0)]
+        );
+    }
+
+    #[test]
+    fn test_cursor_reports_replaced_view() {
+        let kind = PendingFilterKind::Logs(Filter::new());
+        let store = PendingViewStore::default();
+        let publish = |logs_per_receipt: &[usize], payload_id| {
+            let mut view = view(1, logs_per_receipt);
+            view.payload_id = Some(PayloadId::new([payload_id; 8]));
+            store.publish(view);
+        };
+        publish(&[1, 1], 1);
+        let mut cursor = Cursor::latest(&store.load_blocks());
+
+        // the receipts there before the cursor started weren't reported, so nothing is retracted
+        publish(&[1], 2);
+        let blocks = store.load_blocks();
+        let advance = cursor.advance(&blocks, &kind);
+        let (replaced, position) = advance.replaced.unwrap();
+        assert_eq!((replaced.receipts.len(), position), (2, 2));
+        assert_eq!(advance.views.len(), 1);
+        assert_eq!(advance.views[0].1, 0);
+
+        // the restarted block was reported from its first receipt
+        publish(&[1, 1], 3);
+        let blocks = store.load_blocks();
+        let (replaced, position) = cursor.advance(&blocks, &kind).replaced.unwrap();
+        assert_eq!((replaced.receipts.len(), position), (1, 0));
+    }
+
+    #[test]
+    fn test_filter_reports_dropped_flashblocks() {
+        let store = PendingViewStore::default();
+        let filters = PendingFilters::default();
+        store.publish(view(1, &[1]));
+        let id = filters
+            .install_logs(Filter::new(), &store.load_blocks())
+            .unwrap();
+
+        // block 2 falls out of the retained range before the filter is polled
+        store.publish(view(2, &[1]));
+        store.publish(view(2 + RETAINED_BLOCKS, &[1]));
+        match filters.changes(&id, &store.load_blocks(), no_render) {
+            Some(Err(error)) => assert_eq!(error.code(), FLASHBLOCKS_MISSED_CODE),
+            other => panic!("unexpected changes: {other:?}"),
+        }
+
+        store.publish(view(2 + RETAINED_BLOCKS, &[1, 1]));
+        assert_eq!(
+            logs(filters.changes(&id, &store.load_blocks(), no_render)),
+            vec![(2 + RETAINED_BLOCKS, 1)]
+        );
+    }
+
+    #[test]
+    fn test_transaction_filter_reports_new_hashes() {
+        let store = PendingViewStore::default();
+        let filters = PendingFilters::default();
+        store.publish(transactions_view(1, 1));
+        let kind = PendingTransactionFilterKind::Hashes;
+        let id = filters
+            .install_transactions(kind, &store.load_blocks())
+            .unwrap();
+
+        let first = transactions_view(1, 3);
+        let second = transactions_view(2, 1);
+        let expected: Vec<TxHash> = first.block.body.transactions[1..]
+            .iter()
+            .chain(&second.block.body.transactions)
+            .map(|tx| tx.tx_hash())
+            .collect();
+        store.publish(first);
+        store.publish(second);
+        match filters.changes(&id, &store.load_blocks(), no_render) {
+            Some(Ok(FilterChanges::Hashes(hashes))) => assert_eq!(hashes, expected),
+            other => panic!("unexpected changes: {other:?}"),
+        }
+        assert!(filters.log_filter(&id).is_none());
+    }
+
+    #[test]
+    fn test_block_filter_modes() {
+        let store = PendingViewStore::default();
+        let filters = PendingFilters::default();
+        store.publish(flashblocks_view(1, 1));
+        let blocks = store.load_blocks();
+        let per_flashblock = filters
+            .install_blocks(BlockFilterMode::Flashblock, &blocks)
+            .unwrap();
+        let per_block = filters
+            .install_blocks(BlockFilterMode::Block, &blocks)
+            .unwrap();
+
+        store.publish(flashblocks_view(1, 3));
+        store.publish(flashblocks_view(2, 1));
+        let hashes = |id| match filters.changes(id, &store.load_blocks(), no_render) {
+            Some(Ok(FilterChanges::Hashes(hashes))) => hashes,
+            other => panic!("unexpected changes: {other:?}"),
+        };
+        assert_eq!(
+            hashes(&per_flashblock),
+            vec![
+                B256::with_last_byte(17),
+                B256::with_last_byte(18),
+                B256::with_last_byte(32)
+            ]
+        );
+        // block 1 is complete once block 2 started, block 2 is still being built
+        assert_eq!(hashes(&per_block), vec![B256::with_last_byte(18)]);
+        assert!(hashes(&per_block).is_empty());
+    }
+
+    #[test]
+    fn test_filters_expire_and_are_capped() {
+        let clock = Arc::new(ManualClock::default());
+        let store = PendingViewStore::default();
+        let filters = PendingFilters::default()
+            .with_clock(SharedClock::new(clock.clone()))
+            .with_max_filters(2);
+        store.publish(view(1, &[1]));
+        let blocks = store.load_blocks();
+        let polled = filters.install_logs(Filter::new(), &blocks).unwrap();
+        let idle = filters.install_logs(Filter::new(), &blocks).unwrap();
+        match filters.install_logs(Filter::new(), &blocks) {
+            Err(error) => assert_eq!(error.code(), FILTER_LIMIT_CODE),
+            other => panic!("unexpected install: {other:?}"),
+        }
+
+        // polling one filter drops the other once it expired, which frees its slot
+        clock.advance(FILTER_TTL / 2);
+        assert!(filters.changes(&polled, &blocks, no_render).is_some());
+        clock.advance(FILTER_TTL / 2);
+        assert!(filters.changes(&polled, &blocks, no_render).is_some());
+        assert!(filters.changes(&idle, &blocks, no_render).is_none());
+        assert!(filters.install_logs(Filter::new(), &blocks).is_ok());
+    }
+
+    #[test]
+    fn test_block_filter_mode() {
+        for mode in [
+            BlockFilterMode::Canonical,
+            BlockFilterMode::Flashblock,
+            BlockFilterMode::Block,
+        ] {
+            assert_eq!(mode.to_string().parse::<BlockFilterMode>(), Ok(mode));
+        }
+        assert!("head".parse::<BlockFilterMode>().is_err());
+    }
+}
@@ -1,18 +1,48 @@
 use crate::cache::{Cache, CacheKey};
-use alloy_primitives::{map::foldhash::HashMap, Address, Bytes, U256};
-use alloy_rpc_types_engine::{ExecutionPayloadV1, ExecutionPayloadV2, ExecutionPayloadV3};
-use futures_util::StreamExt;
+#[cfg(any(test, feature = "fault-injection"))]
+use crate::faults::FaultInjector;
+use alloy_eips::eip7685::EMPTY_REQUESTS_HASH;
+use alloy_primitives::{map::foldhash::HashMap, Address, Bytes, TxHash, B256, U256};
+use alloy_rpc_types_engine::{
+    ExecutionPayloadV1, ExecutionPayloadV2, ExecutionPayloadV3, PayloadError, PayloadId,
+};
+use futures_util::{SinkExt, StreamExt};
+use reth_optimism_chainspec::{OpChainSpec, BASE_MAINNET};
+use reth_optimism_forks::OpHardforks;
 use reth_optimism_primitives::{OpBlock, OpReceipt, OpTransactionSigned};
-use rollup_boost::primitives::{ExecutionPayloadBaseV1, FlashblocksPayloadV1};
+use rollup_boost::primitives::{
+    ExecutionPayloadBaseV1, ExecutionPayloadFlashblockDeltaV1, FlashblocksPayloadV1,
+};
 use serde::{Deserialize, Serialize};
-use std::{io::Read, str::FromStr, sync::Arc};
+use serde_json::value::RawValue;
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashSet, VecDeque},
+    io::Read,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 use tokio::sync::mpsc;
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
-use tracing::error;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tracing::{error, info, warn};
 use url::Url;
 
-use crate::metrics::Metrics;
-use alloy_consensus::transaction::SignerRecoverable;
+use crate::metrics::{BuilderMetrics, Metrics, UpstreamInfoMetrics, ValidatorMetrics};
+use crate::pending::{
+    AuditCheck, FlashblockAudit, PendingView, PendingViewStore, PreconfirmationInfo,
+    RETAINED_BLOCKS,
+};
+use crate::startup::{StartupReport, CHAIN_MATCHES, FIRST_PAYLOAD_PARSED, WEBSOCKET_REACHABLE};
+use crate::upstream::{self, UpstreamConfig, UpstreamInfo, UpstreamInfoStore};
+use crate::validation::{
+    ChainIdCheck, ChainIdDecision, ChainIdValidator, PayloadValidator, DEFAULT_VALIDATORS,
+};
+use alloy_consensus::proofs::calculate_transaction_root;
+use alloy_consensus::transaction::{Recovered, SignerRecoverable};
+use alloy_consensus::{Transaction, TxReceipt};
 use std::time::Instant;
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -30,36 +60,179 @@ pub struct Metadata {
     pub block_number: u64,
 }
 
+/// Account changes made by a single flashblock, kept so intra-block state diffs can be served.
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct FlashblockAccountChanges {
+    pub balances: HashMap<Address, U256>,
+    pub nonce_increments: HashMap<Address, u64>,
+}
+
+/// Wire format of a flashblock. The metadata is kept as raw json so it can be decoded straight
+/// into [`Metadata`] instead of going through a `serde_json::Value` first.
+#[derive(Deserialize)]
+struct FlashblockFrame<'a> {
+    payload_id: PayloadId,
+    index: u64,
+    base: Option<ExecutionPayloadBaseV1>,
+    diff: ExecutionPayloadFlashblockDeltaV1,
+    #[serde(borrow)]
+    metadata: &'a RawValue,
+}
+
+/// Number of workers applying flashblocks unless configured otherwise.
+pub const DEFAULT_PAYLOAD_WORKERS: usize = 2;
+
+/// Number of leading bytes logged for unexpected websocket frames.
+const UNEXPECTED_FRAME_LOG_BYTES: usize = 64;
+
+/// Number of flashblocks kept while the chain id is unverified in enforce mode, the oldest are
+/// dropped beyond it.
+const MAX_HELD_FLASHBLOCKS: usize = 100;
+
 // Simplify actor messages to just handle shutdown
 #[derive(Debug)]
 enum ActorMessage {
-    BestPayload { payload: FlashblocksPayloadV1 },
+    BestPayload {
+        payload: FlashblocksPayloadV1,
+        metadata: Metadata,
+        /// Websocket frame the flashblock arrived in
+        frame: Bytes,
+        received_at: Instant,
+    },
+    /// The next block started, sent after the flashblocks of `block_number` so the worker
+    /// applying them reports the block complete once they are all applied
+    Complete { block_number: u64 },
 }
 
 pub struct FlashblocksClient {
     sender: mpsc::Sender<ActorMessage>,
     mailbox: mpsc::Receiver<ActorMessage>,
     cache: Arc<Cache>,
+    pending: Arc<PendingViewStore>,
     metrics: Metrics,
+    upstream_config: UpstreamConfig,
+    upstream_info_url: Option<Url>,
+    chain_id_validator: Option<ChainIdValidator>,
+    chain_spec: Arc<OpChainSpec>,
+    payload_workers: usize,
+    log_unexpected_frames: bool,
+    startup_report: Arc<StartupReport>,
+    upstream_info: Arc<UpstreamInfoStore>,
+    validators: Vec<Arc<dyn PayloadValidator>>,
+    #[cfg(any(test, feature = "fault-injection"))]
+    faults: Option<Arc<FaultInjector>>,
 }
 
 impl FlashblocksClient {
-    pub fn new(cache: Arc<Cache>) -> Self {
+    pub fn new(cache: Arc<Cache>, pending: Arc<PendingViewStore>) -> Self {
         let (sender, mailbox) = mpsc::channel(100);
 
         Self {
             sender,
             mailbox,
             cache,
+            pending,
             metrics: Metrics::default(),
+            upstream_config: UpstreamConfig::default(),
+            upstream_info_url: None,
+            chain_id_validator: None,
+            chain_spec: BASE_MAINNET.clone(),
+            payload_workers: DEFAULT_PAYLOAD_WORKERS,
+            log_unexpected_frames: false,
+            startup_report: Arc::new(StartupReport::default()),
+            upstream_info: Arc::new(UpstreamInfoStore::default()),
+            validators: DEFAULT_VALIDATORS
+                .iter()
+                .map(|validator| Arc::new(*validator) as Arc<dyn PayloadValidator>)
+                .collect(),
+            #[cfg(any(test, feature = "fault-injection"))]
+            faults: None,
         }
     }
 
+    pub fn with_upstream_config(mut self, upstream_config: UpstreamConfig) -> Self {
+        self.upstream_config = upstream_config;
+        self
+    }
+
+    /// JSON-RPC endpoint of the flashblocks source, used to verify its chain id on startup.
+    pub fn with_upstream_info_url(mut self, url: Url) -> Self {
+        self.upstream_info_url = Some(url);
+        self
+    }
+
+    /// Verify that the flashblocks source serves `chain_id` before trusting its payloads.
+    pub fn with_chain_id_check(mut self, chain_id: u64, mode: ChainIdCheck) -> Self {
+        self.chain_id_validator = Some(ChainIdValidator::new(chain_id, mode));
+        self
+    }
+
+    /// Chain the flashblocks are for, deciding the hardfork dependent fields of the pending
+    /// blocks from their timestamp.
+    pub fn with_chain_spec(mut self, chain_spec: Arc<OpChainSpec>) -> Self {
+        self.chain_spec = chain_spec;
+        self
+    }
+
+    /// Number of workers applying flashblocks. Heights are spread across the workers while the
+    /// flashblocks of a height always go to the same one, so a backlog of old heights after a
+    /// stall doesn't hold up the current one.
+    pub fn with_payload_workers(mut self, workers: usize) -> Self {
+        self.payload_workers = workers.max(1);
+        self
+    }
+
+    /// Log the first bytes of websocket frames that aren't flashblocks or control frames, to
+    /// spot protocol changes on the upstream. They are always counted.
+    pub fn with_log_unexpected_frames(mut self, enabled: bool) -> Self {
+        self.log_unexpected_frames = enabled;
+        self
+    }
+
+    /// Replaces the validators run on every flashblock before it is applied.
+    pub fn with_payload_validators(mut self, validators: Vec<Arc<dyn PayloadValidator>>) -> Self {
+        self.validators = validators;
+        self
+    }
+
+    /// Adds a validator run after the configured ones.
+    pub fn with_payload_validator(mut self, validator: Arc<dyn PayloadValidator>) -> Self {
+        self.validators.push(validator);
+        self
+    }
+
+    /// Report shared with the RPC, resolved as the websocket connects and payloads arrive.
+    pub fn with_startup_report(mut self, startup_report: Arc<StartupReport>) -> Self {
+        self.startup_report = startup_report;
+        self
+    }
+
+    /// Store shared with the RPC, updated with what the upstream announces on every connection.
+    pub fn with_upstream_info(mut self, upstream_info: Arc<UpstreamInfoStore>) -> Self {
+        self.upstream_info = upstream_info;
+        self
+    }
+
+    /// Injects `faults` into the websocket frames, to exercise how the pending state degrades.
+    #[cfg(any(test, feature = "fault-injection"))]
+    pub fn with_fault_injector(mut self, faults: Arc<FaultInjector>) -> Self {
+        self.faults = Some(faults);
+        self
+    }
+
     pub fn init(&mut self, ws_url: String) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let url = Url::parse(&ws_url)?;
         println!("trying to connect to {:?}", url);
         let sender = self.sender.clone();
         let cache_clone = self.cache.clone();
+        let pending = self.pending.clone();
+        let ws_pending = self.pending.clone();
+        let upstream_config = self.upstream_config.clone();
+        let log_unexpected_frames = self.log_unexpected_frames;
+        let startup_report = self.startup_report.clone();
+        let upstream_info = self.upstream_info.clone();
+        #[cfg(any(test, feature = "fault-injection"))]
+        let faults = self.faults.clone();
 
         // Take ownership of mailbox for the actor loop
         let mut mailbox = std::mem::replace(&mut self.mailbox, mpsc::channel(1).1);
@@ -69,57 +242,144 @@ impl FlashblocksClient {
         tokio::spawn(async move {
             let mut backoff = std::time::Duration::from_secs(1);
             const MAX_BACKOFF: std::time::Duration = std::time::Duration::from_secs(10);
+            let mut attempt: usize = 0;
+            let mut first_payload_parsed = false;
+            let mut exported_info = None;
 
             loop {
-                match connect_async(url.as_str()).await {
-                    Ok((ws_stream, _)) => {
+                let result = upstream::connect(&url, &upstream_config, attempt).await;
+                attempt = attempt.wrapping_add(1);
+
+                match result {
+                    Ok((ws_stream, mut announced)) => {
                         println!("WebSocket connected!");
-                        let (_write, mut read) = ws_stream.split();
+                        announced.connected_at = ws_pending.clock().unix_millis();
+                        // only the host, the url may carry credentials
+                        startup_report.pass(
+                            WEBSOCKET_REACHABLE,
+                            format!("connected to {}", url.host_str().unwrap_or_default()),
+                        );
+                        report_upstream_info(&announced, &upstream_info, &mut exported_info);
+                        upstream_info.connected();
+                        ws_pending.start_sync();
+                        let (mut write, mut read) = ws_stream.split();
                         // Handle incoming messages
                         while let Some(msg) = read.next().await {
                             metrics.upstream_messages.increment(1);
-                            let msg_start_time = Instant::now();
+                            if let Ok(Message::Text(text)) = &msg {
+                                if announced.apply_frame(text) {
+                                    report_upstream_info(
+                                        &announced,
+                                        &upstream_info,
+                                        &mut exported_info,
+                                    );
+                                    continue;
+                                }
+                            }
+                            let msg_start_time = ws_pending.clock().now();
 
                             match msg {
                                 Ok(Message::Binary(bytes)) => {
-                                    let text = match try_parse_message(&bytes) {
-                                        Ok(text) => text,
+                                    #[cfg(any(test, feature = "fault-injection"))]
+                                    if faults.as_ref().is_some_and(|faults| faults.drop_frame()) {
+                                        continue;
+                                    }
+                                    // kept as received, to debug how a frame was parsed
+                                    let frame = Bytes::from(bytes.clone());
+                                    let sequence = ws_pending.record_raw_frame(frame.clone());
+                                    let json = match try_parse_message(&bytes) {
+                                        Ok(json) => json,
                                         Err(e) => {
                                             error!("Failed to decode message: {}", e);
+                                            ws_pending.raw_frame_failed(sequence, e.to_string());
                                             continue;
                                         }
                                     };
 
-                                    let payload: FlashblocksPayloadV1 =
-                                        match serde_json::from_str(&text) {
-                                            Ok(m) => m,
-                                            Err(e) => {
-                                                error!("failed to parse message: {}", e);
-                                                continue;
+                                    let (payload, metadata) = match decode_flashblock(&json) {
+                                        Ok(decoded) => decoded,
+                                        Err(e) => {
+                                            error!("failed to parse message: {}", e);
+                                            ws_pending.raw_frame_failed(sequence, e.to_string());
+                                            if !first_payload_parsed {
+                                                startup_report
+                                                    .fail(FIRST_PAYLOAD_PARSED, e.to_string());
                                             }
-                                        };
-
-                                    let _ =
-                                        sender.send(ActorMessage::BestPayload { payload }).await;
+                                            continue;
+                                        }
+                                    };
+                                    #[cfg(any(test, feature = "fault-injection"))]
+                                    let metadata = match &faults {
+                                        Some(faults) => faults.corrupt(metadata),
+                                        None => metadata,
+                                    };
+                                    upstream_info.payload_received();
+                                    if !first_payload_parsed {
+                                        first_payload_parsed = true;
+                                        startup_report.pass(
+                                            FIRST_PAYLOAD_PARSED,
+                                            format!(
+                                                "block {} flashblock {}",
+                                                metadata.block_number, payload.index
+                                            ),
+                                        );
+                                    }
+                                    ws_pending.raw_frame_decoded(
+                                        sequence,
+                                        metadata.block_number,
+                                        payload.index,
+                                    );
+
+                                    let _ = sender
+                                        .send(ActorMessage::BestPayload {
+                                            payload,
+                                            metadata,
+                                            frame,
+                                            received_at: msg_start_time,
+                                        })
+                                        .await;
                                     metrics
                                         .websocket_processing_duration
-                                        .record(msg_start_time.elapsed());
+                                        .record(ws_pending.clock().since(msg_start_time));
+                                }
+                                Ok(Message::Ping(_)) => {
+                                    // the pong is queued when the ping is read, flushing sends
+                                    // it without waiting for the next write
+                                    if let Err(e) = write.flush().await {
+                                        metrics.upstream_errors.increment(1);
+                                        error!("Failed to answer ping: {}", e);
+                                        break;
+                                    }
                                 }
+                                Ok(Message::Pong(_)) => {}
                                 Ok(Message::Close(_)) => break,
+                                Ok(message) => {
+                                    metrics.upstream_unexpected_frames.increment(1);
+                                    if log_unexpected_frames {
+                                        warn!(
+                                            "Unexpected {} frame from upstream: {}",
+                                            frame_kind(&message),
+                                            frame_prefix(message)
+                                        );
+                                    }
+                                }
                                 Err(e) => {
                                     metrics.upstream_errors.increment(1);
                                     error!("Error receiving message: {}", e);
                                     break;
                                 }
-                                _ => {} // Handle other message types if needed
                             }
                         }
+                        upstream_info.disconnected();
                     }
                     Err(e) => {
                         error!(
                             "WebSocket connection error, retrying in {:?}: {}",
                             backoff, e
                         );
+                        if startup_report.is_unresolved(WEBSOCKET_REACHABLE) {
+                            startup_report.fail(WEBSOCKET_REACHABLE, e.to_string());
+                        }
                         tokio::time::sleep(backoff).await;
                         // Double the backoff time, but cap at MAX_BACKOFF
                         backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
@@ -130,12 +390,79 @@ impl FlashblocksClient {
         });
 
         // Spawn actor's event loop
+        let mut chain_id_validator = self.chain_id_validator.clone();
+        let upstream_info_url = self.upstream_info_url.clone();
+        let highest_dispatched = Arc::new(AtomicU64::new(0));
+        let mut dispatcher = PayloadDispatcher::new(
+            (0..self.payload_workers)
+                .map(|_| {
+                    spawn_payload_worker(
+                        cache_clone.clone(),
+                        pending.clone(),
+                        self.validators.clone(),
+                        self.chain_spec.clone(),
+                        highest_dispatched.clone(),
+                    )
+                })
+                .collect(),
+            highest_dispatched,
+        );
+        let startup_report = self.startup_report.clone();
+        let actor_metrics = self.metrics.clone();
         tokio::spawn(async move {
+            match chain_id_validator.as_ref() {
+                None => startup_report.skip(CHAIN_MATCHES, "no chain id check configured"),
+                Some(validator) if validator.is_disabled() => {
+                    startup_report.skip(CHAIN_MATCHES, "chain id check disabled")
+                }
+                Some(_) => {}
+            }
+            let mut chain_reported = false;
+            let mut held = VecDeque::new();
+            if let (Some(validator), Some(info_url)) =
+                (chain_id_validator.as_mut(), upstream_info_url.as_ref())
+            {
+                validator.check_upstream(info_url).await;
+                chain_reported = report_chain_id(validator, &startup_report);
+            }
+
             while let Some(message) = mailbox.recv().await {
+                actor_metrics.mailbox_depth.set(mailbox.len() as f64);
                 match message {
-                    ActorMessage::BestPayload { payload } => {
-                        process_payload(payload, cache_clone.clone());
+                    ActorMessage::BestPayload {
+                        payload,
+                        metadata,
+                        frame,
+                        received_at,
+                    } => {
+                        if let Some(validator) = chain_id_validator.as_mut() {
+                            let decision = validator.check(&payload);
+                            if !chain_reported {
+                                chain_reported = report_chain_id(validator, &startup_report);
+                            }
+                            match decision {
+                                ChainIdDecision::Accept => {
+                                    for (payload, metadata, frame, received_at) in held.drain(..) {
+                                        dispatcher.dispatch(payload, metadata, frame, received_at);
+                                    }
+                                }
+                                ChainIdDecision::Hold => {
+                                    if held.len() == MAX_HELD_FLASHBLOCKS {
+                                        held.pop_front();
+                                    }
+                                    held.push_back((payload, metadata, frame, received_at));
+                                    continue;
+                                }
+                                ChainIdDecision::Reject => {
+                                    held.clear();
+                                    continue;
+                                }
+                            }
+                        }
+                        dispatcher.dispatch(payload, metadata, frame, received_at);
                     }
+                    // completions are only sent by the dispatcher to the workers
+                    ActorMessage::Complete { .. } => {}
                 }
             }
         });
@@ -144,38 +471,332 @@ impl FlashblocksClient {
     }
 }
 
-fn try_parse_message(bytes: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
-    if let Ok(text) = String::from_utf8(bytes.to_vec()) {
-        if text.trim_start().starts_with("{") {
-            return Ok(text);
+/// Logs and exports what the upstream announced, replacing what the previous connection did.
+fn report_upstream_info(
+    announced: &UpstreamInfo,
+    upstream_info: &UpstreamInfoStore,
+    exported: &mut Option<UpstreamInfoMetrics>,
+) {
+    info!("Upstream announced {}", announced);
+    if let Some(previous) = exported.take() {
+        previous.info.set(0);
+    }
+    let metrics = UpstreamInfoMetrics::for_info(announced);
+    metrics.info.set(1);
+    *exported = Some(metrics);
+    upstream_info.set(announced.clone());
+}
+
+/// Records the outcome of the chain id check once one was reached, returning whether it was.
+fn report_chain_id(validator: &ChainIdValidator, startup_report: &StartupReport) -> bool {
+    match validator.verified() {
+        Some(true) => startup_report.pass(CHAIN_MATCHES, "source serves the node's chain"),
+        Some(false) => startup_report.fail(CHAIN_MATCHES, "source serves a different chain"),
+        None => return false,
+    }
+    true
+}
+
+/// Routes the flashblocks to the payload workers, those of a height always to the same worker so
+/// they are applied in order. A worker that falls behind doesn't hold up the others: the
+/// flashblocks it has no room for are dropped, along with the rest of their height until it
+/// starts over, as they would build on a block missing a flashblock.
+struct PayloadDispatcher {
+    workers: Vec<mpsc::Sender<ActorMessage>>,
+    /// Heights that lost a flashblock to a full worker
+    overflowed: HashSet<u64>,
+    /// Heights that received flashblocks and weren't completed by a later one yet
+    open: BTreeSet<u64>,
+    /// Highest height dispatched to any worker, shared with the workers
+    highest: Arc<AtomicU64>,
+    metrics: Metrics,
+}
+
+impl PayloadDispatcher {
+    fn new(workers: Vec<mpsc::Sender<ActorMessage>>, highest: Arc<AtomicU64>) -> Self {
+        Self {
+            workers,
+            overflowed: HashSet::new(),
+            open: BTreeSet::new(),
+            highest,
+            metrics: Metrics::default(),
+        }
+    }
+
+    fn worker(&self, block_number: u64) -> &mpsc::Sender<ActorMessage> {
+        &self.workers[block_number as usize % self.workers.len()]
+    }
+
+    fn dispatch(
+        &mut self,
+        payload: FlashblocksPayloadV1,
+        metadata: Metadata,
+        frame: Bytes,
+        received_at: Instant,
+    ) {
+        let block_number = metadata.block_number;
+        let index = payload.index;
+        // only the retained heights can still receive flashblocks
+        self.overflowed
+            .retain(|&overflowed| overflowed + RETAINED_BLOCKS > block_number);
+        if index == 0 {
+            self.overflowed.remove(&block_number);
+            self.complete_below(block_number);
+        } else if self.overflowed.contains(&block_number) {
+            self.metrics.payloads_dropped_overflow.increment(1);
+            return;
+        }
+        self.open.insert(block_number);
+        self.highest.fetch_max(block_number, Ordering::Relaxed);
+
+        let message = ActorMessage::BestPayload {
+            payload,
+            metadata,
+            frame,
+            received_at,
+        };
+        match self.worker(block_number).try_send(message) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                warn!(
+                    "Payload worker of block {} is full, dropping flashblock {} and the rest of the block",
+                    block_number, index
+                );
+                self.metrics.payloads_dropped_overflow.increment(1);
+                self.overflowed.insert(block_number);
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                error!("Payload worker of block {} stopped", block_number);
+            }
+        }
+    }
+
+    /// Tells the workers of the heights below `block_number` that their blocks are complete,
+    /// behind the flashblocks already queued for them.
+    fn complete_below(&mut self, block_number: u64) {
+        let open = self.open.split_off(&block_number);
+        for completed in std::mem::replace(&mut self.open, open) {
+            let message = ActorMessage::Complete {
+                block_number: completed,
+            };
+            if self.worker(completed).try_send(message).is_err() {
+                warn!(
+                    "Payload worker of block {} is full, its completion isn't reported",
+                    completed
+                );
+            }
         }
     }
+}
+
+/// Spawns a worker applying the flashblocks it receives in order. Flashblocks that are already
+/// superseded by a queued one or by a height dispatched to any worker, or fail a validator,
+/// are skipped.
+fn spawn_payload_worker(
+    cache: Arc<Cache>,
+    pending: Arc<PendingViewStore>,
+    validators: Vec<Arc<dyn PayloadValidator>>,
+    chain_spec: Arc<OpChainSpec>,
+    highest_dispatched: Arc<AtomicU64>,
+) -> mpsc::Sender<ActorMessage> {
+    let (sender, mut mailbox) = mpsc::channel(100);
+    tokio::spawn(async move {
+        let metrics = Metrics::default();
+        let mut batch = Vec::new();
+        while mailbox.recv_many(&mut batch, 100).await > 0 {
+            metrics.worker_queue_depth.record(batch.len() as f64);
+            // the other workers may have received heights far enough ahead to drop these
+            let highest = highest_dispatched.load(Ordering::Relaxed);
+            let payloads = batch.iter().filter_map(|message| match message {
+                ActorMessage::BestPayload {
+                    payload, metadata, ..
+                } => Some((metadata.block_number, payload.index)),
+                ActorMessage::Complete { .. } => None,
+            });
+            let mut superseded = superseded_payloads(payloads, highest).into_iter();
+            for message in batch.drain(..) {
+                let (payload, metadata, frame, received_at) = match message {
+                    ActorMessage::BestPayload {
+                        payload,
+                        metadata,
+                        frame,
+                        received_at,
+                    } => (payload, metadata, frame, received_at),
+                    ActorMessage::Complete { block_number } => {
+                        pending.complete(block_number);
+                        continue;
+                    }
+                };
+                if superseded.next().unwrap_or_default() {
+                    metrics.payloads_skipped_superseded.increment(1);
+                    continue;
+                }
+                metrics
+                    .mailbox_latency
+                    .record(pending.clock().since(received_at));
+                if !passes_validators(&validators, &payload, &metadata) {
+                    continue;
+                }
+                cache.before_writes().await;
+                process_flashblock(
+                    payload,
+                    metadata,
+                    &frame,
+                    cache.clone(),
+                    &pending,
+                    &chain_spec,
+                    received_at,
+                );
+            }
+        }
+    });
+    sender
+}
+
+fn passes_validators(
+    validators: &[Arc<dyn PayloadValidator>],
+    payload: &FlashblocksPayloadV1,
+    metadata: &Metadata,
+) -> bool {
+    for validator in validators {
+        if let Err(reason) = validator.validate(payload, metadata) {
+            warn!(
+                "Dropping flashblock {} of block {}, {} validation failed: {}",
+                payload.index,
+                metadata.block_number,
+                validator.name(),
+                reason
+            );
+            ValidatorMetrics::for_validator(validator.name())
+                .rejected
+                .increment(1);
+            return false;
+        }
+    }
+    true
+}
+
+/// Flags the queued flashblocks, given as `(block number, index)` in arrival order, whose
+/// result would be thrown away: the block was started over by a later first flashblock of the
+/// same height, which is queued on the same worker, or `highest`, the highest height
+/// dispatched to any worker, is far enough ahead to drop the height from the pending views.
+fn superseded_payloads(
+    payloads: impl DoubleEndedIterator<Item = (u64, u64)>,
+    highest: u64,
+) -> Vec<bool> {
+    let mut superseded = Vec::new();
+    let mut restarted = HashSet::new();
+    for (block_number, index) in payloads.rev() {
+        superseded
+            .push(restarted.contains(&block_number) || block_number + RETAINED_BLOCKS <= highest);
+        if index == 0 {
+            restarted.insert(block_number);
+        }
+    }
+    superseded.reverse();
+    superseded
+}
+
+fn frame_kind(message: &Message) -> &'static str {
+    match message {
+        Message::Text(_) => "text",
+        Message::Binary(_) => "binary",
+        Message::Ping(_) => "ping",
+        Message::Pong(_) => "pong",
+        Message::Close(_) => "close",
+        Message::Frame(_) => "raw",
+    }
+}
+
+/// Hex encodes the first bytes of a frame, marking frames that were cut.
+fn frame_prefix(message: Message) -> String {
+    let data = message.into_data();
+    let prefix =
+        alloy_primitives::hex::encode_prefixed(&data[..data.len().min(UNEXPECTED_FRAME_LOG_BYTES)]);
+    if data.len() > UNEXPECTED_FRAME_LOG_BYTES {
+        format!("{prefix}... ({} bytes)", data.len())
+    } else {
+        prefix
+    }
+}
+
+/// Returns the json of a frame, which is either sent as is or brotli compressed. Uncompressed
+/// frames are borrowed, utf-8 is validated while decoding the json.
+fn try_parse_message(bytes: &[u8]) -> Result<Cow<'_, [u8]>, Box<dyn std::error::Error>> {
+    if bytes.trim_ascii_start().starts_with(b"{") {
+        return Ok(Cow::Borrowed(bytes));
+    }
 
     let mut decompressor = brotli::Decompressor::new(bytes, 4096);
     let mut decompressed = Vec::new();
     decompressor.read_to_end(&mut decompressed)?;
+    Ok(Cow::Owned(decompressed))
+}
+
+/// Decodes a flashblock and its metadata from json in a single pass. The returned payload's
+/// `metadata` is left empty.
+pub fn decode_flashblock(
+    json: &[u8],
+) -> Result<(FlashblocksPayloadV1, Metadata), Box<dyn std::error::Error>> {
+    let frame: FlashblockFrame<'_> = serde_json::from_slice(json)?;
+    let metadata = serde_json::from_str(frame.metadata.get())?;
+    let payload = FlashblocksPayloadV1 {
+        payload_id: frame.payload_id,
+        index: frame.index,
+        base: frame.base,
+        diff: frame.diff,
+        metadata: serde_json::Value::Null,
+    };
+    Ok((payload, metadata))
+}
 
-    let text = String::from_utf8(decompressed)?;
-    Ok(text)
+/// Decodes a websocket frame as it was received, compressed or not, into the flashblock it
+/// carried with its metadata.
+pub fn decode_frame(frame: &[u8]) -> Result<FlashblocksPayloadV1, Box<dyn std::error::Error>> {
+    let json = try_parse_message(frame)?;
+    Ok(serde_json::from_slice(&json)?)
 }
 
-fn process_payload(payload: FlashblocksPayloadV1, cache: Arc<Cache>) {
+/// Applies a flashblock whose metadata is still embedded in the payload.
+#[cfg(test)]
+fn process_payload(
+    payload: FlashblocksPayloadV1,
+    cache: Arc<Cache>,
+    pending: &PendingViewStore,
+    frame_received_at: Instant,
+) {
+    let metadata = serde_json::from_value(payload.metadata.clone()).unwrap();
+    let frame = serde_json::to_vec(&payload).unwrap();
+    process_flashblock(
+        payload,
+        metadata,
+        &frame,
+        cache,
+        pending,
+        &BASE_MAINNET,
+        frame_received_at,
+    );
+}
+
+/// Applies a flashblock and publishes the resulting pending view. `frame_received_at` is when
+/// the websocket `frame` carrying the flashblock arrived, used to measure the ingest lag.
+fn process_flashblock(
+    payload: FlashblocksPayloadV1,
+    metadata: Metadata,
+    frame: &[u8],
+    cache: Arc<Cache>,
+    pending: &PendingViewStore,
+    chain_spec: &OpChainSpec,
+    frame_received_at: Instant,
+) {
     let metrics = Metrics::default();
     let msg_processing_start_time = Instant::now();
-
-    // Convert metadata with error handling
-    let metadata: Metadata = match serde_json::from_value(payload.metadata) {
-        Ok(m) => m,
-        Err(e) => {
-            error!("Failed to deserialize metadata: {}", e);
-            return;
-        }
-    };
+    let received_at = pending.clock().unix_millis();
 
     let block_number = metadata.block_number;
+    let receipts = parse_receipts(&metadata.receipts);
     let diff = payload.diff;
-    let withdrawals = diff.withdrawals.clone();
-    let diff_transactions = diff.transactions.clone();
+    let diff_tx_count = diff.transactions.len();
 
     // Skip if index is 0 and base is not cached, likely the first payload
     // Can't do pending block with this because already missing blocks
@@ -188,20 +809,52 @@ fn process_payload(payload: FlashblocksPayloadV1, cache: Arc<Cache>) {
     }
 
     // Track flashblock indices and record metrics
-    update_flashblocks_index(payload.index, &cache, &metrics);
+    update_flashblocks_index(payload.index, block_number, &cache, &metrics);
 
-    // Prevent updating to older blocks
-    let current_block = cache.get::<OpBlock>(&CacheKey::PendingBlock);
-    if current_block.is_some() && current_block.unwrap().number > block_number {
+    // Flashblocks of different heights may interleave, but blocks that fell out of the
+    // retained range are no longer tracked
+    if pending.is_behind(block_number) {
         return;
     }
 
+    // Flashblocks after the first extend the view of the same block, the first one starts over
+    let parent_view = pending
+        .latest_published(block_number)
+        .filter(|_| payload.index != 0);
+    if payload.index != 0 {
+        match parent_view.as_ref().map(|view| view.flashblock_index) {
+            // the block was dropped, the rest of it is skipped until it starts over
+            None => return,
+            // received again, its transactions are already in the block
+            Some(last) if payload.index <= last => return,
+            Some(last) if payload.index > last + 1 => {
+                warn!(
+                    "Flashblock {} of block {} follows flashblock {}, dropping the block",
+                    payload.index, block_number, last
+                );
+                metrics.flashblock_gaps.increment(1);
+                pending.invalidate(block_number);
+                return;
+            }
+            Some(_) => {}
+        }
+    }
+
     // base only appears once in the first payload index
     let base = if let Some(base) = payload.base {
+        pending.clock().observe_block(base.timestamp);
         if let Err(e) = cache.set(CacheKey::Base(block_number), &base, Some(10)) {
             error!("Failed to set base in cache: {}", e);
             return;
         }
+        // the fee recipient on the base identifies the builder of this block
+        if let Err(e) = cache.set(
+            CacheKey::BlockBuilder(block_number),
+            &base.fee_recipient,
+            Some(10),
+        ) {
+            error!("Failed to set block builder in cache: {}", e);
+        }
         base
     } else {
         match cache.get(&CacheKey::Base(block_number)) {
@@ -213,24 +866,111 @@ fn process_payload(payload: FlashblocksPayloadV1, cache: Arc<Cache>) {
         }
     };
 
-    let transactions = match get_and_set_transactions(
-        diff_transactions,
-        payload.index,
-        block_number,
-        cache.clone(),
+    let builder_metrics = BuilderMetrics::for_builder(base.fee_recipient);
+    builder_metrics.flashblocks.increment(1);
+
+    // the transactions of the earlier flashblocks were decoded into the parent view
+    let known = parent_view
+        .as_ref()
+        .map_or(&[][..], |view| view.block.body.transactions.as_slice());
+    let block = match extend_block(&base, &diff, known, chain_spec) {
+        Ok(block) => block,
+        Err(e) => {
+            error!("Failed to convert execution payload to block: {}", e);
+            return;
+        }
+    };
+
+    let mut view = match build_pending_view(
+        block,
+        PreconfirmationInfo {
+            index: payload.index,
+            received_at,
+        },
+        parent_view.as_deref(),
+        payload.payload_id,
+        &receipts,
+        &metadata,
     ) {
-        Ok(txs) => txs,
+        Ok(view) => view,
         Err(e) => {
-            error!("Failed to get and set transactions: {}", e);
+            error!("Failed to build pending view: {}", e);
             return;
         }
     };
 
+    // every flashblock changes the hash of the block, so entities always carry the latest one
+    view.block_hash = diff.block_hash;
+
+    // the transactions added by this flashblock are at the end of the block
+    let new_senders: Vec<Address> = view
+        .senders
+        .iter()
+        .skip(view.senders.len().saturating_sub(diff_tx_count))
+        .copied()
+        .collect();
+    if let Err(e) = set_account_changes(
+        payload.index,
+        block_number,
+        &new_senders,
+        &metadata,
+        cache.clone(),
+    ) {
+        error!("Failed to set account changes in cache: {}", e);
+    }
+
+    // "pending" because users query the block using "pending" tag
+    // This is an optimistic update will likely need to tweak in the future
+    view.received_at = frame_received_at;
+    let view = pending.publish(view);
+    metrics.ingest_lag.record(view.ingest_lag());
+
+    if pending.flashblocks().subscriber_count() > 0 {
+        if let Err(e) = publish_flashblock(pending, frame) {
+            error!("Failed to re-broadcast flashblock: {}", e);
+        }
+    }
+
+    metrics
+        .block_processing_duration
+        .record(msg_processing_start_time.elapsed());
+    builder_metrics
+        .block_processing_duration
+        .record(msg_processing_start_time.elapsed());
+
+    // check duration on the most heavy payload
+    if payload.index == 0 {
+        println!(
+            "block processing time: {:?}",
+            msg_processing_start_time.elapsed()
+        );
+    }
+}
+
+/// Re-broadcasts an applied flashblock as the json it was received as, only decompressed.
+fn publish_flashblock(
+    pending: &PendingViewStore,
+    frame: &[u8],
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let json = String::from_utf8(try_parse_message(frame)?.into_owned())?;
+    Ok(pending
+        .flashblocks()
+        .publish_raw(RawValue::from_string(json)?))
+}
+
+/// The block as of the flashblock carrying `diff`, `transactions` being every transaction of
+/// the block up to it.
+pub(crate) fn assemble_block(
+    base: &ExecutionPayloadBaseV1,
+    diff: &ExecutionPayloadFlashblockDeltaV1,
+    transactions: Vec<Bytes>,
+    chain_spec: &OpChainSpec,
+) -> Result<OpBlock, PayloadError> {
     let execution_payload: ExecutionPayloadV3 = ExecutionPayloadV3 {
         blob_gas_used: 0,
         excess_blob_gas: 0,
         payload_inner: ExecutionPayloadV2 {
-            withdrawals,
+            withdrawals: diff.withdrawals.clone(),
             payload_inner: ExecutionPayloadV1 {
                 parent_hash: base.parent_hash,
                 fee_recipient: base.fee_recipient,
@@ -242,282 +982,307 @@ fn process_payload(payload: FlashblocksPayloadV1, cache: Arc<Cache>) {
                 gas_limit: base.gas_limit,
                 gas_used: diff.gas_used,
                 timestamp: base.timestamp,
-                extra_data: base.extra_data,
-                base_fee_per_gas: U256::from(1000),
+                extra_data: base.extra_data.clone(),
+                base_fee_per_gas: base.base_fee_per_gas,
                 block_hash: diff.block_hash,
                 transactions,
             },
         },
     };
 
-    let block: OpBlock = match execution_payload.try_into_block() {
-        Ok(block) => block,
-        Err(e) => {
-            error!("Failed to convert execution payload to block: {}", e);
-            return;
-        }
-    };
-
-    // "pending" because users query the block using "pending" tag
-    // This is an optimistic update will likely need to tweak in the future
-    if let Err(e) = cache.set(CacheKey::PendingBlock, &block, Some(10)) {
-        error!("Failed to set pending block in cache: {}", e);
-        return;
-    }
+    let mut block: OpBlock = execution_payload.try_into_block()?;
+    apply_hardfork_fields(
+        &mut block,
+        chain_spec,
+        base.parent_beacon_block_root,
+        diff.withdrawals_root,
+    );
+    Ok(block)
+}
 
-    // set block to block number as well
-    if let Err(e) = cache.set(CacheKey::Block(block_number), &block, Some(10)) {
-        error!("Failed to set block in cache: {}", e);
-        return;
+/// Like [`assemble_block`], with `known` the transactions of the earlier flashblocks of the
+/// block, already decoded. Only the transactions `diff` appends are decoded.
+fn extend_block(
+    base: &ExecutionPayloadBaseV1,
+    diff: &ExecutionPayloadFlashblockDeltaV1,
+    known: &[OpTransactionSigned],
+    chain_spec: &OpChainSpec,
+) -> Result<OpBlock, PayloadError> {
+    let mut block = assemble_block(base, diff, diff.transactions.clone(), chain_spec)?;
+    if !known.is_empty() {
+        let mut transactions = Vec::with_capacity(known.len() + block.body.transactions.len());
+        transactions.extend_from_slice(known);
+        transactions.append(&mut block.body.transactions);
+        block.header.transactions_root = calculate_transaction_root(&transactions);
+        block.body.transactions = transactions;
     }
+    Ok(block)
+}
 
-    let diff_receipts = match get_and_set_txs_and_receipts(
-        block.clone(),
-        block_number,
-        cache.clone(),
-        metadata.clone(),
-    ) {
-        Ok(receipts) => receipts,
-        Err(e) => {
-            error!("Failed to get and set receipts: {}", e);
-            return;
-        }
-    };
-
-    // update all receipts
-    let _receipts = match get_and_set_all_receipts(
-        payload.index,
-        block_number,
-        cache.clone(),
-        diff_receipts.clone(),
-    ) {
-        Ok(receipts) => receipts,
-        Err(e) => {
-            error!("Failed to get and set all receipts: {}", e);
-            return;
-        }
-    };
-
-    // Store account balances
-    for (address, balance) in metadata.new_account_balances.iter() {
-        if let Err(e) = cache.set(
-            CacheKey::AccountBalance(Address::from_str(address).unwrap()),
-            &balance,
-            Some(10),
-        ) {
-            error!("Failed to set account balance in cache: {}", e);
+/// Sets the fields of `block` that depend on the hardforks active at its timestamp, which the
+/// execution payload conversion fills in as if every hardfork was active.
+fn apply_hardfork_fields(
+    block: &mut OpBlock,
+    chain_spec: &OpChainSpec,
+    parent_beacon_block_root: B256,
+    withdrawals_root: B256,
+) {
+    let timestamp = block.header.timestamp;
+    let header = &mut block.header;
+
+    if chain_spec.is_canyon_active_at_timestamp(timestamp) {
+        // from isthmus on the builder sends the storage root of the L2 to L1 message passer
+        if chain_spec.is_isthmus_active_at_timestamp(timestamp) {
+            header.withdrawals_root = Some(withdrawals_root);
         }
+    } else {
+        header.withdrawals_root = None;
+        block.body.withdrawals = None;
     }
 
-    metrics
-        .block_processing_duration
-        .record(msg_processing_start_time.elapsed());
-
-    // check duration on the most heavy payload
-    if payload.index == 0 {
-        println!(
-            "block processing time: {:?}",
-            msg_processing_start_time.elapsed()
-        );
+    if chain_spec.is_ecotone_active_at_timestamp(timestamp) {
+        header.parent_beacon_block_root = Some(parent_beacon_block_root);
+    } else {
+        header.blob_gas_used = None;
+        header.excess_blob_gas = None;
+        header.parent_beacon_block_root = None;
     }
+
+    header.requests_hash = chain_spec
+        .is_isthmus_active_at_timestamp(timestamp)
+        .then_some(EMPTY_REQUESTS_HASH);
 }
 
-fn update_flashblocks_index(index: u64, cache: &Arc<Cache>, metrics: &Metrics) {
+fn update_flashblocks_index(index: u64, block_number: u64, cache: &Arc<Cache>, metrics: &Metrics) {
     if index == 0 {
-        // Get highest index from previous block
-        if let Some(prev_highest_index) = cache.get::<u64>(&CacheKey::HighestPayloadIndex) {
+        // The previous block is complete once the next one starts
+        let prev_block_number = block_number.saturating_sub(1);
+        if let Some(prev_highest_index) =
+            cache.get::<u64>(&CacheKey::HighestPayloadIndex(prev_block_number))
+        {
             // Record metric: total flash blocks = highest_index + 1 (since it's 0-indexed)
             metrics
                 .flashblocks_in_block
                 .record((prev_highest_index + 1) as f64);
-            println!("Previous block had {} flash blocks", prev_highest_index + 1);
-        }
-
-        // Reset highest index to 0 for new block
-        if let Err(e) = cache.set(CacheKey::HighestPayloadIndex, &0u64, Some(10)) {
-            error!("Failed to reset highest flash index: {}", e);
-        }
-    } else {
-        // Update highest index if current index is higher
-        let current_highest = cache
-            .get::<u64>(&CacheKey::HighestPayloadIndex)
-            .unwrap_or(0);
-        if index > current_highest {
-            if let Err(e) = cache.set(CacheKey::HighestPayloadIndex, &index, Some(10)) {
-                error!("Failed to update highest flash index: {}", e);
+            if let Some(builder) = cache.get::<Address>(&CacheKey::BlockBuilder(prev_block_number))
+            {
+                BuilderMetrics::for_builder(builder)
+                    .flashblocks_in_block
+                    .record((prev_highest_index + 1) as f64);
             }
+            println!("Previous block had {} flash blocks", prev_highest_index + 1);
         }
     }
-}
 
-fn get_and_set_transactions(
-    transactions: Vec<Bytes>,
-    payload_index: u64,
-    block_number: u64,
-    cache: Arc<Cache>,
-) -> Result<Vec<Bytes>, Box<dyn std::error::Error>> {
-    // update incremental transactions
-    let transactions = if payload_index == 0 {
-        transactions
-    } else {
-        let existing = match cache.get::<Vec<Bytes>>(&CacheKey::DiffTransactions(block_number)) {
-            Some(existing) => existing,
-            None => return Err("Failed to get pending transactions from cache".into()),
-        };
-        existing
-            .into_iter()
-            .chain(transactions.iter().cloned())
-            .collect()
-    };
-
-    cache.set(
-        CacheKey::DiffTransactions(block_number),
-        &transactions,
+    // Update highest index of this block if current index is higher
+    if let Err(e) = cache.update(
+        CacheKey::HighestPayloadIndex(block_number),
         Some(10),
-    )?;
+        |highest: Option<u64>| highest.map_or(index, |highest| highest.max(index)),
+    ) {
+        error!("Failed to update highest flash index: {}", e);
+    }
+}
 
-    Ok(transactions)
+/// Parses the metadata receipt keys into transaction hashes, so lookups don't depend on the
+/// case or prefix the builder used to format them.
+fn parse_receipts(receipts: &HashMap<String, OpReceipt>) -> HashMap<TxHash, OpReceipt> {
+    receipts
+        .iter()
+        .filter_map(|(tx_hash, receipt)| match TxHash::from_str(tx_hash) {
+            Ok(tx_hash) => Some((tx_hash, receipt.clone())),
+            Err(e) => {
+                error!("Invalid receipt transaction hash {}: {}", tx_hash, e);
+                None
+            }
+        })
+        .collect()
 }
 
-fn get_and_set_txs_and_receipts(
+/// Builds the view of the block after this flashblock, reusing everything `parent` already
+/// derived for the transactions of earlier flashblocks. The state of earlier flashblocks is
+/// shared with `parent` rather than copied, this flashblock only adds its own.
+fn build_pending_view(
     block: OpBlock,
-    block_number: u64,
-    cache: Arc<Cache>,
-    metadata: Metadata,
-) -> Result<Vec<OpReceipt>, Box<dyn std::error::Error>> {
-    let mut diff_receipts: Vec<OpReceipt> = vec![];
-    // Store tx transaction signed
-    for (idx, transaction) in block.body.transactions.iter().enumerate() {
-        // check if exists, if not update
-        let existing_tx =
-            cache.get::<OpTransactionSigned>(&CacheKey::Transaction(transaction.tx_hash()));
-        if existing_tx.is_none() {
-            if let Err(e) = cache.set(
-                CacheKey::Transaction(transaction.tx_hash()),
-                &transaction,
-                Some(10),
-            ) {
-                error!("Failed to set transaction in cache: {}", e);
-                continue;
-            }
-            // update tx index
-            if let Err(e) = cache.set(
-                CacheKey::TransactionIndex(transaction.tx_hash()),
-                &idx,
-                Some(10),
-            ) {
-                error!("Failed to set transaction index in cache: {}", e);
-                continue;
+    preconfirmation: PreconfirmationInfo,
+    parent: Option<&PendingView>,
+    payload_id: PayloadId,
+    receipts: &HashMap<TxHash, OpReceipt>,
+    metadata: &Metadata,
+) -> Result<PendingView, Box<dyn std::error::Error>> {
+    // a parent the block doesn't extend was replaced, nothing it derived can be reused
+    let parent = parent.filter(|view| view.is_extended_by(&block, Some(payload_id)));
+    let known = parent.map_or(0, |view| view.senders.len());
+
+    let mut senders = parent.map(|view| view.senders.clone()).unwrap_or_default();
+    let mut recovered = parent
+        .map(|view| view.recovered.clone())
+        .unwrap_or_default();
+    let mut new_senders = Vec::with_capacity(block.body.transactions.len() - known);
+    let mut new_recovered = Vec::with_capacity(block.body.transactions.len() - known);
+    for transaction in &block.body.transactions[known..] {
+        let sender = transaction.recover_signer()?;
+        new_senders.push(sender);
+        new_recovered.push(Recovered::new_unchecked(transaction.clone(), sender));
+    }
+    senders.push_chunk(new_senders);
+    recovered.push_chunk(new_recovered);
+
+    let mut view =
+        PendingView::with_recovered(block, preconfirmation.index, senders, recovered, parent);
+    view.payload_id = Some(payload_id);
+    if let Some(parent) = parent {
+        view.receipts = parent.receipts.clone();
+        view.preconfirmations = parent.preconfirmations.clone();
+        view.balances = parent.balances.clone();
+        view.nonces = parent.nonces.clone();
+        view.deployments = parent.deployments.clone();
+        view.audit = parent.audit.clone();
+    }
+    let known_receipts = view.receipts.len();
+
+    // receipts stay aligned with the transactions, so stop at the first one that is missing
+    let new_receipts: Vec<OpReceipt> = view
+        .block
+        .body
+        .transactions
+        .iter()
+        .skip(view.receipts.len())
+        .map_while(|transaction| receipts.get(&transaction.tx_hash()).cloned())
+        .collect();
+    view.preconfirmations
+        .push_chunk(vec![preconfirmation; new_receipts.len()]);
+    view.receipts.push_chunk(new_receipts);
+
+    let mut nonces: HashMap<Address, BTreeSet<u64>> = HashMap::default();
+    let mut deployments = HashMap::default();
+    for (index, transaction) in view.block.body.transactions.iter().enumerate().skip(known) {
+        if !transaction.is_deposit() {
+            nonces
+                .entry(view.senders[index])
+                .or_default()
+                .insert(transaction.nonce());
+        }
+    }
+    // the receipts tell which transactions deployed a contract
+    for index in known_receipts..view.receipts.len() {
+        let Some(receipt) = view.receipts.get(index) else {
+            continue;
+        };
+        // deposits don't carry their nonce, it is only known once their receipt arrives
+        if let OpReceipt::Deposit(receipt) = receipt {
+            if let Some(nonce) = receipt.deposit_nonce {
+                nonces.entry(view.senders[index]).or_default().insert(nonce);
             }
+        }
+        let transaction = &view.block.body.transactions[index];
+        if let Some(contract) = deployed_contract(transaction, view.senders[index], receipt) {
+            deployments.insert(contract, index);
+        }
+    }
+    view.nonces.push_layer(nonces);
+    view.deployments.push_layer(deployments);
 
-            // update tx count for each from address
-            if let Ok(from) = transaction.recover_signer() {
-                // Get current tx count, default to 0 if not found
-                let current_count = cache
-                    .get::<u64>(&CacheKey::TransactionCount {
-                        address: from,
-                        block_number,
-                    })
-                    .unwrap_or(0);
-                // Increment tx count by 1
-                if let Err(e) = cache.set(
-                    CacheKey::TransactionCount {
-                        address: from,
-                        block_number,
-                    },
-                    &(current_count + 1),
-                    Some(10),
-                ) {
-                    error!("Failed to set transaction count in cache: {}", e);
-                }
-
-                // also keep track of sender of each transaction
-                if let Err(e) = cache.set(
-                    CacheKey::TransactionSender(transaction.tx_hash()),
-                    &from,
-                    Some(10),
-                ) {
-                    error!("Failed to set transaction sender in cache: {}", e);
-                }
+    let mut balances = HashMap::default();
+    for (address, balance) in metadata.new_account_balances.iter() {
+        balances.insert(Address::from_str(address)?, U256::from_str(balance)?);
+    }
+    view.balances.push_layer(balances);
 
-                // also keep track of the block number of each transaction
-                if let Err(e) = cache.set(
-                    CacheKey::TransactionBlockNumber(transaction.tx_hash()),
-                    &block_number,
-                    Some(10),
-                ) {
-                    error!("Failed to set transaction sender in cache: {}", e);
-                }
-            }
-        }
+    let hashes = |range: std::ops::Range<usize>| -> Vec<TxHash> {
+        view.block.body.transactions[range]
+            .iter()
+            .map(|transaction| transaction.tx_hash())
+            .collect()
+    };
+    let transaction_count = view.block.body.transactions.len();
+    let audit = FlashblockAudit {
+        index: preconfirmation.index,
+        block_hash: view.block_hash,
+        received_at: preconfirmation.received_at,
+        transactions: hashes(known..transaction_count),
+        metadata_receipts: hashes(known_receipts..view.receipts.len()),
+        missing_receipts: hashes(view.receipts.len()..transaction_count),
+        checks: vec![
+            // flashblocks after the first build on the view of the previous one
+            AuditCheck {
+                name: "extends-parent".to_string(),
+                passed: preconfirmation.index == 0 || parent.is_some(),
+            },
+            AuditCheck {
+                name: "receipts-complete".to_string(),
+                passed: view.receipts.len() == transaction_count,
+            },
+            AuditCheck {
+                name: "gas-within-limit".to_string(),
+                passed: view.block.header.gas_used <= view.block.header.gas_limit,
+            },
+        ],
+    };
+    view.audit.push(audit);
 
-        // TODO: move this into the transaction check
-        if metadata
-            .receipts
-            .contains_key(&transaction.tx_hash().to_string())
-        {
-            // find receipt in metadata and set it in cache
-            let receipt = metadata
-                .receipts
-                .get(&transaction.tx_hash().to_string())
-                .unwrap();
-            if let Err(e) = cache.set(CacheKey::Receipt(transaction.tx_hash()), receipt, Some(10)) {
-                error!("Failed to set receipt in cache: {}", e);
-                continue;
-            }
-            // map receipt's block number as well
-            if let Err(e) = cache.set(
-                CacheKey::ReceiptBlock(transaction.tx_hash()),
-                &block_number,
-                Some(10),
-            ) {
-                error!("Failed to set receipt block in cache: {}", e);
-                continue;
-            }
+    Ok(view)
+}
 
-            diff_receipts.push(receipt.clone());
-        }
+/// Address of the contract `transaction` deployed, if it is a creation that succeeded.
+fn deployed_contract(
+    transaction: &OpTransactionSigned,
+    sender: Address,
+    receipt: &OpReceipt,
+) -> Option<Address> {
+    if !transaction.kind().is_create() || !receipt.status() {
+        return None;
     }
-
-    Ok(diff_receipts)
+    let nonce = match receipt {
+        OpReceipt::Deposit(receipt) => receipt.deposit_nonce?,
+        _ => transaction.nonce(),
+    };
+    Some(sender.create(nonce))
 }
 
-fn get_and_set_all_receipts(
+fn set_account_changes(
     payload_index: u64,
     block_number: u64,
+    new_senders: &[Address],
+    metadata: &Metadata,
     cache: Arc<Cache>,
-    diff_receipts: Vec<OpReceipt>,
-) -> Result<Vec<OpReceipt>, Box<dyn std::error::Error>> {
-    // update all receipts
-    let receipts = if payload_index == 0 {
-        // get receipts and sort by cumulative gas used
-        diff_receipts
-    } else {
-        let existing = match cache.get::<Vec<OpReceipt>>(&CacheKey::PendingReceipts(block_number)) {
-            Some(existing) => existing,
-            None => {
-                return Err("Failed to get pending receipts from cache".into());
-            }
-        };
-        existing
-            .into_iter()
-            .chain(diff_receipts.iter().cloned())
-            .collect()
-    };
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut changes = FlashblockAccountChanges::default();
+    for (address, balance) in metadata.new_account_balances.iter() {
+        changes
+            .balances
+            .insert(Address::from_str(address)?, U256::from_str(balance)?);
+    }
+
+    for sender in new_senders {
+        *changes.nonce_increments.entry(*sender).or_default() += 1;
+    }
 
-    cache.set(CacheKey::PendingReceipts(block_number), &receipts, Some(10))?;
+    cache.set(
+        CacheKey::AccountChanges {
+            block_number,
+            index: payload_index,
+        },
+        &changes,
+        Some(10),
+    )?;
 
-    Ok(receipts)
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use alloy_consensus::{Receipt, TxReceipt};
-    use alloy_primitives::{Address, B256};
-    use alloy_rpc_types_engine::PayloadId;
-    use rollup_boost::primitives::{ExecutionPayloadBaseV1, ExecutionPayloadFlashblockDeltaV1};
+    use crate::test_utils::EIP1559_TX;
+    use alloy_consensus::{Receipt, SignableTransaction, TxEip1559, TxReceipt};
+    use alloy_eips::eip2718::{Decodable2718, Encodable2718};
+    use alloy_primitives::{Address, Signature, TxKind, B256};
+    use op_alloy_consensus::OpTxEnvelope;
+    use std::io::Write;
     use std::str::FromStr;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
 
     fn create_first_payload() -> FlashblocksPayloadV1 {
         // First payload (index 0) setup remains the same
@@ -608,7 +1373,7 @@ mod tests {
         // Create second payload (index 1) with transactions
         // tx1 hash: 0x3cbbc9a6811ac5b2a2e5780bdb67baffc04246a59f39e398be048f1b2d05460c
         // tx2 hash: 0xa6155b295085d3b87a3c86e342fe11c3b22f9952d0d85d9d34d223b7d6a17cd8
-        let tx1 = Bytes::from_str("0x02f87483014a3482017e8459682f0084596830a98301f1d094b01866f195533de16eb929b73f87280693ca0cb480844e71d92dc001a0a658c18bdba29dd4022ee6640fdd143691230c12b3c8c86cf5c1a1f1682cc1e2a0248a28763541ebed2b87ecea63a7024b5c2b7de58539fa64c887b08f5faf29c1").unwrap();
+        let tx1 = Bytes::from_str(EIP1559_TX).unwrap();
         let tx2 = Bytes::from_str("0xf8cd82016d8316e5708302c01c94f39635f2adf40608255779ff742afe13de31f57780b8646e530e9700000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000001bc16d674ec8000000000000000000000000000000000000000000000000000156ddc81eed2a36d68302948ba0a608703e79b22164f74523d188a11f81c25a65dd59535bab1cd1d8b30d115f3ea07f4cfbbad77a139c9209d3bded89091867ff6b548dd714109c61d1f8e7a84d14").unwrap();
 
         let delta2 = ExecutionPayloadFlashblockDeltaV1 {
@@ -668,122 +1433,529 @@ mod tests {
     #[test]
     fn test_process_payload() {
         let cache = Arc::new(Cache::default());
+        let pending = PendingViewStore::default();
 
         let payload = create_first_payload();
 
         // Process first payload
-        process_payload(payload, cache.clone());
+        process_payload(payload, cache.clone(), &pending, Instant::now());
+        let first_view = pending.load().unwrap();
 
         let payload2 = create_second_payload();
         // Process second payload
-        process_payload(payload2, cache.clone());
+        process_payload(payload2, cache.clone(), &pending, Instant::now());
 
         // Verify final state
-        let final_block = cache.get::<OpBlock>(&CacheKey::PendingBlock).unwrap();
+        let view = pending.load().unwrap();
+        assert_eq!(view.generation, first_view.generation + 1);
+        assert_eq!(view.flashblock_index, 1);
+        let final_block = &view.block;
         assert_eq!(final_block.body.transactions.len(), 2);
         assert_eq!(final_block.header.state_root, B256::repeat_byte(0x1));
         assert_eq!(final_block.header.receipts_root, B256::repeat_byte(0x2));
         assert_eq!(final_block.header.gas_used, 21000);
 
+        // the block hash follows the latest flashblock
+        assert_eq!(first_view.block_hash, B256::ZERO);
+        assert_eq!(view.block_hash, B256::repeat_byte(0x3));
+
         // Verify account balance was updated
-        let balance = cache
-            .get::<String>(&CacheKey::AccountBalance(
-                Address::from_str("0x1234567890123456789012345678901234567890").unwrap(),
-            ))
+        let balance = view
+            .balance(Address::from_str("0x1234567890123456789012345678901234567890").unwrap())
             .unwrap();
-        assert_eq!(balance, "0x1234");
+        assert_eq!(balance, U256::from(0x1234));
+
+        // Verify the builder identity was recorded from the base
+        let builder = cache.get::<Address>(&CacheKey::BlockBuilder(1)).unwrap();
+        assert_eq!(
+            builder,
+            Address::from_str("0x1234567890123456789012345678901234567890").unwrap()
+        );
 
-        let tx1_receipt = cache
-            .get::<OpReceipt>(&CacheKey::Receipt(
+        let tx1 = view
+            .transaction(
                 B256::from_str(
                     "0x3cbbc9a6811ac5b2a2e5780bdb67baffc04246a59f39e398be048f1b2d05460c",
                 )
                 .unwrap(),
-            ))
+            )
             .unwrap();
-        assert_eq!(tx1_receipt.cumulative_gas_used(), 21000);
+        assert_eq!(tx1.receipt().unwrap().cumulative_gas_used(), 21000);
 
-        let tx2_receipt = cache
-            .get::<OpReceipt>(&CacheKey::Receipt(
+        let tx2 = view
+            .transaction(
                 B256::from_str(
                     "0xa6155b295085d3b87a3c86e342fe11c3b22f9952d0d85d9d34d223b7d6a17cd8",
                 )
                 .unwrap(),
-            ))
+            )
             .unwrap();
-        assert_eq!(tx2_receipt.cumulative_gas_used(), 42000);
+        assert_eq!(tx2.receipt().unwrap().cumulative_gas_used(), 42000);
 
-        // verify tx_sender, tx_block_number, tx_idx
-        let tx_sender = cache
-            .get::<Address>(&CacheKey::TransactionSender(
-                B256::from_str(
-                    "0x3cbbc9a6811ac5b2a2e5780bdb67baffc04246a59f39e398be048f1b2d05460c",
-                )
-                .unwrap(),
-            ))
-            .unwrap();
+        // receipts record the flashblock they were preconfirmed in
+        assert_eq!(tx1.preconfirmation().unwrap().index, 1);
+        assert_eq!(tx2.preconfirmation(), tx1.preconfirmation());
+
+        // verify senders, indices and per sender transaction counts
+        let tx_sender = tx1.sender();
         assert_eq!(
             tx_sender,
             Address::from_str("0xb63d5fd2e6c53fe06680c47736aba771211105e4").unwrap()
         );
+        assert_eq!(tx1.index, 0);
+        assert_eq!(tx1.recovered().signer(), tx_sender);
 
-        let tx_block_number = cache
-            .get::<u64>(&CacheKey::TransactionBlockNumber(
-                B256::from_str(
-                    "0x3cbbc9a6811ac5b2a2e5780bdb67baffc04246a59f39e398be048f1b2d05460c",
-                )
-                .unwrap(),
-            ))
-            .unwrap();
-        assert_eq!(tx_block_number, 1);
-
-        let tx_idx = cache
-            .get::<u64>(&CacheKey::TransactionIndex(
-                B256::from_str(
-                    "0x3cbbc9a6811ac5b2a2e5780bdb67baffc04246a59f39e398be048f1b2d05460c",
-                )
-                .unwrap(),
-            ))
-            .unwrap();
-        assert_eq!(tx_idx, 0);
-
-        let tx_sender2 = cache
-            .get::<Address>(&CacheKey::TransactionSender(
-                B256::from_str(
-                    "0xa6155b295085d3b87a3c86e342fe11c3b22f9952d0d85d9d34d223b7d6a17cd8",
-                )
-                .unwrap(),
-            ))
-            .unwrap();
+        let tx_sender2 = tx2.sender();
         assert_eq!(
             tx_sender2,
             Address::from_str("0x6e5e56b972374e4fde8390df0033397df931a49d").unwrap()
         );
+        assert_eq!(tx2.index, 1);
 
-        let tx_block_number2 = cache
-            .get::<u64>(&CacheKey::TransactionBlockNumber(
-                B256::from_str(
-                    "0xa6155b295085d3b87a3c86e342fe11c3b22f9952d0d85d9d34d223b7d6a17cd8",
-                )
-                .unwrap(),
-            ))
+        assert_eq!(view.transaction_count(tx_sender), 1);
+        assert_eq!(view.transaction_count(tx_sender2), 1);
+        assert_eq!(view.next_nonce(tx_sender), Some(383));
+
+        // verify the account changes recorded for the second flashblock
+        let changes = cache
+            .get::<FlashblockAccountChanges>(&CacheKey::AccountChanges {
+                block_number: 1,
+                index: 1,
+            })
             .unwrap();
-        assert_eq!(tx_block_number2, 1);
+        assert_eq!(
+            changes
+                .balances
+                .get(&Address::from_str("0x1234567890123456789012345678901234567890").unwrap()),
+            Some(&U256::from(0x1234))
+        );
+        assert_eq!(changes.nonce_increments.get(&tx_sender), Some(&1));
+        assert_eq!(changes.nonce_increments.get(&tx_sender2), Some(&1));
+
+        // every flashblock is recorded in the audit of the block
+        assert_eq!(view.audit.len(), 2);
+        assert_eq!(view.audit[0], first_view.audit[0]);
+        let audit = &view.audit[1];
+        assert_eq!(audit.index, 1);
+        assert_eq!(
+            audit.transactions,
+            vec![tx1.transaction().tx_hash(), tx2.transaction().tx_hash()]
+        );
+        assert_eq!(audit.metadata_receipts, audit.transactions);
+        assert!(audit.missing_receipts.is_empty());
+        assert!(audit.checks.iter().all(|check| check.passed));
+
+        // the payload id maps back to the block
+        assert_eq!(view.payload_id, Some(PayloadId::new([0; 8])));
+        assert_eq!(
+            pending
+                .payload(PayloadId::new([0; 8]))
+                .unwrap()
+                .block_number,
+            1
+        );
+    }
+
+    #[test]
+    fn test_new_block_keeps_previous_view() {
+        let cache = Arc::new(Cache::default());
+        let pending = PendingViewStore::default();
 
-        let tx_idx2 = cache
-            .get::<u64>(&CacheKey::TransactionIndex(
+        process_payload(
+            create_first_payload(),
+            cache.clone(),
+            &pending,
+            Instant::now(),
+        );
+        process_payload(
+            create_second_payload(),
+            cache.clone(),
+            &pending,
+            Instant::now(),
+        );
+        process_payload(
+            create_payload_with_index(0, 2),
+            cache.clone(),
+            &pending,
+            Instant::now(),
+        );
+
+        let view = pending.load().unwrap();
+        assert_eq!(view.block_number(), 2);
+        assert!(view.block.body.transactions.is_empty());
+
+        // transactions of the previous block are still found until it is imported
+        let blocks = pending.load_blocks();
+        let tx = blocks
+            .transaction(
                 B256::from_str(
                     "0xa6155b295085d3b87a3c86e342fe11c3b22f9952d0d85d9d34d223b7d6a17cd8",
                 )
                 .unwrap(),
-            ))
+            )
             .unwrap();
-        assert_eq!(tx_idx2, 1);
+        assert_eq!(tx.view.block_number(), 1);
+        assert_eq!(tx.receipt().unwrap().cumulative_gas_used(), 42000);
+    }
+
+    #[test]
+    fn test_interleaved_blocks() {
+        let cache = Arc::new(Cache::default());
+        let pending = PendingViewStore::default();
+
+        // block 2 starts building before the last flashblock of block 1 arrives
+        process_payload(
+            create_first_payload(),
+            cache.clone(),
+            &pending,
+            Instant::now(),
+        );
+        process_payload(
+            create_payload_with_index(0, 2),
+            cache.clone(),
+            &pending,
+            Instant::now(),
+        );
+        process_payload(
+            create_second_payload(),
+            cache.clone(),
+            &pending,
+            Instant::now(),
+        );
+
+        assert_eq!(pending.load().unwrap().block_number(), 2);
+
+        let blocks = pending.load_blocks();
+        let view = blocks.for_block(1).unwrap();
+        assert_eq!(view.flashblock_index, 1);
+        assert_eq!(view.block.body.transactions.len(), 2);
+        assert_eq!(blocks.block_numbers().collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_ingest_lag_measured_from_frame_receipt() {
+        let cache = Arc::new(Cache::default());
+        let pending = PendingViewStore::default();
+
+        let received_at = Instant::now() - std::time::Duration::from_millis(50);
+        process_payload(create_first_payload(), cache.clone(), &pending, received_at);
+
+        let view = pending.load().unwrap();
+        assert_eq!(view.received_at, received_at);
+        assert!(view.ingest_lag() >= std::time::Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_superseded_payloads() {
+        let superseded = |payloads: &[(u64, u64)], highest| {
+            superseded_payloads(payloads.iter().copied(), highest)
+        };
+
+        // flashblocks of a block build on each other
+        assert_eq!(
+            superseded(&[(1, 0), (1, 1), (1, 2)], 1),
+            vec![false, false, false]
+        );
+        // a block that started over drops the flashblocks before it
+        assert_eq!(
+            superseded(&[(1, 1), (1, 2), (2, 0), (1, 0), (1, 1)], 2),
+            vec![true, true, false, false, false]
+        );
+        // heights that fall out of the retained range are dropped, even when the height ahead
+        // went to another worker
+        assert_eq!(
+            superseded(&[(1, 1), (2, 1)], 1 + RETAINED_BLOCKS),
+            vec![true, false]
+        );
+    }
+
+    #[test]
+    fn test_dispatcher_routes_heights_in_order() {
+        let (workers, mut mailboxes): (Vec<_>, Vec<_>) = (0..2).map(|_| mpsc::channel(4)).unzip();
+        let highest = Arc::new(AtomicU64::new(0));
+        let mut dispatcher = PayloadDispatcher::new(workers, highest.clone());
+        let mut dispatch = |block_number, index| {
+            let payload = create_payload_with_index(index, block_number);
+            let metadata = serde_json::from_value(payload.metadata.clone()).unwrap();
+            dispatcher.dispatch(payload, metadata, Bytes::new(), Instant::now());
+        };
+        // flashblocks as (block number, index), completions without an index
+        let received = |mailbox: &mut mpsc::Receiver<ActorMessage>| {
+            std::iter::from_fn(|| mailbox.try_recv().ok())
+                .map(|message| match message {
+                    ActorMessage::BestPayload {
+                        payload, metadata, ..
+                    } => (metadata.block_number, Some(payload.index)),
+                    ActorMessage::Complete { block_number } => (block_number, None),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // the flashblocks of a height go to the same worker in order, followed by its
+        // completion once the next height starts
+        dispatch(1, 0);
+        dispatch(1, 1);
+        dispatch(2, 0);
+        assert_eq!(
+            received(&mut mailboxes[1]),
+            vec![(1, Some(0)), (1, Some(1)), (1, None)]
+        );
+        assert_eq!(received(&mut mailboxes[0]), vec![(2, Some(0))]);
+        // a height starting over doesn't complete it
+        dispatch(2, 0);
+        assert_eq!(received(&mut mailboxes[0]), vec![(2, Some(0))]);
+
+        // a full worker drops the rest of the height without holding up the other heights
+        for index in 0..6 {
+            dispatch(3, index);
+        }
+        assert_eq!(
+            received(&mut mailboxes[1]),
+            vec![(3, Some(0)), (3, Some(1)), (3, Some(2)), (3, Some(3))]
+        );
+        dispatch(3, 6);
+        assert!(received(&mut mailboxes[1]).is_empty());
+        dispatch(4, 0);
+        assert_eq!(received(&mut mailboxes[0]), vec![(2, None), (4, Some(0))]);
+        assert_eq!(received(&mut mailboxes[1]), vec![(3, None)]);
+
+        // until the height starts over
+        dispatch(3, 0);
+        assert_eq!(received(&mut mailboxes[1]), vec![(3, Some(0))]);
+        // the workers skip what the highest height drops, whichever worker it went to
+        assert_eq!(highest.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn test_frame_prefix() {
+        assert_eq!(frame_prefix(Message::text("ab")), "0x6162");
+        let long = frame_prefix(Message::binary(vec![0u8; 100]));
+        assert!(long.ends_with("... (100 bytes)"));
+        assert_eq!(long.len(), 2 + 2 * UNEXPECTED_FRAME_LOG_BYTES + 15);
+    }
+
+    fn signed_transaction(to: TxKind, nonce: u64) -> OpTransactionSigned {
+        let tx = TxEip1559 {
+            chain_id: 8453,
+            nonce,
+            to,
+            gas_limit: 100_000,
+            ..Default::default()
+        };
+        let envelope = OpTxEnvelope::Eip1559(tx.into_signed(Signature::test_signature()));
+        OpTransactionSigned::decode_2718(&mut envelope.encoded_2718().as_slice()).unwrap()
+    }
+
+    #[test]
+    fn test_extends_parent() {
+        let payload_id = PayloadId::new([1; 8]);
+        let metadata = Metadata {
+            block_number: 1,
+            receipts: HashMap::default(),
+            new_account_balances: HashMap::default(),
+        };
+        let build = |nonces: &[u64], index: u64, parent: Option<&PendingView>, id: PayloadId| {
+            let mut block = OpBlock::default();
+            block.header.number = 1;
+            block.body.transactions = nonces
+                .iter()
+                .map(|nonce| signed_transaction(TxKind::Call(Address::ZERO), *nonce))
+                .collect();
+            let preconfirmation = PreconfirmationInfo {
+                index,
+                received_at: 0,
+            };
+            build_pending_view(
+                block,
+                preconfirmation,
+                parent,
+                id,
+                &HashMap::default(),
+                &metadata,
+            )
+            .unwrap()
+        };
+        let extends_parent = |view: &PendingView| {
+            let checks = &view.audit.last().unwrap().checks;
+            checks
+                .iter()
+                .any(|check| check.name == "extends-parent" && check.passed)
+        };
+
+        let parent = build(&[0], 0, None, payload_id);
+        let view = build(&[0, 1], 1, Some(&parent), payload_id);
+        assert!(extends_parent(&view));
+        assert_eq!(view.audit.len(), 2);
+        assert_eq!(view.next_nonce(view.senders[0]), Some(2));
+        // the parent's transactions are found through its index
+        for (index, transaction) in view.block.body.transactions.iter().enumerate() {
+            assert_eq!(
+                view.transaction(transaction.tx_hash()).unwrap().index,
+                index
+            );
+        }
+
+        // the block started over under another payload
+        let restarted = build(&[0, 1], 1, Some(&parent), PayloadId::new([2; 8]));
+        assert!(!extends_parent(&restarted));
+        assert_eq!(restarted.audit.len(), 1);
+
+        // or with other transactions, nothing derived from the parent is kept
+        let replaced = build(&[5, 1], 1, Some(&parent), payload_id);
+        assert!(!extends_parent(&replaced));
+        assert_eq!(replaced.senders.len(), 2);
+        assert_eq!(replaced.transaction_count(replaced.senders[0]), 2);
+    }
+
+    #[test]
+    fn test_extend_block() {
+        let base = create_first_payload().base.unwrap();
+        let mut diff = create_second_payload().diff;
+        let all = diff.transactions.clone();
+
+        // the first transaction came with an earlier flashblock
+        let earlier = diff.transactions.remove(0);
+        let known = [OpTransactionSigned::decode_2718(&mut earlier.as_ref()).unwrap()];
+        let extended = extend_block(&base, &diff, &known, &BASE_MAINNET).unwrap();
+        assert_eq!(
+            extended,
+            assemble_block(&base, &diff, all, &BASE_MAINNET).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_deployed_contract() {
+        let sender = Address::repeat_byte(0x1);
+        let transaction = |to: TxKind| signed_transaction(to, 3);
+        let receipt = |status: bool| {
+            OpReceipt::Eip1559(Receipt {
+                status: status.into(),
+                cumulative_gas_used: 50_000,
+                logs: vec![],
+            })
+        };
+
+        let create = transaction(TxKind::Create);
+        assert_eq!(
+            deployed_contract(&create, sender, &receipt(true)),
+            Some(sender.create(3))
+        );
+        // a reverted creation deploys nothing
+        assert_eq!(deployed_contract(&create, sender, &receipt(false)), None);
+        let call = transaction(TxKind::Call(Address::repeat_byte(0x2)));
+        assert_eq!(deployed_contract(&call, sender, &receipt(true)), None);
+    }
+
+    #[test]
+    fn test_decode_flashblock() {
+        let payload = create_second_payload();
+        let json = serde_json::to_vec(&payload).unwrap();
+
+        let (decoded, metadata) = decode_flashblock(&try_parse_message(&json).unwrap()).unwrap();
+        let expected: Metadata = serde_json::from_value(payload.metadata).unwrap();
+        assert_eq!(decoded.index, payload.index);
+        assert_eq!(decoded.diff.transactions, payload.diff.transactions);
+        assert_eq!(decoded.diff.block_hash, payload.diff.block_hash);
+        assert!(decoded.metadata.is_null());
+        assert_eq!(metadata.block_number, expected.block_number);
+        assert_eq!(metadata.receipts, expected.receipts);
+        assert_eq!(metadata.new_account_balances, expected.new_account_balances);
+    }
+
+    #[test]
+    fn test_applied_flashblocks_are_rebroadcast() {
+        let cache = Arc::new(Cache::default());
+        let pending = PendingViewStore::default();
+        let mut flashblocks = pending.flashblocks().subscribe();
+
+        let payload = create_first_payload();
+        process_payload(payload.clone(), cache.clone(), &pending, Instant::now());
+        // skipped, the block it extends was never started
+        process_payload(
+            create_payload_with_index(1, 5),
+            cache,
+            &pending,
+            Instant::now(),
+        );
+
+        let notification = flashblocks.try_recv().unwrap();
+        let rebroadcast: FlashblocksPayloadV1 =
+            serde_json::from_str(notification.payload().get()).unwrap();
+        assert_eq!(rebroadcast.index, payload.index);
+        assert_eq!(rebroadcast.metadata["block_number"], 1);
+        assert!(flashblocks.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_rebroadcast_frame_as_received() {
+        let pending = PendingViewStore::default();
+        let mut flashblocks = pending.flashblocks().subscribe();
+        // fields this node doesn't know of are passed on
+        let json = r#"{"payload_id":"0x0000000000000000","index":3,"unknown":{"a":1}}"#;
+        let mut frame = Vec::new();
+        {
+            let mut compressor = brotli::CompressorWriter::new(&mut frame, 4096, 5, 22);
+            compressor.write_all(json.as_bytes()).unwrap();
+        }
+
+        assert_eq!(publish_flashblock(&pending, &frame).unwrap(), 1);
+        assert_eq!(flashblocks.try_recv().unwrap().payload().get(), json);
+        assert_eq!(publish_flashblock(&pending, json.as_bytes()).unwrap(), 1);
+        assert_eq!(flashblocks.try_recv().unwrap().payload().get(), json);
+    }
+
+    #[test]
+    fn test_decode_frame() {
+        let payload = create_second_payload();
+        let mut frame = Vec::new();
+        {
+            let mut compressor = brotli::CompressorWriter::new(&mut frame, 4096, 5, 22);
+            compressor
+                .write_all(&serde_json::to_vec(&payload).unwrap())
+                .unwrap();
+        }
+
+        let decoded = decode_frame(&frame).unwrap();
+        assert_eq!(decoded.index, payload.index);
+        assert_eq!(decoded.diff.block_hash, payload.diff.block_hash);
+        assert_eq!(decoded.metadata, payload.metadata);
+        assert!(decode_frame(b"not a frame").is_err());
+    }
+
+    #[test]
+    fn test_parse_receipts_mixed_case() {
+        let receipt = OpReceipt::Legacy(Receipt {
+            status: true.into(),
+            cumulative_gas_used: 21000,
+            logs: vec![],
+        });
+        let mut receipts = HashMap::default();
+        receipts.insert(
+            "0x3CBBC9A6811AC5B2A2E5780BDB67BAFFC04246A59F39E398BE048F1B2D05460C".to_string(),
+            receipt.clone(),
+        );
+        receipts.insert(
+            "a6155B295085d3b87a3c86e342fe11c3b22f9952d0d85d9d34d223b7d6a17cd8".to_string(),
+            receipt.clone(),
+        );
+        receipts.insert("not a hash".to_string(), receipt);
+
+        let parsed = parse_receipts(&receipts);
+        assert_eq!(parsed.len(), 2);
+        assert!(parsed.contains_key(
+            &TxHash::from_str("0x3cbbc9a6811ac5b2a2e5780bdb67baffc04246a59f39e398be048f1b2d05460c")
+                .unwrap()
+        ));
+        assert!(parsed.contains_key(
+            &TxHash::from_str("0xa6155b295085d3b87a3c86e342fe11c3b22f9952d0d85d9d34d223b7d6a17cd8")
+                .unwrap()
+        ));
     }
 
     #[test]
     fn test_skip_initial_non_zero_index_payload() {
         let cache = Arc::new(Cache::default());
+        let pending = PendingViewStore::default();
 
         let metadata = Metadata {
             block_number: 1,
@@ -800,81 +1972,230 @@ mod tests {
         };
 
         // Process payload
-        process_payload(payload, cache.clone());
+        process_payload(payload, cache.clone(), &pending, Instant::now());
 
         // Verify no block was stored, since it skips the first payload
-        assert!(cache.get::<OpBlock>(&CacheKey::PendingBlock).is_none());
+        assert!(pending.load().is_none());
     }
 
     #[test]
     fn test_flash_block_tracking() {
         // Create cache
         let cache = Arc::new(Cache::default());
+        let pending = PendingViewStore::default();
 
         // Process first block with 3 flash blocks
         // Block 1, payload 0 (starts a new block)
         let payload1_0 = create_payload_with_index(0, 1);
-        process_payload(payload1_0, cache.clone());
+        process_payload(payload1_0, cache.clone(), &pending, Instant::now());
 
         // Check that highest_payload_index was set to 0
-        let highest = cache.get::<u64>(&CacheKey::HighestPayloadIndex).unwrap();
+        let highest = cache.get::<u64>(&CacheKey::HighestPayloadIndex(1)).unwrap();
         assert_eq!(highest, 0);
 
         // Block 1, payload 1
         let payload1_1 = create_payload_with_index(1, 1);
-        process_payload(payload1_1, cache.clone());
+        process_payload(payload1_1, cache.clone(), &pending, Instant::now());
 
         // Check that highest_payload_index was updated
-        let highest = cache.get::<u64>(&CacheKey::HighestPayloadIndex).unwrap();
+        let highest = cache.get::<u64>(&CacheKey::HighestPayloadIndex(1)).unwrap();
         assert_eq!(highest, 1);
 
         // Block 1, payload 2
         let payload1_2 = create_payload_with_index(2, 1);
-        process_payload(payload1_2, cache.clone());
+        process_payload(payload1_2, cache.clone(), &pending, Instant::now());
 
         // Check that highest_payload_index was updated
-        let highest = cache.get::<u64>(&CacheKey::HighestPayloadIndex).unwrap();
+        let highest = cache.get::<u64>(&CacheKey::HighestPayloadIndex(1)).unwrap();
         assert_eq!(highest, 2);
 
         // Now start a new block (block 2, payload 0)
         let payload2_0 = create_payload_with_index(0, 2);
-        process_payload(payload2_0, cache.clone());
+        process_payload(payload2_0, cache.clone(), &pending, Instant::now());
 
-        // Check that highest_payload_index was reset to 0
-        let highest = cache.get::<u64>(&CacheKey::HighestPayloadIndex).unwrap();
+        // Check that block 2 starts at 0 and block 1 keeps its own record
+        let highest = cache.get::<u64>(&CacheKey::HighestPayloadIndex(2)).unwrap();
         assert_eq!(highest, 0);
+        let highest = cache.get::<u64>(&CacheKey::HighestPayloadIndex(1)).unwrap();
+        assert_eq!(highest, 2);
 
         // Block 2, payload 1 (out of order with payload 3)
         let payload2_1 = create_payload_with_index(1, 2);
-        process_payload(payload2_1, cache.clone());
+        process_payload(payload2_1, cache.clone(), &pending, Instant::now());
 
         // Check that highest_payload_index was updated
-        let highest = cache.get::<u64>(&CacheKey::HighestPayloadIndex).unwrap();
+        let highest = cache.get::<u64>(&CacheKey::HighestPayloadIndex(2)).unwrap();
         assert_eq!(highest, 1);
 
         // Block 2, payload 3 (skipping 2)
         let payload2_3 = create_payload_with_index(3, 2);
-        process_payload(payload2_3, cache.clone());
+        process_payload(payload2_3, cache.clone(), &pending, Instant::now());
 
         // Check that highest_payload_index was updated
-        let highest = cache.get::<u64>(&CacheKey::HighestPayloadIndex).unwrap();
+        let highest = cache.get::<u64>(&CacheKey::HighestPayloadIndex(2)).unwrap();
         assert_eq!(highest, 3);
 
         // Block 2, payload 2 (out of order, should not change highest)
         let payload2_2 = create_payload_with_index(2, 2);
-        process_payload(payload2_2, cache.clone());
+        process_payload(payload2_2, cache.clone(), &pending, Instant::now());
 
         // Check that highest_payload_index is still 3
-        let highest = cache.get::<u64>(&CacheKey::HighestPayloadIndex).unwrap();
+        let highest = cache.get::<u64>(&CacheKey::HighestPayloadIndex(2)).unwrap();
         assert_eq!(highest, 3);
 
         // Start block 3, payload 0
         let payload3_0 = create_payload_with_index(0, 3);
-        process_payload(payload3_0, cache.clone());
+        process_payload(payload3_0, cache.clone(), &pending, Instant::now());
 
-        // Check that highest_payload_index was reset to 0
+        // Check that block 3 starts at 0
         // Also verify metric would have been recorded (though we can't directly check the metric's value)
-        let highest = cache.get::<u64>(&CacheKey::HighestPayloadIndex).unwrap();
+        let highest = cache.get::<u64>(&CacheKey::HighestPayloadIndex(3)).unwrap();
         assert_eq!(highest, 0);
     }
+
+    #[test]
+    fn test_hardfork_fields_follow_timestamp() {
+        let block_at = |timestamp| {
+            let mut block = OpBlock::default();
+            block.header.timestamp = timestamp;
+            block.header.withdrawals_root = Some(B256::ZERO);
+            block.header.blob_gas_used = Some(0);
+            block.header.excess_blob_gas = Some(0);
+            block.body.withdrawals = Some(Default::default());
+            apply_hardfork_fields(&mut block, &BASE_MAINNET, B256::repeat_byte(1), B256::ZERO);
+            block
+        };
+
+        // before canyon
+        let block = block_at(1700000000);
+        assert_eq!(block.header.withdrawals_root, None);
+        assert_eq!(block.body.withdrawals, None);
+        assert_eq!(block.header.blob_gas_used, None);
+        assert_eq!(block.header.parent_beacon_block_root, None);
+
+        // canyon but not ecotone yet
+        let block = block_at(1705000000);
+        assert_eq!(block.header.withdrawals_root, Some(B256::ZERO));
+        assert_eq!(block.header.blob_gas_used, None);
+        assert_eq!(block.header.parent_beacon_block_root, None);
+
+        // ecotone
+        let block = block_at(1710374401);
+        assert_eq!(block.header.blob_gas_used, Some(0));
+        assert_eq!(block.header.excess_blob_gas, Some(0));
+        assert_eq!(
+            block.header.parent_beacon_block_root,
+            Some(B256::repeat_byte(1))
+        );
+    }
+
+    /// Streams `payloads` over a local websocket to a client injecting `faults`, so they go
+    /// through the websocket loop, the dispatcher and a single payload worker in order.
+    async fn stream_with_faults(
+        payloads: Vec<FlashblocksPayloadV1>,
+        faults: FaultInjector,
+        cache: Arc<Cache>,
+        pending: Arc<PendingViewStore>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws_stream = accept_async(stream).await.unwrap();
+            for payload in payloads {
+                let frame = serde_json::to_vec(&payload).unwrap();
+                ws_stream.send(Message::binary(frame)).await.unwrap();
+            }
+            // keep the connection open so the client doesn't reconnect
+            std::future::pending::<()>().await;
+        });
+
+        let mut client = FlashblocksClient::new(cache, pending)
+            .with_payload_workers(1)
+            .with_fault_injector(Arc::new(faults));
+        client.init(format!("ws://{addr}")).unwrap();
+    }
+
+    /// Waits until the flashblocks streamed to the client were applied as far as `done`.
+    async fn wait_until(done: impl Fn() -> bool) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !done() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("flashblocks weren't applied in time");
+    }
+
+    #[tokio::test]
+    async fn test_dropped_frame_drops_the_block() {
+        let cache = Arc::new(Cache::default());
+        let pending = Arc::new(PendingViewStore::default());
+        let faults = FaultInjector::default().with_drop_every(3);
+
+        let payloads = vec![
+            create_payload_with_index(0, 1),
+            create_payload_with_index(1, 1),
+            // dropped
+            create_payload_with_index(2, 1),
+            create_payload_with_index(3, 1),
+            create_payload_with_index(4, 1),
+            create_payload_with_index(0, 2),
+        ];
+        stream_with_faults(payloads, faults, cache, pending.clone()).await;
+        wait_until(|| pending.latest_published(2).is_some()).await;
+
+        // flashblock 3 doesn't follow flashblock 1, so block 1 is dropped rather than served
+        // without the transactions of flashblock 2, and so are the flashblocks after it
+        assert!(pending.latest_published(1).is_none());
+        assert_eq!(pending.load().unwrap().block_number(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_block_is_skipped() {
+        let cache = Arc::new(Cache::default());
+        let pending = Arc::new(PendingViewStore::default());
+        let faults = FaultInjector::default().with_corrupt_every(3);
+
+        let payloads = vec![
+            create_payload_with_index(0, 1),
+            create_payload_with_index(1, 1),
+            // claims block 3, rejected as its base is for block 2
+            create_payload_with_index(0, 2),
+            // no base was accepted for block 2
+            create_payload_with_index(1, 2),
+            // the next block recovers
+            create_payload_with_index(0, 3),
+        ];
+        stream_with_faults(payloads, faults, cache.clone(), pending.clone()).await;
+        wait_until(|| pending.latest_published(3).is_some()).await;
+
+        assert_eq!(pending.latest_published(1).unwrap().flashblock_index, 1);
+        assert!(pending.latest_published(2).is_none());
+        assert!(cache
+            .get::<ExecutionPayloadBaseV1>(&CacheKey::Base(2))
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_slow_cache_writes() {
+        let faults = Arc::new(FaultInjector::default().with_write_delay(Duration::from_millis(5)));
+        let cache = Arc::new(Cache::default().with_fault_injector(faults));
+        let pending = Arc::new(PendingViewStore::default());
+
+        let started_at = Instant::now();
+        let payloads = vec![create_first_payload(), create_second_payload()];
+        stream_with_faults(payloads, FaultInjector::default(), cache, pending.clone()).await;
+        wait_until(|| {
+            pending
+                .load()
+                .is_some_and(|view| view.flashblock_index == 1)
+        })
+        .await;
+
+        // slow writes delay the pending state but don't lose any of it
+        assert!(started_at.elapsed() >= Duration::from_millis(10));
+        let view = pending.load().unwrap();
+        assert_eq!(view.block.body.transactions.len(), 2);
+    }
 }
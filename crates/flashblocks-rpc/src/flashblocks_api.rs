@@ -0,0 +1,961 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::filters::{Advance, PendingFilterKind};
+use crate::flashblocks::decode_frame;
+use crate::pending::{
+    GasProgress, PayloadRecord, PendingBlocks, PendingView, PendingViewStore, RawFrame,
+    SyncProgress,
+};
+use crate::pubsub::{
+    forward_to_sink, SlowSubscriberPolicy, SubscriberInfo, Subscribers, ViewSubscription,
+};
+use crate::reconciliation::ReconciliationHistory;
+use crate::rpc::{render_pending_block, render_pending_receipt};
+use crate::startup::{CheckStatus, StartupReport, CHAIN_MATCHES};
+use crate::submissions::{SubmissionState, SubmissionTracker};
+use crate::tags::PendingTagMode;
+use crate::upstream::{ConnectionStatus, UpstreamInfoStore};
+use crate::validation::ChainIdCheck;
+use alloy_consensus::Transaction;
+use alloy_primitives::{Address, Bytes, TxHash, B256, U256};
+use alloy_rpc_types_engine::PayloadId;
+use alloy_rpc_types_eth::state::StateOverride;
+use alloy_rpc_types_eth::PendingTransactionFilterKind;
+use jsonrpsee::{
+    core::{async_trait, RpcResult, SubscriptionResult},
+    proc_macros::rpc,
+    PendingSubscriptionSink, RpcModule, SubscriptionSink,
+};
+use op_alloy_network::Optimism;
+use reth::providers::{StateProviderFactory, TransactionsProvider};
+use reth::rpc::server_types::result::{internal_rpc_err, invalid_params_rpc_err};
+use reth_optimism_chainspec::OpChainSpec;
+use reth_rpc_eth_api::{RpcBlock, RpcReceipt};
+use rollup_boost::primitives::FlashblocksPayloadV1;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// Most addresses `flashblocks_getBalances` looks up in one call.
+const MAX_BALANCE_ADDRESSES: usize = 1_000;
+
+/// Most addresses one `flashblocks_subscribeAccount` subscription watches.
+const MAX_WATCHED_ADDRESSES: usize = 1_000;
+
+/// State of the flashblocks ingest and whether the node serves it, for operators and health
+/// checks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlashblocksStatus {
+    pub upstream: ConnectionStatus,
+    /// Heights with flashblocks in flight, in ascending order
+    pub block_numbers: Vec<u64>,
+    /// Furthest preconfirmed block and its highest flashblock index
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flashblock_index: Option<u64>,
+    /// Generation of the furthest preconfirmed block's view
+    pub generation: u64,
+    /// Time since the latest flashblock became visible
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age_millis: Option<u64>,
+    /// Whether `pending` requests are answered from the flashblocks rather than the node
+    pub serving: bool,
+}
+
+/// How the node is configured to serve the flashblocks, which tells whether `pending` requests
+/// are answered from them.
+#[derive(Debug, Clone, Copy)]
+pub struct ServingConfig {
+    /// Whether the `eth` overrides are mounted under `eth`, they aren't on mirrors
+    pub overrides: bool,
+    /// Whether the overrides only compare their answers with the node's
+    pub shadow_mode: bool,
+    pub pending_tag_mode: PendingTagMode,
+    pub chain_id_check: ChainIdCheck,
+}
+
+impl Default for ServingConfig {
+    fn default() -> Self {
+        Self {
+            overrides: true,
+            shadow_mode: false,
+            pending_tag_mode: PendingTagMode::default(),
+            chain_id_check: ChainIdCheck::default(),
+        }
+    }
+}
+
+impl ServingConfig {
+    /// Whether `pending` requests are answered from the flashblocks, given the outcome of the
+    /// chain id check and whether there is a preconfirmed block.
+    pub fn serving(&self, chain_matches: Option<CheckStatus>, preconfirmed: bool) -> bool {
+        let chain_rejected = self.chain_id_check == ChainIdCheck::Enforce
+            && chain_matches == Some(CheckStatus::Failed);
+        self.overrides
+            && !self.shadow_mode
+            && self.pending_tag_mode == PendingTagMode::Flashblocks
+            && !chain_rejected
+            && preconfirmed
+    }
+}
+
+/// The pending block with its receipts and the account changes of the flashblocks, all taken
+/// from the same flashblock.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingSnapshot {
+    pub flashblock_index: u64,
+    pub block: RpcBlock<Optimism>,
+    pub receipts: Vec<RpcReceipt<Optimism>>,
+    /// Balances and next nonces set by the in-flight blocks, by address
+    pub accounts: StateOverride,
+}
+
+impl PendingSnapshot {
+    /// Captures the furthest preconfirmed block of `blocks`, none without one.
+    pub fn capture(blocks: &PendingBlocks, full: bool, chain_spec: &OpChainSpec) -> Option<Self> {
+        let view = blocks.latest()?;
+        Some(Self {
+            flashblock_index: view.flashblock_index,
+            block: render_pending_block(view, full),
+            receipts: view
+                .transactions()
+                .filter_map(|tx| Some(render_pending_receipt(tx, tx.receipt()?, chain_spec)))
+                .collect(),
+            accounts: blocks.state_overrides(0),
+        })
+    }
+}
+
+/// Where a transaction is in its lifecycle, as far as this node can tell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum TransactionStatus {
+    /// Neither submitted through this node, preconfirmed nor canonical
+    Unknown,
+    /// Sent through this node's `eth_sendRawTransaction`, not preconfirmed yet
+    #[serde(rename_all = "camelCase")]
+    Submitted {
+        /// Whether the node forwarded the transaction to the sequencer
+        forwarded: bool,
+        submitted_at: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    Preconfirmed {
+        block_number: u64,
+        /// Flashblock the transaction was first preconfirmed in
+        #[serde(skip_serializing_if = "Option::is_none")]
+        flashblock_index: Option<u64>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Confirmed { block_number: u64 },
+    /// Preconfirmed, but left out of the canonical block
+    #[serde(rename_all = "camelCase")]
+    Dropped { block_number: u64 },
+}
+
+/// Summary of the furthest preconfirmed block, small enough to poll.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingSummary {
+    pub block_number: u64,
+    pub flashblock_index: u64,
+    /// Block hash reported by the builder for the latest flashblock
+    pub block_hash: B256,
+    /// Payload the builder sent the flashblocks under
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload_id: Option<PayloadId>,
+    pub transaction_count: u64,
+    pub gas_used: u64,
+    pub gas_limit: u64,
+    pub timestamp: u64,
+    /// Time since the latest flashblock became visible
+    pub age_millis: u64,
+}
+
+impl PendingSummary {
+    /// Summarizes `view`, published `age` ago.
+    pub fn from_view(view: &PendingView, age: Duration) -> Self {
+        Self {
+            block_number: view.block_number(),
+            flashblock_index: view.flashblock_index,
+            block_hash: view.block_hash,
+            payload_id: view.payload_id,
+            transaction_count: view.block.body.transactions.len() as u64,
+            gas_used: view.block.header.gas_used,
+            gas_limit: view.block.header.gas_limit,
+            timestamp: view.block.header.timestamp,
+            age_millis: age.as_millis() as u64,
+        }
+    }
+}
+
+/// Balances of `addresses` after the flashblocks in `blocks`, in the order requested. Accounts
+/// the flashblocks didn't touch are read from `canonical`.
+fn pending_balances(
+    blocks: &PendingBlocks,
+    addresses: &[Address],
+    mut canonical: impl FnMut(Address) -> RpcResult<U256>,
+) -> RpcResult<Vec<U256>> {
+    addresses
+        .iter()
+        .map(|address| match blocks.balance(*address) {
+            Some(balance) => Ok(balance),
+            None => canonical(*address),
+        })
+        .collect()
+}
+
+/// Status of a transaction that isn't canonical, from the in-flight blocks, the recent
+/// reconciliations and the transactions submitted through this node.
+fn non_canonical_status(
+    blocks: &PendingBlocks,
+    reconciliations: &ReconciliationHistory,
+    submissions: &SubmissionTracker,
+    tx_hash: TxHash,
+) -> TransactionStatus {
+    if let Some(tx) = blocks.transaction(tx_hash) {
+        return TransactionStatus::Preconfirmed {
+            block_number: tx.view.block_number(),
+            flashblock_index: tx
+                .preconfirmation()
+                .map(|preconfirmation| preconfirmation.index),
+        };
+    }
+    if let Some(block_number) = reconciliations.dropped_from(tx_hash) {
+        return TransactionStatus::Dropped { block_number };
+    }
+    match submissions.get(tx_hash) {
+        Some(submission) if submission.state == SubmissionState::Submitted => {
+            TransactionStatus::Submitted {
+                forwarded: submission.forwarded,
+                submitted_at: submission.submitted_at,
+            }
+        }
+        // the view that preconfirmed it expired before the canonical block arrived
+        Some(submission) => match submission.block_number {
+            Some(block_number) => TransactionStatus::Preconfirmed {
+                block_number,
+                flashblock_index: None,
+            },
+            None => TransactionStatus::Unknown,
+        },
+        None => TransactionStatus::Unknown,
+    }
+}
+
+/// A flashblock touching a watched account, pushed by `flashblocks_subscribeAccount`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountUpdate {
+    pub address: Address,
+    pub block_number: u64,
+    pub flashblock_index: u64,
+    /// Balance after the flashblock, when the flashblocks set it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance: Option<U256>,
+    /// Next nonce after the flashblock, when the account sent transactions in the block
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+    /// Transactions from or to the account preconfirmed by the flashblock
+    pub transactions: Vec<TxHash>,
+}
+
+/// Accounts watched by a subscription, with the balance and nonce last pushed for each.
+#[derive(Debug, Default)]
+struct AccountWatch {
+    accounts: BTreeMap<Address, (Option<U256>, Option<u64>)>,
+}
+
+impl AccountWatch {
+    fn new(addresses: BTreeSet<Address>) -> Self {
+        Self {
+            accounts: addresses
+                .into_iter()
+                .map(|address| (address, (None, None)))
+                .collect(),
+        }
+    }
+
+    /// Updates for the watched accounts whose balance or nonce changed in `view`, or that sent
+    /// or received one of its transactions from `position` on.
+    fn observe(&mut self, view: &PendingView, position: usize) -> Vec<AccountUpdate> {
+        let mut involved: HashMap<Address, Vec<TxHash>> = HashMap::new();
+        for tx in (position..view.block.body.transactions.len())
+            .filter_map(|index| view.transaction_at(index))
+        {
+            let tx_hash = tx.transaction().tx_hash();
+            let mut parties = BTreeSet::from([tx.sender()]);
+            parties.extend(tx.transaction().to());
+            for address in parties {
+                if self.accounts.contains_key(&address) {
+                    involved.entry(address).or_default().push(tx_hash);
+                }
+            }
+        }
+
+        let mut updates = Vec::new();
+        for (address, last) in self.accounts.iter_mut() {
+            let state = (view.balance(*address), view.next_nonce(*address));
+            let changed = state != (None, None) && *last != state;
+            let transactions = involved.remove(address).unwrap_or_default();
+            if !changed && transactions.is_empty() {
+                continue;
+            }
+            updates.push(AccountUpdate {
+                address: *address,
+                block_number: view.block_number(),
+                flashblock_index: view.flashblock_index,
+                balance: state.0,
+                nonce: state.1,
+                transactions,
+            });
+            *last = state;
+        }
+        updates
+    }
+}
+
+/// Pushes the updates of the watched accounts from every flashblock until the subscriber goes
+/// away.
+async fn forward_account_updates(
+    sink: SubscriptionSink,
+    mut watch: AccountWatch,
+    subscription: ViewSubscription,
+) {
+    let updates = |advance: Advance<'_>| {
+        advance
+            .views
+            .into_iter()
+            .flat_map(|(view, position)| watch.observe(view, position))
+            .collect::<Vec<_>>()
+    };
+    subscription.forward(sink, updates).await
+}
+
+/// Lays out `flashblocks` by index up to the highest one, with `None` for the missing indexes.
+fn with_gaps<T>(flashblocks: BTreeMap<u64, T>) -> Vec<Option<T>> {
+    let mut ordered = Vec::new();
+    for (index, flashblock) in flashblocks {
+        ordered.resize_with(index as usize, || None);
+        ordered.push(Some(flashblock));
+    }
+    ordered
+}
+
+/// Read only view of the flashblocks ingest. Unlike the `eth` overrides and the `base`
+/// namespace it doesn't touch the node's state, so it is safe to expose on mirror deployments.
+#[cfg_attr(not(test), rpc(server, namespace = "flashblocks"))]
+#[cfg_attr(test, rpc(server, client, namespace = "flashblocks"))]
+pub trait FlashblocksApi {
+    /// Returns the state of the upstream connection and the ingest, and whether `pending`
+    /// requests are being served from the flashblocks.
+    #[method(name = "getStatus")]
+    async fn get_status(&self) -> RpcResult<FlashblocksStatus>;
+
+    /// Returns a summary of the furthest preconfirmed block, or `null` without one.
+    #[method(name = "getLatest")]
+    async fn get_latest(&self) -> RpcResult<Option<PendingSummary>>;
+
+    /// Returns the block built by one of the recent payloads, to correlate with rollup-boost
+    /// and builder logs.
+    #[method(name = "getBlockByPayloadId")]
+    async fn get_block_by_payload_id(
+        &self,
+        payload_id: PayloadId,
+    ) -> RpcResult<Option<PayloadRecord>>;
+
+    /// Returns the websocket frame flashblock `index` of `block_number` was received in, exactly
+    /// as it was on the wire (brotli compressed frames stay compressed). Frames are kept for the
+    /// current and previous block.
+    #[method(name = "getRawPayload")]
+    async fn get_raw_payload(&self, block_number: u64, index: u64) -> RpcResult<Option<Bytes>>;
+
+    /// Returns the websocket frames kept in arrival order, with the flashblock each decoded to
+    /// or why it didn't decode. The frames of the current and previous block are kept, and the
+    /// last frames that didn't decode.
+    #[method(name = "getRawFrames")]
+    async fn get_raw_frames(&self) -> RpcResult<Vec<RawFrame>>;
+
+    /// Returns flashblock `index` of `block_number` with its metadata, decoded from the frame
+    /// it was received in. Flashblocks are kept for the current and previous block.
+    #[method(name = "getFlashblock")]
+    async fn get_flashblock(
+        &self,
+        block_number: u64,
+        index: u64,
+    ) -> RpcResult<Option<FlashblocksPayloadV1>>;
+
+    /// Returns the flashblocks received for `block_number`, each at its index, with `null` for
+    /// the indexes that never arrived. Empty once the block is no longer retained.
+    #[method(name = "getFlashblocksByBlockNumber")]
+    async fn get_flashblocks_by_block_number(
+        &self,
+        block_number: u64,
+    ) -> RpcResult<Vec<Option<FlashblocksPayloadV1>>>;
+
+    /// Returns whether the transaction is unknown, submitted through this node, preconfirmed,
+    /// confirmed or was dropped from the block it was preconfirmed in.
+    #[method(name = "getTransactionStatus")]
+    async fn get_transaction_status(&self, tx_hash: TxHash) -> RpcResult<TransactionStatus>;
+
+    /// Returns the pending block, its receipts and the balances and nonces changed by the
+    /// flashblocks, all from the same flashblock, or `null` without a pending block.
+    #[method(name = "getPendingSnapshot")]
+    async fn get_pending_snapshot(&self, full: bool) -> RpcResult<Option<PendingSnapshot>>;
+
+    /// Returns the pending balances of up to 1000 addresses, in the order requested, all read
+    /// from the same flashblock.
+    #[method(name = "getBalances")]
+    async fn get_balances(&self, addresses: Vec<Address>) -> RpcResult<Vec<U256>>;
+
+    /// Streams `{block, index, gasUsed, gasLimit}` after every flashblock, so block fullness
+    /// can be tracked without pulling the block. Subscribers that fall behind only receive the
+    /// latest progress.
+    #[subscription(
+        name = "subscribeGasProgress" => "gasProgress",
+        unsubscribe = "unsubscribeGasProgress",
+        item = GasProgress
+    )]
+    async fn subscribe_gas_progress(&self) -> SubscriptionResult;
+
+    /// Streams every flashblock this node applied as the json the sequencer's websocket sent,
+    /// so downstream services can follow this node instead of the sequencer. Subscribers that
+    /// fall behind are disconnected rather than silently missing flashblocks.
+    #[subscription(
+        name = "subscribe" => "flashblock",
+        unsubscribe = "unsubscribe",
+        item = FlashblocksPayloadV1
+    )]
+    async fn subscribe(&self) -> SubscriptionResult;
+
+    /// Returns how far the pending view got in catching up since the flashblocks websocket last
+    /// connected. `syncing` turns false once a block was followed from its first flashblock.
+    #[method(name = "syncing")]
+    async fn syncing(&self) -> RpcResult<SyncProgress>;
+
+    /// Streams the catch-up progress after every (re)connection of the flashblocks websocket,
+    /// until a notification with `syncing: false` tells the pending view is healthy again, so
+    /// orchestration knows when to route traffic back to the node.
+    #[subscription(
+        name = "subscribeSyncing" => "syncing",
+        unsubscribe = "unsubscribeSyncing",
+        item = SyncProgress
+    )]
+    async fn subscribe_syncing(&self) -> SubscriptionResult;
+
+    /// Streams an update whenever a flashblock changes the balance or nonce of one of
+    /// `addresses`, or preconfirms a transaction from or to one of them, so deposits can be
+    /// picked up without polling.
+    #[subscription(
+        name = "subscribeAccount" => "account",
+        unsubscribe = "unsubscribeAccount",
+        item = AccountUpdate
+    )]
+    async fn subscribe_account(&self, addresses: Vec<Address>) -> SubscriptionResult;
+
+    /// Returns the live subscriptions of this namespace with their queue depth, delivery lag
+    /// and drop counts, to find the slow consumers holding up the fan-out.
+    #[method(name = "getSubscribers")]
+    async fn get_subscribers(&self) -> RpcResult<Vec<SubscriberInfo>>;
+}
+
+#[derive(Debug)]
+pub struct FlashblocksApiExt<Provider> {
+    provider: Provider,
+    pending: Arc<PendingViewStore>,
+    chain_spec: Arc<OpChainSpec>,
+    subscribers: Arc<Subscribers>,
+    upstream_info: Arc<UpstreamInfoStore>,
+    reconciliations: Arc<ReconciliationHistory>,
+    submissions: Arc<SubmissionTracker>,
+    startup_report: Arc<StartupReport>,
+    serving: ServingConfig,
+}
+
+impl<Provider> FlashblocksApiExt<Provider> {
+    pub fn new(
+        provider: Provider,
+        pending: Arc<PendingViewStore>,
+        chain_spec: Arc<OpChainSpec>,
+    ) -> Self {
+        Self {
+            provider,
+            pending,
+            chain_spec,
+            subscribers: Arc::new(Subscribers::default()),
+            upstream_info: Arc::new(UpstreamInfoStore::default()),
+            reconciliations: Arc::new(ReconciliationHistory::default()),
+            submissions: Arc::new(SubmissionTracker::default()),
+            startup_report: Arc::new(StartupReport::default()),
+            serving: ServingConfig::default(),
+        }
+    }
+
+    /// Tell from the chain id check of `startup_report` whether the flashblocks are rejected.
+    pub fn with_startup_report(mut self, startup_report: Arc<StartupReport>) -> Self {
+        self.startup_report = startup_report;
+        self
+    }
+
+    /// Report whether `pending` requests are served from the flashblocks according to
+    /// `serving`.
+    pub fn with_serving(mut self, serving: ServingConfig) -> Self {
+        self.serving = serving;
+        self
+    }
+
+    /// Report the connection tracked by the flashblocks client the store is shared with.
+    pub fn with_upstream_info(mut self, upstream_info: Arc<UpstreamInfoStore>) -> Self {
+        self.upstream_info = upstream_info;
+        self
+    }
+
+    /// Tell dropped transactions from the reconciliations recorded in `reconciliations`.
+    pub fn with_reconciliations(mut self, reconciliations: Arc<ReconciliationHistory>) -> Self {
+        self.reconciliations = reconciliations;
+        self
+    }
+
+    /// Report the transactions submitted through the `eth` overrides sharing `submissions`.
+    pub fn with_submissions(mut self, submissions: Arc<SubmissionTracker>) -> Self {
+        self.submissions = submissions;
+        self
+    }
+}
+
+/// Methods completing the flashblocks with the node's state, which a mirror doesn't serve.
+const CANONICAL_STATE_METHODS: [&str; 1] = ["flashblocks_getBalances"];
+
+impl<Provider> FlashblocksApiExt<Provider>
+where
+    Provider: StateProviderFactory + TransactionsProvider + Send + Sync + 'static,
+{
+    /// The namespace as mounted on mirrors, without the methods reading the node's state.
+    pub fn into_mirror_module(self) -> RpcModule<Self> {
+        let mut module = self.into_rpc();
+        for method in CANONICAL_STATE_METHODS {
+            module.remove_method(method);
+        }
+        module
+    }
+}
+
+#[async_trait]
+impl<Provider> FlashblocksApiServer for FlashblocksApiExt<Provider>
+where
+    Provider: StateProviderFactory + TransactionsProvider + Send + Sync + 'static,
+{
+    async fn get_status(&self) -> RpcResult<FlashblocksStatus> {
+        debug!("get_status");
+        let blocks = self.pending.load_blocks();
+        let latest = blocks.latest();
+        let chain_matches = self.startup_report.status(CHAIN_MATCHES);
+        Ok(FlashblocksStatus {
+            upstream: self.upstream_info.connection(),
+            block_numbers: blocks.block_numbers().collect(),
+            block_number: latest.map(|view| view.block_number()),
+            flashblock_index: latest.map(|view| view.flashblock_index),
+            generation: latest.map(|view| view.generation).unwrap_or_default(),
+            age_millis: latest.map(|view| self.pending.age(view).as_millis() as u64),
+            serving: self.serving.serving(chain_matches, latest.is_some()),
+        })
+    }
+
+    async fn get_latest(&self) -> RpcResult<Option<PendingSummary>> {
+        debug!("get_latest");
+        Ok(self
+            .pending
+            .load_blocks()
+            .latest()
+            .map(|view| PendingSummary::from_view(view, self.pending.age(view))))
+    }
+
+    async fn get_block_by_payload_id(
+        &self,
+        payload_id: PayloadId,
+    ) -> RpcResult<Option<PayloadRecord>> {
+        debug!("get_block_by_payload_id: {:?}", payload_id);
+        Ok(self.pending.payload(payload_id))
+    }
+
+    async fn get_raw_payload(&self, block_number: u64, index: u64) -> RpcResult<Option<Bytes>> {
+        debug!("get_raw_payload: {} {}", block_number, index);
+        Ok(self.pending.raw_frame(block_number, index))
+    }
+
+    async fn get_raw_frames(&self) -> RpcResult<Vec<RawFrame>> {
+        debug!("get_raw_frames");
+        Ok(self.pending.recent_raw_frames())
+    }
+
+    async fn get_flashblock(
+        &self,
+        block_number: u64,
+        index: u64,
+    ) -> RpcResult<Option<FlashblocksPayloadV1>> {
+        debug!("get_flashblock: {} {}", block_number, index);
+        self.pending
+            .raw_frame(block_number, index)
+            .map(|frame| decode_frame(&frame))
+            .transpose()
+            .map_err(|e| internal_rpc_err(format!("failed to decode flashblock: {e}")))
+    }
+
+    async fn get_flashblocks_by_block_number(
+        &self,
+        block_number: u64,
+    ) -> RpcResult<Vec<Option<FlashblocksPayloadV1>>> {
+        debug!("get_flashblocks_by_block_number: {}", block_number);
+        let mut flashblocks = BTreeMap::new();
+        for (index, frame) in self.pending.raw_frames(block_number) {
+            let flashblock = decode_frame(&frame)
+                .map_err(|e| internal_rpc_err(format!("failed to decode flashblock: {e}")))?;
+            flashblocks.insert(index, flashblock);
+        }
+        Ok(with_gaps(flashblocks))
+    }
+
+    async fn get_pending_snapshot(&self, full: bool) -> RpcResult<Option<PendingSnapshot>> {
+        debug!("get_pending_snapshot: {}", full);
+        let blocks = self.pending.load_blocks();
+        Ok(PendingSnapshot::capture(&blocks, full, &self.chain_spec))
+    }
+
+    async fn get_transaction_status(&self, tx_hash: TxHash) -> RpcResult<TransactionStatus> {
+        debug!("get_transaction_status: {:?}", tx_hash);
+        let canonical = self
+            .provider
+            .transaction_by_hash_with_meta(tx_hash)
+            .map_err(|e| internal_rpc_err(e.to_string()))?;
+        if let Some((_, meta)) = canonical {
+            return Ok(TransactionStatus::Confirmed {
+                block_number: meta.block_number,
+            });
+        }
+        let blocks = self.pending.load_blocks();
+        Ok(non_canonical_status(
+            &blocks,
+            &self.reconciliations,
+            &self.submissions,
+            tx_hash,
+        ))
+    }
+
+    async fn get_balances(&self, addresses: Vec<Address>) -> RpcResult<Vec<U256>> {
+        debug!("get_balances: {}", addresses.len());
+        if addresses.len() > MAX_BALANCE_ADDRESSES {
+            return Err(invalid_params_rpc_err(format!(
+                "at most {MAX_BALANCE_ADDRESSES} addresses can be requested"
+            )));
+        }
+
+        let blocks = self.pending.load_blocks();
+        let state = self
+            .provider
+            .latest()
+            .map_err(|e| internal_rpc_err(e.to_string()))?;
+        pending_balances(&blocks, &addresses, |address| {
+            state
+                .account_balance(&address)
+                .map(Option::unwrap_or_default)
+                .map_err(|e| internal_rpc_err(e.to_string()))
+        })
+    }
+
+    async fn subscribe_gas_progress(
+        &self,
+        pending_sink: PendingSubscriptionSink,
+    ) -> SubscriptionResult {
+        debug!("subscribe_gas_progress");
+        let sink = pending_sink.accept().await?;
+        let (receiver, subscriber) = self.subscribers.subscribe(
+            self.pending.gas_progress(),
+            sink.connection_id().0 as u64,
+            "gasProgress",
+        );
+        tokio::spawn(forward_to_sink(
+            sink,
+            receiver,
+            SlowSubscriberPolicy::Coalesce,
+            subscriber,
+        ));
+        Ok(())
+    }
+
+    async fn subscribe(&self, pending_sink: PendingSubscriptionSink) -> SubscriptionResult {
+        debug!("subscribe");
+        let sink = pending_sink.accept().await?;
+        let (receiver, subscriber) = self.subscribers.subscribe(
+            self.pending.flashblocks(),
+            sink.connection_id().0 as u64,
+            "flashblock",
+        );
+        tokio::spawn(forward_to_sink(
+            sink,
+            receiver,
+            SlowSubscriberPolicy::Disconnect,
+            subscriber,
+        ));
+        Ok(())
+    }
+
+    async fn syncing(&self) -> RpcResult<SyncProgress> {
+        debug!("syncing");
+        Ok(self.pending.sync_progress())
+    }
+
+    async fn subscribe_syncing(&self, pending_sink: PendingSubscriptionSink) -> SubscriptionResult {
+        debug!("subscribe_syncing");
+        let sink = pending_sink.accept().await?;
+        let (receiver, subscriber) = self.subscribers.subscribe(
+            self.pending.sync_notifications(),
+            sink.connection_id().0 as u64,
+            "syncing",
+        );
+        tokio::spawn(forward_to_sink(
+            sink,
+            receiver,
+            SlowSubscriberPolicy::Coalesce,
+            subscriber,
+        ));
+        Ok(())
+    }
+
+    async fn subscribe_account(
+        &self,
+        pending_sink: PendingSubscriptionSink,
+        addresses: Vec<Address>,
+    ) -> SubscriptionResult {
+        debug!("subscribe_account: {}", addresses.len());
+        let addresses: BTreeSet<Address> = addresses.into_iter().collect();
+        if addresses.is_empty() || addresses.len() > MAX_WATCHED_ADDRESSES {
+            pending_sink
+                .reject(invalid_params_rpc_err(format!(
+                    "between 1 and {MAX_WATCHED_ADDRESSES} addresses can be watched"
+                )))
+                .await;
+            return Ok(());
+        }
+        let sink = pending_sink.accept().await?;
+        let watch = AccountWatch::new(addresses);
+        let subscription = ViewSubscription::new(
+            Arc::clone(&self.pending),
+            PendingFilterKind::Transactions(PendingTransactionFilterKind::Hashes),
+            SlowSubscriberPolicy::DropOldest,
+            &self.subscribers,
+            sink.connection_id().0 as u64,
+            "account",
+        );
+        tokio::spawn(forward_account_updates(sink, watch, subscription));
+        Ok(())
+    }
+
+    async fn get_subscribers(&self) -> RpcResult<Vec<SubscriberInfo>> {
+        debug!("get_subscribers");
+        Ok(self.subscribers.list())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_consensus::{SignableTransaction, TxEip1559};
+    use alloy_eips::eip2718::{Decodable2718, Encodable2718};
+    use alloy_primitives::{Sealed, Signature, TxKind};
+    use op_alloy_consensus::{OpTxEnvelope, TxDeposit};
+    use reth_optimism_chainspec::BASE_MAINNET;
+    use reth_optimism_primitives::{OpBlock, OpTransactionSigned};
+
+    #[test]
+    fn test_with_gaps() {
+        assert!(with_gaps(BTreeMap::<u64, u64>::new()).is_empty());
+        let flashblocks = BTreeMap::from([(0, 'a'), (1, 'b'), (4, 'e')]);
+        assert_eq!(
+            with_gaps(flashblocks),
+            vec![Some('a'), Some('b'), None, None, Some('e')]
+        );
+    }
+
+    #[test]
+    fn test_serving() {
+        let config = ServingConfig::default();
+        assert!(config.serving(Some(CheckStatus::Passed), true));
+        assert!(config.serving(Some(CheckStatus::Pending), true));
+        assert!(!config.serving(Some(CheckStatus::Passed), false));
+        assert!(!config.serving(Some(CheckStatus::Failed), true));
+
+        let warn = ServingConfig {
+            chain_id_check: ChainIdCheck::Warn,
+            ..config
+        };
+        assert!(warn.serving(Some(CheckStatus::Failed), true));
+        let shadow = ServingConfig {
+            shadow_mode: true,
+            ..config
+        };
+        assert!(!shadow.serving(Some(CheckStatus::Passed), true));
+        let node = ServingConfig {
+            pending_tag_mode: PendingTagMode::Node,
+            ..config
+        };
+        assert!(!node.serving(Some(CheckStatus::Passed), true));
+        let mirror = ServingConfig {
+            overrides: false,
+            ..config
+        };
+        assert!(!mirror.serving(Some(CheckStatus::Passed), true));
+    }
+
+    #[test]
+    fn test_pending_snapshot() {
+        let store = PendingViewStore::default();
+        assert!(PendingSnapshot::capture(&store.load_blocks(), false, &BASE_MAINNET).is_none());
+
+        let address = Address::repeat_byte(0x1);
+        let mut block = OpBlock::default();
+        block.header.number = 4;
+        let mut view = PendingView::new(block, 2, Vec::new());
+        view.balances.insert(address, U256::from(7));
+        store.publish(view);
+
+        let snapshot =
+            PendingSnapshot::capture(&store.load_blocks(), false, &BASE_MAINNET).unwrap();
+        assert_eq!(snapshot.block.header.number, 4);
+        assert_eq!(snapshot.flashblock_index, 2);
+        assert!(snapshot.receipts.is_empty());
+        assert_eq!(snapshot.accounts[&address].balance, Some(U256::from(7)));
+    }
+
+    #[test]
+    fn test_account_watch() {
+        let (sender, recipient, idle) = (
+            Address::repeat_byte(0x1),
+            Address::repeat_byte(0x2),
+            Address::repeat_byte(0x3),
+        );
+        let tx = TxEip1559 {
+            chain_id: 8453,
+            to: TxKind::Call(recipient),
+            gas_limit: 21000,
+            ..Default::default()
+        };
+        let envelope = OpTxEnvelope::Eip1559(tx.into_signed(Signature::test_signature()));
+        let tx = OpTransactionSigned::decode_2718(&mut envelope.encoded_2718().as_slice()).unwrap();
+        let tx_hash = tx.tx_hash();
+        let mut block = OpBlock::default();
+        block.header.number = 4;
+        block.body.transactions.push(tx);
+        let mut view = PendingView::new(block, 1, vec![sender]);
+        view.balances.insert(sender, U256::from(7));
+        view.nonces.insert(sender, BTreeSet::from([0]));
+
+        let mut watch = AccountWatch::new(BTreeSet::from([sender, recipient, idle]));
+        let updates = watch.observe(&view, 0);
+        assert_eq!(
+            updates,
+            vec![
+                AccountUpdate {
+                    address: sender,
+                    block_number: 4,
+                    flashblock_index: 1,
+                    balance: Some(U256::from(7)),
+                    nonce: Some(1),
+                    transactions: vec![tx_hash],
+                },
+                AccountUpdate {
+                    address: recipient,
+                    block_number: 4,
+                    flashblock_index: 1,
+                    balance: None,
+                    nonce: None,
+                    transactions: vec![tx_hash],
+                },
+            ]
+        );
+
+        // nothing changed since the last push
+        assert!(watch.observe(&view, 1).is_empty());
+        view.balances.insert(sender, U256::from(5));
+        let [update] = watch.observe(&view, 1).try_into().unwrap();
+        assert_eq!(update.balance, Some(U256::from(5)));
+        assert!(update.transactions.is_empty());
+    }
+
+    #[test]
+    fn test_non_canonical_status() {
+        let envelope = OpTxEnvelope::Deposit(Sealed::new(TxDeposit::default()));
+        let tx = OpTransactionSigned::decode_2718(&mut envelope.encoded_2718().as_slice()).unwrap();
+        let tx_hash = tx.tx_hash();
+        let mut block = OpBlock::default();
+        block.header.number = 4;
+        block.body.transactions.push(tx);
+        let store = PendingViewStore::default();
+        store.publish(PendingView::new(block, 0, vec![Address::ZERO]));
+
+        let reconciliations = ReconciliationHistory::default();
+        let submissions = SubmissionTracker::default();
+        let status = |tx_hash| {
+            non_canonical_status(
+                &store.load_blocks(),
+                &reconciliations,
+                &submissions,
+                tx_hash,
+            )
+        };
+        assert_eq!(
+            status(tx_hash),
+            TransactionStatus::Preconfirmed {
+                block_number: 4,
+                flashblock_index: None,
+            }
+        );
+        assert_eq!(status(TxHash::ZERO), TransactionStatus::Unknown);
+
+        submissions.submitted(TxHash::ZERO, true);
+        assert!(matches!(
+            status(TxHash::ZERO),
+            TransactionStatus::Submitted {
+                forwarded: true,
+                ..
+            }
+        ));
+        submissions.preconfirmed(TxHash::ZERO, 3);
+        assert_eq!(
+            status(TxHash::ZERO),
+            TransactionStatus::Preconfirmed {
+                block_number: 3,
+                flashblock_index: None,
+            }
+        );
+
+        let status = TransactionStatus::Dropped { block_number: 4 };
+        assert_eq!(
+            serde_json::to_value(status).unwrap(),
+            serde_json::json!({"status": "dropped", "blockNumber": 4})
+        );
+    }
+
+    #[test]
+    fn test_pending_balances() {
+        let store = PendingViewStore::default();
+        let changed = Address::repeat_byte(0x1);
+        let mut view = PendingView::new(OpBlock::default(), 0, Vec::new());
+        view.balances.insert(changed, U256::from(5));
+        store.publish(view);
+
+        let untouched = Address::repeat_byte(0x2);
+        let mut canonical_reads = Vec::new();
+        let balances = pending_balances(&store.load_blocks(), &[untouched, changed], |address| {
+            canonical_reads.push(address);
+            Ok(U256::from(1))
+        })
+        .unwrap();
+
+        assert_eq!(balances, vec![U256::from(1), U256::from(5)]);
+        assert_eq!(canonical_reads, vec![untouched]);
+    }
+}
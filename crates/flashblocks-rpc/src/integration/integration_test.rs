@@ -2,9 +2,10 @@
 mod tests {
     use crate::flashblocks::Metadata;
     use crate::integration::{op_reth::OpRethConfig, IntegrationFramework};
+    use crate::tags::PRECONFIRMED_UNAVAILABLE_CODE;
     use alloy_consensus::Receipt;
     use alloy_eips::BlockNumberOrTag;
-    use alloy_primitives::{Address, Bytes, B256, U256};
+    use alloy_primitives::{keccak256, Address, Bytes, B256, U256};
     use alloy_provider::Identity;
     use alloy_provider::{Provider, ProviderBuilder};
     use alloy_rpc_types_engine::PayloadId;
@@ -113,14 +114,10 @@ mod tests {
         payload
     }
 
-    #[tokio::test]
-    async fn integration_test_get_pending_block() -> eyre::Result<()> {
-        let mut framework =
-            IntegrationFramework::new("integration_test_get_pending_block").unwrap();
-
-        // Start WebSocket server
-        let ws_server = tokio::spawn(async move {
-            let addr = "127.0.0.1:1239".parse::<SocketAddr>().unwrap();
+    /// Serves the two test flashblocks to every connection, five seconds apart.
+    fn spawn_flashblocks_server(addr: &str) -> tokio::task::JoinHandle<()> {
+        let addr = addr.parse::<SocketAddr>().unwrap();
+        tokio::spawn(async move {
             let listener = TcpListener::bind(&addr).await.unwrap();
             println!("WebSocket server listening on: {}", addr);
 
@@ -149,7 +146,16 @@ mod tests {
                     break;
                 }
             }
-        });
+        })
+    }
+
+    #[tokio::test]
+    async fn integration_test_get_pending_block() -> eyre::Result<()> {
+        let mut framework =
+            IntegrationFramework::new("integration_test_get_pending_block").unwrap();
+
+        // Start WebSocket server
+        let ws_server = spawn_flashblocks_server("127.0.0.1:1239");
 
         // Setup genesis path
         let mut genesis_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -246,6 +252,13 @@ mod tests {
             .await?;
         assert!(tx.is_some());
 
+        // check raw transaction by hash, the bytes hash to the transaction hash
+        let tx_hash =
+            B256::from_str("0xa6155b295085d3b87a3c86e342fe11c3b22f9952d0d85d9d34d223b7d6a17cd8")
+                .unwrap();
+        let raw = provider.get_raw_transaction_by_hash(tx_hash).await?;
+        assert_eq!(keccak256(raw.unwrap()), tx_hash);
+
         // check balance
         // use curl command to get balance with pending tag, since alloy provider doesn't support pending tag
         let output = std::process::Command::new("curl")
@@ -292,8 +305,290 @@ mod tests {
         let nonce = U256::from_str(response["result"].as_str().unwrap()).unwrap();
         assert_eq!(nonce, U256::from_str("0x0").unwrap());
 
+        // latest blocks aren't served from the flashblocks unless asked to
+        let block = provider
+            .get_block_by_number(BlockNumberOrTag::Latest)
+            .await?
+            .unwrap();
+        assert_eq!(block.header.number, 0);
+
         // Don't forget to cleanup
         ws_server.abort();
         Ok(())
     }
+
+    #[tokio::test]
+    async fn integration_test_latest_as_pending() -> eyre::Result<()> {
+        let mut framework =
+            IntegrationFramework::new("integration_test_latest_as_pending").unwrap();
+        let ws_server = spawn_flashblocks_server("127.0.0.1:1243");
+
+        let mut genesis_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        genesis_path.push("src/integration/genesis.json");
+
+        let reth_data_dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let reth = OpRethConfig::new()
+            .chain_config_path(genesis_path)
+            .data_dir(reth_data_dir)
+            .auth_rpc_port(1240)
+            .network_port(1241)
+            .http_port(1242)
+            .websocket_url("ws://localhost:1243")
+            .latest_as_pending(&[
+                "eth_getBlockByNumber",
+                "eth_getBalance",
+                "eth_getTransactionCount",
+            ]);
+        framework.start("base-reth-node", &reth).await.unwrap();
+
+        // wait for both flashblocks, the pending block is one ahead of the genesis head
+        tokio::time::sleep(Duration::from_secs(6)).await;
+
+        let provider: alloy_provider::RootProvider<Optimism> =
+            ProviderBuilder::<Identity, Identity, Optimism>::default()
+                .connect_http("http://localhost:1242".parse()?);
+
+        // eth_getBlockByNumber
+        let block = provider
+            .get_block_by_number(BlockNumberOrTag::Latest)
+            .await?
+            .unwrap();
+        assert_eq!(block.header.number, 1);
+        assert_eq!(block.transactions.len(), 2);
+
+        // eth_getBalance
+        let balance = provider
+            .get_balance(Address::from_str(
+                "0x1234567890123456789012345678901234567890",
+            )?)
+            .await?;
+        assert_eq!(balance, U256::from(0x1234));
+
+        // eth_getTransactionCount
+        let nonce = provider
+            .get_transaction_count(Address::from_str(
+                "0x6e5e56b972374e4fde8390df0033397df931a49d",
+            )?)
+            .await?;
+        assert_eq!(nonce, 1);
+
+        ws_server.abort();
+        Ok(())
+    }
+
+    /// Starts a node whose flashblocks stream never sends a flashblock, its ports starting at
+    /// `port`, and returns the error code of `method` called with `params`.
+    async fn error_code(
+        name: &str,
+        port: u16,
+        method: &str,
+        params: serde_json::Value,
+    ) -> eyre::Result<i64> {
+        let mut framework = IntegrationFramework::new(name).unwrap();
+        let mut genesis_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        genesis_path.push("src/integration/genesis.json");
+        let reth_data_dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let reth = OpRethConfig::new()
+            .chain_config_path(genesis_path)
+            .data_dir(reth_data_dir)
+            .auth_rpc_port(port)
+            .network_port(port + 1)
+            .http_port(port + 2)
+            .websocket_url(&format!("ws://localhost:{}", port + 3));
+        framework.start("base-reth-node", &reth).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": 1,
+        });
+        let output = std::process::Command::new("curl")
+            .arg(format!("http://localhost:{}", port + 2))
+            .arg("-X")
+            .arg("POST")
+            .arg("-H")
+            .arg("Content-Type: application/json")
+            .arg("-d")
+            .arg(request.to_string())
+            .output()?;
+        let response: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+        Ok(response["error"]["code"].as_i64().unwrap_or_default())
+    }
+
+    const ACCOUNT: &str = "0x1234567890123456789012345678901234567890";
+
+    #[tokio::test]
+    async fn integration_test_preconfirmed_block_by_number() -> eyre::Result<()> {
+        let params = serde_json::json!(["preconfirmed", false]);
+        let code = error_code(
+            "integration_test_preconfirmed_block_by_number",
+            1300,
+            "eth_getBlockByNumber",
+            params,
+        )
+        .await?;
+        assert_eq!(code, PRECONFIRMED_UNAVAILABLE_CODE as i64);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn integration_test_preconfirmed_block_receipts() -> eyre::Result<()> {
+        let params = serde_json::json!(["preconfirmed"]);
+        let code = error_code(
+            "integration_test_preconfirmed_block_receipts",
+            1310,
+            "eth_getBlockReceipts",
+            params,
+        )
+        .await?;
+        assert_eq!(code, PRECONFIRMED_UNAVAILABLE_CODE as i64);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn integration_test_preconfirmed_balance() -> eyre::Result<()> {
+        let params = serde_json::json!([ACCOUNT, "preconfirmed"]);
+        let code = error_code(
+            "integration_test_preconfirmed_balance",
+            1320,
+            "eth_getBalance",
+            params,
+        )
+        .await?;
+        assert_eq!(code, PRECONFIRMED_UNAVAILABLE_CODE as i64);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn integration_test_preconfirmed_account() -> eyre::Result<()> {
+        let params = serde_json::json!([ACCOUNT, "preconfirmed"]);
+        let code = error_code(
+            "integration_test_preconfirmed_account",
+            1330,
+            "eth_getAccount",
+            params,
+        )
+        .await?;
+        assert_eq!(code, PRECONFIRMED_UNAVAILABLE_CODE as i64);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn integration_test_preconfirmed_code() -> eyre::Result<()> {
+        let params = serde_json::json!([ACCOUNT, "preconfirmed"]);
+        let code = error_code(
+            "integration_test_preconfirmed_code",
+            1340,
+            "eth_getCode",
+            params,
+        )
+        .await?;
+        assert_eq!(code, PRECONFIRMED_UNAVAILABLE_CODE as i64);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn integration_test_preconfirmed_proof() -> eyre::Result<()> {
+        let params = serde_json::json!([ACCOUNT, [], "preconfirmed"]);
+        let code = error_code(
+            "integration_test_preconfirmed_proof",
+            1350,
+            "eth_getProof",
+            params,
+        )
+        .await?;
+        assert_eq!(code, PRECONFIRMED_UNAVAILABLE_CODE as i64);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn integration_test_preconfirmed_transaction_count() -> eyre::Result<()> {
+        let params = serde_json::json!([ACCOUNT, "preconfirmed"]);
+        let code = error_code(
+            "integration_test_preconfirmed_transaction_count",
+            1360,
+            "eth_getTransactionCount",
+            params,
+        )
+        .await?;
+        assert_eq!(code, PRECONFIRMED_UNAVAILABLE_CODE as i64);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn integration_test_preconfirmed_transaction_by_index() -> eyre::Result<()> {
+        let params = serde_json::json!(["preconfirmed", "0x0"]);
+        let code = error_code(
+            "integration_test_preconfirmed_transaction_by_index",
+            1370,
+            "eth_getTransactionByBlockNumberAndIndex",
+            params,
+        )
+        .await?;
+        assert_eq!(code, PRECONFIRMED_UNAVAILABLE_CODE as i64);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn integration_test_preconfirmed_new_filter() -> eyre::Result<()> {
+        // filters follow the flashblocks as they arrive, so they install before the first one
+        let params = serde_json::json!([{ "fromBlock": "latest", "toBlock": "preconfirmed" }]);
+        let code = error_code(
+            "integration_test_preconfirmed_new_filter",
+            1380,
+            "eth_newFilter",
+            params,
+        )
+        .await?;
+        assert_eq!(code, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn integration_test_shadow_mode_proof() -> eyre::Result<()> {
+        let mut framework =
+            IntegrationFramework::new("integration_test_shadow_mode_proof").unwrap();
+        let ws_server = spawn_flashblocks_server("127.0.0.1:1393");
+
+        let mut genesis_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        genesis_path.push("src/integration/genesis.json");
+
+        let reth_data_dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let reth = OpRethConfig::new()
+            .chain_config_path(genesis_path)
+            .data_dir(reth_data_dir)
+            .auth_rpc_port(1390)
+            .network_port(1391)
+            .http_port(1392)
+            .websocket_url("ws://localhost:1393")
+            .shadow_mode(true);
+        framework.start("base-reth-node", &reth).await.unwrap();
+
+        tokio::time::sleep(Duration::from_secs(6)).await;
+
+        // shadow mode serves the node's own pending proof instead of rejecting it
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "eth_getProof",
+            "params": [ACCOUNT, [], "pending"],
+            "id": 1,
+        });
+        let output = std::process::Command::new("curl")
+            .arg("http://localhost:1392")
+            .arg("-X")
+            .arg("POST")
+            .arg("-H")
+            .arg("Content-Type: application/json")
+            .arg("-d")
+            .arg(request.to_string())
+            .output()?;
+        let response: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+        assert!(response["error"].is_null());
+        assert_eq!(response["result"]["address"], ACCOUNT);
+
+        ws_server.abort();
+        Ok(())
+    }
 }
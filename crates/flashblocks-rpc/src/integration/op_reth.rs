@@ -24,6 +24,8 @@ pub struct OpRethConfig {
     http_port: Option<u16>,
     network_port: Option<u16>,
     websocket_url: Option<String>,
+    latest_as_pending: Vec<String>,
+    shadow_mode: bool,
 }
 
 impl OpRethConfig {
@@ -60,6 +62,16 @@ impl OpRethConfig {
         self.http_port = Some(port);
         self
     }
+
+    pub fn latest_as_pending(mut self, methods: &[&str]) -> Self {
+        self.latest_as_pending = methods.iter().map(|method| method.to_string()).collect();
+        self
+    }
+
+    pub fn shadow_mode(mut self, enabled: bool) -> Self {
+        self.shadow_mode = enabled;
+        self
+    }
 }
 
 impl Service for OpRethConfig {
@@ -108,6 +120,15 @@ impl Service for OpRethConfig {
             cmd.arg("--websocket-url").arg(websocket_url);
         }
 
+        if !self.latest_as_pending.is_empty() {
+            cmd.arg("--latest-as-pending")
+                .arg(self.latest_as_pending.join(","));
+        }
+
+        if self.shadow_mode {
+            cmd.arg("--flashblocks-shadow-mode");
+        }
+
         cmd
     }
 
@@ -1,7 +1,40 @@
+pub mod admin_api;
+pub mod alerts;
+pub mod base_api;
 pub mod cache;
+pub mod canonical;
+pub mod clock;
+pub mod compat;
+pub mod debug_api;
+pub mod debug_overrides;
+pub mod eth_pubsub;
+#[cfg(any(test, feature = "fault-injection"))]
+pub mod faults;
+pub mod filters;
 pub mod flashblocks;
+pub mod flashblocks_api;
 mod metrics;
+pub mod modules_api;
+pub mod origins;
+pub mod pending;
+pub mod pending_block;
+pub mod pubsub;
+pub mod reconciliation;
+pub mod replacements;
 pub mod rpc;
+pub mod simulations;
+pub mod startup;
+pub mod status_http;
+pub mod submissions;
+pub mod summaries;
+pub mod tags;
+pub mod upstream;
+pub mod validation;
+pub mod watchlist;
 
+#[cfg(test)]
+mod conformance;
 #[cfg(test)]
 mod integration;
+#[cfg(test)]
+mod test_utils;
@@ -1,3 +1,5 @@
+use crate::upstream::UpstreamInfo;
+use alloy_primitives::Address;
 use metrics::{Counter, Gauge, Histogram};
 use metrics_derive::Metrics;
 #[derive(Metrics, Clone)]
@@ -9,6 +11,9 @@ pub struct Metrics {
     #[metric(describe = "Count of messages received from the upstream source")]
     pub upstream_messages: Gauge,
 
+    #[metric(describe = "Count of websocket frames from the upstream that weren't flashblocks")]
+    pub upstream_unexpected_frames: Counter,
+
     #[metric(describe = "Time taken to process a message")]
     pub block_processing_duration: Histogram,
 
@@ -27,6 +32,262 @@ pub struct Metrics {
     #[metric(describe = "Count of times flashblocks get_block_by_number is called")]
     pub get_block_by_number: Counter,
 
+    #[metric(describe = "Count of times flashblocks get_block_by_hash is called")]
+    pub get_block_by_hash: Counter,
+
+    #[metric(describe = "Count of times flashblocks get_transaction_by_hash is called")]
+    pub get_transaction_by_hash: Counter,
+
+    #[metric(describe = "Count of times flashblocks get_raw_transaction_by_hash is called")]
+    pub get_raw_transaction_by_hash: Counter,
+
+    #[metric(describe = "Count of times flashblocks gas_price is called")]
+    pub gas_price: Counter,
+
+    #[metric(describe = "Count of times flashblocks max_priority_fee_per_gas is called")]
+    pub max_priority_fee_per_gas: Counter,
+
+    #[metric(describe = "Count of times flashblocks estimate_gas is called")]
+    pub estimate_gas: Counter,
+
+    #[metric(describe = "Count of times flashblocks call is called")]
+    pub call: Counter,
+
+    #[metric(describe = "Count of times flashblocks call_many is called")]
+    pub call_many: Counter,
+
+    #[metric(describe = "Count of times flashblocks simulate_v1 is called")]
+    pub simulate_v1: Counter,
+
+    #[metric(describe = "Count of times flashblocks get_account is called")]
+    pub get_account: Counter,
+
+    #[metric(describe = "Count of times flashblocks get_code is called")]
+    pub get_code: Counter,
+
+    #[metric(describe = "Count of eth_getProof requests the preconfirmed state can't prove")]
+    pub get_proof_unavailable: Counter,
+
+    #[metric(describe = "Count of times flashblocks get_block_receipts is called")]
+    pub get_block_receipts: Counter,
+
+    #[metric(
+        describe = "Count of times flashblocks get_transaction_by_block_number_and_index is called"
+    )]
+    pub get_transaction_by_block_number_and_index: Counter,
+
+    #[metric(
+        describe = "Count of times flashblocks get_transaction_by_block_hash_and_index is called"
+    )]
+    pub get_transaction_by_block_hash_and_index: Counter,
+
+    #[metric(describe = "Count of times flashblocks get_logs is called")]
+    pub get_logs: Counter,
+
+    #[metric(describe = "Count of filters installed on the flashblocks state")]
+    pub new_filter: Counter,
+
+    #[metric(describe = "Count of times flashblocks send_raw_transaction is called")]
+    pub send_raw_transaction: Counter,
+
+    #[metric(describe = "Count of times flashblocks send_raw_transaction_sync is called")]
+    pub send_raw_transaction_sync: Counter,
+
+    #[metric(
+        describe = "Count of synchronous sends whose transaction wasn't preconfirmed in time"
+    )]
+    pub send_raw_transaction_sync_timeouts: Counter,
+
+    #[metric(describe = "Count of times flashblocks trace_call is called")]
+    pub trace_call: Counter,
+
+    #[metric(describe = "Count of times flashblocks get_raw_block is called")]
+    pub get_raw_block: Counter,
+
+    #[metric(describe = "Count of times flashblocks get_raw_receipts is called")]
+    pub get_raw_receipts: Counter,
+
+    #[metric(describe = "Count of times flashblocks subscribe is called")]
+    pub subscribe: Counter,
+
+    #[metric(describe = "Count of block filters installed on the flashblocks state")]
+    pub new_block_filter: Counter,
+
+    #[metric(describe = "Count of pending transaction filters installed")]
+    pub new_pending_transaction_filter: Counter,
+
     #[metric(describe = "Number of flashblocks in a block")]
     pub flashblocks_in_block: Histogram,
+
+    #[metric(describe = "Count of queued flashblocks skipped because a newer one superseded them")]
+    pub payloads_skipped_superseded: Counter,
+
+    #[metric(
+        describe = "Count of flashblocks dropped because the payload worker of their height was full"
+    )]
+    pub payloads_dropped_overflow: Counter,
+
+    #[metric(describe = "Count of blocks dropped because one of their flashblocks was missing")]
+    pub flashblock_gaps: Counter,
+
+    #[metric(describe = "Time from receiving a flashblock to it being visible to RPC readers")]
+    pub ingest_lag: Histogram,
+
+    #[metric(describe = "Number of flashblocks waiting in the actor mailbox")]
+    pub mailbox_depth: Gauge,
+
+    #[metric(describe = "Number of flashblocks a payload worker picked up at once")]
+    pub worker_queue_depth: Histogram,
+
+    #[metric(describe = "Time a flashblock spent queued between being received and processed")]
+    pub mailbox_latency: Histogram,
+
+    #[metric(
+        describe = "Count of preconfirmed transactions that took the nonce of a different pooled transaction"
+    )]
+    pub replaced_transactions: Counter,
+
+    #[metric(describe = "Time from publishing a notification to sending it to a subscriber")]
+    pub subscription_fanout_duration: Histogram,
+
+    #[metric(describe = "Count of notifications dropped for slow subscribers")]
+    pub subscription_notifications_dropped: Counter,
+
+    #[metric(describe = "Count of notifications coalesced into a newer one for slow subscribers")]
+    pub subscription_notifications_coalesced: Counter,
+
+    #[metric(describe = "Count of subscribers disconnected for falling behind")]
+    pub subscription_slow_disconnects: Counter,
+
+    #[metric(describe = "Count of canonical blocks compared with their preconfirmation")]
+    pub reconciled_blocks: Counter,
+
+    #[metric(describe = "Count of canonical blocks that differed from their preconfirmation")]
+    pub reconciliation_mismatches: Counter,
+
+    #[metric(describe = "Count of preconfirmed transactions that were waiting in the txpool")]
+    pub preconfirmed_from_pool: Counter,
+
+    #[metric(describe = "Count of preconfirmed transactions never seen in the txpool")]
+    pub preconfirmed_unknown_origin: Counter,
+}
+
+/// Metrics segmented by the builder (the fee recipient carried on the payload base) that
+/// produced the flashblocks for a block.
+#[derive(Metrics, Clone)]
+#[metrics(scope = "reth_flashblocks_builder")]
+pub struct BuilderMetrics {
+    #[metric(describe = "Count of flashblocks received from the builder")]
+    pub flashblocks: Counter,
+
+    #[metric(describe = "Time taken to process a flashblock from the builder")]
+    pub block_processing_duration: Histogram,
+
+    #[metric(describe = "Number of flashblocks in a block built by the builder")]
+    pub flashblocks_in_block: Histogram,
+}
+
+impl BuilderMetrics {
+    pub fn for_builder(builder: Address) -> Self {
+        Self::new_with_labels(&[("builder", builder.to_string())])
+    }
+}
+
+/// What the upstream announced about itself, exported as the labels of a gauge.
+#[derive(Metrics, Clone)]
+#[metrics(scope = "reth_flashblocks_upstream")]
+pub struct UpstreamInfoMetrics {
+    #[metric(describe = "Set to 1 for the software and protocol version of the current upstream")]
+    pub info: Gauge,
+}
+
+impl UpstreamInfoMetrics {
+    pub fn for_info(info: &UpstreamInfo) -> Self {
+        Self::new_with_labels(&info.metric_labels())
+    }
+}
+
+/// Requests that could have been served from the flashblocks state but fell back, segmented by
+/// method and reason.
+#[derive(Metrics, Clone)]
+#[metrics(scope = "reth_flashblocks_fallback")]
+pub struct FallbackMetrics {
+    #[metric(describe = "Count of requests that fell back to the canonical state")]
+    pub fallbacks: Counter,
+}
+
+impl FallbackMetrics {
+    pub fn for_fallback(method: &'static str, reason: impl ToString) -> Self {
+        Self::new_with_labels(&[
+            ("method", method.to_string()),
+            ("reason", reason.to_string()),
+        ])
+    }
+}
+
+/// Simulations on the pending state that were aborted before they finished, segmented by
+/// method and reason.
+#[derive(Metrics, Clone)]
+#[metrics(scope = "reth_flashblocks_simulation")]
+pub struct SimulationMetrics {
+    #[metric(describe = "Count of simulations aborted before they finished")]
+    pub aborted: Counter,
+}
+
+impl SimulationMetrics {
+    pub fn for_abort(method: &'static str, reason: impl ToString) -> Self {
+        Self::new_with_labels(&[
+            ("method", method.to_string()),
+            ("reason", reason.to_string()),
+        ])
+    }
+}
+
+/// Flashblocks dropped by a payload validator, segmented by validator.
+#[derive(Metrics, Clone)]
+#[metrics(scope = "reth_flashblocks_validator")]
+pub struct ValidatorMetrics {
+    #[metric(describe = "Count of flashblocks the validator rejected")]
+    pub rejected: Counter,
+}
+
+impl ValidatorMetrics {
+    pub fn for_validator(validator: &str) -> Self {
+        Self::new_with_labels(&[("validator", validator.to_string())])
+    }
+}
+
+/// Preconfirmed balances of the accounts on the watch list, segmented by address.
+#[derive(Metrics, Clone)]
+#[metrics(scope = "reth_flashblocks_watched_account")]
+pub struct WatchedAccountMetrics {
+    #[metric(describe = "Preconfirmed balance of the account in ether")]
+    pub balance: Gauge,
+
+    #[metric(describe = "Count of flashblocks that changed the balance of the account")]
+    pub balance_changes: Counter,
+}
+
+impl WatchedAccountMetrics {
+    pub fn for_address(address: Address) -> Self {
+        Self::new_with_labels(&[("address", address.to_string())])
+    }
+}
+
+/// Comparisons of flashblocks answers with the standard ones in shadow mode, segmented by
+/// method.
+#[derive(Metrics, Clone)]
+#[metrics(scope = "reth_flashblocks_shadow")]
+pub struct ShadowMetrics {
+    #[metric(describe = "Count of flashblocks answers compared with the standard answer")]
+    pub comparisons: Counter,
+
+    #[metric(describe = "Count of flashblocks answers that differed from the standard answer")]
+    pub mismatches: Counter,
+}
+
+impl ShadowMetrics {
+    pub fn for_method(method: &str) -> Self {
+        Self::new_with_labels(&[("method", method.to_string())])
+    }
 }
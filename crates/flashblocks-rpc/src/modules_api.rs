@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+
+use jsonrpsee::{
+    core::{async_trait, RpcResult},
+    proc_macros::rpc,
+};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// Version reported for the namespaces and methods served by this crate.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Version the node reports for its own namespaces.
+const STANDARD_VERSION: &str = "1.0";
+
+/// Namespaces served by the node with their version, and the methods answered from the
+/// flashblocks state.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RpcModules {
+    #[serde(flatten)]
+    pub namespaces: BTreeMap<String, String>,
+    /// Overridden methods, versioned as `flashblocks/<version>`
+    #[serde(
+        rename = "flashblocksOverrides",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub overrides: BTreeMap<String, String>,
+}
+
+#[cfg_attr(not(test), rpc(server, namespace = "rpc"))]
+#[cfg_attr(test, rpc(server, client, namespace = "rpc"))]
+pub trait RpcModulesApi {
+    /// Returns the namespaces served by the node with their version, the flashblocks
+    /// namespaces included. The methods answered from the flashblocks state are listed apart
+    /// under `flashblocksOverrides`, so clients can detect preconfirmation support.
+    #[method(name = "modules")]
+    async fn rpc_modules(&self) -> RpcResult<RpcModules>;
+}
+
+#[derive(Debug, Default)]
+pub struct RpcModulesExt {
+    modules: RpcModules,
+}
+
+impl RpcModulesExt {
+    /// Lists the node's own `namespaces`, served on any transport.
+    pub fn new(namespaces: impl IntoIterator<Item = impl ToString>) -> Self {
+        Self {
+            modules: RpcModules {
+                namespaces: namespaces
+                    .into_iter()
+                    .map(|namespace| (namespace.to_string(), STANDARD_VERSION.to_string()))
+                    .collect(),
+                overrides: BTreeMap::new(),
+            },
+        }
+    }
+
+    /// Lists a namespace served by this crate.
+    pub fn with_namespace(mut self, namespace: &str) -> Self {
+        self.modules
+            .namespaces
+            .insert(namespace.to_string(), VERSION.to_string());
+        self
+    }
+
+    /// Lists methods answered from the flashblocks state.
+    pub fn with_overrides(mut self, methods: impl IntoIterator<Item = impl ToString>) -> Self {
+        for method in methods {
+            self.modules
+                .overrides
+                .insert(method.to_string(), format!("flashblocks/{VERSION}"));
+        }
+        self
+    }
+}
+
+#[async_trait]
+impl RpcModulesApiServer for RpcModulesExt {
+    async fn rpc_modules(&self) -> RpcResult<RpcModules> {
+        debug!("rpc_modules");
+        Ok(self.modules.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rpc_modules() {
+        let modules = RpcModulesExt::new(["eth", "net", "eth"])
+            .with_namespace("flashblocks")
+            .with_overrides(["eth_getBalance"])
+            .rpc_modules()
+            .await
+            .unwrap();
+
+        assert_eq!(modules.namespaces["eth"], STANDARD_VERSION);
+        assert_eq!(modules.namespaces["flashblocks"], VERSION);
+        assert_eq!(modules.namespaces.len(), 3);
+        assert_eq!(
+            modules.overrides["eth_getBalance"],
+            format!("flashblocks/{VERSION}")
+        );
+
+        let json = serde_json::to_value(&modules).unwrap();
+        assert_eq!(json["eth"], STANDARD_VERSION);
+        assert_eq!(
+            json["flashblocksOverrides"]["eth_getBalance"],
+            format!("flashblocks/{VERSION}")
+        );
+        assert_eq!(serde_json::from_value::<RpcModules>(json).unwrap(), modules);
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+use crate::metrics::Metrics;
+use crate::pending::{PendingView, PendingViewStore};
+use alloy_primitives::TxHash;
+use reth::transaction_pool::TransactionPool;
+use serde::{Deserialize, Serialize};
+
+/// Number of blocks whose transaction origins are kept for `base_getTransactionOrigins`.
+const RETAINED_ORIGIN_BLOCKS: usize = 64;
+
+/// Where a preconfirmed transaction was seen before the builder included it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TransactionOrigin {
+    /// The transaction was waiting in the local txpool, so it went through the public mempool
+    Pool,
+    /// The transaction was never seen locally, e.g. it was sent to the builder privately
+    Unknown,
+}
+
+/// Number of preconfirmed transactions of each origin.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OriginCounts {
+    pub pool: u64,
+    pub unknown: u64,
+}
+
+impl OriginCounts {
+    fn add(&mut self, origin: TransactionOrigin) {
+        match origin {
+            TransactionOrigin::Pool => self.pool += 1,
+            TransactionOrigin::Unknown => self.unknown += 1,
+        }
+    }
+}
+
+/// Origins of the preconfirmed transactions of a block. Deposits aren't counted, they never go
+/// through a txpool.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockOrigins {
+    pub block_number: u64,
+    pub total: OriginCounts,
+    /// Counts of the transactions first preconfirmed in each flashblock, by index
+    pub flashblocks: BTreeMap<u64, OriginCounts>,
+}
+
+/// Origins of the most recent blocks, shared between the tagger and the RPC.
+#[derive(Debug, Default)]
+pub struct OriginTracker {
+    blocks: RwLock<BTreeMap<u64, BlockOrigins>>,
+}
+
+impl OriginTracker {
+    fn record(&self, block_number: u64, flashblock_index: u64, origin: TransactionOrigin) {
+        let mut blocks = self.blocks.write().unwrap();
+        let block = blocks.entry(block_number).or_insert_with(|| BlockOrigins {
+            block_number,
+            total: OriginCounts::default(),
+            flashblocks: BTreeMap::new(),
+        });
+        block.total.add(origin);
+        block
+            .flashblocks
+            .entry(flashblock_index)
+            .or_default()
+            .add(origin);
+        while blocks.len() > RETAINED_ORIGIN_BLOCKS {
+            blocks.pop_first();
+        }
+    }
+
+    /// Forgets the origins of `block_number`, when a different block replaced it.
+    fn reset(&self, block_number: u64) {
+        self.blocks.write().unwrap().remove(&block_number);
+    }
+
+    pub fn for_block(&self, block_number: u64) -> Option<BlockOrigins> {
+        self.blocks.read().unwrap().get(&block_number).cloned()
+    }
+}
+
+/// Tags the transactions of every new flashblock with whether they were waiting in the txpool.
+#[derive(Debug)]
+pub struct OriginTagger<Pool> {
+    pool: Pool,
+    pending: Arc<PendingViewStore>,
+    tracker: Arc<OriginTracker>,
+    /// Number of transactions already tagged per block
+    checked: HashMap<u64, usize>,
+    metrics: Metrics,
+}
+
+impl<Pool: TransactionPool> OriginTagger<Pool> {
+    pub fn new(pool: Pool, pending: Arc<PendingViewStore>, tracker: Arc<OriginTracker>) -> Self {
+        Self {
+            pool,
+            pending,
+            tracker,
+            checked: HashMap::new(),
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Tags the transactions preconfirmed since the last call.
+    pub fn check(&mut self) {
+        let blocks = self.pending.load_blocks();
+        self.checked
+            .retain(|block_number, _| blocks.for_block(*block_number).is_some());
+
+        for block_number in blocks.block_numbers() {
+            let Some(view) = blocks.for_block(block_number) else {
+                continue;
+            };
+            let checked = self.checked.entry(block_number).or_default();
+            // a new block at the same height starts over
+            if *checked > view.senders.len() {
+                *checked = 0;
+                self.tracker.reset(block_number);
+            }
+
+            let origins = tag_origins(view, *checked, |tx_hash| self.pool.contains(&tx_hash));
+            *checked = view.senders.len();
+
+            for (flashblock_index, origin) in origins {
+                match origin {
+                    TransactionOrigin::Pool => self.metrics.preconfirmed_from_pool.increment(1),
+                    TransactionOrigin::Unknown => {
+                        self.metrics.preconfirmed_unknown_origin.increment(1)
+                    }
+                }
+                self.tracker.record(block_number, flashblock_index, origin);
+            }
+        }
+    }
+
+    /// Tags the transactions of every published view.
+    pub async fn run(mut self) {
+        let mut updates = self.pending.view_updates();
+        while updates.changed().await {
+            self.check();
+        }
+    }
+}
+
+/// Returns the flashblock index and origin of the transactions of `view` from index `from`,
+/// using `in_pool` to look them up in the txpool.
+fn tag_origins(
+    view: &PendingView,
+    from: usize,
+    in_pool: impl Fn(TxHash) -> bool,
+) -> Vec<(u64, TransactionOrigin)> {
+    view.transactions()
+        .skip(from)
+        // deposits never go through the txpool
+        .filter(|tx| !tx.transaction().is_deposit())
+        .map(|tx| {
+            let flashblock_index = tx
+                .preconfirmation()
+                .map_or(view.flashblock_index, |preconfirmation| {
+                    preconfirmation.index
+                });
+            let origin = if in_pool(tx.transaction().tx_hash()) {
+                TransactionOrigin::Pool
+            } else {
+                TransactionOrigin::Unknown
+            };
+            (flashblock_index, origin)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::signed_tx;
+    use alloy_primitives::Address;
+    use reth_optimism_primitives::OpBlock;
+
+    #[test]
+    fn test_tag_origins() {
+        let mut block = OpBlock::default();
+        block.header.number = 1;
+        block.body.transactions = vec![signed_tx(0), signed_tx(1), signed_tx(2)];
+        let view = PendingView::new(block, 2, vec![Address::ZERO; 3]);
+        let pooled = signed_tx(1).tx_hash();
+
+        let origins = tag_origins(&view, 0, |tx_hash| tx_hash == pooled);
+        assert_eq!(
+            origins,
+            vec![
+                (2, TransactionOrigin::Unknown),
+                (2, TransactionOrigin::Pool),
+                (2, TransactionOrigin::Unknown),
+            ]
+        );
+
+        // already tagged transactions are skipped
+        assert_eq!(
+            tag_origins(&view, 2, |_| true),
+            vec![(2, TransactionOrigin::Pool)]
+        );
+    }
+
+    #[test]
+    fn test_tracker_counts_per_flashblock() {
+        let tracker = OriginTracker::default();
+        tracker.record(1, 0, TransactionOrigin::Pool);
+        tracker.record(1, 1, TransactionOrigin::Pool);
+        tracker.record(1, 1, TransactionOrigin::Unknown);
+
+        let origins = tracker.for_block(1).unwrap();
+        assert_eq!(
+            origins.total,
+            OriginCounts {
+                pool: 2,
+                unknown: 1
+            }
+        );
+        assert_eq!(
+            origins.flashblocks[&1],
+            OriginCounts {
+                pool: 1,
+                unknown: 1
+            }
+        );
+
+        tracker.reset(1);
+        assert!(tracker.for_block(1).is_none());
+
+        for block_number in 0..=RETAINED_ORIGIN_BLOCKS as u64 {
+            tracker.record(block_number, 0, TransactionOrigin::Unknown);
+        }
+        assert!(tracker.for_block(0).is_none());
+        assert!(tracker.for_block(RETAINED_ORIGIN_BLOCKS as u64).is_some());
+    }
+}
@@ -0,0 +1,1806 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::hash::Hash;
+use std::ops::{Index, RangeInclusive};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::clock::SharedClock;
+use crate::pubsub::{FanOut, Subscriber, Subscribers};
+use alloy_consensus::constants::{EMPTY_ROOT_HASH, KECCAK_EMPTY};
+use alloy_consensus::transaction::Recovered;
+use alloy_consensus::{Transaction, TxReceipt};
+use alloy_primitives::{Address, Bytes, Sealed, TxHash, B256, U256};
+use alloy_rpc_types::{Filter, Header, Log};
+use alloy_rpc_types_engine::PayloadId;
+use alloy_rpc_types_eth::{state::StateOverride, Account, BlockOverrides};
+use arc_swap::ArcSwap;
+use reth_optimism_primitives::{OpBlock, OpReceipt, OpTransactionSigned};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+use tracing::error;
+
+/// How long a view is served after it was published, so a stalled flashblocks stream doesn't
+/// keep answering `pending` requests with stale data.
+const PENDING_VIEW_TTL: Duration = Duration::from_secs(10);
+
+/// Number of heights kept below the furthest preconfirmed block. This covers blocks that are
+/// built ahead of each other as well as completed blocks the node hasn't imported yet.
+pub(crate) const RETAINED_BLOCKS: u64 = 4;
+
+/// Gas used by a transfer that runs no code.
+const TRANSFER_GAS: u64 = 21_000;
+
+/// Number of recent payloads whose block can be looked up by payload id.
+const RECENT_PAYLOADS: usize = 1024;
+
+/// Number of heights, the furthest one included, whose raw flashblock frames are kept.
+const RAW_FRAME_BLOCKS: u64 = 2;
+
+/// Most recent websocket frames that didn't decode kept.
+const UNDECODABLE_FRAMES: usize = 16;
+
+/// Views buffered for the tasks following the published views. Slower tasks skip the oldest
+/// ones and load the latest state instead.
+const PUBLISHED_VIEWS_CAPACITY: usize = 64;
+
+/// Flashblock interval assumed until the stream shows its own cadence.
+pub const DEFAULT_FLASHBLOCK_INTERVAL_MS: u64 = 200;
+
+/// Block time assumed until the timestamps of two heights were seen, the one of OP Stack chains.
+pub const DEFAULT_BLOCK_TIME_MS: u64 = 2000;
+
+/// The flashblock a transaction was first preconfirmed in.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PreconfirmationInfo {
+    pub index: u64,
+    /// Unix timestamp in milliseconds at which the flashblock was processed
+    pub received_at: u64,
+}
+
+/// How full a preconfirmed block is after one of its flashblocks.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GasProgress {
+    pub block: u64,
+    pub index: u64,
+    pub gas_used: u64,
+    pub gas_limit: u64,
+}
+
+impl GasProgress {
+    pub fn from_view(view: &PendingView) -> Self {
+        Self {
+            block: view.block_number(),
+            index: view.flashblock_index,
+            gas_used: view.block.header.gas_used,
+            gas_limit: view.block.header.gas_limit,
+        }
+    }
+}
+
+/// Header of a preconfirmed block after one of its flashblocks, pushed to the `newHeads`
+/// subscribers following the flashblocks.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FlashblockHead {
+    #[serde(flatten)]
+    pub header: Header,
+    pub flashblock_index: u64,
+}
+
+impl FlashblockHead {
+    pub fn from_view(view: &PendingView) -> Self {
+        let header = Sealed::new_unchecked(view.block.header.clone(), view.block_hash);
+        Self {
+            header: Header::from_consensus(header, None, None),
+            flashblock_index: view.flashblock_index,
+        }
+    }
+}
+
+/// How far the pending view got in catching up with the flashblocks stream since the websocket
+/// last (re)connected. Flashblocks of a block whose first flashblock was missed can't be
+/// applied, so the view is healthy again once a block was followed from its first flashblock.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncProgress {
+    pub syncing: bool,
+    /// Number of heights with a flashblock applied since the connection
+    pub recovered_blocks: u64,
+    /// Number of flashblocks applied since the connection
+    pub recovered_flashblocks: u64,
+    /// Height and index of the last applied flashblock
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<u64>,
+}
+
+impl Default for SyncProgress {
+    fn default() -> Self {
+        Self {
+            syncing: true,
+            recovered_blocks: 0,
+            recovered_flashblocks: 0,
+            block: None,
+            index: None,
+        }
+    }
+}
+
+impl SyncProgress {
+    /// Accounts for a published view, returning whether the view was still catching up.
+    fn record(&mut self, view: &PendingView) -> bool {
+        if !self.syncing {
+            return false;
+        }
+        self.recovered_flashblocks += 1;
+        if self.block != Some(view.block_number()) {
+            self.recovered_blocks += 1;
+        }
+        self.block = Some(view.block_number());
+        self.index = Some(view.flashblock_index);
+        self.syncing = view.flashblock_index != 0;
+        true
+    }
+}
+
+/// The block a payload built, to correlate node data with rollup-boost and builder logs.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PayloadRecord {
+    pub payload_id: PayloadId,
+    pub block_number: u64,
+    /// Unix timestamp in milliseconds at which the first flashblock of the payload was processed
+    pub first_seen_at: u64,
+}
+
+/// A check run while applying a flashblock to the view of its block.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct AuditCheck {
+    pub name: String,
+    pub passed: bool,
+}
+
+/// How a flashblock was applied to the view of its block, kept to debug discrepancies with
+/// what downstream consumers saw.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FlashblockAudit {
+    pub index: u64,
+    /// Block hash sent with the flashblock
+    pub block_hash: B256,
+    /// Unix timestamp in milliseconds at which the flashblock was processed
+    pub received_at: u64,
+    /// Transactions added to the block by the flashblock
+    pub transactions: Vec<TxHash>,
+    /// Transactions whose receipt was taken from the flashblock's metadata, including earlier
+    /// transactions whose receipt was missing until now
+    pub metadata_receipts: Vec<TxHash>,
+    /// Transactions left without a receipt. Receipts are never synthesized, these are served
+    /// without one until the builder sends it.
+    pub missing_receipts: Vec<TxHash>,
+    pub checks: Vec<AuditCheck>,
+}
+
+/// An append-only sequence shared between the views of a block. Each flashblock adds a chunk,
+/// so extending the sequence only copies the chunk pointers instead of the elements.
+#[derive(Debug, Clone)]
+pub struct Chunks<T> {
+    chunks: Vec<Arc<[T]>>,
+    len: usize,
+}
+
+impl<T> Default for Chunks<T> {
+    fn default() -> Self {
+        Self {
+            chunks: Vec::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<T> Chunks<T> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, mut index: usize) -> Option<&T> {
+        for chunk in &self.chunks {
+            if index < chunk.len() {
+                return Some(&chunk[index]);
+            }
+            index -= chunk.len();
+        }
+        None
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.chunks.iter().flat_map(|chunk| chunk.iter())
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.chunks.first().and_then(|chunk| chunk.first())
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.chunks.last().and_then(|chunk| chunk.last())
+    }
+
+    pub fn push_chunk(&mut self, chunk: Vec<T>) {
+        if !chunk.is_empty() {
+            self.len += chunk.len();
+            self.chunks.push(chunk.into());
+        }
+    }
+
+    pub fn push(&mut self, item: T) {
+        self.push_chunk(vec![item]);
+    }
+}
+
+impl<T> Index<usize> for Chunks<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("index out of bounds")
+    }
+}
+
+/// A map shared between the views of a block like [`Chunks`]. Each flashblock adds a layer with
+/// the entries it set, so extending the map only copies the layer pointers.
+#[derive(Debug, Clone)]
+pub struct LayeredMap<K, V> {
+    layers: Vec<Arc<HashMap<K, V>>>,
+}
+
+impl<K, V> Default for LayeredMap<K, V> {
+    fn default() -> Self {
+        Self { layers: Vec::new() }
+    }
+}
+
+impl<K: Eq + Hash, V> LayeredMap<K, V> {
+    /// The value `key` was last set to.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.layers.iter().rev().find_map(|layer| layer.get(key))
+    }
+
+    /// Every value `key` was set to, oldest first.
+    pub fn get_all<'a>(&'a self, key: &'a K) -> impl Iterator<Item = &'a V> {
+        self.layers.iter().filter_map(move |layer| layer.get(key))
+    }
+
+    /// The keys that were set, each once.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// The entries with the value each key was last set to.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let mut latest = HashMap::new();
+        for layer in &self.layers {
+            latest.extend(layer.iter());
+        }
+        latest.into_iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn push_layer(&mut self, layer: impl IntoIterator<Item = (K, V)>) {
+        let layer: HashMap<K, V> = layer.into_iter().collect();
+        if !layer.is_empty() {
+            self.layers.push(Arc::new(layer));
+        }
+    }
+
+    /// Sets a single entry, in a layer of its own.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.push_layer([(key, value)]);
+    }
+}
+
+/// A consistent snapshot of the block being built, published once per flashblock.
+///
+/// The per transaction vectors are aligned with `block.body.transactions`. `receipts` and
+/// `preconfirmations` may be shorter if the builder didn't send a receipt for a transaction.
+/// Everything but the block itself is shared with the previous views of the block, each
+/// flashblock only adds its own transactions and account changes.
+#[derive(Debug, Clone)]
+pub struct PendingView {
+    /// Incremented every time a view is published
+    pub generation: u64,
+    pub block: OpBlock,
+    /// Block hash sent with the latest flashblock, reported on receipts, logs and transactions
+    pub block_hash: B256,
+    /// Highest flashblock index included in the view
+    pub flashblock_index: u64,
+    /// Payload the builder sent the flashblocks under
+    pub payload_id: Option<PayloadId>,
+    pub senders: Chunks<Address>,
+    /// The transactions paired with their sender, so rendering them needs no further lookups
+    pub recovered: Chunks<Recovered<OpTransactionSigned>>,
+    pub receipts: Chunks<OpReceipt>,
+    pub preconfirmations: Chunks<PreconfirmationInfo>,
+    /// Balances changed by the flashblocks of this block
+    pub balances: LayeredMap<Address, U256>,
+    /// Nonces used by each account in this block, by flashblock. Tracking the nonces rather
+    /// than a count keeps the projected nonce exact when a transaction is replaced by one with
+    /// the same nonce.
+    pub nonces: LayeredMap<Address, BTreeSet<u64>>,
+    /// Contracts deployed by the transactions of this block, with the index of the transaction
+    /// that deployed them. The flashblocks don't send the deployed code.
+    pub deployments: LayeredMap<Address, usize>,
+    /// One entry per flashblock applied to the block, in order
+    pub audit: Chunks<FlashblockAudit>,
+    /// When the websocket frame that produced this view was received
+    pub received_at: Instant,
+    pub published_at: Instant,
+    /// Index of every transaction in the block, a layer per flashblock
+    transaction_indices: LayeredMap<TxHash, usize>,
+    /// `receipts` flattened on the first receipt request served from this view
+    receipts_slice: OnceLock<Vec<OpReceipt>>,
+}
+
+/// A transaction found in a [`PendingView`].
+#[derive(Debug, Clone, Copy)]
+pub struct PendingTransaction<'a> {
+    pub view: &'a PendingView,
+    pub index: usize,
+}
+
+impl PendingTransaction<'_> {
+    pub fn transaction(&self) -> &OpTransactionSigned {
+        &self.view.block.body.transactions[self.index]
+    }
+
+    pub fn sender(&self) -> Address {
+        self.view.senders[self.index]
+    }
+
+    /// The transaction paired with the sender recovered at ingest, so serving it never
+    /// recovers the signature again.
+    pub fn recovered(&self) -> &Recovered<OpTransactionSigned> {
+        self.view
+            .recovered
+            .get(self.index)
+            .expect("recovered transactions are aligned with the block")
+    }
+
+    pub fn receipt(&self) -> Option<&OpReceipt> {
+        self.view.receipts.get(self.index)
+    }
+
+    pub fn preconfirmation(&self) -> Option<PreconfirmationInfo> {
+        self.view.preconfirmations.get(self.index).copied()
+    }
+}
+
+impl PendingView {
+    pub fn new(block: OpBlock, flashblock_index: u64, senders: Vec<Address>) -> Self {
+        let mut recovered = Chunks::default();
+        recovered.push_chunk(
+            block
+                .body
+                .transactions
+                .iter()
+                .zip(&senders)
+                .map(|(tx, sender)| Recovered::new_unchecked(tx.clone(), *sender))
+                .collect(),
+        );
+        let mut shared_senders = Chunks::default();
+        shared_senders.push_chunk(senders);
+        Self::with_recovered(block, flashblock_index, shared_senders, recovered, None)
+    }
+
+    /// Builds a view around transactions that were already paired with their senders, e.g. by
+    /// the previous views of the block. The transactions of `parent`, whose block this one
+    /// extends, keep its index, only those appended since are indexed.
+    pub fn with_recovered(
+        block: OpBlock,
+        flashblock_index: u64,
+        senders: Chunks<Address>,
+        recovered: Chunks<Recovered<OpTransactionSigned>>,
+        parent: Option<&PendingView>,
+    ) -> Self {
+        let known = parent.map_or(0, |parent| parent.block.body.transactions.len());
+        let mut transaction_indices = parent
+            .map(|parent| parent.transaction_indices.clone())
+            .unwrap_or_default();
+        transaction_indices.push_layer(
+            block
+                .body
+                .transactions
+                .iter()
+                .enumerate()
+                .skip(known)
+                .map(|(index, tx)| (tx.tx_hash(), index)),
+        );
+
+        Self {
+            generation: 0,
+            block_hash: block.header.hash_slow(),
+            block,
+            flashblock_index,
+            payload_id: None,
+            senders,
+            recovered,
+            receipts: Chunks::default(),
+            preconfirmations: Chunks::default(),
+            balances: LayeredMap::default(),
+            nonces: LayeredMap::default(),
+            deployments: LayeredMap::default(),
+            audit: Chunks::default(),
+            received_at: Instant::now(),
+            published_at: Instant::now(),
+            transaction_indices,
+            receipts_slice: OnceLock::new(),
+        }
+    }
+
+    pub fn block_number(&self) -> u64 {
+        self.block.number
+    }
+
+    /// Whether `block`, built under `payload_id`, is the block of this view with more
+    /// transactions appended. Only the last transaction of this view is compared, a restarted
+    /// block doesn't have it at the same position.
+    pub fn is_extended_by(&self, block: &OpBlock, payload_id: Option<PayloadId>) -> bool {
+        let known = self.block.body.transactions.len();
+        self.block_number() == block.number
+            && self.payload_id == payload_id
+            && known <= block.body.transactions.len()
+            && (known == 0
+                || self.block.body.transactions[known - 1].tx_hash()
+                    == block.body.transactions[known - 1].tx_hash())
+    }
+
+    /// Whether this view is `earlier` with more flashblocks applied, rather than its block
+    /// started over from a new first flashblock.
+    pub fn extends(&self, earlier: &PendingView) -> bool {
+        if !earlier.is_extended_by(&self.block, self.payload_id) {
+            return false;
+        }
+        match earlier.audit.last() {
+            Some(last) => self
+                .audit
+                .get(earlier.audit.len() - 1)
+                .is_some_and(|flashblock| flashblock.block_hash == last.block_hash),
+            None => self.flashblock_index >= earlier.flashblock_index,
+        }
+    }
+
+    /// Time from receiving the flashblock to the view becoming visible to RPC readers.
+    pub fn ingest_lag(&self) -> Duration {
+        self.published_at
+            .saturating_duration_since(self.received_at)
+    }
+
+    /// The receipts as a contiguous slice, which building RPC receipts requires. They are only
+    /// flattened once per view and off the ingest path.
+    pub fn receipts_slice(&self) -> &[OpReceipt] {
+        self.receipts_slice
+            .get_or_init(|| self.receipts.iter().cloned().collect())
+    }
+
+    /// Logs matching `filter` of the receipts received so far, positioned like they will be in
+    /// the canonical block.
+    pub fn logs(&self, filter: &Filter) -> Vec<Log> {
+        self.logs_from(filter, 0)
+    }
+
+    /// Like [`Self::logs`], skipping the receipts of the transactions before
+    /// `first_transaction`, so the logs of new flashblocks can be read incrementally.
+    pub fn logs_from(&self, filter: &Filter, first_transaction: usize) -> Vec<Log> {
+        let mut logs = Vec::new();
+        let mut log_index = 0;
+        for (index, receipt) in self.receipts.iter().enumerate() {
+            for log in receipt.logs() {
+                if index >= first_transaction && filter.matches(log) {
+                    logs.push(Log {
+                        inner: log.clone(),
+                        block_hash: Some(self.block_hash),
+                        block_number: Some(self.block.number),
+                        block_timestamp: Some(self.block.timestamp),
+                        transaction_hash: self
+                            .block
+                            .body
+                            .transactions
+                            .get(index)
+                            .map(|tx| tx.tx_hash()),
+                        transaction_index: Some(index as u64),
+                        log_index: Some(log_index),
+                        removed: false,
+                    });
+                }
+                log_index += 1;
+            }
+        }
+        logs
+    }
+
+    /// When the view stops being served.
+    pub fn expires_at(&self) -> Instant {
+        self.published_at + PENDING_VIEW_TTL
+    }
+
+    fn is_fresh(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.published_at) <= PENDING_VIEW_TTL
+    }
+
+    /// Iterates over the transactions of this block in order.
+    pub fn transactions(&self) -> impl Iterator<Item = PendingTransaction<'_>> {
+        (0..self.block.body.transactions.len())
+            .map(|index| PendingTransaction { view: self, index })
+    }
+
+    /// Looks up a transaction in this block.
+    pub fn transaction(&self, tx_hash: TxHash) -> Option<PendingTransaction<'_>> {
+        self.transaction_indices
+            .get(&tx_hash)
+            .map(|&index| PendingTransaction { view: self, index })
+    }
+
+    /// The transaction at `index` in the block.
+    pub fn transaction_at(&self, index: usize) -> Option<PendingTransaction<'_>> {
+        (index < self.block.body.transactions.len())
+            .then_some(PendingTransaction { view: self, index })
+    }
+
+    /// Number of distinct nonces `address` used in this block.
+    pub fn transaction_count(&self, address: Address) -> u64 {
+        self.nonces
+            .get_all(&address)
+            .flatten()
+            .collect::<BTreeSet<_>>()
+            .len() as u64
+    }
+
+    /// The nonce following the highest one `address` used in this block.
+    pub fn next_nonce(&self, address: Address) -> Option<u64> {
+        self.nonces
+            .get_all(&address)
+            .filter_map(|nonces| nonces.last())
+            .max()
+            .map(|nonce| nonce + 1)
+    }
+
+    /// Latest balance of `address` set by the flashblocks of this block.
+    pub fn balance(&self, address: Address) -> Option<U256> {
+        self.balances.get(&address).copied()
+    }
+
+    /// Whether a transaction of this block may have written storage, which isn't sent with the
+    /// flashblocks. Only the transfers that ran no code are known not to, and the L1 attributes
+    /// deposit starting the block, which only updates the L1 block info.
+    pub fn writes_storage(&self) -> bool {
+        let mut cumulative_gas_used = 0;
+        for tx in self.transactions() {
+            let Some(receipt) = tx.receipt() else {
+                return true;
+            };
+            let gas_used = receipt
+                .cumulative_gas_used()
+                .saturating_sub(cumulative_gas_used);
+            cumulative_gas_used = receipt.cumulative_gas_used();
+            let transaction = tx.transaction();
+            if tx.index == 0 && transaction.is_deposit() {
+                continue;
+            }
+            if transaction.is_create() || !transaction.input().is_empty() || gas_used > TRANSFER_GAS
+            {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The block environment of this block, for calls run in its place on the canonical head.
+    /// Fields set by `request` take precedence.
+    pub fn block_overrides(&self, request: Option<BlockOverrides>) -> BlockOverrides {
+        let mut overrides = request.unwrap_or_default();
+        overrides.number = overrides.number.or(Some(U256::from(self.block.number)));
+        overrides.time = overrides.time.or(Some(self.block.timestamp));
+        overrides.coinbase = overrides.coinbase.or(Some(self.block.beneficiary));
+        overrides
+    }
+
+    /// Priority fee per gas `percentile` percent of the transactions of the block paid at most,
+    /// deposits excluded. `None` until the block has such a transaction.
+    pub fn priority_fee_percentile(&self, percentile: u8) -> Option<u128> {
+        let base_fee = self.block.base_fee_per_gas.unwrap_or_default();
+        let mut fees: Vec<u128> = self
+            .block
+            .body
+            .transactions
+            .iter()
+            .filter(|tx| !tx.is_deposit())
+            .filter_map(|tx| tx.effective_tip_per_gas(base_fee))
+            .collect();
+        if fees.is_empty() {
+            return None;
+        }
+        fees.sort_unstable();
+        Some(fees[(fees.len() - 1) * percentile.min(100) as usize / 100])
+    }
+
+    /// Average time between the flashblocks of this block so far, in milliseconds.
+    pub fn flashblock_interval_ms(&self) -> u64 {
+        match (self.audit.first(), self.audit.last()) {
+            (Some(first), Some(last)) if self.audit.len() > 1 => {
+                last.received_at.saturating_sub(first.received_at) / (self.audit.len() as u64 - 1)
+            }
+            _ => DEFAULT_FLASHBLOCK_INTERVAL_MS,
+        }
+    }
+
+    /// Milliseconds from `now` (a unix timestamp in milliseconds) until the flashblock after
+    /// the latest one is expected. A stream that is overdue is given a full interval.
+    pub fn next_flashblock_in_ms(&self, now: u64) -> u64 {
+        let interval = self.flashblock_interval_ms();
+        let next = self
+            .audit
+            .last()
+            .map_or(now, |last| last.received_at + interval);
+        if next > now {
+            next - now
+        } else {
+            interval
+        }
+    }
+}
+
+/// The views of every block with flashblocks in flight, keyed by height. Sequencers may start
+/// building a block before the previous one is complete, so more than one height can be
+/// receiving flashblocks at a time.
+#[derive(Debug, Clone, Default)]
+pub struct PendingBlocks {
+    views: BTreeMap<u64, Arc<PendingView>>,
+    /// Highest generation of the views dropped from the blocks rather than replaced by a later
+    /// view of their block
+    dropped_generation: u64,
+    clock: SharedClock,
+}
+
+impl PendingBlocks {
+    fn fresh(&self) -> impl DoubleEndedIterator<Item = &Arc<PendingView>> {
+        let now = self.clock.now();
+        self.views.values().filter(move |view| view.is_fresh(now))
+    }
+
+    /// The view of the furthest preconfirmed block.
+    pub fn latest(&self) -> Option<&Arc<PendingView>> {
+        self.fresh().next_back()
+    }
+
+    pub fn for_block(&self, block_number: u64) -> Option<&Arc<PendingView>> {
+        self.views
+            .get(&block_number)
+            .filter(|view| view.is_fresh(self.clock.now()))
+    }
+
+    /// Fresh views of the heights in `range`, in ascending order.
+    pub fn range(&self, range: RangeInclusive<u64>) -> impl Iterator<Item = &Arc<PendingView>> {
+        let now = self.clock.now();
+        self.views
+            .range(range)
+            .map(|(_, view)| view)
+            .filter(move |view| view.is_fresh(now))
+    }
+
+    /// Time between blocks, observed from the timestamps of the heights kept. Unknown until two
+    /// heights were seen.
+    pub fn block_time_ms(&self) -> Option<u64> {
+        let first = self.views.values().next()?;
+        let last = self.views.values().next_back()?;
+        let blocks = last.block_number() - first.block_number();
+        (blocks > 0).then(|| {
+            last.block
+                .timestamp
+                .saturating_sub(first.block.timestamp)
+                .saturating_mul(1000)
+                / blocks
+        })
+    }
+
+    /// Generation of the latest view published, including dropped and expired ones.
+    pub fn generation(&self) -> u64 {
+        self.views
+            .values()
+            .map(|view| view.generation)
+            .max()
+            .unwrap_or_default()
+            .max(self.dropped_generation)
+    }
+
+    /// Whether a view published after `generation` was dropped or expired, so a reader that
+    /// read up to `generation` never sees some of the flashblocks.
+    pub fn missed_since(&self, generation: u64) -> bool {
+        let now = self.clock.now();
+        self.dropped_generation > generation
+            || self
+                .views
+                .values()
+                .any(|view| view.generation > generation && !view.is_fresh(now))
+    }
+
+    /// When the most recent view was published, including expired ones.
+    pub fn last_published_at(&self) -> Option<Instant> {
+        self.views.values().map(|view| view.published_at).max()
+    }
+
+    /// Heights with a fresh view, in ascending order.
+    pub fn block_numbers(&self) -> impl Iterator<Item = u64> + '_ {
+        self.fresh().map(|view| view.block_number())
+    }
+
+    /// The view of the in-flight block whose latest flashblock has `block_hash`.
+    pub fn by_hash(&self, block_hash: B256) -> Option<&Arc<PendingView>> {
+        self.fresh()
+            .rev()
+            .find(|view| view.block_hash == block_hash)
+    }
+
+    /// Looks up a transaction in any of the in-flight blocks.
+    pub fn transaction(&self, tx_hash: TxHash) -> Option<PendingTransaction<'_>> {
+        self.fresh()
+            .rev()
+            .find_map(|view| view.transaction(tx_hash))
+    }
+
+    /// Latest balance of `address` after the flashblocks of `block_number` and the blocks
+    /// before it.
+    pub fn balance_at(&self, address: Address, block_number: u64) -> Option<U256> {
+        self.fresh()
+            .rev()
+            .filter(|view| view.block_number() <= block_number)
+            .find_map(|view| view.balance(address))
+    }
+
+    /// Latest balance of `address` across all in-flight blocks.
+    pub fn balance(&self, address: Address) -> Option<U256> {
+        self.fresh().rev().find_map(|view| view.balance(address))
+    }
+
+    /// Whether the blocks from `block_number` on may have written storage, which
+    /// [`Self::state_overrides`] can't carry.
+    pub fn writes_storage(&self, block_number: u64) -> bool {
+        self.range(block_number..=u64::MAX)
+            .any(|view| view.writes_storage())
+    }
+
+    /// The account changes of the blocks from `block_number` on as state overrides, to run
+    /// calls against the canonical state as if the flashblocks were applied. Storage writes
+    /// aren't sent with the flashblocks, only balances and nonces are overridden.
+    pub fn state_overrides(&self, block_number: u64) -> StateOverride {
+        let mut overrides = StateOverride::default();
+        for view in self.range(block_number..=u64::MAX) {
+            for (address, balance) in view.balances.iter() {
+                overrides.entry(*address).or_default().balance = Some(*balance);
+            }
+            for address in view.nonces.keys() {
+                overrides.entry(*address).or_default().nonce = view.next_nonce(*address);
+            }
+        }
+        overrides
+    }
+
+    /// [`Self::state_overrides`] with the overrides of a request applied on top. Each field set
+    /// by the request takes precedence.
+    pub fn merged_state_overrides(
+        &self,
+        block_number: u64,
+        request_overrides: Option<StateOverride>,
+    ) -> StateOverride {
+        let mut overrides = self.state_overrides(block_number);
+        for (address, mut account) in request_overrides.unwrap_or_default() {
+            if let Some(pending) = overrides.remove(&address) {
+                account.balance = account.balance.or(pending.balance);
+                account.nonce = account.nonce.or(pending.nonce);
+            }
+            overrides.insert(address, account);
+        }
+        overrides
+    }
+
+    /// The transaction of the in-flight blocks that deployed a contract at `address`, as the
+    /// view of its block and its index there.
+    pub fn deployment(&self, address: Address) -> Option<(Arc<PendingView>, usize)> {
+        self.fresh()
+            .rev()
+            .find_map(|view| Some((view.clone(), *view.deployments.get(&address)?)))
+    }
+
+    /// The nonce following the highest one `address` used in the in-flight blocks from
+    /// `block_number` on.
+    pub fn next_nonce_since(&self, address: Address, block_number: u64) -> Option<u64> {
+        self.fresh()
+            .filter(|view| view.block_number() >= block_number)
+            .filter_map(|view| view.next_nonce(address))
+            .max()
+    }
+
+    /// `canonical`, the account at the canonical head, after the blocks from `block_number` on.
+    /// Storage writes aren't sent with the flashblocks, so the storage root stays canonical.
+    pub fn account(
+        &self,
+        address: Address,
+        block_number: u64,
+        canonical: Option<Account>,
+    ) -> Option<Account> {
+        let balance = self
+            .range(block_number..=u64::MAX)
+            .rev()
+            .find_map(|view| view.balance(address));
+        let nonce = self.next_nonce_since(address, block_number);
+        if canonical.is_none() && balance.is_none() && nonce.is_none() {
+            return None;
+        }
+
+        let mut account = canonical.unwrap_or(Account {
+            balance: U256::ZERO,
+            nonce: 0,
+            code_hash: KECCAK_EMPTY,
+            storage_root: EMPTY_ROOT_HASH,
+        });
+        account.balance = balance.unwrap_or(account.balance);
+        account.nonce = account.nonce.max(nonce.unwrap_or_default());
+        Some(account)
+    }
+}
+
+/// A websocket frame as it arrived, before any decompression or parsing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawFrame {
+    /// Arrival order of the frame since the node started
+    pub sequence: u64,
+    /// Unix timestamp in milliseconds at which the frame arrived
+    pub received_at: u64,
+    /// Flashblock the frame decoded to, unset until it decoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<u64>,
+    /// Why the frame didn't decode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub frame: Bytes,
+}
+
+/// Websocket frames by arrival. The frames of the current and previous block are kept, and the
+/// last [`UNDECODABLE_FRAMES`] that didn't decode.
+#[derive(Debug, Default)]
+struct RawFrames {
+    next_sequence: u64,
+    frames: VecDeque<RawFrame>,
+}
+
+impl RawFrames {
+    fn get_mut(&mut self, sequence: u64) -> Option<&mut RawFrame> {
+        self.frames
+            .iter_mut()
+            .rev()
+            .find(|frame| frame.sequence == sequence)
+    }
+
+    fn evict(&mut self) {
+        let highest = self
+            .frames
+            .iter()
+            .filter_map(|frame| frame.block_number)
+            .max();
+        let mut undecodable = self
+            .frames
+            .iter()
+            .filter(|frame| frame.error.is_some())
+            .count();
+        self.frames.retain(|frame| {
+            if frame.error.is_some() {
+                // oldest first
+                let keep = undecodable <= UNDECODABLE_FRAMES;
+                undecodable -= 1;
+                return keep;
+            }
+            match (frame.block_number, highest) {
+                (Some(block_number), Some(highest)) => block_number + RAW_FRAME_BLOCKS > highest,
+                // still being decoded
+                _ => true,
+            }
+        });
+    }
+}
+
+/// Hands every published view to the tasks following them.
+#[derive(Debug)]
+struct PublishedViews(broadcast::Sender<Arc<PendingView>>);
+
+impl Default for PublishedViews {
+    fn default() -> Self {
+        Self(broadcast::channel(PUBLISHED_VIEWS_CAPACITY).0)
+    }
+}
+
+/// Holds the latest [`PendingView`] of every in-flight block. The ingest side publishes a new
+/// view per flashblock and RPC readers load them without locking, always seeing a complete
+/// snapshot.
+#[derive(Debug, Default)]
+pub struct PendingViewStore {
+    blocks: ArcSwap<PendingBlocks>,
+    /// Generation of the last published view
+    generation: Arc<AtomicU64>,
+    gas_progress: FanOut,
+    /// Head of every published view
+    flashblock_heads: FanOut,
+    /// Final head of every block, once the next block started
+    block_heads: FanOut,
+    /// Every flashblock applied to the views, as received
+    flashblocks: FanOut,
+    sync_progress: Mutex<SyncProgress>,
+    sync_notifications: FanOut,
+    published: PublishedViews,
+    payloads: Mutex<VecDeque<PayloadRecord>>,
+    /// Websocket frames as received
+    raw_frames: Mutex<RawFrames>,
+    clock: SharedClock,
+}
+
+impl PendingViewStore {
+    /// Clock the views are timestamped and expired with.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.blocks = ArcSwap::from_pointee(PendingBlocks {
+            clock: clock.clone(),
+            ..Default::default()
+        });
+        self.clock = clock;
+        self
+    }
+
+    pub fn clock(&self) -> &SharedClock {
+        &self.clock
+    }
+
+    /// Time since `view` was published.
+    pub fn age(&self, view: &PendingView) -> Duration {
+        self.clock.since(view.published_at)
+    }
+
+    /// Returns the view of the furthest preconfirmed block, unless it is too old to be trusted.
+    pub fn load(&self) -> Option<Arc<PendingView>> {
+        self.blocks.load().latest().cloned()
+    }
+
+    /// Returns the views of all in-flight blocks.
+    pub fn load_blocks(&self) -> Arc<PendingBlocks> {
+        self.blocks.load_full()
+    }
+
+    /// Returns the last published view of `block_number` regardless of its age.
+    pub fn latest_published(&self, block_number: u64) -> Option<Arc<PendingView>> {
+        self.blocks.load().views.get(&block_number).cloned()
+    }
+
+    /// Whether views were published but all of them expired, i.e. the flashblocks stream
+    /// stalled.
+    pub fn is_stale(&self) -> bool {
+        let blocks = self.blocks.load();
+        !blocks.views.is_empty() && blocks.latest().is_none()
+    }
+
+    /// Whether `block_number` is too far behind the furthest preconfirmed block to be tracked.
+    pub fn is_behind(&self, block_number: u64) -> bool {
+        self.blocks
+            .load()
+            .views
+            .keys()
+            .next_back()
+            .is_some_and(|&highest| block_number + RETAINED_BLOCKS <= highest)
+    }
+
+    /// Publishes `view`, replacing the previous view of the same block and dropping blocks that
+    /// fell out of the retained range.
+    pub fn publish(&self, mut view: PendingView) -> Arc<PendingView> {
+        view.generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        view.published_at = self.clock.now();
+        let view = Arc::new(view);
+        // invalidations may swap the blocks concurrently, so retry on conflicts
+        self.blocks.rcu(|current| {
+            let mut blocks = PendingBlocks::clone(current);
+            blocks.views.insert(view.block_number(), view.clone());
+            if let Some(&highest) = blocks.views.keys().next_back() {
+                let mut dropped_generation = blocks.dropped_generation;
+                blocks.views.retain(|&block_number, view| {
+                    let retained = block_number + RETAINED_BLOCKS > highest;
+                    if !retained {
+                        dropped_generation = dropped_generation.max(view.generation);
+                    }
+                    retained
+                });
+                blocks.dropped_generation = dropped_generation;
+            }
+            blocks
+        });
+
+        if let Err(e) = self.gas_progress.publish(&GasProgress::from_view(&view)) {
+            error!("Failed to publish gas progress: {}", e);
+        }
+        let head = FlashblockHead::from_view(&view);
+        if let Err(e) = self.flashblock_heads.publish(&head) {
+            error!("Failed to publish flashblock head: {}", e);
+        }
+        let mut sync_progress = self.sync_progress.lock().unwrap();
+        if sync_progress.record(&view) {
+            self.notify_sync_progress(&sync_progress);
+        }
+        drop(sync_progress);
+        self.record_payload(&view);
+        // nobody may be following the views
+        let _ = self.published.0.send(view.clone());
+        view
+    }
+
+    /// Publishes the final head of `block_number`. Called by the worker applying its
+    /// flashblocks once the next block started and the ones queued before were applied.
+    pub fn complete(&self, block_number: u64) {
+        let Some(view) = self.latest_published(block_number) else {
+            return;
+        };
+        let head = FlashblockHead::from_view(&view);
+        if let Err(e) = self.block_heads.publish(&head) {
+            error!("Failed to publish block head: {}", e);
+        }
+    }
+
+    fn record_payload(&self, view: &PendingView) {
+        let Some(payload_id) = view.payload_id else {
+            return;
+        };
+        let mut payloads = self.payloads.lock().unwrap();
+        // flashblocks of a payload only interleave with those of the other retained heights
+        if payloads
+            .iter()
+            .rev()
+            .take(RETAINED_BLOCKS as usize)
+            .any(|record| record.payload_id == payload_id)
+        {
+            return;
+        }
+        if payloads.len() == RECENT_PAYLOADS {
+            payloads.pop_front();
+        }
+        payloads.push_back(PayloadRecord {
+            payload_id,
+            block_number: view.block_number(),
+            first_seen_at: view.audit.last().map_or(0, |audit| audit.received_at),
+        });
+    }
+
+    /// Looks up the block built by one of the recent payloads.
+    pub fn payload(&self, payload_id: PayloadId) -> Option<PayloadRecord> {
+        self.payloads
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|record| record.payload_id == payload_id)
+            .copied()
+    }
+
+    /// Keeps a websocket frame as it arrived, before any decompression or parsing, and returns
+    /// its sequence number to record what it decoded to.
+    pub fn record_raw_frame(&self, frame: Bytes) -> u64 {
+        let received_at = self.clock.unix_millis();
+        let mut raw_frames = self.raw_frames.lock().unwrap();
+        let sequence = raw_frames.next_sequence;
+        raw_frames.next_sequence += 1;
+        raw_frames.frames.push_back(RawFrame {
+            sequence,
+            received_at,
+            block_number: None,
+            index: None,
+            error: None,
+            frame,
+        });
+        sequence
+    }
+
+    /// Records that frame `sequence` decoded to flashblock `index` of `block_number`.
+    pub fn raw_frame_decoded(&self, sequence: u64, block_number: u64, index: u64) {
+        let mut raw_frames = self.raw_frames.lock().unwrap();
+        if let Some(frame) = raw_frames.get_mut(sequence) {
+            frame.block_number = Some(block_number);
+            frame.index = Some(index);
+        }
+        raw_frames.evict();
+    }
+
+    /// Records why frame `sequence` didn't decode.
+    pub fn raw_frame_failed(&self, sequence: u64, error: String) {
+        let mut raw_frames = self.raw_frames.lock().unwrap();
+        if let Some(frame) = raw_frames.get_mut(sequence) {
+            frame.error = Some(error);
+        }
+        raw_frames.evict();
+    }
+
+    /// Returns the websocket frame flashblock `index` of `block_number` was last received in.
+    pub fn raw_frame(&self, block_number: u64, index: u64) -> Option<Bytes> {
+        self.raw_frames
+            .lock()
+            .unwrap()
+            .frames
+            .iter()
+            .rev()
+            .find(|frame| frame.block_number == Some(block_number) && frame.index == Some(index))
+            .map(|frame| frame.frame.clone())
+    }
+
+    /// Returns the websocket frames of the flashblocks of `block_number`, by index.
+    pub fn raw_frames(&self, block_number: u64) -> BTreeMap<u64, Bytes> {
+        self.raw_frames
+            .lock()
+            .unwrap()
+            .frames
+            .iter()
+            .filter(|frame| frame.block_number == Some(block_number))
+            .filter_map(|frame| Some((frame.index?, frame.frame.clone())))
+            .collect()
+    }
+
+    /// Returns the websocket frames kept, those that didn't decode included, in arrival order.
+    pub fn recent_raw_frames(&self) -> Vec<RawFrame> {
+        self.raw_frames
+            .lock()
+            .unwrap()
+            .frames
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Receives every view published from now on.
+    pub fn subscribe_views(&self) -> broadcast::Receiver<Arc<PendingView>> {
+        self.published.0.subscribe()
+    }
+
+    /// Wakes up after the views published from now on, for tasks that read the store as it
+    /// changes rather than every view.
+    pub fn view_updates(&self) -> ViewUpdates {
+        ViewUpdates(self.subscribe_views())
+    }
+
+    /// Receives every view published from now on on behalf of `subscription`, reported with
+    /// `subscribers` until the returned handle is dropped.
+    pub fn track_views(
+        &self,
+        subscribers: &Arc<Subscribers>,
+        connection_id: u64,
+        subscription: impl Into<String>,
+    ) -> (broadcast::Receiver<Arc<PendingView>>, Subscriber) {
+        let views = self.subscribe_views();
+        let subscriber = subscribers.track(
+            self.generation.clone(),
+            PUBLISHED_VIEWS_CAPACITY,
+            connection_id,
+            subscription,
+        );
+        (views, subscriber)
+    }
+
+    /// Notifications of the head of every published flashblock.
+    pub fn flashblock_heads(&self) -> &FanOut {
+        &self.flashblock_heads
+    }
+
+    /// Notifications of the final head of every block, once the next block started.
+    pub fn block_heads(&self) -> &FanOut {
+        &self.block_heads
+    }
+
+    /// Notifications of every flashblock applied to the views, with its metadata.
+    pub fn flashblocks(&self) -> &FanOut {
+        &self.flashblocks
+    }
+
+    /// Notifications of the block fullness after every published flashblock.
+    pub fn gas_progress(&self) -> &FanOut {
+        &self.gas_progress
+    }
+
+    /// Starts tracking the catch-up of the pending view, when the websocket (re)connected.
+    pub fn start_sync(&self) {
+        let mut sync_progress = self.sync_progress.lock().unwrap();
+        *sync_progress = SyncProgress::default();
+        self.notify_sync_progress(&sync_progress);
+    }
+
+    pub fn sync_progress(&self) -> SyncProgress {
+        *self.sync_progress.lock().unwrap()
+    }
+
+    /// Notifications of the catch-up progress, from a (re)connection until the pending view is
+    /// healthy again.
+    pub fn sync_notifications(&self) -> &FanOut {
+        &self.sync_notifications
+    }
+
+    fn notify_sync_progress(&self, sync_progress: &SyncProgress) {
+        if let Err(e) = self.sync_notifications.publish(sync_progress) {
+            error!("Failed to publish sync progress: {}", e);
+        }
+    }
+
+    /// Drops the view of `block_number`, returning whether there was one. The block is rebuilt
+    /// from the next flashblock with index 0.
+    pub fn invalidate(&self, block_number: u64) -> bool {
+        let previous = self.blocks.rcu(|current| {
+            let mut blocks = PendingBlocks::clone(current);
+            if let Some(view) = blocks.views.remove(&block_number) {
+                blocks.dropped_generation = blocks.dropped_generation.max(view.generation);
+            }
+            blocks
+        });
+        previous.views.contains_key(&block_number)
+    }
+
+    /// Drops the views of all blocks, returning the heights that were dropped.
+    pub fn invalidate_all(&self) -> Vec<u64> {
+        let previous = self.blocks.rcu(|current| PendingBlocks {
+            views: BTreeMap::new(),
+            dropped_generation: current.generation(),
+            clock: current.clock.clone(),
+        });
+        previous.views.keys().copied().collect()
+    }
+}
+
+/// Publications of a [`PendingViewStore`], see [`PendingViewStore::view_updates`].
+#[derive(Debug)]
+pub struct ViewUpdates(broadcast::Receiver<Arc<PendingView>>);
+
+impl ViewUpdates {
+    /// Waits for views to be published since the last call, returning `false` once the store is
+    /// gone. Views published meanwhile are covered by a single wake-up, they are in the store.
+    pub async fn changed(&mut self) -> bool {
+        if matches!(self.0.recv().await, Err(RecvError::Closed)) {
+            return false;
+        }
+        loop {
+            match self.0.try_recv() {
+                Ok(_) | Err(TryRecvError::Lagged(_)) => continue,
+                Err(TryRecvError::Empty) | Err(TryRecvError::Closed) => return true,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn view(block_number: u64) -> PendingView {
+        let mut block = OpBlock::default();
+        block.header.number = block_number;
+        PendingView::new(block, 0, Vec::new())
+    }
+
+    #[test]
+    fn test_chunks() {
+        let mut chunks = Chunks::default();
+        chunks.push_chunk(vec![0, 1]);
+        chunks.push_chunk(Vec::new());
+        let shared = chunks.clone();
+        chunks.push_chunk(vec![2]);
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks.get(2), Some(&2));
+        assert_eq!(chunks.get(3), None);
+        assert_eq!(chunks.iter().copied().collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(shared.len(), 2);
+        assert!(Arc::ptr_eq(&shared.chunks[0], &chunks.chunks[0]));
+    }
+
+    #[test]
+    fn test_layered_map() {
+        let mut map = LayeredMap::default();
+        map.push_layer(HashMap::from([(1, 'a'), (2, 'b')]));
+        map.push_layer(HashMap::new());
+        let shared = map.clone();
+        map.push_layer(HashMap::from([(2, 'c')]));
+
+        assert_eq!(map.get(&2), Some(&'c'));
+        assert_eq!(map.get(&3), None);
+        assert_eq!(map.get_all(&2).collect::<Vec<_>>(), vec![&'b', &'c']);
+        let mut entries: Vec<_> = map.iter().collect();
+        entries.sort();
+        assert_eq!(entries, vec![(&1, &'a'), (&2, &'c')]);
+        assert_eq!(shared.get(&2), Some(&'b'));
+        assert!(Arc::ptr_eq(&shared.layers[0], &map.layers[0]));
+    }
+
+    #[test]
+    fn test_publish_keeps_blocks_by_height() {
+        let store = PendingViewStore::default();
+        assert!(store.load().is_none());
+
+        store.publish(view(1));
+        let mut last = view(1);
+        last.balances.insert(Address::ZERO, U256::from(1));
+        last.nonces.insert(Address::ZERO, BTreeSet::from([4, 5]));
+        store.publish(last);
+
+        // block 3 is built ahead while block 2 is still receiving flashblocks
+        let mut ahead = view(3);
+        ahead.nonces.insert(Address::ZERO, BTreeSet::from([6]));
+        store.publish(ahead);
+        let building = store.publish(view(2));
+
+        let blocks = store.load_blocks();
+        assert_eq!(building.generation, 4);
+        assert_eq!(store.load().unwrap().block_number(), 3);
+        assert_eq!(blocks.block_numbers().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(
+            blocks
+                .range(2..=5)
+                .map(|view| view.block_number())
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(blocks.for_block(1).unwrap().generation, 2);
+        assert_eq!(blocks.balance_at(Address::ZERO, 2), Some(U256::from(1)));
+        assert_eq!(blocks.balance_at(Address::ZERO, 0), None);
+        assert_eq!(blocks.next_nonce_since(Address::ZERO, 1), Some(7));
+        assert_eq!(blocks.next_nonce_since(Address::ZERO, 4), None);
+        assert_eq!(
+            blocks
+                .for_block(1)
+                .unwrap()
+                .transaction_count(Address::ZERO),
+            2
+        );
+    }
+
+    #[test]
+    fn test_by_hash() {
+        let store = PendingViewStore::default();
+        let mut first = view(1);
+        first.block_hash = B256::repeat_byte(0x1);
+        store.publish(first);
+        let mut next = view(2);
+        next.block_hash = B256::repeat_byte(0x2);
+        store.publish(next);
+
+        let block_number = |hash| {
+            let blocks = store.load_blocks();
+            blocks.by_hash(hash).map(|view| view.block_number())
+        };
+        assert_eq!(block_number(B256::repeat_byte(0x1)), Some(1));
+        assert_eq!(block_number(B256::repeat_byte(0x2)), Some(2));
+
+        // a later flashblock of the block replaces its hash
+        let mut last = view(2);
+        last.block_hash = B256::repeat_byte(0x3);
+        store.publish(last);
+        assert_eq!(block_number(B256::repeat_byte(0x2)), None);
+        assert_eq!(block_number(B256::repeat_byte(0x3)), Some(2));
+    }
+
+    #[test]
+    fn test_block_time() {
+        let store = PendingViewStore::default();
+        let view = |number: u64, timestamp: u64| {
+            let mut block = OpBlock::default();
+            block.header.number = number;
+            block.header.timestamp = timestamp;
+            PendingView::new(block, 0, Vec::new())
+        };
+        store.publish(view(10, 100));
+        assert_eq!(store.load_blocks().block_time_ms(), None);
+        // heights may be skipped
+        store.publish(view(13, 106));
+        assert_eq!(store.load_blocks().block_time_ms(), Some(2000));
+    }
+
+    #[test]
+    fn test_publish_drops_old_blocks() {
+        let store = PendingViewStore::default();
+        for block_number in 1..=6 {
+            store.publish(view(block_number));
+        }
+
+        let blocks = store.load_blocks();
+        assert_eq!(blocks.block_numbers().collect::<Vec<_>>(), vec![3, 4, 5, 6]);
+        assert!(store.is_behind(2));
+        assert!(!store.is_behind(3));
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let store = PendingViewStore::default();
+        for block_number in 1..=3 {
+            store.publish(view(block_number));
+        }
+
+        let generation = store.load_blocks().generation();
+        assert!(store.invalidate(3));
+        assert!(!store.invalidate(3));
+        assert_eq!(store.load().unwrap().block_number(), 2);
+        assert!(store.load_blocks().missed_since(generation - 1));
+        assert!(!store.load_blocks().missed_since(generation));
+        assert_eq!(store.invalidate_all(), vec![1, 2]);
+        assert!(store.load().is_none());
+        assert_eq!(store.load_blocks().generation(), generation);
+    }
+
+    #[test]
+    fn test_views_expire_with_clock() {
+        let clock = Arc::new(ManualClock::default());
+        let store = PendingViewStore::default().with_clock(SharedClock::new(clock.clone()));
+        store.publish(view(1));
+        clock.advance(Duration::from_secs(5));
+        store.publish(view(2));
+
+        clock.advance(PENDING_VIEW_TTL);
+        assert_eq!(store.age(&store.load().unwrap()), PENDING_VIEW_TTL);
+        assert!(store.load_blocks().for_block(1).is_none());
+        assert!(!store.is_stale());
+
+        clock.advance(Duration::from_millis(1));
+        assert!(store.load().is_none());
+        assert!(store.is_stale());
+    }
+
+    #[test]
+    fn test_publish_notifies_gas_progress() {
+        let store = PendingViewStore::default();
+        let mut receiver = store.gas_progress().subscribe();
+        store.publish(view(1));
+        store.publish(view(2));
+
+        assert!(receiver.try_recv().is_ok());
+        assert!(receiver.try_recv().is_ok());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_publish_notifies_heads() {
+        let store = PendingViewStore::default();
+        let mut flashblock_heads = store.flashblock_heads().subscribe();
+        let mut block_heads = store.block_heads().subscribe();
+        store.publish(view(1));
+        let mut last = view(1);
+        last.flashblock_index = 1;
+        store.publish(last);
+        store.publish(view(2));
+
+        let heads = std::iter::from_fn(|| flashblock_heads.try_recv().ok());
+        assert_eq!(heads.count(), 3);
+        // block 1 completes once its worker is told so, with its last flashblock
+        assert!(block_heads.try_recv().is_err());
+        store.complete(1);
+        let completed = block_heads.try_recv().unwrap();
+        let completed: FlashblockHead = serde_json::from_str(completed.payload().get()).unwrap();
+        assert_eq!(completed.header.number, 1);
+        assert_eq!(completed.flashblock_index, 1);
+        assert!(block_heads.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_subscribe_views() {
+        let store = PendingViewStore::default();
+        store.publish(view(1));
+        let mut receiver = store.subscribe_views();
+        store.publish(view(2));
+
+        assert_eq!(receiver.try_recv().unwrap().block_number(), 2);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_view_updates() {
+        let store = PendingViewStore::default();
+        let mut updates = store.view_updates();
+        store.publish(view(1));
+        store.publish(view(2));
+
+        // both publications wake the reader once
+        assert!(updates.changed().await);
+        let waited = tokio::time::timeout(Duration::from_millis(10), updates.changed()).await;
+        assert!(waited.is_err());
+
+        store.publish(view(3));
+        assert!(updates.changed().await);
+    }
+
+    #[test]
+    fn test_sync_progress() {
+        let store = PendingViewStore::default();
+        let mut receiver = store.sync_notifications().subscribe();
+        store.start_sync();
+
+        // the connection was established in the middle of block 1
+        let mut mid_block = view(1);
+        mid_block.flashblock_index = 3;
+        store.publish(mid_block);
+        store.publish(view(2));
+        let progress = store.sync_progress();
+        assert!(!progress.syncing);
+        assert_eq!(progress.recovered_blocks, 2);
+        assert_eq!(progress.recovered_flashblocks, 2);
+        assert_eq!(progress.block, Some(2));
+
+        // nothing is sent once the view is healthy
+        store.publish(view(3));
+        for _ in 0..3 {
+            assert!(receiver.try_recv().is_ok());
+        }
+        assert!(receiver.try_recv().is_err());
+
+        store.start_sync();
+        assert!(store.sync_progress().syncing);
+        assert_eq!(store.sync_progress().recovered_flashblocks, 0);
+    }
+
+    #[test]
+    fn test_payload_records() {
+        let store = PendingViewStore::default();
+        let payload_id = PayloadId::new([1; 8]);
+        let mut first = view(1);
+        first.payload_id = Some(payload_id);
+        store.publish(first.clone());
+        first.flashblock_index = 1;
+        store.publish(first);
+
+        let mut second = view(2);
+        second.payload_id = Some(PayloadId::new([2; 8]));
+        store.publish(second);
+
+        let record = store.payload(payload_id).unwrap();
+        assert_eq!(record.block_number, 1);
+        assert_eq!(store.payloads.lock().unwrap().len(), 2);
+        assert!(store.payload(PayloadId::new([3; 8])).is_none());
+    }
+
+    #[test]
+    fn test_raw_frames_of_two_blocks_are_kept() {
+        let store = PendingViewStore::default();
+        let record = |block_number, index, frame: &'static [u8]| {
+            let sequence = store.record_raw_frame(Bytes::from_static(frame));
+            store.raw_frame_decoded(sequence, block_number, index);
+        };
+        record(1, 0, b"frame 1.0");
+        record(2, 0, b"frame 2.0");
+        record(2, 1, b"frame 2.1");
+        assert_eq!(
+            store.raw_frame(1, 0),
+            Some(Bytes::from_static(b"frame 1.0"))
+        );
+
+        assert_eq!(
+            store.raw_frames(2).into_keys().collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+
+        record(3, 0, b"frame 3.0");
+        assert!(store.raw_frame(1, 0).is_none());
+        assert!(store.raw_frames(1).is_empty());
+        assert_eq!(
+            store.raw_frame(2, 1),
+            Some(Bytes::from_static(b"frame 2.1"))
+        );
+        assert!(store.raw_frame(3, 1).is_none());
+    }
+
+    #[test]
+    fn test_undecodable_raw_frames_are_kept() {
+        let store = PendingViewStore::default();
+        let sequence = store.record_raw_frame(Bytes::from_static(b"frame 1.0"));
+        store.raw_frame_decoded(sequence, 1, 0);
+        for _ in 0..=UNDECODABLE_FRAMES {
+            let sequence = store.record_raw_frame(Bytes::from_static(b"garbage"));
+            store.raw_frame_failed(sequence, "invalid json".to_string());
+        }
+        // still decoding
+        let in_flight = store.record_raw_frame(Bytes::from_static(b"frame 1.1"));
+
+        let frames = store.recent_raw_frames();
+        assert_eq!(frames.len(), UNDECODABLE_FRAMES + 2);
+        assert_eq!(frames[0].block_number, Some(1));
+        // the oldest undecodable frame was dropped
+        assert_eq!(frames[1].sequence, 2);
+        assert_eq!(frames[1].error.as_deref(), Some("invalid json"));
+        assert_eq!(frames[1].frame, Bytes::from_static(b"garbage"));
+        assert_eq!(frames.last().unwrap().sequence, in_flight);
+        assert!(store.raw_frames(1).contains_key(&0));
+        assert!(!store.raw_frames(1).contains_key(&1));
+    }
+
+    #[test]
+    fn test_logs_are_filtered_and_indexed() {
+        let log = |address: Address| alloy_primitives::Log {
+            address,
+            data: alloy_primitives::LogData::new_unchecked(vec![B256::ZERO], Bytes::new()),
+        };
+        let receipt = |logs| {
+            OpReceipt::Eip1559(alloy_consensus::Receipt {
+                status: true.into(),
+                cumulative_gas_used: 21000,
+                logs,
+            })
+        };
+        let watched = Address::repeat_byte(0x1);
+        let mut view = view(5);
+        view.receipts.push_chunk(vec![
+            receipt(vec![log(Address::ZERO), log(watched)]),
+            receipt(vec![log(watched)]),
+        ]);
+
+        let logs = view.logs(&Filter::new().address(watched));
+        assert_eq!(
+            logs.iter()
+                .map(|log| (log.transaction_index, log.log_index))
+                .collect::<Vec<_>>(),
+            vec![(Some(0), Some(1)), (Some(1), Some(2))]
+        );
+        assert_eq!(logs[0].block_number, Some(5));
+        assert_eq!(view.logs(&Filter::new()).len(), 3);
+
+        let later = view.logs_from(&Filter::new(), 1);
+        assert_eq!(later.len(), 1);
+        assert_eq!(later[0].log_index, Some(2));
+    }
+
+    #[test]
+    fn test_state_overrides() {
+        let store = PendingViewStore::default();
+        let sender = Address::repeat_byte(0x1);
+        let contract = Address::repeat_byte(0x2);
+
+        let mut first = view(1);
+        first.balances.insert(sender, U256::from(10));
+        first.nonces.insert(sender, BTreeSet::from([1]));
+        store.publish(first);
+        let mut second = view(2);
+        second.balances.insert(sender, U256::from(5));
+        second.nonces.insert(sender, BTreeSet::from([2, 3]));
+        second.deployments.insert(contract, 0);
+        store.publish(second);
+
+        let overrides = store.load_blocks().state_overrides(1);
+        assert_eq!(overrides[&sender].balance, Some(U256::from(5)));
+        assert_eq!(overrides[&sender].nonce, Some(4));
+        // the deployed code isn't known without running the deployment
+        assert!(!overrides.contains_key(&contract));
+        let (view, index) = store.load_blocks().deployment(contract).unwrap();
+        assert_eq!((view.block_number(), index), (2, 0));
+        assert!(store.load_blocks().deployment(sender).is_none());
+
+        // blocks before the one asked for are left out
+        assert!(store.load_blocks().state_overrides(3).is_empty());
+
+        // fields set by the request win, the others keep the flashblocks' values
+        let mut request = StateOverride::default();
+        request.entry(sender).or_default().balance = Some(U256::from(1));
+        let merged = store.load_blocks().merged_state_overrides(1, Some(request));
+        assert_eq!(merged[&sender].balance, Some(U256::from(1)));
+        assert_eq!(merged[&sender].nonce, Some(4));
+    }
+
+    #[test]
+    fn test_priority_fee_percentile() {
+        let tx = |tip: u128| {
+            let tx = alloy_consensus::TxEip1559 {
+                max_fee_per_gas: 1000 + tip,
+                max_priority_fee_per_gas: tip,
+                ..Default::default()
+            };
+            let signature = alloy_primitives::Signature::test_signature();
+            OpTransactionSigned::Eip1559(alloy_consensus::Signed::new_unhashed(tx, signature))
+        };
+        let mut block = OpBlock::default();
+        block.header.base_fee_per_gas = Some(1000);
+        let empty = PendingView::new(block.clone(), 0, Vec::new());
+        assert_eq!(empty.priority_fee_percentile(60), None);
+
+        block.body.transactions = [5, 1, 3, 2, 4].map(tx).to_vec();
+        let view = PendingView::new(block, 0, vec![Address::ZERO; 5]);
+        assert_eq!(view.priority_fee_percentile(0), Some(1));
+        assert_eq!(view.priority_fee_percentile(60), Some(3));
+        assert_eq!(view.priority_fee_percentile(100), Some(5));
+    }
+
+    #[test]
+    fn test_writes_storage() {
+        let tx = |input: &'static [u8]| {
+            let tx = alloy_consensus::TxEip1559 {
+                to: Address::repeat_byte(0x2).into(),
+                input: Bytes::from_static(input),
+                ..Default::default()
+            };
+            let signature = alloy_primitives::Signature::test_signature();
+            OpTransactionSigned::Eip1559(alloy_consensus::Signed::new_unhashed(tx, signature))
+        };
+        let receipt = |cumulative_gas_used| {
+            OpReceipt::Eip1559(alloy_consensus::Receipt {
+                status: true.into(),
+                cumulative_gas_used,
+                logs: vec![],
+            })
+        };
+        let view = |transactions: Vec<OpTransactionSigned>, receipts: Vec<OpReceipt>| {
+            let mut block = OpBlock::default();
+            let senders = vec![Address::ZERO; transactions.len()];
+            block.body.transactions = transactions;
+            let mut view = PendingView::new(block, 0, senders);
+            view.receipts.push_chunk(receipts);
+            view
+        };
+
+        let transfers = view(vec![tx(&[]), tx(&[])], vec![receipt(21000), receipt(42000)]);
+        assert!(!transfers.writes_storage());
+        // a transfer running the code of its recipient
+        let fallback = view(vec![tx(&[]), tx(&[])], vec![receipt(21000), receipt(50000)]);
+        assert!(fallback.writes_storage());
+        let call = view(vec![tx(&[0x1])], vec![receipt(30000)]);
+        assert!(call.writes_storage());
+        // the gas a transaction without receipt used isn't known
+        let unknown = view(vec![tx(&[])], vec![]);
+        assert!(unknown.writes_storage());
+
+        let store = PendingViewStore::default();
+        let mut first = transfers;
+        first.block.header.number = 1;
+        store.publish(first);
+        let mut second = call;
+        second.block.header.number = 2;
+        store.publish(second);
+        assert!(store.load_blocks().writes_storage(1));
+        assert!(!store.load_blocks().writes_storage(3));
+    }
+
+    #[test]
+    fn test_account() {
+        let store = PendingViewStore::default();
+        let sender = Address::repeat_byte(0x1);
+        let funded = Address::repeat_byte(0x2);
+        let canonical = || Account {
+            balance: U256::from(100),
+            nonce: 7,
+            code_hash: KECCAK_EMPTY,
+            storage_root: B256::repeat_byte(0x3),
+        };
+
+        let mut view = view(2);
+        view.balances.insert(sender, U256::from(90));
+        view.nonces.insert(sender, BTreeSet::from([7]));
+        view.balances.insert(funded, U256::from(10));
+        store.publish(view);
+        let blocks = store.load_blocks();
+
+        let account = blocks.account(sender, 2, Some(canonical())).unwrap();
+        assert_eq!(account.balance, U256::from(90));
+        assert_eq!(account.nonce, 8);
+        assert_eq!(account.storage_root, B256::repeat_byte(0x3));
+
+        let account = blocks.account(funded, 2, None).unwrap();
+        assert_eq!(account.balance, U256::from(10));
+        assert_eq!(account.code_hash, KECCAK_EMPTY);
+        assert_eq!(account.storage_root, EMPTY_ROOT_HASH);
+
+        assert!(blocks.account(Address::repeat_byte(0x4), 2, None).is_none());
+        // the block isn't built on the canonical head the account was read at
+        assert_eq!(
+            blocks.account(sender, 3, Some(canonical())),
+            Some(canonical())
+        );
+    }
+}
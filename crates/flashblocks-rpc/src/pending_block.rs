@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use crate::pending::PendingViewStore;
+use reth::providers::{BlockNumReader, BlockReader};
+use reth::rpc::server_types::eth::PendingBlock;
+use reth_optimism_primitives::{OpBlock, OpReceipt};
+use reth_primitives::RecoveredBlock;
+use reth_rpc_eth_api::helpers::LoadPendingBlock;
+use reth_rpc_eth_api::RpcNodeCore;
+use tracing::{debug, error};
+
+/// Installs the flashblocks pending view as the eth API's locally built pending block.
+///
+/// Core methods reading the `pending` block (`eth_getBlockReceipts`, ...) reuse that block
+/// instead of building one from the txpool, and those replaying it (`eth_callMany`,
+/// `debug_traceCallMany` on `pending`) see the storage its transactions wrote, which the
+/// flashblocks don't send. Calls on `pending` (`eth_call`, `eth_estimateGas`) only take its block
+/// environment, they still run on the canonical state. The eth API only reuses the block while
+/// it is the child of the canonical head and hasn't expired.
+#[derive(Debug)]
+pub struct PendingBlockSync<Eth> {
+    eth_api: Eth,
+    pending: Arc<PendingViewStore>,
+    last_generation: u64,
+}
+
+impl<Eth> PendingBlockSync<Eth>
+where
+    Eth:
+        LoadPendingBlock + RpcNodeCore<Provider: BlockReader<Block = OpBlock, Receipt = OpReceipt>>,
+{
+    pub fn new(eth_api: Eth, pending: Arc<PendingViewStore>) -> Self {
+        Self {
+            eth_api,
+            pending,
+            last_generation: 0,
+        }
+    }
+
+    /// Installs the view of the block after the canonical head, if it changed since the last
+    /// call and has a receipt for every transaction.
+    pub async fn sync(&mut self) {
+        let head = match self.eth_api.provider().best_block_number() {
+            Ok(head) => head,
+            Err(e) => {
+                error!("Failed to read the canonical head: {}", e);
+                return;
+            }
+        };
+        let Some(view) = self.pending.load_blocks().for_block(head + 1).cloned() else {
+            return;
+        };
+        if view.generation == self.last_generation
+            || view.receipts.len() != view.block.body.transactions.len()
+        {
+            return;
+        }
+        self.last_generation = view.generation;
+
+        let senders = view.senders.iter().copied().collect();
+        let block = RecoveredBlock::new_unhashed(view.block.clone(), senders);
+        let pending_block = PendingBlock {
+            expires_at: view.expires_at(),
+            block: Arc::new(block),
+            receipts: Arc::new(view.receipts_slice().to_vec()),
+        };
+        *self.eth_api.pending_block().lock().await = Some(pending_block);
+        debug!(
+            "Installed pending block {} at flashblock {}",
+            view.block_number(),
+            view.flashblock_index
+        );
+    }
+
+    /// Syncs after every publication. A view published before the node imported its parent is
+    /// installed with the next flashblock.
+    pub async fn run(mut self) {
+        let mut updates = self.pending.view_updates();
+        while updates.changed().await {
+            self.sync().await;
+        }
+    }
+}
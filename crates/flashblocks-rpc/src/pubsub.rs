@@ -0,0 +1,636 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::filters::{Advance, Cursor, PendingFilterKind};
+use crate::metrics::Metrics;
+use crate::pending::{PendingBlocks, PendingView, PendingViewStore};
+use jsonrpsee::core::server::{SubscriptionMessage, SubscriptionSink};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use tokio::sync::broadcast::{
+    self,
+    error::{RecvError, TryRecvError},
+};
+
+/// A notification serialized once at publish time and shared by every subscriber.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    payload: Arc<RawValue>,
+    published_at: Instant,
+    /// Position of the notification in the fan-out, starting at 1
+    sequence: u64,
+}
+
+impl Notification {
+    /// The serialized notification.
+    pub fn payload(&self) -> &RawValue {
+        &self.payload
+    }
+}
+
+/// How a subscriber that falls behind the broadcast buffer is treated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SlowSubscriberPolicy {
+    /// Skip the notifications that were overwritten and continue from the oldest retained one.
+    #[default]
+    DropOldest,
+    /// Skip straight to the newest notification, for streams where only the latest state
+    /// matters.
+    Coalesce,
+    /// Close the subscription.
+    Disconnect,
+}
+
+/// Broadcasts notifications to any number of subscribers, serializing each one only once
+/// regardless of how many connections are attached.
+#[derive(Debug, Clone)]
+pub struct FanOut {
+    sender: broadcast::Sender<Notification>,
+    /// Sequence of the last published notification
+    published: Arc<AtomicU64>,
+    capacity: usize,
+}
+
+impl FanOut {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            sender,
+            published: Arc::new(AtomicU64::new(0)),
+            capacity,
+        }
+    }
+
+    /// Serializes `item` and broadcasts it, returning the number of subscribers it was sent to.
+    /// Nothing is serialized when there are no subscribers.
+    pub fn publish<T: Serialize>(&self, item: &T) -> Result<usize, serde_json::Error> {
+        if self.sender.receiver_count() == 0 {
+            return Ok(0);
+        }
+
+        let notification = Notification {
+            payload: Arc::from(serde_json::value::to_raw_value(item)?),
+            published_at: Instant::now(),
+            sequence: self.published.fetch_add(1, Ordering::Relaxed) + 1,
+        };
+        Ok(self.sender.send(notification).unwrap_or(0))
+    }
+
+    /// Broadcasts already serialized json as is, returning the number of subscribers it was sent
+    /// to.
+    pub fn publish_raw(&self, payload: Box<RawValue>) -> usize {
+        if self.sender.receiver_count() == 0 {
+            return 0;
+        }
+
+        let notification = Notification {
+            payload: Arc::from(payload),
+            published_at: Instant::now(),
+            sequence: self.published.fetch_add(1, Ordering::Relaxed) + 1,
+        };
+        self.sender.send(notification).unwrap_or(0)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Notification> {
+        self.sender.subscribe()
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+impl Default for FanOut {
+    fn default() -> Self {
+        Self::new(128)
+    }
+}
+
+/// Counters of a single subscriber.
+#[derive(Debug, Default)]
+struct SubscriberStats {
+    /// Sequence of the last notification published on the fan-out
+    published: Arc<AtomicU64>,
+    capacity: usize,
+    /// Sequence of the last notification the subscriber received
+    received: AtomicU64,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+    coalesced: AtomicU64,
+    /// Time from publishing to delivering the last notification
+    lag_micros: AtomicU64,
+}
+
+impl SubscriberStats {
+    fn receive(&self, notification: &Notification) {
+        self.received
+            .store(notification.sequence, Ordering::Relaxed);
+    }
+
+    fn deliver(&self, published_at: Instant, metrics: &Metrics) {
+        let lag = Instant::now().saturating_duration_since(published_at);
+        metrics.subscription_fanout_duration.record(lag);
+        self.delivered.fetch_add(1, Ordering::Relaxed);
+        self.lag_micros
+            .store(lag.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Notifications published but not yet received, at most the fan-out buffer.
+    fn queue_depth(&self) -> u64 {
+        let published = self.published.load(Ordering::Relaxed);
+        published
+            .saturating_sub(self.received.load(Ordering::Relaxed))
+            .min(self.capacity as u64)
+    }
+}
+
+/// A subscriber of one of the fan-outs, as reported by `flashblocks_getSubscribers`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriberInfo {
+    pub id: u64,
+    /// The RPC connection the subscription was made on
+    pub connection_id: u64,
+    pub subscription: String,
+    /// Notifications waiting to be sent, bounded by the fan-out buffer
+    pub queue_depth: u64,
+    /// Time from publishing to sending the last delivered notification
+    pub lag_micros: u64,
+    pub delivered: u64,
+    /// Notifications skipped because the subscriber fell behind the buffer
+    pub dropped: u64,
+    /// Notifications replaced by a newer one before they were sent
+    pub coalesced: u64,
+}
+
+#[derive(Debug)]
+struct SubscriberEntry {
+    connection_id: u64,
+    subscription: String,
+    stats: Arc<SubscriberStats>,
+}
+
+/// The live subscribers of the fan-outs an API serves, so slow consumers can be told apart.
+#[derive(Debug, Default)]
+pub struct Subscribers {
+    next_id: AtomicU64,
+    subscribers: Mutex<BTreeMap<u64, SubscriberEntry>>,
+}
+
+impl Subscribers {
+    /// Subscribes to `fan_out` on behalf of `subscription` on `connection_id`. The subscriber
+    /// is reported until the returned handle is dropped.
+    pub fn subscribe(
+        self: &Arc<Self>,
+        fan_out: &FanOut,
+        connection_id: u64,
+        subscription: impl Into<String>,
+    ) -> (broadcast::Receiver<Notification>, Subscriber) {
+        let receiver = fan_out.subscribe();
+        let subscriber = self.track(
+            fan_out.published.clone(),
+            fan_out.capacity,
+            connection_id,
+            subscription,
+        );
+        (receiver, subscriber)
+    }
+
+    /// Tracks a subscriber of a broadcast channel other than a fan-out, whose last sent item
+    /// has the sequence `published` and which buffers `capacity` items.
+    pub fn track(
+        self: &Arc<Self>,
+        published: Arc<AtomicU64>,
+        capacity: usize,
+        connection_id: u64,
+        subscription: impl Into<String>,
+    ) -> Subscriber {
+        let stats = Arc::new(SubscriberStats {
+            // only notifications published from now on are received
+            received: AtomicU64::new(published.load(Ordering::Relaxed)),
+            published,
+            capacity,
+            ..Default::default()
+        });
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.subscribers.lock().unwrap().insert(
+            id,
+            SubscriberEntry {
+                connection_id,
+                subscription: subscription.into(),
+                stats: stats.clone(),
+            },
+        );
+        Subscriber {
+            id,
+            subscribers: self.clone(),
+            stats,
+        }
+    }
+
+    pub fn list(&self) -> Vec<SubscriberInfo> {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&id, entry)| SubscriberInfo {
+                id,
+                connection_id: entry.connection_id,
+                subscription: entry.subscription.clone(),
+                queue_depth: entry.stats.queue_depth(),
+                lag_micros: entry.stats.lag_micros.load(Ordering::Relaxed),
+                delivered: entry.stats.delivered.load(Ordering::Relaxed),
+                dropped: entry.stats.dropped.load(Ordering::Relaxed),
+                coalesced: entry.stats.coalesced.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+/// Handle of a tracked subscriber, removing it from [`Subscribers`] when dropped.
+#[derive(Debug)]
+pub struct Subscriber {
+    id: u64,
+    subscribers: Arc<Subscribers>,
+    stats: Arc<SubscriberStats>,
+}
+
+impl Subscriber {
+    /// Records receiving the item with sequence `sequence` from the channel.
+    pub fn received(&self, sequence: u64) {
+        self.stats.received.store(sequence, Ordering::Relaxed);
+    }
+
+    /// Records skipping `skipped` items the subscriber fell behind on.
+    pub fn dropped(&self, skipped: u64, metrics: &Metrics) {
+        metrics
+            .subscription_notifications_dropped
+            .increment(skipped);
+        self.stats.dropped.fetch_add(skipped, Ordering::Relaxed);
+    }
+
+    /// Records skipping `skipped` items in favor of a newer one.
+    pub fn coalesced(&self, skipped: u64, metrics: &Metrics) {
+        metrics
+            .subscription_notifications_coalesced
+            .increment(skipped);
+        self.stats.coalesced.fetch_add(skipped, Ordering::Relaxed);
+    }
+
+    /// Records delivering an item, published at `published_at` if it was published at all.
+    pub fn delivered(&self, published_at: Option<Instant>, metrics: &Metrics) {
+        match published_at {
+            Some(published_at) => self.stats.deliver(published_at, metrics),
+            None => {
+                self.stats.delivered.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        self.subscribers
+            .subscribers
+            .lock()
+            .unwrap()
+            .remove(&self.id);
+    }
+}
+
+/// Forwards notifications to `sink` until the subscriber goes away, the fan-out is dropped or
+/// the subscriber is disconnected for being too slow.
+pub async fn forward_to_sink(
+    sink: SubscriptionSink,
+    mut receiver: broadcast::Receiver<Notification>,
+    policy: SlowSubscriberPolicy,
+    subscriber: Subscriber,
+) {
+    let metrics = Metrics::default();
+    let stats = &subscriber.stats;
+    loop {
+        let next = next_notification(&mut receiver, policy, &metrics, stats);
+        let notification = tokio::select! {
+            _ = sink.closed() => break,
+            notification = next => notification,
+        };
+        let Some(notification) = notification else {
+            break;
+        };
+
+        let message = SubscriptionMessage::from(notification.payload.as_ref().to_owned());
+        if sink.send(message).await.is_err() {
+            break;
+        }
+        stats.deliver(notification.published_at, &metrics);
+    }
+}
+
+/// A subscription following the flashblocks views. What the views added is read through a
+/// [`Cursor`], so a subscriber falling behind the views skips to the latest one and still
+/// receives everything, unless its policy is [`SlowSubscriberPolicy::Disconnect`].
+pub(crate) struct ViewSubscription {
+    pending: Arc<PendingViewStore>,
+    views: broadcast::Receiver<Arc<PendingView>>,
+    cursor: Cursor,
+    kind: PendingFilterKind,
+    policy: SlowSubscriberPolicy,
+    subscriber: Subscriber,
+    /// When the last view read was published
+    published_at: Option<Instant>,
+    metrics: Metrics,
+}
+
+impl ViewSubscription {
+    /// Follows the views published from now on for the entries of `kind`, reporting the
+    /// subscriber with `subscribers`.
+    pub(crate) fn new(
+        pending: Arc<PendingViewStore>,
+        kind: PendingFilterKind,
+        policy: SlowSubscriberPolicy,
+        subscribers: &Arc<Subscribers>,
+        connection_id: u64,
+        subscription: &str,
+    ) -> Self {
+        let (views, subscriber) = pending.track_views(subscribers, connection_id, subscription);
+        Self {
+            cursor: Cursor::latest(&pending.load_blocks()),
+            pending,
+            views,
+            kind,
+            policy,
+            subscriber,
+            published_at: None,
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Waits for views to be published and returns the blocks to advance on, or `None` once
+    /// the subscription has to close.
+    pub(crate) async fn next(&mut self) -> Option<Arc<PendingBlocks>> {
+        match self.views.recv().await {
+            Ok(view) => self.subscriber.received(view.generation),
+            Err(RecvError::Lagged(skipped)) if self.policy == SlowSubscriberPolicy::Disconnect => {
+                self.subscriber.dropped(skipped, &self.metrics);
+                self.metrics.subscription_slow_disconnects.increment(1);
+                return None;
+            }
+            // the skipped views are read from the blocks
+            Err(RecvError::Lagged(skipped)) => self.subscriber.coalesced(skipped, &self.metrics),
+            Err(RecvError::Closed) => return None,
+        }
+        // the views already queued are read from the blocks along with this one
+        let mut coalesced = 0;
+        while let Ok(view) = self.views.try_recv() {
+            self.subscriber.received(view.generation);
+            coalesced += 1;
+        }
+        if coalesced > 0 {
+            self.subscriber.coalesced(coalesced, &self.metrics);
+        }
+        Some(self.pending.load_blocks())
+    }
+
+    /// Moves past the views of `blocks`, or `None` when some of them were dropped before they
+    /// could be read, as the subscriber can't tell what it missed.
+    pub(crate) fn advance<'a>(&mut self, blocks: &'a PendingBlocks) -> Option<Advance<'a>> {
+        let advance = self.cursor.advance(blocks, &self.kind);
+        if advance.missed {
+            return None;
+        }
+        if let Some((view, _)) = advance.views.last() {
+            self.published_at = Some(view.published_at);
+        }
+        Some(advance)
+    }
+
+    /// Sends `items` in order, returning whether the subscriber is still there. Items read
+    /// from the views are sent with `from_views`.
+    pub(crate) async fn send<T: Serialize>(
+        &self,
+        sink: &SubscriptionSink,
+        items: Vec<T>,
+        from_views: bool,
+    ) -> bool {
+        let published_at = self.published_at.filter(|_| from_views);
+        for item in items {
+            let Ok(message) = serde_json::value::to_raw_value(&item) else {
+                return false;
+            };
+            if sink.send(SubscriptionMessage::from(message)).await.is_err() {
+                return false;
+            }
+            self.subscriber.delivered(published_at, &self.metrics);
+        }
+        true
+    }
+
+    /// Sends the items `items` makes of what every view added until the subscriber goes away
+    /// or the subscription has to close.
+    pub(crate) async fn forward<T: Serialize>(
+        mut self,
+        sink: SubscriptionSink,
+        mut items: impl FnMut(Advance<'_>) -> Vec<T>,
+    ) {
+        loop {
+            let blocks = tokio::select! {
+                _ = sink.closed() => break,
+                blocks = self.next() => blocks,
+            };
+            let Some(blocks) = blocks else {
+                break;
+            };
+            let Some(advance) = self.advance(&blocks) else {
+                break;
+            };
+            if !self.send(&sink, items(advance), true).await {
+                break;
+            }
+        }
+    }
+}
+
+async fn next_notification(
+    receiver: &mut broadcast::Receiver<Notification>,
+    policy: SlowSubscriberPolicy,
+    metrics: &Metrics,
+    stats: &SubscriberStats,
+) -> Option<Notification> {
+    loop {
+        match receiver.recv().await {
+            Ok(mut notification) => {
+                if policy == SlowSubscriberPolicy::Coalesce {
+                    loop {
+                        match receiver.try_recv() {
+                            Ok(newer) => {
+                                metrics.subscription_notifications_coalesced.increment(1);
+                                stats.coalesced.fetch_add(1, Ordering::Relaxed);
+                                notification = newer;
+                            }
+                            Err(TryRecvError::Lagged(skipped)) => {
+                                metrics
+                                    .subscription_notifications_dropped
+                                    .increment(skipped);
+                                stats.dropped.fetch_add(skipped, Ordering::Relaxed);
+                            }
+                            Err(_) => break,
+                        }
+                    }
+                }
+                stats.receive(&notification);
+                return Some(notification);
+            }
+            Err(RecvError::Lagged(skipped)) => {
+                metrics
+                    .subscription_notifications_dropped
+                    .increment(skipped);
+                stats.dropped.fetch_add(skipped, Ordering::Relaxed);
+                if policy == SlowSubscriberPolicy::Disconnect {
+                    metrics.subscription_slow_disconnects.increment(1);
+                    return None;
+                }
+            }
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pending::RETAINED_BLOCKS;
+    use alloy_rpc_types_eth::PendingTransactionFilterKind;
+    use reth_optimism_primitives::OpBlock;
+
+    fn value(notification: &Notification) -> u64 {
+        serde_json::from_str(notification.payload.get()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_publish_without_subscribers_is_noop() {
+        let fan_out = FanOut::new(4);
+        assert_eq!(fan_out.publish(&1u64).unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_skips_overwritten() {
+        let fan_out = FanOut::new(2);
+        let mut receiver = fan_out.subscribe();
+        for i in 0..4u64 {
+            fan_out.publish(&i).unwrap();
+        }
+
+        let metrics = Metrics::default();
+        let stats = SubscriberStats::default();
+        let policy = SlowSubscriberPolicy::DropOldest;
+        let first = next_notification(&mut receiver, policy, &metrics, &stats).await;
+        assert_eq!(value(&first.unwrap()), 2);
+        let second = next_notification(&mut receiver, policy, &metrics, &stats).await;
+        assert_eq!(value(&second.unwrap()), 3);
+    }
+
+    #[tokio::test]
+    async fn test_coalesce_returns_latest() {
+        let fan_out = FanOut::new(8);
+        let mut receiver = fan_out.subscribe();
+        for i in 0..4u64 {
+            fan_out.publish(&i).unwrap();
+        }
+
+        let metrics = Metrics::default();
+        let stats = SubscriberStats::default();
+        let latest = next_notification(
+            &mut receiver,
+            SlowSubscriberPolicy::Coalesce,
+            &metrics,
+            &stats,
+        )
+        .await;
+        assert_eq!(value(&latest.unwrap()), 3);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_slow_subscriber() {
+        let fan_out = FanOut::new(2);
+        let mut receiver = fan_out.subscribe();
+        for i in 0..4u64 {
+            fan_out.publish(&i).unwrap();
+        }
+
+        let metrics = Metrics::default();
+        let stats = SubscriberStats::default();
+        let next = next_notification(
+            &mut receiver,
+            SlowSubscriberPolicy::Disconnect,
+            &metrics,
+            &stats,
+        )
+        .await;
+        assert!(next.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_stats() {
+        let subscribers = Arc::new(Subscribers::default());
+        let fan_out = FanOut::new(2);
+        let (mut receiver, subscriber) = subscribers.subscribe(&fan_out, 7, "gasProgress");
+        for i in 1..=3u64 {
+            fan_out.publish(&i).unwrap();
+        }
+
+        let [info] = subscribers.list().try_into().unwrap();
+        assert_eq!(info.connection_id, 7);
+        assert_eq!(info.queue_depth, 2);
+
+        let metrics = Metrics::default();
+        let policy = SlowSubscriberPolicy::DropOldest;
+        let next = next_notification(&mut receiver, policy, &metrics, &subscriber.stats).await;
+        assert_eq!(value(&next.unwrap()), 2);
+        let [info] = subscribers.list().try_into().unwrap();
+        assert_eq!(info.dropped, 1);
+        assert_eq!(info.queue_depth, 1);
+
+        drop(subscriber);
+        assert!(subscribers.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_view_subscription() {
+        let view = |block_number| {
+            let mut block = OpBlock::default();
+            block.header.number = block_number;
+            PendingView::new(block, 0, Vec::new())
+        };
+        let pending = Arc::new(PendingViewStore::default());
+        let subscribers = Arc::new(Subscribers::default());
+        let mut subscription = ViewSubscription::new(
+            pending.clone(),
+            PendingFilterKind::Transactions(PendingTransactionFilterKind::Hashes),
+            SlowSubscriberPolicy::DropOldest,
+            &subscribers,
+            7,
+            "account",
+        );
+        let [info] = subscribers.list().try_into().unwrap();
+        assert_eq!(info.subscription, "account");
+
+        pending.publish(view(1));
+        pending.publish(view(2));
+        let blocks = subscription.next().await.unwrap();
+        assert_eq!(subscription.advance(&blocks).unwrap().views.len(), 2);
+        let [info] = subscribers.list().try_into().unwrap();
+        assert_eq!(info.coalesced, 1);
+        assert_eq!(info.queue_depth, 0);
+
+        // block 3 falls out of the retained range before it is read
+        pending.publish(view(3));
+        pending.publish(view(3 + RETAINED_BLOCKS));
+        let blocks = subscription.next().await.unwrap();
+        assert!(subscription.advance(&blocks).is_none());
+
+        drop(subscription);
+        assert!(subscribers.list().is_empty());
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, RwLock};
+
+use crate::metrics::Metrics;
+use crate::pending::{PendingView, PendingViewStore, RETAINED_BLOCKS};
+use crate::summaries::{BlockSummary, SummaryStore};
+use alloy_consensus::TxReceipt;
+use alloy_primitives::{TxHash, B256};
+use reth::providers::{BlockNumReader, BlockReader};
+use reth_optimism_primitives::{OpBlock, OpReceipt};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+/// Number of reconciled blocks kept for `base_getReconciliationHistory`, unless configured
+/// otherwise.
+pub const DEFAULT_RECONCILIATION_HISTORY: usize = 64;
+
+/// A preconfirmed receipt that doesn't match the canonical one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptDiff {
+    pub transaction_hash: TxHash,
+    /// Receipt fields that differ, among `status`, `cumulativeGasUsed` and `logs`
+    pub fields: Vec<String>,
+}
+
+/// How the last preconfirmed view of a block compared to the canonical block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reconciliation {
+    pub block_number: u64,
+    pub canonical_hash: B256,
+    /// Block hash sent with the last flashblock of the block
+    pub preconfirmed_hash: B256,
+    pub flashblock_index: u64,
+    /// Number of preconfirmed transactions included in the canonical block
+    pub matched: u64,
+    /// Preconfirmed transactions missing from the canonical block
+    pub missing: Vec<TxHash>,
+    /// Canonical transactions that were never preconfirmed
+    pub unexpected: Vec<TxHash>,
+    pub receipt_diffs: Vec<ReceiptDiff>,
+    /// Unix timestamp in milliseconds at which the block was reconciled
+    pub reconciled_at: u64,
+}
+
+impl Reconciliation {
+    pub fn is_consistent(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty() && self.receipt_diffs.is_empty()
+    }
+}
+
+/// The most recent reconciliations, shared between the reconciler and the RPC.
+#[derive(Debug)]
+pub struct ReconciliationHistory {
+    recent: RwLock<VecDeque<Reconciliation>>,
+    capacity: usize,
+}
+
+impl Default for ReconciliationHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_RECONCILIATION_HISTORY)
+    }
+}
+
+impl ReconciliationHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            recent: RwLock::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    fn record(&self, reconciliation: Reconciliation) {
+        let mut recent = self.recent.write().unwrap();
+        if recent.len() == self.capacity {
+            recent.pop_front();
+        }
+        if self.capacity > 0 {
+            recent.push_back(reconciliation);
+        }
+    }
+
+    /// Returns the recorded reconciliations, newest first, at most `limit` of them.
+    pub fn recent(&self, limit: Option<usize>) -> Vec<Reconciliation> {
+        self.recent
+            .read()
+            .unwrap()
+            .iter()
+            .rev()
+            .take(limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+
+    /// The block `tx_hash` was preconfirmed in but left out of once canonical, among the
+    /// recorded reconciliations.
+    pub fn dropped_from(&self, tx_hash: TxHash) -> Option<u64> {
+        self.recent
+            .read()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|reconciliation| reconciliation.missing.contains(&tx_hash))
+            .map(|reconciliation| reconciliation.block_number)
+    }
+}
+
+/// Compares every new canonical block with the last preconfirmed view of its height.
+#[derive(Debug)]
+pub struct Reconciler<Provider> {
+    provider: Provider,
+    pending: Arc<PendingViewStore>,
+    history: Arc<ReconciliationHistory>,
+    summaries: Option<Arc<SummaryStore>>,
+    /// Highest canonical block checked so far
+    last_block: Option<u64>,
+    metrics: Metrics,
+}
+
+impl<Provider> Reconciler<Provider>
+where
+    Provider: BlockNumReader + BlockReader<Block = OpBlock, Receipt = OpReceipt>,
+{
+    pub fn new(
+        provider: Provider,
+        pending: Arc<PendingViewStore>,
+        history: Arc<ReconciliationHistory>,
+    ) -> Self {
+        Self {
+            provider,
+            pending,
+            history,
+            summaries: None,
+            last_block: None,
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Records a summary of every reconciled block in `summaries`.
+    pub fn with_summaries(mut self, summaries: Arc<SummaryStore>) -> Self {
+        self.summaries = Some(summaries);
+        self
+    }
+
+    /// Reconciles the canonical blocks imported since the last call.
+    pub fn check(&mut self) {
+        let head = match self.provider.best_block_number() {
+            Ok(head) => head,
+            Err(e) => {
+                error!("Failed to read the canonical head: {}", e);
+                return;
+            }
+        };
+        // views of older heights are no longer retained
+        let from = self
+            .last_block
+            .map_or(head, |last| last + 1)
+            .max(head.saturating_sub(RETAINED_BLOCKS));
+        self.last_block = Some(head);
+
+        for block_number in from..=head {
+            let Some(view) = self.pending.latest_published(block_number) else {
+                continue;
+            };
+            let (block, receipts) = match (
+                self.provider.block_by_number(block_number),
+                self.provider.receipts_by_block(block_number.into()),
+            ) {
+                (Ok(Some(block)), Ok(Some(receipts))) => (block, receipts),
+                (Err(e), _) | (_, Err(e)) => {
+                    error!("Failed to read canonical block {}: {}", block_number, e);
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let reconciled_at = self.pending.clock().unix_millis();
+            let reconciliation = reconcile(&view, &block, &receipts, reconciled_at);
+            self.metrics.reconciled_blocks.increment(1);
+            if !reconciliation.is_consistent() {
+                self.metrics.reconciliation_mismatches.increment(1);
+                info!(
+                    "Block {} differs from its preconfirmation: {} missing, {} unexpected, {} receipt diffs",
+                    block_number,
+                    reconciliation.missing.len(),
+                    reconciliation.unexpected.len(),
+                    reconciliation.receipt_diffs.len()
+                );
+            }
+            if let Some(summaries) = &self.summaries {
+                summaries.record(BlockSummary::new(&view, &reconciliation));
+            }
+            self.history.record(reconciliation);
+        }
+    }
+
+    /// Reconciles the blocks the node imported as flashblocks are published. A block is
+    /// reconciled with the first flashblock published after it became canonical.
+    pub async fn run(mut self) {
+        let mut updates = self.pending.view_updates();
+        while updates.changed().await {
+            self.check();
+        }
+    }
+}
+
+/// Compares the transactions and receipts of `view` with the canonical `block`, at the unix
+/// time `reconciled_at`.
+fn reconcile(
+    view: &PendingView,
+    block: &OpBlock,
+    receipts: &[OpReceipt],
+    reconciled_at: u64,
+) -> Reconciliation {
+    let mut matched = 0;
+    let mut unexpected = Vec::new();
+    let mut receipt_diffs = Vec::new();
+    let mut included = HashSet::new();
+    for (index, tx) in block.body.transactions.iter().enumerate() {
+        let tx_hash = tx.tx_hash();
+        included.insert(tx_hash);
+        let Some(preconfirmed) = view.transaction(tx_hash) else {
+            unexpected.push(tx_hash);
+            continue;
+        };
+        matched += 1;
+
+        let (Some(preconfirmed), Some(canonical)) = (preconfirmed.receipt(), receipts.get(index))
+        else {
+            continue;
+        };
+        let mut fields = Vec::new();
+        if preconfirmed.status() != canonical.status() {
+            fields.push("status".to_string());
+        }
+        if preconfirmed.cumulative_gas_used() != canonical.cumulative_gas_used() {
+            fields.push("cumulativeGasUsed".to_string());
+        }
+        if preconfirmed.logs() != canonical.logs() {
+            fields.push("logs".to_string());
+        }
+        if !fields.is_empty() {
+            receipt_diffs.push(ReceiptDiff {
+                transaction_hash: tx_hash,
+                fields,
+            });
+        }
+    }
+
+    let missing = view
+        .transactions()
+        .map(|tx| tx.transaction().tx_hash())
+        .filter(|tx_hash| !included.contains(tx_hash))
+        .collect();
+
+    Reconciliation {
+        block_number: block.number,
+        canonical_hash: block.header.hash_slow(),
+        preconfirmed_hash: view.block_hash,
+        flashblock_index: view.flashblock_index,
+        matched,
+        missing,
+        unexpected,
+        receipt_diffs,
+        reconciled_at,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::signed_tx;
+    use alloy_consensus::Receipt;
+
+    fn receipt(cumulative_gas_used: u64) -> OpReceipt {
+        OpReceipt::Eip1559(Receipt {
+            status: true.into(),
+            cumulative_gas_used,
+            logs: vec![],
+        })
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let mut preconfirmed = OpBlock::default();
+        preconfirmed.header.number = 1;
+        preconfirmed.body.transactions = vec![signed_tx(0), signed_tx(1), signed_tx(2)];
+        let mut view = PendingView::new(preconfirmed, 3, Vec::new());
+        view.receipts
+            .push_chunk(vec![receipt(21000), receipt(42000), receipt(63000)]);
+
+        // the canonical block dropped the second transaction and added a new one
+        let mut canonical = OpBlock::default();
+        canonical.header.number = 1;
+        canonical.body.transactions = vec![signed_tx(0), signed_tx(2), signed_tx(3)];
+        let receipts = vec![receipt(21000), receipt(42000), receipt(63000)];
+
+        let reconciliation = reconcile(&view, &canonical, &receipts, 0);
+        assert_eq!(reconciliation.block_number, 1);
+        assert_eq!(reconciliation.flashblock_index, 3);
+        assert_eq!(reconciliation.matched, 2);
+        assert_eq!(reconciliation.missing, vec![signed_tx(1).tx_hash()]);
+        assert_eq!(reconciliation.unexpected, vec![signed_tx(3).tx_hash()]);
+        let history = ReconciliationHistory::default();
+        history.record(reconciliation.clone());
+        assert_eq!(history.dropped_from(signed_tx(1).tx_hash()), Some(1));
+        assert_eq!(history.dropped_from(signed_tx(0).tx_hash()), None);
+        assert_eq!(
+            reconciliation.receipt_diffs,
+            vec![ReceiptDiff {
+                transaction_hash: signed_tx(2).tx_hash(),
+                fields: vec!["cumulativeGasUsed".to_string()],
+            }]
+        );
+        assert!(!reconciliation.is_consistent());
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let history = ReconciliationHistory::new(2);
+        let view = PendingView::new(OpBlock::default(), 0, Vec::new());
+        for block_number in 1..=3 {
+            let mut block = OpBlock::default();
+            block.header.number = block_number;
+            history.record(reconcile(&view, &block, &[], 0));
+        }
+
+        let recent = history.recent(None);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].block_number, 3);
+        assert_eq!(history.recent(Some(1)).len(), 1);
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+
+use crate::metrics::Metrics;
+use crate::pending::{PendingView, PendingViewStore};
+use alloy_consensus::Transaction;
+use alloy_primitives::{Address, TxHash};
+use reth::transaction_pool::TransactionPool;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// Number of replacements kept for `base_getReplacedTransactions`.
+const RETAINED_REPLACEMENTS: usize = 1024;
+
+/// A preconfirmed transaction that took the nonce of a different transaction waiting in the
+/// txpool, either a fee bump that raced the original or a frontrun.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplacedTransaction {
+    pub sender: Address,
+    pub nonce: u64,
+    pub pooled_hash: TxHash,
+    pub preconfirmed_hash: TxHash,
+    pub block_number: u64,
+    pub flashblock_index: u64,
+    /// Unix timestamp in milliseconds at which the replacement was detected
+    pub detected_at: u64,
+}
+
+/// The most recent replacements, shared between the detector and the RPC.
+#[derive(Debug, Default)]
+pub struct ReplacementTracker {
+    recent: RwLock<VecDeque<ReplacedTransaction>>,
+}
+
+impl ReplacementTracker {
+    fn record(&self, replacement: ReplacedTransaction) {
+        let mut recent = self.recent.write().unwrap();
+        if recent.len() == RETAINED_REPLACEMENTS {
+            recent.pop_front();
+        }
+        recent.push_back(replacement);
+    }
+
+    /// Returns the recorded replacements, newest first, optionally only those of `sender`.
+    pub fn recent(&self, sender: Option<Address>) -> Vec<ReplacedTransaction> {
+        self.recent
+            .read()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|replacement| sender.is_none_or(|sender| replacement.sender == sender))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Compares the transactions of every new flashblock with the txpool and records the ones that
+/// share a sender and nonce with a pooled transaction of a different hash.
+#[derive(Debug)]
+pub struct ReplacementDetector<Pool> {
+    pool: Pool,
+    pending: Arc<PendingViewStore>,
+    tracker: Arc<ReplacementTracker>,
+    /// Number of transactions already checked per block
+    checked: HashMap<u64, usize>,
+    metrics: Metrics,
+}
+
+impl<Pool: TransactionPool> ReplacementDetector<Pool> {
+    pub fn new(
+        pool: Pool,
+        pending: Arc<PendingViewStore>,
+        tracker: Arc<ReplacementTracker>,
+    ) -> Self {
+        Self {
+            pool,
+            pending,
+            tracker,
+            checked: HashMap::new(),
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Checks the transactions preconfirmed since the last call.
+    pub fn check(&mut self) {
+        let blocks = self.pending.load_blocks();
+        self.checked
+            .retain(|block_number, _| blocks.for_block(*block_number).is_some());
+
+        for block_number in blocks.block_numbers() {
+            let Some(view) = blocks.for_block(block_number) else {
+                continue;
+            };
+            let checked = self.checked.entry(block_number).or_default();
+            // a new block at the same height starts over
+            if *checked > view.senders.len() {
+                *checked = 0;
+            }
+
+            let detected_at = self.pending.clock().unix_millis();
+            let replacements = find_replacements(view, *checked, detected_at, |sender, nonce| {
+                self.pool
+                    .get_transaction_by_sender_and_nonce(sender, nonce)
+                    .map(|tx| *tx.hash())
+            });
+            *checked = view.senders.len();
+
+            for replacement in replacements {
+                info!(
+                    "Preconfirmed transaction {} replaced pooled transaction {} (sender {}, nonce {})",
+                    replacement.preconfirmed_hash,
+                    replacement.pooled_hash,
+                    replacement.sender,
+                    replacement.nonce
+                );
+                self.metrics.replaced_transactions.increment(1);
+                self.tracker.record(replacement);
+            }
+        }
+    }
+
+    /// Checks the transactions of every published view.
+    pub async fn run(mut self) {
+        let mut updates = self.pending.view_updates();
+        while updates.changed().await {
+            self.check();
+        }
+    }
+}
+
+/// Returns the transactions of `view` from index `from` whose sender and nonce resolve to a
+/// different hash through `pooled_hash`, detected at the unix time `detected_at`.
+fn find_replacements(
+    view: &PendingView,
+    from: usize,
+    detected_at: u64,
+    pooled_hash: impl Fn(Address, u64) -> Option<TxHash>,
+) -> Vec<ReplacedTransaction> {
+    view.transactions()
+        .skip(from)
+        // deposits never go through the txpool
+        .filter(|tx| !tx.transaction().is_deposit())
+        .filter_map(|tx| {
+            let preconfirmed_hash = tx.transaction().tx_hash();
+            let nonce = tx.transaction().nonce();
+            let pooled_hash = pooled_hash(tx.sender(), nonce)?;
+            (pooled_hash != preconfirmed_hash).then(|| ReplacedTransaction {
+                sender: tx.sender(),
+                nonce,
+                pooled_hash,
+                preconfirmed_hash,
+                block_number: view.block_number(),
+                flashblock_index: tx
+                    .preconfirmation()
+                    .map_or(view.flashblock_index, |preconfirmation| {
+                        preconfirmation.index
+                    }),
+                detected_at,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::eip1559_tx;
+    use alloy_consensus::transaction::SignerRecoverable;
+    use alloy_primitives::B256;
+    use reth_optimism_primitives::OpBlock;
+
+    #[test]
+    fn test_find_replacements() {
+        let tx = eip1559_tx();
+        let sender = tx.recover_signer().unwrap();
+        let hash = tx.tx_hash();
+
+        let mut block = OpBlock::default();
+        block.body.transactions.push(tx);
+        let view = PendingView::new(block, 2, vec![sender]);
+
+        // the same transaction is pooled
+        assert!(find_replacements(&view, 0, 0, |_, _| Some(hash)).is_empty());
+        // nothing is pooled for the nonce
+        assert!(find_replacements(&view, 0, 0, |_, _| None).is_empty());
+
+        let pooled_hash = B256::repeat_byte(0x1);
+        let replacements = find_replacements(&view, 0, 0, |address, nonce| {
+            (address == sender && nonce == 382).then_some(pooled_hash)
+        });
+        assert_eq!(replacements.len(), 1);
+        assert_eq!(replacements[0].pooled_hash, pooled_hash);
+        assert_eq!(replacements[0].preconfirmed_hash, hash);
+        assert_eq!(replacements[0].flashblock_index, 2);
+
+        // already checked transactions are skipped
+        assert!(find_replacements(&view, 1, 0, |_, _| Some(pooled_hash)).is_empty());
+    }
+}
@@ -1,35 +1,108 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{Debug, Display, Formatter};
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::cache::{Cache, CacheKey};
-use crate::metrics::Metrics;
+use crate::cache::Cache;
+use crate::canonical::canonical_nonce;
+use crate::compat;
+use crate::filters::{BlockFilterMode, PendingFilters};
+use crate::metrics::{FallbackMetrics, Metrics, ShadowMetrics};
+use crate::pending::{PendingBlocks, PendingTransaction, PendingView, PendingViewStore};
+use crate::simulations::{cancellable, CancelInspector};
+use crate::submissions::SubmissionTracker;
+use crate::tags::{
+    preconfirmed_unavailable, PendingTagMode, PreconfirmedFilter, PreconfirmedOr,
+    PreconfirmedStateContext,
+};
 use alloy_consensus::transaction::TransactionMeta;
 use alloy_consensus::{transaction::Recovered, transaction::TransactionInfo};
+use alloy_eips::eip2718::Encodable2718;
 use alloy_eips::{BlockId, BlockNumberOrTag};
-use alloy_primitives::{Address, Sealable, TxHash, U256};
+use alloy_primitives::{Address, Bytes, Sealed, TxHash, B256, U256};
 use alloy_rpc_types::TransactionTrait;
-use alloy_rpc_types::{BlockTransactions, Header};
+use alloy_rpc_types::{
+    BlockTransactions, Filter, FilterBlockOption, FilterChanges, FilterId, Header, Index, Log,
+};
+use alloy_rpc_types_engine::PayloadId;
+use alloy_rpc_types_eth::simulate::{SimulatePayload, SimulatedBlock};
+use alloy_rpc_types_eth::{
+    state::{EvmOverrides, StateOverride},
+    Account, BlockOverrides, Bundle, EIP1186AccountProofResponse, EthCallResponse,
+    PendingTransactionFilterKind, StateContext, TransactionIndex, TransactionRequest,
+};
+use alloy_serde::JsonStorageKey;
 use jsonrpsee::{
-    core::{async_trait, RpcResult},
+    core::{async_trait, RegisterMethodError, RpcResult},
     proc_macros::rpc,
+    types::ErrorObject,
+    RpcModule,
 };
+use metrics::Counter;
 use op_alloy_consensus::OpTxEnvelope;
 use op_alloy_consensus::{OpDepositReceipt, OpReceiptEnvelope};
 use op_alloy_network::Optimism;
 use op_alloy_rpc_types::Transaction;
+use reth::providers::HeaderProvider;
 use reth::providers::TransactionsProvider;
+use reth::rpc::server_types::eth::error::ensure_success;
 use reth::rpc::server_types::eth::TransactionSource;
-use reth::{api::BlockBody, providers::HeaderProvider};
+use reth::rpc::server_types::result::internal_rpc_err;
 use reth_optimism_chainspec::OpChainSpec;
 use reth_optimism_primitives::{OpBlock, OpReceipt, OpTransactionSigned};
 use reth_optimism_rpc::OpReceiptBuilder;
-use reth_rpc_eth_api::helpers::EthTransactions;
+use reth_rpc_eth_api::helpers::{
+    Call, EstimateCall, EthCall, EthTransactions, LoadFee, LoadState, SpawnBlocking,
+};
 use reth_rpc_eth_api::{helpers::FullEthApi, RpcBlock};
 use reth_rpc_eth_api::{
     helpers::{EthBlocks, EthState},
     RpcNodeCore,
 };
-use reth_rpc_eth_api::{RpcReceipt, RpcTransaction};
-use tracing::{debug, error, info};
+use reth_rpc_eth_api::{EthFilterApiServer, RpcReceipt, RpcTransaction};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, error, info, instrument};
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Percentile of the priority fees paid in the pending block that fee suggestions are at least
+/// as high as, the same percentile the node's gas price oracle uses for canonical blocks.
+const PRIORITY_FEE_PERCENTILE: u8 = 60;
+
+/// How long `eth_sendRawTransactionSync` waits for the transaction to be preconfirmed when the
+/// request doesn't say.
+const DEFAULT_SEND_SYNC_TIMEOUT_MS: u64 = 2_000;
+
+/// Longest wait `eth_sendRawTransactionSync` accepts, so requests can't hold connections open.
+const MAX_SEND_SYNC_TIMEOUT_MS: u64 = 10_000;
+
+/// Error code of `eth_sendRawTransactionSync` when the transaction wasn't preconfirmed in time.
+const SEND_SYNC_TIMEOUT_CODE: i32 = 4;
+
+/// Error code of `eth_getProof` on the preconfirmed state, the EIP-1474 "resource unavailable".
+const PROOF_UNAVAILABLE_CODE: i32 = -32002;
+
+/// Methods of the overrides that need the node's filter API.
+const FILTER_METHODS: [&str; 7] = [
+    "eth_getLogs",
+    "eth_newFilter",
+    "eth_newBlockFilter",
+    "eth_newPendingTransactionFilter",
+    "eth_getFilterChanges",
+    "eth_getFilterLogs",
+    "eth_uninstallFilter",
+];
+
+/// Correlation id recorded on the span of every request handled by the overrides, so the
+/// flashblocks lookups and canonical fallbacks of a single request can be followed in the logs.
+fn next_request_id() -> u64 {
+    NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed)
+}
 
 #[cfg_attr(not(test), rpc(server, namespace = "eth"))]
 #[cfg_attr(test, rpc(server, client, namespace = "eth"))]
@@ -37,90 +110,610 @@ pub trait EthApiOverride {
     #[method(name = "getBlockByNumber")]
     async fn block_by_number(
         &self,
-        number: BlockNumberOrTag,
+        number: PreconfirmedOr<BlockNumberOrTag>,
         full: bool,
-    ) -> RpcResult<Option<RpcBlock<op_alloy_network::Optimism>>>;
+    ) -> RpcResult<Option<PendingBlock>>;
+
+    /// Also resolves the hash of the pending block, as returned by `getBlockByNumber(pending)`,
+    /// while its flashblocks are in flight.
+    #[method(name = "getBlockByHash")]
+    async fn block_by_hash(&self, hash: B256, full: bool) -> RpcResult<Option<PendingBlock>>;
 
     #[method(name = "getTransactionReceipt")]
-    async fn get_transaction_receipt(
+    async fn get_transaction_receipt(&self, tx_hash: TxHash) -> RpcResult<Option<PendingReceipt>>;
+
+    #[method(name = "getBlockReceipts")]
+    async fn block_receipts(
         &self,
-        tx_hash: TxHash,
-    ) -> RpcResult<Option<RpcReceipt<Optimism>>>;
+        block_id: PreconfirmedOr<BlockId>,
+    ) -> RpcResult<Option<Vec<PendingReceipt>>>;
 
     #[method(name = "getBalance")]
-    async fn get_balance(&self, address: Address, block_number: Option<BlockId>)
-        -> RpcResult<U256>;
+    async fn get_balance(
+        &self,
+        address: Address,
+        block_number: Option<PreconfirmedOr<BlockId>>,
+    ) -> RpcResult<U256>;
+
+    #[method(name = "getAccount")]
+    async fn get_account(
+        &self,
+        address: Address,
+        block: PreconfirmedOr<BlockId>,
+    ) -> RpcResult<Option<Account>>;
+
+    #[method(name = "getCode")]
+    async fn get_code(
+        &self,
+        address: Address,
+        block_number: Option<PreconfirmedOr<BlockId>>,
+    ) -> RpcResult<Bytes>;
+
+    /// Proofs need the state trie, which the flashblocks don't update, so `pending` fails with
+    /// code -32002 while the flashblocks serve it rather than proving state other `pending`
+    /// responses contradict. Shadow mode serves every other `pending` response from the node,
+    /// so there the node's proof is served and only the rejection is counted.
+    #[method(name = "getProof")]
+    async fn get_proof(
+        &self,
+        address: Address,
+        keys: Vec<JsonStorageKey>,
+        block_number: Option<PreconfirmedOr<BlockId>>,
+    ) -> RpcResult<EIP1186AccountProofResponse>;
 
     #[method(name = "getTransactionCount")]
     async fn get_transaction_count(
         &self,
         address: Address,
-        block_number: Option<BlockId>,
+        block_number: Option<PreconfirmedOr<BlockId>>,
     ) -> RpcResult<U256>;
 
     #[method(name = "getTransactionByHash")]
-    async fn transaction_by_hash(
+    async fn transaction_by_hash(&self, tx_hash: TxHash) -> RpcResult<Option<TransactionResponse>>;
+
+    #[method(name = "getTransactionByBlockNumberAndIndex")]
+    async fn transaction_by_block_number_and_index(
         &self,
-        tx_hash: TxHash,
-    ) -> RpcResult<Option<RpcTransaction<Optimism>>>;
+        number: PreconfirmedOr<BlockNumberOrTag>,
+        index: Index,
+    ) -> RpcResult<Option<TransactionResponse>>;
+
+    /// Also resolves the hash of the pending block while its flashblocks are in flight.
+    #[method(name = "getTransactionByBlockHashAndIndex")]
+    async fn transaction_by_block_hash_and_index(
+        &self,
+        hash: B256,
+        index: Index,
+    ) -> RpcResult<Option<TransactionResponse>>;
+
+    #[method(name = "getRawTransactionByHash")]
+    async fn raw_transaction_by_hash(&self, tx_hash: TxHash) -> RpcResult<Option<Bytes>>;
+
+    #[method(name = "getLogs")]
+    async fn get_logs(&self, filter: PreconfirmedFilter) -> RpcResult<Vec<Log>>;
+
+    #[method(name = "newFilter")]
+    async fn new_filter(&self, filter: PreconfirmedFilter) -> RpcResult<FilterId>;
+
+    #[method(name = "newBlockFilter")]
+    async fn new_block_filter(&self) -> RpcResult<FilterId>;
+
+    #[method(name = "newPendingTransactionFilter")]
+    async fn new_pending_transaction_filter(
+        &self,
+        kind: Option<PendingTransactionFilterKind>,
+    ) -> RpcResult<FilterId>;
+
+    #[method(name = "getFilterChanges")]
+    async fn filter_changes(&self, id: FilterId) -> RpcResult<FilterChanges<Transaction>>;
+
+    #[method(name = "getFilterLogs")]
+    async fn filter_logs(&self, id: FilterId) -> RpcResult<Vec<Log>>;
+
+    #[method(name = "uninstallFilter")]
+    async fn uninstall_filter(&self, id: FilterId) -> RpcResult<bool>;
+
+    #[method(name = "gasPrice")]
+    async fn gas_price(&self) -> RpcResult<U256>;
+
+    #[method(name = "maxPriorityFeePerGas")]
+    async fn max_priority_fee_per_gas(&self) -> RpcResult<U256>;
+
+    /// Estimates on the `pending` block run on top of the canonical head with the flashblocks
+    /// applied. Only the balances and nonces the flashblocks send are applied, so while a
+    /// pending transaction may have written storage the estimate runs on the node's own
+    /// pending block, and `preconfirmed` estimates fail as unavailable.
+    #[method(name = "estimateGas")]
+    async fn estimate_gas(
+        &self,
+        request: TransactionRequest,
+        block_number: Option<PreconfirmedOr<BlockId>>,
+        state_override: Option<StateOverride>,
+    ) -> RpcResult<U256>;
+
+    /// Calls on the `pending` block run on top of the canonical head with the flashblocks
+    /// applied. Reverts fail the call with the node's `execution reverted` error, carrying the
+    /// revert data and its decoded reason. As with `eth_estimateGas`, calls run on the node's
+    /// own pending block while a pending transaction may have written storage, and
+    /// `preconfirmed` calls fail as unavailable.
+    #[method(name = "call")]
+    async fn call(
+        &self,
+        request: TransactionRequest,
+        block_number: Option<PreconfirmedOr<BlockId>>,
+        state_overrides: Option<StateOverride>,
+        block_overrides: Option<Box<BlockOverrides>>,
+    ) -> RpcResult<Bytes>;
+
+    /// Bundles on the `pending` block fall back like `eth_call` when the flashblocks may have
+    /// written storage.
+    #[method(name = "callMany")]
+    async fn call_many(
+        &self,
+        bundles: Vec<Bundle>,
+        state_context: Option<PreconfirmedStateContext>,
+        state_override: Option<StateOverride>,
+    ) -> RpcResult<Vec<Vec<EthCallResponse>>>;
+
+    /// Simulations on the `pending` block fall back like `eth_call` when the flashblocks may
+    /// have written storage.
+    #[method(name = "simulateV1")]
+    async fn simulate_v1(
+        &self,
+        payload: SimulatePayload,
+        block_number: Option<PreconfirmedOr<BlockId>>,
+    ) -> RpcResult<Vec<SimulatedBlock<RpcBlock<Optimism>>>>;
+
+    #[method(name = "sendRawTransaction")]
+    async fn send_raw_transaction(&self, transaction: Bytes) -> RpcResult<B256>;
+
+    /// Submits a transaction and waits for a flashblock to include it, returning its
+    /// preconfirmed receipt. Fails with code 4 and the transaction hash when `timeout_ms` passes
+    /// first.
+    #[method(name = "sendRawTransactionSync")]
+    async fn send_raw_transaction_sync(
+        &self,
+        transaction: Bytes,
+        timeout_ms: Option<u64>,
+    ) -> RpcResult<PendingReceipt>;
+}
+
+/// The node's own `eth` filter API. Serves the logs of canonical blocks, which `eth_getLogs`
+/// requests up to the pending block are completed with, and the filters that aren't installed
+/// on the flashblocks state.
+#[async_trait]
+pub trait CanonicalFilters: Debug + Send + Sync {
+    async fn logs(&self, filter: Filter) -> RpcResult<Vec<Log>>;
+
+    async fn new_filter(&self, filter: Filter) -> RpcResult<FilterId>;
+
+    async fn new_block_filter(&self) -> RpcResult<FilterId>;
+
+    async fn filter_changes(&self, id: FilterId) -> RpcResult<FilterChanges<Transaction>>;
+
+    async fn filter_logs(&self, id: FilterId) -> RpcResult<Vec<Log>>;
+
+    async fn uninstall_filter(&self, id: FilterId) -> RpcResult<bool>;
+}
+
+#[async_trait]
+impl<T> CanonicalFilters for T
+where
+    T: EthFilterApiServer<Transaction> + Debug,
+{
+    async fn logs(&self, filter: Filter) -> RpcResult<Vec<Log>> {
+        EthFilterApiServer::logs(self, filter).await
+    }
+
+    async fn new_filter(&self, filter: Filter) -> RpcResult<FilterId> {
+        EthFilterApiServer::new_filter(self, filter).await
+    }
+
+    async fn new_block_filter(&self) -> RpcResult<FilterId> {
+        EthFilterApiServer::new_block_filter(self).await
+    }
+
+    async fn filter_changes(&self, id: FilterId) -> RpcResult<FilterChanges<Transaction>> {
+        EthFilterApiServer::filter_changes(self, id).await
+    }
+
+    async fn filter_logs(&self, id: FilterId) -> RpcResult<Vec<Log>> {
+        EthFilterApiServer::filter_logs(self, id).await
+    }
+
+    async fn uninstall_filter(&self, id: FilterId) -> RpcResult<bool> {
+        EthFilterApiServer::uninstall_filter(self, id).await
+    }
 }
 
 #[derive(Debug)]
 pub struct EthApiExt<Eth> {
-    #[allow(dead_code)] // temporary until we implement the flashblocks API
     eth_api: Eth,
     cache: Arc<Cache>,
+    pending: Arc<PendingViewStore>,
     metrics: Metrics,
     chain_spec: Arc<OpChainSpec>,
+    latest_as_pending: HashSet<LatestAsPendingMethod>,
+    receipt_flashblock_fields: bool,
+    shadow_mode: bool,
+    block_payload_id: bool,
+    pending_compat: bool,
+    response_format: ResponseFormat,
+    canonical_filters: Option<Arc<dyn CanonicalFilters>>,
+    filters: Arc<PendingFilters>,
+    block_filter_mode: BlockFilterMode,
+    sequencer_forwarding: bool,
+    submissions: Arc<SubmissionTracker>,
+    pending_tag_mode: PendingTagMode,
+}
+
+/// Why a request that could be served from the flashblocks state wasn't.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FallbackReason {
+    /// The flashblocks state has nothing for the request
+    CacheMiss,
+    /// The flashblocks stream stalled and its state expired
+    Stale,
+    /// Serving the request from the flashblocks state isn't enabled
+    Disabled,
+    /// The flashblocks state isn't built on top of the canonical head
+    ValidationFailed,
+    /// The flashblocks may have written storage, which calls on them can't see
+    StorageWrites,
+}
+
+impl Display for FallbackReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CacheMiss => write!(f, "cache-miss"),
+            Self::Stale => write!(f, "stale"),
+            Self::Disabled => write!(f, "disabled"),
+            Self::ValidationFailed => write!(f, "validation-failed"),
+            Self::StorageWrites => write!(f, "storage-writes"),
+        }
+    }
+}
+
+impl FallbackReason {
+    /// Reason for not finding something in the flashblocks state of `pending`.
+    pub fn for_miss(pending: &PendingViewStore) -> Self {
+        if pending.is_stale() {
+            Self::Stale
+        } else {
+            Self::CacheMiss
+        }
+    }
+
+    /// Counts a request to `method` that fell back for this reason.
+    pub fn record(self, method: &'static str) {
+        debug!("{} not served from flashblocks: {}", method, self);
+        FallbackMetrics::for_fallback(method, self)
+            .fallbacks
+            .increment(1);
+    }
+}
+
+/// A block, optionally tagged with the payload the builder sent its flashblocks under.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingBlock {
+    #[serde(flatten)]
+    pub block: RpcBlock<Optimism>,
+    pub payload_id: Option<PayloadId>,
+    /// Render the block the way strict client libraries expect, see [`compat::adjust_block`]
+    #[serde(skip)]
+    pub compat: bool,
+    #[serde(skip)]
+    pub format: ResponseFormat,
+}
+
+impl Serialize for PendingBlock {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Fields<'a> {
+            #[serde(flatten)]
+            block: &'a RpcBlock<Optimism>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            payload_id: Option<PayloadId>,
+        }
+
+        let fields = Fields {
+            block: &self.block,
+            payload_id: self.payload_id,
+        };
+        if !self.compat && self.format == ResponseFormat::Optimism {
+            return fields.serialize(serializer);
+        }
+        let mut block = serde_json::to_value(fields).map_err(serde::ser::Error::custom)?;
+        if self.format == ResponseFormat::Ethereum {
+            compat::to_ethereum_block(&mut block);
+        }
+        if self.compat {
+            compat::adjust_block(&mut block);
+        }
+        block.serialize(serializer)
+    }
+}
+
+impl From<RpcBlock<Optimism>> for PendingBlock {
+    fn from(block: RpcBlock<Optimism>) -> Self {
+        Self {
+            block,
+            payload_id: None,
+            compat: false,
+            format: ResponseFormat::Optimism,
+        }
+    }
+}
+
+/// A transaction receipt, optionally tagged with the flashblock it was preconfirmed in.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingReceipt {
+    #[serde(flatten)]
+    pub receipt: RpcReceipt<Optimism>,
+    pub flashblock_index: Option<u64>,
+    /// Unix timestamp in milliseconds at which the flashblock was processed
+    pub preconfirmed_at: Option<u64>,
+    #[serde(skip)]
+    pub format: ResponseFormat,
+}
+
+impl Serialize for PendingReceipt {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Fields<'a> {
+            #[serde(flatten)]
+            receipt: &'a RpcReceipt<Optimism>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            flashblock_index: Option<u64>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            preconfirmed_at: Option<u64>,
+        }
+
+        let fields = Fields {
+            receipt: &self.receipt,
+            flashblock_index: self.flashblock_index,
+            preconfirmed_at: self.preconfirmed_at,
+        };
+        if self.format == ResponseFormat::Optimism {
+            return fields.serialize(serializer);
+        }
+        let mut receipt = serde_json::to_value(fields).map_err(serde::ser::Error::custom)?;
+        compat::to_ethereum_receipt(&mut receipt);
+        receipt.serialize(serializer)
+    }
+}
+
+/// Answer of `eth_getTransactionByHash`, rendered in the configured [`ResponseFormat`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct TransactionResponse {
+    pub transaction: RpcTransaction<Optimism>,
+    #[serde(skip)]
+    pub format: ResponseFormat,
+}
+
+impl Serialize for TransactionResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.format == ResponseFormat::Optimism {
+            return self.transaction.serialize(serializer);
+        }
+        let mut transaction =
+            serde_json::to_value(&self.transaction).map_err(serde::ser::Error::custom)?;
+        compat::to_ethereum_transaction(&mut transaction);
+        transaction.serialize(serializer)
+    }
+}
+
+impl From<RpcReceipt<Optimism>> for PendingReceipt {
+    fn from(receipt: RpcReceipt<Optimism>) -> Self {
+        Self {
+            receipt,
+            flashblock_index: None,
+            preconfirmed_at: None,
+            format: ResponseFormat::Optimism,
+        }
+    }
+}
+
+/// Network types the block, transaction and receipt overrides render their answers with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResponseFormat {
+    /// The Optimism network types, as served by the node's own methods
+    #[default]
+    Optimism,
+    /// The plain Ethereum types, without the L1 fee and deposit fields
+    Ethereum,
+}
+
+impl FromStr for ResponseFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "optimism" => Ok(Self::Optimism),
+            "ethereum" => Ok(Self::Ethereum),
+            _ => Err(format!("invalid response format: {s}")),
+        }
+    }
+}
+
+impl Display for ResponseFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Optimism => write!(f, "optimism"),
+            Self::Ethereum => write!(f, "ethereum"),
+        }
+    }
+}
+
+/// Methods that can be opted in to answer `latest` requests from the flashblocks state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LatestAsPendingMethod {
+    GetBlockByNumber,
+    GetBalance,
+    GetTransactionCount,
+}
+
+impl FromStr for LatestAsPendingMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eth_getBlockByNumber" => Ok(Self::GetBlockByNumber),
+            "eth_getBalance" => Ok(Self::GetBalance),
+            "eth_getTransactionCount" => Ok(Self::GetTransactionCount),
+            _ => Err(format!("method can't serve latest as pending: {s}")),
+        }
+    }
+}
+
+impl LatestAsPendingMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::GetBlockByNumber => "eth_getBlockByNumber",
+            Self::GetBalance => "eth_getBalance",
+            Self::GetTransactionCount => "eth_getTransactionCount",
+        }
+    }
+}
+
+impl Display for LatestAsPendingMethod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
 }
 
 impl<E> EthApiExt<E> {
-    pub fn new(eth_api: E, cache: Arc<Cache>, chain_spec: Arc<OpChainSpec>) -> Self {
+    pub fn new(
+        eth_api: E,
+        cache: Arc<Cache>,
+        pending: Arc<PendingViewStore>,
+        chain_spec: Arc<OpChainSpec>,
+    ) -> Self {
+        let filters = PendingFilters::default().with_clock(pending.clock().clone());
         Self {
             eth_api,
             cache,
+            pending,
             metrics: Metrics::default(),
             chain_spec,
+            latest_as_pending: HashSet::new(),
+            receipt_flashblock_fields: false,
+            shadow_mode: false,
+            block_payload_id: false,
+            pending_compat: false,
+            response_format: ResponseFormat::Optimism,
+            canonical_filters: None,
+            filters: Arc::new(filters),
+            block_filter_mode: BlockFilterMode::default(),
+            sequencer_forwarding: false,
+            submissions: Arc::new(SubmissionTracker::default()),
+            pending_tag_mode: PendingTagMode::default(),
         }
     }
 
-    pub fn transform_block(&self, block: OpBlock, full: bool) -> RpcBlock<Optimism> {
-        let header: alloy_consensus::Header = block.header.clone();
-        let transactions = block.body.transactions.to_vec();
+    /// Serve `eth_getLogs` and the filter API, completing the preconfirmed logs of requests up
+    /// to the pending block with the canonical ones of `canonical_filters` and passing it the
+    /// filters that don't read the flashblocks.
+    pub fn with_canonical_filters(mut self, canonical_filters: Arc<dyn CanonicalFilters>) -> Self {
+        self.canonical_filters = Some(canonical_filters);
+        self
+    }
 
-        if full {
-            let transactions_with_senders = transactions
-                .into_iter()
-                .zip(block.body.recover_signers().unwrap());
-            let converted_txs = transactions_with_senders
-                .enumerate()
-                .map(|(idx, (tx, sender))| {
-                    let signed_tx_ec_recovered = Recovered::new_unchecked(tx.clone(), sender);
-                    let tx_info = TransactionInfo {
-                        hash: Some(tx.tx_hash()),
-                        block_hash: Some(block.header.hash_slow()),
-                        block_number: Some(block.number),
-                        index: Some(idx as u64),
-                        base_fee: block.base_fee_per_gas,
-                    };
-                    self.transform_tx(signed_tx_ec_recovered, tx_info, None)
-                })
-                .collect();
-            RpcBlock::<Optimism> {
-                header: Header::from_consensus(header.seal_slow(), None, None),
-                transactions: BlockTransactions::Full(converted_txs),
-                uncles: Vec::new(),
-                withdrawals: None,
-            }
-        } else {
-            let tx_hashes = transactions.into_iter().map(|tx| tx.tx_hash()).collect();
-            RpcBlock::<Optimism> {
-                header: Header::from_consensus(header.seal_slow(), None, None),
-                transactions: BlockTransactions::Hashes(tx_hashes),
-                uncles: Vec::new(),
-                withdrawals: None,
-            }
+    /// Report the pseudo-heads of `mode` from `eth_newBlockFilter` rather than canonical
+    /// blocks.
+    pub fn with_block_filter_mode(mut self, mode: BlockFilterMode) -> Self {
+        self.block_filter_mode = mode;
+        self
+    }
+
+    /// What the `pending` tag is served from. `preconfirmed` is always served from the
+    /// flashblocks.
+    pub fn with_pending_tag_mode(mut self, mode: PendingTagMode) -> Self {
+        self.pending_tag_mode = mode;
+        self
+    }
+
+    /// Whether the node forwards the transactions sent to it to the sequencer
+    /// (`--rollup.sequencer-http`), as reported for the tracked submissions.
+    pub fn with_sequencer_forwarding(mut self, sequencer_forwarding: bool) -> Self {
+        self.sequencer_forwarding = sequencer_forwarding;
+        self
+    }
+
+    /// Record the transactions sent to the node in `submissions`, shared with the task that
+    /// follows their preconfirmation.
+    pub fn with_submissions(mut self, submissions: Arc<SubmissionTracker>) -> Self {
+        self.submissions = submissions;
+        self
+    }
+
+    fn canonical_filters(&self, method: &str) -> RpcResult<&Arc<dyn CanonicalFilters>> {
+        self.canonical_filters.as_ref().ok_or_else(|| {
+            internal_rpc_err(format!(
+                "{method} is not served by the flashblocks overrides"
+            ))
+        })
+    }
+
+    /// Render the blocks, transactions and receipts of the overrides with the `format` network
+    /// types, whether they are served from the flashblocks state or not.
+    pub fn with_response_format(mut self, format: ResponseFormat) -> Self {
+        self.response_format = format;
+        self
+    }
+
+    /// Render blocks served from the flashblocks state the way strict client libraries expect.
+    pub fn with_pending_compat(mut self, enabled: bool) -> Self {
+        self.pending_compat = enabled;
+        self
+    }
+
+    /// Add `payloadId` to blocks served from the flashblocks state.
+    pub fn with_block_payload_id(mut self, enabled: bool) -> Self {
+        self.block_payload_id = enabled;
+        self
+    }
+
+    /// Compute the flashblocks answer next to the standard one, but serve the standard one and
+    /// only record whether they differ, to evaluate the flashblocks state before serving it.
+    pub fn with_shadow_mode(mut self, enabled: bool) -> Self {
+        self.shadow_mode = enabled;
+        self
+    }
+
+    /// Add `flashblockIndex` and `preconfirmedAt` to receipts served from the flashblocks state.
+    pub fn with_receipt_flashblock_fields(mut self, enabled: bool) -> Self {
+        self.receipt_flashblock_fields = enabled;
+        self
+    }
+
+    /// Serve `latest` requests for `methods` from the flashblocks state whenever the pending
+    /// block directly follows the canonical head.
+    pub fn with_latest_as_pending(
+        mut self,
+        methods: impl IntoIterator<Item = LatestAsPendingMethod>,
+    ) -> Self {
+        self.latest_as_pending.extend(methods);
+        self
+    }
+
+    pub fn transform_block(&self, view: &PendingView, full: bool) -> RpcBlock<Optimism> {
+        render_pending_block(view, full)
+    }
+
+    pub(crate) fn pending_block(&self, view: &PendingView, full: bool) -> PendingBlock {
+        let mut block = PendingBlock::from(self.transform_block(view, full));
+        if self.block_payload_id {
+            block.payload_id = view.payload_id;
         }
+        block.compat = self.pending_compat;
+        block.format = self.response_format;
+        block
     }
 
     pub fn transform_tx(
@@ -129,209 +722,750 @@ impl<E> EthApiExt<E> {
         tx_info: TransactionInfo,
         deposit_receipt: Option<OpDepositReceipt>,
     ) -> Transaction {
-        let tx = tx.convert::<OpTxEnvelope>();
-        let mut deposit_receipt_version = None;
-        let mut deposit_nonce = None;
-
-        if tx.is_deposit() {
-            if let Some(receipt) = deposit_receipt {
-                deposit_receipt_version = receipt.deposit_receipt_version;
-                deposit_nonce = receipt.deposit_nonce;
-            } else {
-                let cached_receipt = self
-                    .cache
-                    .get::<OpReceipt>(&CacheKey::Receipt(tx_info.hash.unwrap()))
-                    .unwrap();
-
-                if let OpReceipt::Deposit(receipt) = cached_receipt {
-                    deposit_receipt_version = receipt.deposit_receipt_version;
-                    deposit_nonce = receipt.deposit_nonce;
-                }
-            }
-        }
-
-        let TransactionInfo {
-            block_hash,
-            block_number,
-            index: transaction_index,
-            base_fee,
-            ..
-        } = tx_info;
-
-        let effective_gas_price = if tx.is_deposit() {
-            // For deposits, we must always set the `gasPrice` field to 0 in rpc
-            // deposit tx don't have a gas price field, but serde of `Transaction` will take care of
-            // it
-            0
-        } else {
-            base_fee
-                .map(|base_fee| {
-                    tx.effective_tip_per_gas(base_fee).unwrap_or_default() + base_fee as u128
-                })
-                .unwrap_or_else(|| tx.max_fee_per_gas())
-        };
+        to_rpc_transaction(tx, tx_info, deposit_receipt)
+    }
 
-        Transaction {
-            inner: alloy_rpc_types_eth::Transaction {
-                inner: tx,
-                block_hash,
-                block_number,
-                transaction_index,
-                effective_gas_price: Some(effective_gas_price),
-            },
-            deposit_nonce,
-            deposit_receipt_version,
+    fn transaction_response(&self, transaction: Transaction) -> TransactionResponse {
+        TransactionResponse {
+            transaction,
+            format: self.response_format,
         }
     }
 
     pub fn transform_receipt(
         &self,
-        receipt: OpReceipt,
-        tx_hash: TxHash,
-        block_number: u64,
+        tx: PendingTransaction<'_>,
+        receipt: &OpReceipt,
         chain_spec: &OpChainSpec,
     ) -> RpcReceipt<Optimism> {
-        let tx = self
-            .cache
-            .get::<OpTransactionSigned>(&CacheKey::Transaction(tx_hash))
-            .unwrap();
+        render_pending_receipt(tx, receipt, chain_spec)
+    }
 
-        let block = self
-            .cache
-            .get::<OpBlock>(&CacheKey::Block(block_number))
-            .unwrap();
-        let mut l1_block_info =
-            reth_optimism_evm::extract_l1_info(&block.body).expect("failed to extract l1 info");
+    /// Fails a request on `preconfirmed` when there is no preconfirmed block to serve it from.
+    /// The node's own pending block isn't the preconfirmed one, so it doesn't fall back to it.
+    fn ensure_preconfirmed(&self, preconfirmed: bool) -> RpcResult<()> {
+        if preconfirmed && self.pending.load().is_none() {
+            return Err(preconfirmed_unavailable());
+        }
+        Ok(())
+    }
 
-        let index = self
-            .cache
-            .get::<u64>(&CacheKey::TransactionIndex(tx_hash))
-            .unwrap();
-        let meta = TransactionMeta {
-            tx_hash,
-            index,
-            block_hash: block.header.hash_slow(),
-            block_number: block.number,
-            base_fee: block.base_fee_per_gas,
-            excess_blob_gas: block.excess_blob_gas,
-            timestamp: block.timestamp,
-        };
+    /// Reason for not finding something in the flashblocks state.
+    fn miss_reason(&self) -> FallbackReason {
+        FallbackReason::for_miss(&self.pending)
+    }
 
-        // get all receipts from cache too
-        let all_receipts = self
-            .cache
-            .get::<Vec<OpReceipt>>(&CacheKey::PendingReceipts(block_number))
-            .unwrap();
+    fn record_fallback(&self, method: &'static str, reason: FallbackReason) {
+        reason.record(method);
+    }
 
-        OpReceiptBuilder::new(
-            chain_spec,
-            &tx,
-            meta,
-            &receipt,
-            &all_receipts,
-            &mut l1_block_info,
-        )
-        .expect("failed to build receipt")
-        .build()
+    /// Serves `flashblocks`, or in shadow mode the `standard` answer after recording whether
+    /// the two differ.
+    async fn serve<T: Serialize>(
+        &self,
+        method: &'static str,
+        flashblocks: T,
+        standard: impl Future<Output = RpcResult<T>>,
+    ) -> RpcResult<T> {
+        if !self.shadow_mode {
+            return Ok(flashblocks);
+        }
+        let standard = standard.await?;
+        self.compare(method, &flashblocks, &standard);
+        Ok(standard)
     }
-}
 
-#[async_trait]
-impl<Eth> EthApiOverrideServer for EthApiExt<Eth>
-where
-    Eth: FullEthApi<NetworkTypes = Optimism> + Send + Sync + 'static,
-    Eth: RpcNodeCore,
-    <Eth as RpcNodeCore>::Provider: HeaderProvider<Header = alloy_consensus::Header>,
-    <Eth as RpcNodeCore>::Provider: TransactionsProvider<Transaction = OpTransactionSigned>,
-{
-    async fn block_by_number(
+    /// Serves a lookup by hash, which the canonical chain answers first and the flashblocks
+    /// only for what isn't canonical yet. In shadow mode nothing is served from the flashblocks,
+    /// their answer is compared with the canonical one once the block is canonical as there is
+    /// nothing to compare it with before. Unknown hashes are ordinary, they only count as a
+    /// fallback while the flashblocks state is stale.
+    fn serve_lookup<T: Serialize>(
         &self,
-        number: BlockNumberOrTag,
-        _full: bool,
-    ) -> RpcResult<Option<RpcBlock<Optimism>>> {
-        debug!("block_by_number: {:?}", number);
-        match number {
-            BlockNumberOrTag::Pending => {
-                debug!("pending block by number, delegating to flashblocks");
-                self.metrics.get_block_by_number.increment(1);
-                if let Some(block) = self.cache.get::<OpBlock>(&CacheKey::PendingBlock) {
-                    return Ok(Some(self.transform_block(block, _full)));
-                } else {
-                    return Ok(None);
+        method: &'static str,
+        served: &Counter,
+        canonical: Option<T>,
+        flashblocks: impl FnOnce() -> Option<T>,
+    ) -> Option<T> {
+        if let Some(canonical) = canonical {
+            if self.shadow_mode {
+                if let Some(flashblocks) = flashblocks() {
+                    self.compare(method, &flashblocks, &canonical);
                 }
             }
-            _ => {
-                info!("non pending block, using standard flow");
-                EthBlocks::rpc_block(&self.eth_api, number.into(), _full)
-                    .await
-                    .map_err(Into::into)
+            return Some(canonical);
+        }
+        let Some(flashblocks) = flashblocks() else {
+            if self.pending.is_stale() {
+                self.record_fallback(method, FallbackReason::Stale);
             }
+            return None;
+        };
+        if self.shadow_mode {
+            return None;
         }
+        served.increment(1);
+        Some(flashblocks)
     }
 
-    async fn get_transaction_receipt(
-        &self,
-        tx_hash: TxHash,
-    ) -> RpcResult<Option<RpcReceipt<Optimism>>> {
-        debug!("get_transaction_receipt: {:?}", tx_hash);
-        let receipt = EthTransactions::transaction_receipt(&self.eth_api, tx_hash).await;
-
-        // check if receipt is none
-        if let Ok(None) = receipt {
-            if let Some(receipt) = self.cache.get::<OpReceipt>(&CacheKey::Receipt(tx_hash)) {
-                self.metrics.get_transaction_receipt.increment(1);
-                return Ok(Some(
-                    self.transform_receipt(
-                        receipt,
-                        tx_hash,
-                        self.cache
-                            .get::<u64>(&CacheKey::ReceiptBlock(tx_hash))
-                            .unwrap(),
-                        self.chain_spec.as_ref(),
-                    ),
-                ));
-            }
+    /// Records whether the flashblocks answer differs from the standard one.
+    fn compare<T: Serialize>(&self, method: &'static str, flashblocks: &T, standard: &T) {
+        let metrics = ShadowMetrics::for_method(method);
+        metrics.comparisons.increment(1);
+        let differences = differences(flashblocks, standard);
+        if differences.is_empty() {
+            debug!("{} flashblocks answer matches the standard one", method);
+        } else {
+            metrics.mismatches.increment(1);
+            info!(
+                "{} flashblocks answer differs from the standard one in: {}",
+                method,
+                differences.join(", ")
+            );
         }
+    }
 
-        return receipt.map_err(Into::into);
+    /// State overrides applying the flashblocks of `view` and the blocks after it to the
+    /// canonical state. Overrides of the request take precedence over them.
+    fn pending_state_overrides(
+        &self,
+        view: &PendingView,
+        request_overrides: Option<StateOverride>,
+    ) -> StateOverride {
+        self.pending
+            .load_blocks()
+            .merged_state_overrides(view.block_number(), request_overrides)
     }
 
-    async fn get_balance(
+    /// Simulates the first block of `payload` in place of the pending block `view`, on top of
+    /// the canonical head with the flashblocks applied and the block environment of `view`.
+    /// Overrides of the request take precedence.
+    pub(crate) fn simulate_on_pending(
         &self,
-        address: Address,
-        block_number: Option<BlockId>,
-    ) -> RpcResult<U256> {
-        debug!("get_balance: {:?}", address);
-        let block_id = block_number.unwrap_or_default();
-        if block_id.is_pending() {
-            self.metrics.get_balance.increment(1);
-            if let Some(balance) = self.cache.get::<U256>(&CacheKey::AccountBalance(address)) {
-                return Ok(balance);
-            }
-            // If pending not found, use standard flow below
+        view: &PendingView,
+        mut payload: SimulatePayload,
+    ) -> SimulatePayload {
+        if let Some(first) = payload.block_state_calls.first_mut() {
+            let state_overrides = first.state_overrides.take();
+            first.state_overrides = Some(self.pending_state_overrides(view, state_overrides));
+            first.block_overrides = Some(view.block_overrides(first.block_overrides.take()));
         }
+        payload
+    }
 
-        EthState::balance(&self.eth_api, address, block_number)
-            .await
-            .map_err(Into::into)
+    /// Renders `tx_hash` from the pending view, if it has been preconfirmed.
+    pub(crate) fn pending_transaction(&self, tx_hash: TxHash) -> Option<Transaction> {
+        let blocks = self.pending.load_blocks();
+        let tx = blocks.transaction(tx_hash)?;
+        Some(self.render_transaction(tx))
     }
 
-    async fn get_transaction_count(
-        &self,
-        address: Address,
-        block_number: Option<BlockId>,
+    pub(crate) fn render_transaction(&self, tx: PendingTransaction<'_>) -> Transaction {
+        render_pending_transaction(tx)
+    }
+
+    /// Builds the receipt of `tx_hash` from the pending view, if it has been preconfirmed.
+    pub(crate) fn pending_receipt(&self, tx_hash: TxHash) -> Option<PendingReceipt> {
+        let blocks = self.pending.load_blocks();
+        let tx = blocks.transaction(tx_hash)?;
+        let receipt = tx.receipt()?;
+        Some(self.render_receipt(tx, receipt))
+    }
+
+    /// Receipts of the transactions of `view` preconfirmed with a receipt, in block order.
+    pub(crate) fn pending_block_receipts(&self, view: &PendingView) -> Vec<PendingReceipt> {
+        view.receipts
+            .iter()
+            .enumerate()
+            .map(|(index, receipt)| {
+                self.render_receipt(PendingTransaction { view, index }, receipt)
+            })
+            .collect()
+    }
+
+    fn render_receipt(&self, tx: PendingTransaction<'_>, receipt: &OpReceipt) -> PendingReceipt {
+        let mut pending_receipt =
+            PendingReceipt::from(self.transform_receipt(tx, receipt, self.chain_spec.as_ref()));
+        pending_receipt.format = self.response_format;
+        if self.receipt_flashblock_fields {
+            if let Some(preconfirmation) = tx.preconfirmation() {
+                pending_receipt.flashblock_index = Some(preconfirmation.index);
+                pending_receipt.preconfirmed_at = Some(preconfirmation.received_at);
+            }
+        }
+        pending_receipt
+    }
+}
+
+/// Top level fields in which two answers differ, or `value` for answers that aren't objects.
+fn differences<T: Serialize>(flashblocks: &T, standard: &T) -> Vec<String> {
+    let (Ok(flashblocks), Ok(standard)) = (
+        serde_json::to_value(flashblocks),
+        serde_json::to_value(standard),
+    ) else {
+        return vec!["value".to_string()];
+    };
+    match (flashblocks, standard) {
+        (Value::Object(flashblocks), Value::Object(standard)) => flashblocks
+            .keys()
+            .chain(standard.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter(|field| flashblocks.get(*field) != standard.get(*field))
+            .cloned()
+            .collect(),
+        (flashblocks, standard) if flashblocks == standard => Vec::new(),
+        _ => vec!["value".to_string()],
+    }
+}
+
+/// Extracts the deposit fields from a receipt, if it belongs to a deposit transaction.
+fn deposit_receipt(receipt: Option<&OpReceipt>) -> Option<OpDepositReceipt> {
+    match receipt {
+        Some(OpReceipt::Deposit(receipt)) => Some(receipt.clone()),
+        _ => None,
+    }
+}
+
+impl<Eth> EthApiExt<Eth>
+where
+    Eth: FullEthApi<NetworkTypes = Optimism> + Send + Sync + 'static,
+{
+    /// Code the creation transaction at `index` of `view` deployed. The flashblocks don't send
+    /// it, so the creation runs again on top of the canonical head with the flashblocks applied.
+    /// Its constructor sees the state after the flashblocks rather than the one it ran against,
+    /// which only matters to constructors reading state the block changed.
+    async fn deployed_code(&self, view: &PendingView, index: usize) -> RpcResult<Bytes> {
+        let deployment = PendingTransaction { view, index };
+        let transaction = deployment.transaction();
+        let request = TransactionRequest::default()
+            .from(deployment.sender())
+            .input(transaction.input().clone().into())
+            .value(transaction.value())
+            .gas_limit(transaction.gas_limit())
+            .into_create();
+        let overrides = EvmOverrides::new(
+            Some(self.pending_state_overrides(view, None)),
+            Some(Box::new(view.block_overrides(None))),
+        );
+        EthCall::call(&self.eth_api, request, Some(BlockId::latest()), overrides)
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Returns the view of the block after the canonical head if `method` serves `latest` from
+    /// the flashblocks state. Records the reason when there is none.
+    async fn pending_view_for_latest(
+        &self,
+        method: LatestAsPendingMethod,
+    ) -> RpcResult<Option<Arc<PendingView>>> {
+        let view = self.try_pending_view_for_latest(method).await?;
+        Ok(view
+            .inspect_err(|reason| self.record_fallback(method.as_str(), *reason))
+            .ok())
+    }
+
+    async fn standard_block(
+        &self,
+        block_id: BlockId,
+        full: bool,
+    ) -> RpcResult<Option<PendingBlock>> {
+        let block = EthBlocks::rpc_block(&self.eth_api, block_id, full)
+            .await
+            .map_err(Into::into)?;
+        Ok(block.map(|block| PendingBlock {
+            format: self.response_format,
+            ..PendingBlock::from(block)
+        }))
+    }
+
+    async fn standard_block_receipts(
+        &self,
+        block_id: BlockId,
+    ) -> RpcResult<Option<Vec<PendingReceipt>>> {
+        let receipts = EthBlocks::block_receipts(&self.eth_api, block_id)
+            .await
+            .map_err(Into::into)?;
+        Ok(receipts.map(|receipts| {
+            receipts
+                .into_iter()
+                .map(|receipt| PendingReceipt {
+                    format: self.response_format,
+                    ..PendingReceipt::from(receipt)
+                })
+                .collect()
+        }))
+    }
+
+    /// The view of the block after the canonical head, which calls on the pending state run in
+    /// place of.
+    async fn pending_view_on_head(&self) -> RpcResult<Option<Arc<PendingView>>> {
+        let latest_header =
+            EthBlocks::rpc_block_header(&self.eth_api, BlockNumberOrTag::Latest.into())
+                .await
+                .map_err(Into::into)?;
+        Ok(latest_header.and_then(|header| {
+            self.pending
+                .load_blocks()
+                .for_block(header.number + 1)
+                .cloned()
+        }))
+    }
+
+    /// The view that calls on the pending state run in place of, on top of the canonical head
+    /// with the account changes of the flashblocks as overrides. Records why there is none,
+    /// including when the flashblocks may have written storage, which the overrides can't
+    /// carry. The node then runs the call on its own pending block, unless the request is on
+    /// `preconfirmed`, which the node's pending block can't stand in for.
+    async fn pending_view_for_call(
+        &self,
+        method: &'static str,
+        preconfirmed: bool,
+    ) -> RpcResult<Option<Arc<PendingView>>> {
+        let Some(view) = self.pending_view_on_head().await? else {
+            self.record_fallback(method, self.miss_reason());
+            if preconfirmed {
+                return Err(preconfirmed_unavailable());
+            }
+            return Ok(None);
+        };
+        if self
+            .pending
+            .load_blocks()
+            .writes_storage(view.block_number())
+        {
+            self.record_fallback(method, FallbackReason::StorageWrites);
+            if preconfirmed {
+                return Err(preconfirmed_unavailable());
+            }
+            return Ok(None);
+        }
+        Ok(Some(view))
+    }
+
+    /// Submits a raw transaction through the node, which forwards it to the sequencer if one
+    /// is configured, and tracks it until it is confirmed.
+    async fn submit_transaction(&self, transaction: Bytes) -> RpcResult<B256> {
+        let tx_hash = EthTransactions::send_raw_transaction(&self.eth_api, transaction)
+            .await
+            .map_err(Into::into)?;
+        self.submissions
+            .submitted(tx_hash, self.sequencer_forwarding);
+        Ok(tx_hash)
+    }
+
+    async fn standard_transaction_by_hash(
+        &self,
+        tx_hash: TxHash,
+    ) -> RpcResult<Option<TransactionResponse>> {
+        let tx = EthTransactions::transaction_by_hash(&self.eth_api, tx_hash)
+            .await
+            .map_err(Into::into)?;
+        let Some(tx_source) = tx else {
+            return Ok(None);
+        };
+        match tx_source {
+            TransactionSource::Pool(tx) => {
+                // Convert the pool transaction
+                let tx_info = TransactionInfo::default();
+                let transaction = self.transform_tx(tx, tx_info, None);
+                Ok(Some(self.transaction_response(transaction)))
+            }
+            TransactionSource::Block {
+                transaction,
+                index,
+                block_hash,
+                block_number,
+                base_fee,
+            } => {
+                // Convert the block transaction
+                let tx_info = TransactionInfo {
+                    hash: Some(tx_hash),
+                    index: Some(index),
+                    block_hash: Some(block_hash),
+                    block_number: Some(block_number),
+                    base_fee,
+                };
+                // preload transaction receipt if it's a deposit transaction
+                if transaction.is_deposit() {
+                    let receipt = EthTransactions::transaction_receipt(&self.eth_api, tx_hash)
+                        .await
+                        .map_err(Into::into)?;
+
+                    match receipt {
+                        Some(txn_receipt) => {
+                            let envelope: OpReceiptEnvelope = txn_receipt.into();
+
+                            if let OpReceiptEnvelope::Deposit(deposit_receipt) = envelope {
+                                let transaction = self.transform_tx(
+                                    transaction,
+                                    tx_info,
+                                    Some(deposit_receipt.receipt),
+                                );
+                                return Ok(Some(self.transaction_response(transaction)));
+                            }
+                        }
+                        None => {
+                            error!(
+                                "could not find receipt for block transaction: {:?}",
+                                tx_hash
+                            );
+                            return Ok(None);
+                        }
+                    }
+                }
+
+                let transaction = self.transform_tx(transaction, tx_info, None);
+                Ok(Some(self.transaction_response(transaction)))
+            }
+        }
+    }
+
+    async fn standard_transaction_by_block_and_index(
+        &self,
+        block_id: BlockId,
+        index: Index,
+    ) -> RpcResult<Option<TransactionResponse>> {
+        let transaction = EthTransactions::transaction_by_block_and_tx_index(
+            &self.eth_api,
+            block_id,
+            index.into(),
+        )
+        .await
+        .map_err(Into::into)?;
+        Ok(transaction.map(|transaction| self.transaction_response(transaction)))
+    }
+
+    async fn try_pending_view_for_latest(
+        &self,
+        method: LatestAsPendingMethod,
+    ) -> RpcResult<Result<Arc<PendingView>, FallbackReason>> {
+        if !self.latest_as_pending.contains(&method) {
+            return Ok(Err(FallbackReason::Disabled));
+        }
+        let latest_header =
+            EthBlocks::rpc_block_header(&self.eth_api, BlockNumberOrTag::Latest.into())
+                .await
+                .map_err(Into::into)?;
+        let Some(header) = latest_header else {
+            return Ok(Err(FallbackReason::ValidationFailed));
+        };
+
+        let blocks = self.pending.load_blocks();
+        if let Some(view) = blocks.for_block(header.number + 1) {
+            return Ok(Ok(view.clone()));
+        }
+        // fresh views that don't build on the canonical head can't be served as `latest`
+        if blocks.latest().is_some() {
+            return Ok(Err(FallbackReason::ValidationFailed));
+        }
+        Ok(Err(self.miss_reason()))
+    }
+}
+
+impl<Eth> EthApiExt<Eth>
+where
+    Eth: FullEthApi<NetworkTypes = Optimism> + Send + Sync + 'static,
+    Eth: RpcNodeCore,
+    <Eth as RpcNodeCore>::Provider: HeaderProvider<Header = alloy_consensus::Header>,
+    <Eth as RpcNodeCore>::Provider: TransactionsProvider<Transaction = OpTransactionSigned>,
+{
+    /// The overrides as an RPC module. The log and filter methods complete the node's own
+    /// ones, so without [`Self::with_canonical_filters`] they aren't registered and the node
+    /// keeps serving them.
+    pub fn into_module(self) -> RpcModule<Self> {
+        let serves_filters = self.canonical_filters.is_some();
+        let mut module = self.into_rpc();
+        if !serves_filters {
+            for method in FILTER_METHODS {
+                module.remove_method(method);
+            }
+        }
+        module
+    }
+}
+
+#[async_trait]
+impl<Eth> EthApiOverrideServer for EthApiExt<Eth>
+where
+    Eth: FullEthApi<NetworkTypes = Optimism> + Send + Sync + 'static,
+    Eth: RpcNodeCore,
+    <Eth as RpcNodeCore>::Provider: HeaderProvider<Header = alloy_consensus::Header>,
+    <Eth as RpcNodeCore>::Provider: TransactionsProvider<Transaction = OpTransactionSigned>,
+{
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn block_by_number(
+        &self,
+        number: PreconfirmedOr<BlockNumberOrTag>,
+        _full: bool,
+    ) -> RpcResult<Option<PendingBlock>> {
+        debug!("block_by_number: {:?}", number);
+        let preconfirmed = number.is_preconfirmed();
+        let (number, flashblocks) = number.resolve(self.pending_tag_mode);
+        match number {
+            BlockNumberOrTag::Pending if flashblocks => {
+                debug!("pending block by number, delegating to flashblocks");
+                self.metrics.get_block_by_number.increment(1);
+                let block = self
+                    .pending
+                    .load()
+                    .map(|view| self.pending_block(&view, _full));
+                if block.is_none() {
+                    self.record_fallback("eth_getBlockByNumber", self.miss_reason());
+                    self.ensure_preconfirmed(preconfirmed)?;
+                }
+                let standard = self.standard_block(number.into(), _full);
+                self.serve("eth_getBlockByNumber", block, standard).await
+            }
+            BlockNumberOrTag::Latest => {
+                if let Some(view) = self
+                    .pending_view_for_latest(LatestAsPendingMethod::GetBlockByNumber)
+                    .await?
+                {
+                    debug!("latest block by number, serving pending flashblocks block");
+                    self.metrics.get_block_by_number.increment(1);
+                    return self
+                        .serve(
+                            "eth_getBlockByNumber",
+                            Some(self.pending_block(&view, _full)),
+                            self.standard_block(number.into(), _full),
+                        )
+                        .await;
+                }
+                self.standard_block(number.into(), _full).await
+            }
+            _ => {
+                info!("non pending block, using standard flow");
+                self.standard_block(number.into(), _full).await
+            }
+        }
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn block_by_hash(&self, hash: B256, full: bool) -> RpcResult<Option<PendingBlock>> {
+        debug!("block_by_hash: {:?}", hash);
+        let block = self.standard_block(hash.into(), full).await?;
+        // the hash changes with every flashblock, only the latest one of a block resolves
+        Ok(self.serve_lookup(
+            "eth_getBlockByHash",
+            &self.metrics.get_block_by_hash,
+            block,
+            || {
+                let blocks = self.pending.load_blocks();
+                blocks
+                    .by_hash(hash)
+                    .map(|view| self.pending_block(view, full))
+            },
+        ))
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn get_transaction_receipt(&self, tx_hash: TxHash) -> RpcResult<Option<PendingReceipt>> {
+        debug!("get_transaction_receipt: {:?}", tx_hash);
+        let receipt = EthTransactions::transaction_receipt(&self.eth_api, tx_hash)
+            .await
+            .map_err(Into::into)?;
+        let receipt = receipt.map(|receipt| PendingReceipt {
+            format: self.response_format,
+            ..PendingReceipt::from(receipt)
+        });
+        Ok(self.serve_lookup(
+            "eth_getTransactionReceipt",
+            &self.metrics.get_transaction_receipt,
+            receipt,
+            || self.pending_receipt(tx_hash),
+        ))
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn block_receipts(
+        &self,
+        block_id: PreconfirmedOr<BlockId>,
+    ) -> RpcResult<Option<Vec<PendingReceipt>>> {
+        debug!("block_receipts: {:?}", block_id);
+        let preconfirmed = block_id.is_preconfirmed();
+        let (block_id, flashblocks) = block_id.resolve(self.pending_tag_mode);
+        if flashblocks {
+            if let Some(view) = self.pending.load() {
+                self.metrics.get_block_receipts.increment(1);
+                let receipts = Some(self.pending_block_receipts(&view));
+                let standard = self.standard_block_receipts(block_id);
+                return self.serve("eth_getBlockReceipts", receipts, standard).await;
+            }
+            self.record_fallback("eth_getBlockReceipts", self.miss_reason());
+            self.ensure_preconfirmed(preconfirmed)?;
+        }
+        self.standard_block_receipts(block_id).await
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn get_balance(
+        &self,
+        address: Address,
+        block_number: Option<PreconfirmedOr<BlockId>>,
+    ) -> RpcResult<U256> {
+        debug!("get_balance: {:?}", address);
+        let preconfirmed = block_number.is_some_and(|block| block.is_preconfirmed());
+        let (block_id, flashblocks) = block_number
+            .unwrap_or_default()
+            .resolve(self.pending_tag_mode);
+        self.ensure_preconfirmed(preconfirmed)?;
+        let blocks = self.pending.load_blocks();
+        let balance = if flashblocks {
+            self.metrics.get_balance.increment(1);
+            let balance = blocks.balance(address);
+            if balance.is_none() {
+                self.record_fallback("eth_getBalance", self.miss_reason());
+            }
+            balance
+        } else if block_id.is_latest() {
+            match self
+                .pending_view_for_latest(LatestAsPendingMethod::GetBalance)
+                .await?
+            {
+                Some(view) => {
+                    self.metrics.get_balance.increment(1);
+                    blocks.balance_at(address, view.block_number())
+                }
+                None => None,
+            }
+        } else {
+            None
+        };
+        // If pending not found, use standard flow below
+        if let Some(balance) = balance {
+            let standard = async {
+                EthState::balance(&self.eth_api, address, Some(block_id))
+                    .await
+                    .map_err(Into::into)
+            };
+            return self.serve("eth_getBalance", balance, standard).await;
+        }
+
+        EthState::balance(&self.eth_api, address, Some(block_id))
+            .await
+            .map_err(Into::into)
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn get_account(
+        &self,
+        address: Address,
+        block: PreconfirmedOr<BlockId>,
+    ) -> RpcResult<Option<Account>> {
+        debug!("get_account: {:?}", address);
+        let preconfirmed = block.is_preconfirmed();
+        let (block, flashblocks) = block.resolve(self.pending_tag_mode);
+        if flashblocks {
+            if let Some(view) = self.pending_view_on_head().await? {
+                self.metrics.get_account.increment(1);
+                // the account at the head the pending blocks build on, with their changes
+                let canonical = EthState::get_account(&self.eth_api, address, BlockId::latest())
+                    .await
+                    .map_err(Into::into)?;
+                let blocks = self.pending.load_blocks();
+                let account = blocks.account(address, view.block_number(), canonical);
+                let standard = async {
+                    EthState::get_account(&self.eth_api, address, block)
+                        .await
+                        .map_err(Into::into)
+                };
+                return self.serve("eth_getAccount", account, standard).await;
+            }
+            self.record_fallback("eth_getAccount", self.miss_reason());
+            if preconfirmed {
+                return Err(preconfirmed_unavailable());
+            }
+        }
+
+        EthState::get_account(&self.eth_api, address, block)
+            .await
+            .map_err(Into::into)
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn get_code(
+        &self,
+        address: Address,
+        block_number: Option<PreconfirmedOr<BlockId>>,
+    ) -> RpcResult<Bytes> {
+        debug!("get_code: {:?}", address);
+        let preconfirmed = block_number.is_some_and(|block| block.is_preconfirmed());
+        let (block_id, flashblocks) = block_number
+            .unwrap_or_default()
+            .resolve(self.pending_tag_mode);
+        self.ensure_preconfirmed(preconfirmed)?;
+        if flashblocks {
+            self.metrics.get_code.increment(1);
+            // only deployments in the flashblocks are served, other code is canonical
+            let deployment = self.pending.load_blocks().deployment(address);
+            if let Some((view, index)) = deployment {
+                let code = self.deployed_code(&view, index).await?;
+                let standard = async {
+                    EthState::get_code(&self.eth_api, address, Some(block_id))
+                        .await
+                        .map_err(Into::into)
+                };
+                return self.serve("eth_getCode", code, standard).await;
+            }
+        }
+
+        EthState::get_code(&self.eth_api, address, Some(block_id))
+            .await
+            .map_err(Into::into)
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn get_proof(
+        &self,
+        address: Address,
+        keys: Vec<JsonStorageKey>,
+        block_number: Option<PreconfirmedOr<BlockId>>,
+    ) -> RpcResult<EIP1186AccountProofResponse> {
+        debug!("get_proof: {:?}", address);
+        let preconfirmed = block_number.is_some_and(|block| block.is_preconfirmed());
+        let (block_id, flashblocks) = block_number
+            .unwrap_or_default()
+            .resolve(self.pending_tag_mode);
+        self.ensure_preconfirmed(preconfirmed)?;
+        // without flashblocks `pending` is the canonical head everywhere, which can be proven
+        if flashblocks && self.pending.load().is_some() {
+            self.metrics.get_proof_unavailable.increment(1);
+            // shadow mode serves the node's `pending` state, which the node can prove
+            if !self.shadow_mode {
+                return Err(ErrorObject::owned(
+                    PROOF_UNAVAILABLE_CODE,
+                    "proofs unavailable for preconfirmed state",
+                    None::<()>,
+                ));
+            }
+        }
+
+        EthState::get_proof(&self.eth_api, address, keys, Some(block_id))
+            .map_err(Into::into)?
+            .await
+            .map_err(Into::into)
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn get_transaction_count(
+        &self,
+        address: Address,
+        block_number: Option<PreconfirmedOr<BlockId>>,
     ) -> RpcResult<U256> {
         debug!("get_transaction_count: {:?}", address);
-        let block_id = block_number.unwrap_or_default();
-        if block_id.is_pending() {
+        let preconfirmed = block_number.is_some_and(|block| block.is_preconfirmed());
+        let (block_id, flashblocks) = block_number
+            .unwrap_or_default()
+            .resolve(self.pending_tag_mode);
+        self.ensure_preconfirmed(preconfirmed)?;
+        let latest_as_pending = block_id.is_latest()
+            && self
+                .pending_view_for_latest(LatestAsPendingMethod::GetTransactionCount)
+                .await?
+                .is_some();
+        if flashblocks || latest_as_pending {
             self.metrics.get_transaction_count.increment(1);
-            let current_nonce = EthState::transaction_count(
-                &self.eth_api,
-                address,
-                Some(BlockId::Number(BlockNumberOrTag::Latest)),
-            )
-            .await
-            .map_err(Into::into)?;
 
             // get the current latest block number
             let latest_block_header =
@@ -344,122 +1478,1060 @@ where
                 header.number
             } else {
                 // If there's no latest block, return the current nonce without additions
-                return Ok(current_nonce);
+                return EthState::transaction_count(
+                    &self.eth_api,
+                    address,
+                    Some(BlockId::Number(BlockNumberOrTag::Latest)),
+                )
+                .await
+                .map_err(Into::into);
             };
 
-            let tx_count = self
-                .cache
-                .get::<u64>(&CacheKey::TransactionCount {
-                    address,
-                    block_number: latest_block_number + 1,
-                })
-                .unwrap_or(0);
+            // the nonce at the head the pending blocks are compared against, cached per block
+            let current_nonce = U256::from(
+                canonical_nonce(&self.eth_api, &self.cache, address, latest_block_number).await?,
+            );
+
+            // nonces used in every block built on top of the canonical head
+            let next_nonce = self
+                .pending
+                .load_blocks()
+                .next_nonce_since(address, latest_block_number + 1)
+                .map(U256::from)
+                .unwrap_or_default();
 
-            return Ok(current_nonce + U256::from(tx_count));
+            let standard = async {
+                EthState::transaction_count(&self.eth_api, address, Some(block_id))
+                    .await
+                    .map_err(Into::into)
+            };
+            return self
+                .serve(
+                    "eth_getTransactionCount",
+                    current_nonce.max(next_nonce),
+                    standard,
+                )
+                .await;
         }
 
-        EthState::transaction_count(&self.eth_api, address, block_number)
+        EthState::transaction_count(&self.eth_api, address, Some(block_id))
             .await
             .map_err(Into::into)
     }
 
-    async fn transaction_by_hash(
-        &self,
-        tx_hash: TxHash,
-    ) -> RpcResult<Option<RpcTransaction<Optimism>>> {
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn transaction_by_hash(&self, tx_hash: TxHash) -> RpcResult<Option<TransactionResponse>> {
         debug!("transaction_by_hash: {:?}", tx_hash);
-        let tx = EthTransactions::transaction_by_hash(&self.eth_api, tx_hash)
+        let tx = self.standard_transaction_by_hash(tx_hash).await?;
+        Ok(self.serve_lookup(
+            "eth_getTransactionByHash",
+            &self.metrics.get_transaction_by_hash,
+            tx,
+            || {
+                self.pending_transaction(tx_hash)
+                    .map(|transaction| self.transaction_response(transaction))
+            },
+        ))
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn transaction_by_block_number_and_index(
+        &self,
+        number: PreconfirmedOr<BlockNumberOrTag>,
+        index: Index,
+    ) -> RpcResult<Option<TransactionResponse>> {
+        debug!(
+            "transaction_by_block_number_and_index: {:?} {:?}",
+            number, index
+        );
+        let preconfirmed = number.is_preconfirmed();
+        let (number, flashblocks) = number.resolve(self.pending_tag_mode);
+        if flashblocks {
+            if let Some(view) = self.pending.load() {
+                self.metrics
+                    .get_transaction_by_block_number_and_index
+                    .increment(1);
+                let transaction = view
+                    .transaction_at(index.into())
+                    .map(|tx| self.transaction_response(self.render_transaction(tx)));
+                let standard = self.standard_transaction_by_block_and_index(number.into(), index);
+                return self
+                    .serve(
+                        "eth_getTransactionByBlockNumberAndIndex",
+                        transaction,
+                        standard,
+                    )
+                    .await;
+            }
+            self.record_fallback(
+                "eth_getTransactionByBlockNumberAndIndex",
+                self.miss_reason(),
+            );
+            self.ensure_preconfirmed(preconfirmed)?;
+        }
+        self.standard_transaction_by_block_and_index(number.into(), index)
+            .await
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn transaction_by_block_hash_and_index(
+        &self,
+        hash: B256,
+        index: Index,
+    ) -> RpcResult<Option<TransactionResponse>> {
+        debug!(
+            "transaction_by_block_hash_and_index: {:?} {:?}",
+            hash, index
+        );
+        let transaction = self
+            .standard_transaction_by_block_and_index(hash.into(), index)
+            .await?;
+        Ok(self.serve_lookup(
+            "eth_getTransactionByBlockHashAndIndex",
+            &self.metrics.get_transaction_by_block_hash_and_index,
+            transaction,
+            || {
+                let blocks = self.pending.load_blocks();
+                let view = blocks.by_hash(hash)?;
+                let tx = view.transaction_at(index.into())?;
+                Some(self.transaction_response(self.render_transaction(tx)))
+            },
+        ))
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn raw_transaction_by_hash(&self, tx_hash: TxHash) -> RpcResult<Option<Bytes>> {
+        debug!("raw_transaction_by_hash: {:?}", tx_hash);
+        let raw = EthTransactions::raw_transaction_by_hash(&self.eth_api, tx_hash)
             .await
             .map_err(Into::into)?;
+        // the 2718 encoding of a decoded transaction is the raw bytes sent in the flashblock
+        Ok(self.serve_lookup(
+            "eth_getRawTransactionByHash",
+            &self.metrics.get_raw_transaction_by_hash,
+            raw,
+            || {
+                let blocks = self.pending.load_blocks();
+                let tx = blocks.transaction(tx_hash)?;
+                Some(tx.transaction().encoded_2718().into())
+            },
+        ))
+    }
 
-        // Process the result without using map() and transpose()
-        if let Some(tx_source) = tx {
-            match tx_source {
-                TransactionSource::Pool(tx) => {
-                    // Convert the pool transaction
-                    let tx_info = TransactionInfo::default();
-                    Ok(Some(self.transform_tx(tx, tx_info, None)))
-                }
-                TransactionSource::Block {
-                    transaction,
-                    index,
-                    block_hash,
-                    block_number,
-                    base_fee,
-                } => {
-                    // Convert the block transaction
-                    let tx_info = TransactionInfo {
-                        hash: Some(tx_hash),
-                        index: Some(index),
-                        block_hash: Some(block_hash),
-                        block_number: Some(block_number),
-                        base_fee,
-                    };
-                    // preload transaction receipt if it's a deposit transaction
-                    if transaction.is_deposit() {
-                        let receipt = EthTransactions::transaction_receipt(&self.eth_api, tx_hash)
-                            .await
-                            .map_err(Into::into)?;
-
-                        match receipt {
-                            Some(txn_receipt) => {
-                                let envelope: OpReceiptEnvelope = txn_receipt.into();
-
-                                if let OpReceiptEnvelope::Deposit(deposit_receipt) = envelope {
-                                    return Ok(Some(self.transform_tx(
-                                        transaction,
-                                        tx_info,
-                                        Some(deposit_receipt.receipt),
-                                    )));
-                                }
-                            }
-                            None => {
-                                error!(
-                                    "could not find receipt for block transaction: {:?}",
-                                    tx_hash
-                                );
-                                return Ok(None);
-                            }
-                        }
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn gas_price(&self) -> RpcResult<U256> {
+        debug!("gas_price");
+        let standard = LoadFee::gas_price(&self.eth_api)
+            .await
+            .map_err(Into::into)?;
+        let Some(view) = self.pending.load() else {
+            self.record_fallback("eth_gasPrice", self.miss_reason());
+            return Ok(standard);
+        };
+        self.metrics.gas_price.increment(1);
+        let suggested = LoadFee::suggested_priority_fee(&self.eth_api)
+            .await
+            .map_err(Into::into)?;
+        let priority_fee = view
+            .priority_fee_percentile(PRIORITY_FEE_PERCENTILE)
+            .map_or(suggested, |fee| suggested.max(U256::from(fee)));
+        let base_fee = U256::from(view.block.base_fee_per_gas.unwrap_or_default());
+        self.serve(
+            "eth_gasPrice",
+            base_fee + priority_fee,
+            std::future::ready(Ok(standard)),
+        )
+        .await
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn max_priority_fee_per_gas(&self) -> RpcResult<U256> {
+        debug!("max_priority_fee_per_gas");
+        let standard = LoadFee::suggested_priority_fee(&self.eth_api)
+            .await
+            .map_err(Into::into)?;
+        // what the canonical blocks paid, raised to what is paid to get in the pending one
+        let Some(fee) = self
+            .pending
+            .load()
+            .and_then(|view| view.priority_fee_percentile(PRIORITY_FEE_PERCENTILE))
+        else {
+            self.record_fallback("eth_maxPriorityFeePerGas", self.miss_reason());
+            return Ok(standard);
+        };
+        self.metrics.max_priority_fee_per_gas.increment(1);
+        self.serve(
+            "eth_maxPriorityFeePerGas",
+            standard.max(U256::from(fee)),
+            std::future::ready(Ok(standard)),
+        )
+        .await
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn estimate_gas(
+        &self,
+        request: TransactionRequest,
+        block_number: Option<PreconfirmedOr<BlockId>>,
+        state_override: Option<StateOverride>,
+    ) -> RpcResult<U256> {
+        debug!("estimate_gas: {:?}", block_number);
+        let preconfirmed = block_number.is_some_and(|block| block.is_preconfirmed());
+        let (block_id, flashblocks) = block_number
+            .unwrap_or_default()
+            .resolve(self.pending_tag_mode);
+        if flashblocks {
+            // estimated on top of the canonical head with the flashblocks applied to it
+            if let Some(view) = self
+                .pending_view_for_call("eth_estimateGas", preconfirmed)
+                .await?
+            {
+                self.metrics.estimate_gas.increment(1);
+                let overrides = self.pending_state_overrides(&view, state_override.clone());
+                let (mut evm_env, at) = LoadState::evm_env_at(&self.eth_api, BlockId::latest())
+                    .await
+                    .map_err(Into::into)?;
+                evm_env.block_env.number = view.block_number();
+                evm_env.block_env.timestamp = view.block.timestamp;
+                evm_env.block_env.beneficiary = view.block.beneficiary;
+                let estimate = self.eth_api.spawn_blocking_io({
+                    let request = request.clone();
+                    move |this| {
+                        let state = this.state_at_block_id(at)?;
+                        EstimateCall::estimate_gas_with(
+                            &this,
+                            evm_env,
+                            request,
+                            state,
+                            Some(overrides),
+                        )
                     }
+                });
+                let estimate = cancellable("eth_estimateGas", |_| async {
+                    estimate.await.map_err(Into::into)
+                })
+                .await?;
+                let standard = async {
+                    EstimateCall::estimate_gas_at(&self.eth_api, request, block_id, state_override)
+                        .await
+                        .map_err(Into::into)
+                };
+                return self.serve("eth_estimateGas", estimate, standard).await;
+            }
+        }
 
-                    Ok(Some(self.transform_tx(transaction, tx_info, None)))
-                }
+        EstimateCall::estimate_gas_at(&self.eth_api, request, block_id, state_override)
+            .await
+            .map_err(Into::into)
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn call(
+        &self,
+        request: TransactionRequest,
+        block_number: Option<PreconfirmedOr<BlockId>>,
+        state_overrides: Option<StateOverride>,
+        block_overrides: Option<Box<BlockOverrides>>,
+    ) -> RpcResult<Bytes> {
+        debug!("call: {:?}", block_number);
+        let preconfirmed = block_number.is_some_and(|block| block.is_preconfirmed());
+        let (block_id, flashblocks) = block_number
+            .unwrap_or_default()
+            .resolve(self.pending_tag_mode);
+        if flashblocks {
+            if let Some(view) = self.pending_view_for_call("eth_call", preconfirmed).await? {
+                self.metrics.call.increment(1);
+                let overrides = self.pending_state_overrides(&view, state_overrides.clone());
+                let pending_block_overrides =
+                    view.block_overrides(block_overrides.clone().map(|overrides| *overrides));
+                let overrides =
+                    EvmOverrides::new(Some(overrides), Some(Box::new(pending_block_overrides)));
+                // runs like `EthCall::call`, with the EVM stopping once the request is dropped.
+                // A revert comes back as the node's revert error rather than empty output
+                let output = cancellable("eth_call", |cancellation| {
+                    let this = self.eth_api.clone();
+                    let output = Call::spawn_with_call_at(
+                        &self.eth_api,
+                        request.clone(),
+                        BlockId::latest(),
+                        overrides,
+                        move |mut db, evm_env, tx_env| {
+                            let inspector = CancelInspector::new(cancellation);
+                            let res =
+                                this.transact_with_inspector(&mut db, evm_env, tx_env, inspector)?;
+                            ensure_success(res.result)
+                        },
+                    );
+                    async move { output.await.map_err(Into::into) }
+                })
+                .await?;
+                let standard = async {
+                    let overrides = EvmOverrides::new(state_overrides, block_overrides);
+                    EthCall::call(&self.eth_api, request, Some(block_id), overrides)
+                        .await
+                        .map_err(Into::into)
+                };
+                return self.serve("eth_call", output, standard).await;
             }
-        } else {
-            // Handle cache lookup for transactions not found in the main lookup
-            if let Some(tx) = self
-                .cache
-                .get::<OpTransactionSigned>(&CacheKey::Transaction(tx_hash))
+        }
+
+        let overrides = EvmOverrides::new(state_overrides, block_overrides);
+        EthCall::call(&self.eth_api, request, Some(block_id), overrides)
+            .await
+            .map_err(Into::into)
+    }
+
+    #[instrument(skip(self, bundles), fields(request_id = next_request_id()))]
+    async fn call_many(
+        &self,
+        bundles: Vec<Bundle>,
+        state_context: Option<PreconfirmedStateContext>,
+        state_override: Option<StateOverride>,
+    ) -> RpcResult<Vec<Vec<EthCallResponse>>> {
+        debug!("call_many: {:?}", state_context);
+        let context = state_context.unwrap_or_default();
+        let preconfirmed = context
+            .block_number
+            .is_some_and(|block| block.is_preconfirmed());
+        let (context, flashblocks) = context.resolve(self.pending_tag_mode);
+        // bundles placed between transactions of the pending block replay it up to there, which
+        // the node does itself when the flashblocks pending block is installed
+        let whole_block = context.transaction_index.unwrap_or_default().is_all();
+        let state_context = Some(context);
+        if flashblocks && whole_block {
+            if let Some(view) = self
+                .pending_view_for_call("eth_callMany", preconfirmed)
+                .await?
             {
-                let sender = self
-                    .cache
-                    .get::<Address>(&CacheKey::TransactionSender(tx_hash))
-                    .unwrap();
-                let block_number = self
-                    .cache
-                    .get::<u64>(&CacheKey::TransactionBlockNumber(tx_hash))
-                    .unwrap();
-                let block = self
-                    .cache
-                    .get::<OpBlock>(&CacheKey::Block(block_number))
-                    .unwrap();
-                let index = self
-                    .cache
-                    .get::<u64>(&CacheKey::TransactionIndex(tx_hash))
-                    .unwrap();
-                let tx_info = TransactionInfo {
-                    hash: Some(tx.tx_hash()),
-                    block_hash: Some(block.header.hash_slow()),
-                    block_number: Some(block.number),
-                    index: Some(index),
-                    base_fee: block.base_fee_per_gas,
+                self.metrics.call_many.increment(1);
+                let overrides = self.pending_state_overrides(&view, state_override.clone());
+                let latest = StateContext {
+                    block_number: Some(BlockId::latest()),
+                    transaction_index: Some(TransactionIndex::All),
                 };
-                let tx = Recovered::new_unchecked(tx, sender);
-                Ok(Some(self.transform_tx(tx, tx_info, None)))
-            } else {
-                Ok(None)
+                // every bundle runs in the pending block
+                let pending_bundles = bundles
+                    .iter()
+                    .cloned()
+                    .map(|mut bundle| {
+                        bundle.block_override =
+                            Some(view.block_overrides(bundle.block_override.take()));
+                        bundle
+                    })
+                    .collect();
+                let responses = EthCall::call_many(
+                    &self.eth_api,
+                    pending_bundles,
+                    Some(latest),
+                    Some(overrides),
+                );
+                let responses = cancellable("eth_callMany", |_| async {
+                    responses.await.map_err(Into::into)
+                })
+                .await?;
+                let standard = async {
+                    EthCall::call_many(&self.eth_api, bundles, state_context, state_override)
+                        .await
+                        .map_err(Into::into)
+                };
+                return self.serve("eth_callMany", responses, standard).await;
+            }
+        }
+
+        EthCall::call_many(&self.eth_api, bundles, state_context, state_override)
+            .await
+            .map_err(Into::into)
+    }
+
+    #[instrument(skip(self, payload), fields(request_id = next_request_id()))]
+    async fn simulate_v1(
+        &self,
+        payload: SimulatePayload,
+        block_number: Option<PreconfirmedOr<BlockId>>,
+    ) -> RpcResult<Vec<SimulatedBlock<RpcBlock<Optimism>>>> {
+        debug!("simulate_v1: {:?}", block_number);
+        let preconfirmed = block_number.is_some_and(|block| block.is_preconfirmed());
+        let (block_id, flashblocks) = block_number
+            .unwrap_or_default()
+            .resolve(self.pending_tag_mode);
+        if flashblocks {
+            if let Some(view) = self
+                .pending_view_for_call("eth_simulateV1", preconfirmed)
+                .await?
+            {
+                self.metrics.simulate_v1.increment(1);
+                let simulated = EthCall::simulate_v1(
+                    &self.eth_api,
+                    self.simulate_on_pending(&view, payload.clone()),
+                    Some(BlockId::latest()),
+                );
+                let simulated = cancellable("eth_simulateV1", |_| async {
+                    simulated.await.map_err(Into::into)
+                })
+                .await?;
+                let standard = async {
+                    EthCall::simulate_v1(&self.eth_api, payload, Some(block_id))
+                        .await
+                        .map_err(Into::into)
+                };
+                return self.serve("eth_simulateV1", simulated, standard).await;
+            }
+        }
+
+        EthCall::simulate_v1(&self.eth_api, payload, Some(block_id))
+            .await
+            .map_err(Into::into)
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn get_logs(&self, filter: PreconfirmedFilter) -> RpcResult<Vec<Log>> {
+        debug!("get_logs: {:?}", filter);
+        let canonical = self.canonical_filters("eth_getLogs")?;
+        let preconfirmed = filter.preconfirmed;
+        let (filter, flashblocks) = filter.resolve(self.pending_tag_mode);
+        let FilterBlockOption::Range {
+            from_block,
+            to_block: Some(BlockNumberOrTag::Pending),
+        } = filter.block_option
+        else {
+            return canonical.logs(filter).await;
+        };
+        if !flashblocks {
+            return canonical.logs(filter).await;
+        }
+
+        let latest_header =
+            EthBlocks::rpc_block_header(&self.eth_api, BlockNumberOrTag::Latest.into())
+                .await
+                .map_err(Into::into)?;
+        let Some(head) = latest_header.map(|header| header.number) else {
+            return canonical.logs(filter).await;
+        };
+        let blocks = self.pending.load_blocks();
+        let logs =
+            pending_range_logs(canonical.as_ref(), &filter, from_block, head, &blocks).await?;
+        let Some(logs) = logs else {
+            self.record_fallback("eth_getLogs", self.miss_reason());
+            if preconfirmed {
+                return Err(preconfirmed_unavailable());
+            }
+            return canonical.logs(filter).await;
+        };
+        self.metrics.get_logs.increment(1);
+        self.serve("eth_getLogs", logs, canonical.logs(filter))
+            .await
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn new_filter(&self, filter: PreconfirmedFilter) -> RpcResult<FilterId> {
+        debug!("new_filter: {:?}", filter);
+        let (filter, flashblocks) = filter.resolve(self.pending_tag_mode);
+        // filters up to the pending block read the flashblocks as they arrive
+        let FilterBlockOption::Range {
+            to_block: Some(BlockNumberOrTag::Pending),
+            ..
+        } = filter.block_option
+        else {
+            return self
+                .canonical_filters("eth_newFilter")?
+                .new_filter(filter)
+                .await;
+        };
+        if !flashblocks {
+            return self
+                .canonical_filters("eth_newFilter")?
+                .new_filter(filter)
+                .await;
+        }
+        self.metrics.new_filter.increment(1);
+        let blocks = self.pending.load_blocks();
+        self.filters.install_logs(filter, &blocks)
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn new_block_filter(&self) -> RpcResult<FilterId> {
+        debug!("new_block_filter");
+        if self.block_filter_mode == BlockFilterMode::Canonical {
+            return self
+                .canonical_filters("eth_newBlockFilter")?
+                .new_block_filter()
+                .await;
+        }
+        self.metrics.new_block_filter.increment(1);
+        let blocks = self.pending.load_blocks();
+        self.filters.install_blocks(self.block_filter_mode, &blocks)
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn new_pending_transaction_filter(
+        &self,
+        kind: Option<PendingTransactionFilterKind>,
+    ) -> RpcResult<FilterId> {
+        debug!("new_pending_transaction_filter: {:?}", kind);
+        // transactions are preconfirmed by the flashblocks well before they are canonical, so
+        // pending transaction filters always follow them
+        self.metrics.new_pending_transaction_filter.increment(1);
+        let kind = kind.unwrap_or(PendingTransactionFilterKind::Hashes);
+        let blocks = self.pending.load_blocks();
+        self.filters.install_transactions(kind, &blocks)
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn filter_changes(&self, id: FilterId) -> RpcResult<FilterChanges<Transaction>> {
+        debug!("filter_changes: {:?}", id);
+        let blocks = self.pending.load_blocks();
+        match self
+            .filters
+            .changes(&id, &blocks, |tx| self.render_transaction(tx))
+        {
+            Some(changes) => Ok(changes?),
+            None => {
+                let canonical = self.canonical_filters("eth_getFilterChanges")?;
+                canonical.filter_changes(id).await
+            }
+        }
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn filter_logs(&self, id: FilterId) -> RpcResult<Vec<Log>> {
+        debug!("filter_logs: {:?}", id);
+        match self.filters.log_filter(&id) {
+            Some(filter) => self.get_logs(filter.into()).await,
+            None => {
+                let canonical = self.canonical_filters("eth_getFilterLogs")?;
+                canonical.filter_logs(id).await
+            }
+        }
+    }
+
+    #[instrument(skip(self), fields(request_id = next_request_id()))]
+    async fn uninstall_filter(&self, id: FilterId) -> RpcResult<bool> {
+        debug!("uninstall_filter: {:?}", id);
+        if self.filters.uninstall(&id) {
+            return Ok(true);
+        }
+        let canonical = self.canonical_filters("eth_uninstallFilter")?;
+        canonical.uninstall_filter(id).await
+    }
+
+    #[instrument(skip(self, transaction), fields(request_id = next_request_id()))]
+    async fn send_raw_transaction(&self, transaction: Bytes) -> RpcResult<B256> {
+        debug!("send_raw_transaction");
+        self.metrics.send_raw_transaction.increment(1);
+        self.submit_transaction(transaction).await
+    }
+
+    #[instrument(skip(self, transaction), fields(request_id = next_request_id()))]
+    async fn send_raw_transaction_sync(
+        &self,
+        transaction: Bytes,
+        timeout_ms: Option<u64>,
+    ) -> RpcResult<PendingReceipt> {
+        debug!("send_raw_transaction_sync: {:?}", timeout_ms);
+        self.metrics.send_raw_transaction_sync.increment(1);
+        let timeout_ms = timeout_ms
+            .unwrap_or(DEFAULT_SEND_SYNC_TIMEOUT_MS)
+            .min(MAX_SEND_SYNC_TIMEOUT_MS);
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(timeout_ms);
+        // subscribed before submitting, so the flashblock including it can't be missed
+        let mut views = self.pending.subscribe_views();
+        let tx_hash = self.submit_transaction(transaction).await?;
+
+        loop {
+            if let Some(receipt) = self.pending_receipt(tx_hash) {
+                return Ok(receipt);
             }
+            match tokio::time::timeout_at(deadline, views.recv()).await {
+                // a lagging receiver only skipped views, the latest state is checked above
+                Ok(Ok(_)) | Ok(Err(RecvError::Lagged(_))) => continue,
+                Ok(Err(RecvError::Closed)) | Err(_) => break,
+            }
+        }
+        self.metrics.send_raw_transaction_sync_timeouts.increment(1);
+        Err(ErrorObject::owned(
+            SEND_SYNC_TIMEOUT_CODE,
+            format!("transaction was not preconfirmed within {timeout_ms}ms"),
+            Some(tx_hash),
+        ))
+    }
+}
+
+/// Renders a transaction preconfirmed by the flashblocks, in the block it is pending in.
+pub(crate) fn render_pending_transaction(tx: PendingTransaction<'_>) -> Transaction {
+    let block = &tx.view.block;
+    let tx_info = TransactionInfo {
+        hash: Some(tx.transaction().tx_hash()),
+        block_hash: Some(tx.view.block_hash),
+        block_number: Some(block.number),
+        index: Some(tx.index as u64),
+        base_fee: block.base_fee_per_gas,
+    };
+    to_rpc_transaction(
+        tx.recovered().clone(),
+        tx_info,
+        deposit_receipt(tx.receipt()),
+    )
+}
+
+/// Renders a flashblock transaction. The envelope is kept as decoded from the flashblock, so
+/// type specific fields such as EIP-7702 authorization lists are rendered like for canonical
+/// transactions.
+fn to_rpc_transaction(
+    tx: Recovered<OpTransactionSigned>,
+    tx_info: TransactionInfo,
+    deposit_receipt: Option<OpDepositReceipt>,
+) -> Transaction {
+    let tx = tx.convert::<OpTxEnvelope>();
+    let mut deposit_receipt_version = None;
+    let mut deposit_nonce = None;
+
+    if tx.is_deposit() {
+        if let Some(receipt) = deposit_receipt {
+            deposit_receipt_version = receipt.deposit_receipt_version;
+            deposit_nonce = receipt.deposit_nonce;
+        }
+    }
+
+    let TransactionInfo {
+        block_hash,
+        block_number,
+        index: transaction_index,
+        base_fee,
+        ..
+    } = tx_info;
+
+    let effective_gas_price = if tx.is_deposit() {
+        // For deposits, we must always set the `gasPrice` field to 0 in rpc
+        // deposit tx don't have a gas price field, but serde of `Transaction` will take care of
+        // it
+        0
+    } else {
+        base_fee
+            .map(|base_fee| {
+                tx.effective_tip_per_gas(base_fee).unwrap_or_default() + base_fee as u128
+            })
+            .unwrap_or_else(|| tx.max_fee_per_gas())
+    };
+
+    Transaction {
+        inner: alloy_rpc_types_eth::Transaction {
+            inner: tx,
+            block_hash,
+            block_number,
+            transaction_index,
+            effective_gas_price: Some(effective_gas_price),
+        },
+        deposit_nonce,
+        deposit_receipt_version,
+    }
+}
+
+/// Renders the pending block in the shape op-geth serves canonical blocks: the withdrawals and
+/// blob fields the flashblocks set, the size of the block built so far and the hash the builder
+/// sent, as the header is incomplete until the block is sealed.
+pub(crate) fn render_pending_block(view: &PendingView, full: bool) -> RpcBlock<Optimism> {
+    let block = &view.block;
+    let transactions = if full {
+        BlockTransactions::Full(
+            view.transactions()
+                .map(render_pending_transaction)
+                .collect(),
+        )
+    } else {
+        BlockTransactions::Hashes(
+            block
+                .body
+                .transactions
+                .iter()
+                .map(|tx| tx.tx_hash())
+                .collect(),
+        )
+    };
+    let header = Sealed::new_unchecked(block.header.clone(), view.block_hash);
+    let size = Some(U256::from(OpBlock::rlp_length_for(
+        &block.header,
+        &block.body,
+    )));
+    RpcBlock::<Optimism> {
+        header: Header::from_consensus(header, None, size),
+        transactions,
+        uncles: Vec::new(),
+        withdrawals: block.body.withdrawals.clone(),
+    }
+}
+
+/// Renders the receipt of a transaction preconfirmed by the flashblocks, with the L1 fee of the
+/// block it is pending in.
+pub(crate) fn render_pending_receipt(
+    tx: PendingTransaction<'_>,
+    receipt: &OpReceipt,
+    chain_spec: &OpChainSpec,
+) -> RpcReceipt<Optimism> {
+    let view = tx.view;
+    let block = &view.block;
+    let mut l1_block_info =
+        reth_optimism_evm::extract_l1_info(&block.body).expect("failed to extract l1 info");
+
+    let meta = TransactionMeta {
+        tx_hash: tx.transaction().tx_hash(),
+        index: tx.index as u64,
+        block_hash: view.block_hash,
+        block_number: block.number,
+        base_fee: block.base_fee_per_gas,
+        excess_blob_gas: block.excess_blob_gas,
+        timestamp: block.timestamp,
+    };
+
+    OpReceiptBuilder::new(
+        chain_spec,
+        tx.transaction(),
+        meta,
+        receipt,
+        view.receipts_slice(),
+        &mut l1_block_info,
+    )
+    .expect("failed to build receipt")
+    .build()
+}
+
+/// Logs of a request up to the pending block: those of the canonical blocks up to `head`,
+/// followed by those of the pending blocks built on top of it. `None` when no pending block is
+/// in range.
+async fn pending_range_logs(
+    canonical: &dyn CanonicalFilters,
+    filter: &Filter,
+    from_block: Option<BlockNumberOrTag>,
+    head: u64,
+    blocks: &PendingBlocks,
+) -> RpcResult<Option<Vec<Log>>> {
+    // only the blocks built on top of the canonical head are served from flashblocks
+    let from = match from_block {
+        Some(BlockNumberOrTag::Number(number)) => number,
+        Some(BlockNumberOrTag::Pending) => {
+            blocks.latest().map_or(u64::MAX, |view| view.block_number())
+        }
+        _ => 0,
+    };
+    let views: Vec<_> = blocks.range(from.max(head + 1)..=u64::MAX).collect();
+    if views.is_empty() {
+        return Ok(None);
+    }
+
+    // the canonical part ends at the head the pending blocks were selected against, so a
+    // block imported in between isn't returned twice
+    let mut logs = if from <= head {
+        canonical.logs(filter.clone().to_block(head)).await?
+    } else {
+        Vec::new()
+    };
+    logs.extend(views.iter().flat_map(|view| view.logs(filter)));
+    Ok(Some(logs))
+}
+
+/// Moves the methods of `module` from the `eth` namespace to `namespace`, so the overrides can
+/// be served next to the node's own `eth` methods (e.g. as `baseeth_getBalance`) while clients
+/// migrate.
+pub fn into_namespace<Context>(
+    mut module: RpcModule<Context>,
+    namespace: &str,
+) -> Result<RpcModule<Context>, RegisterMethodError> {
+    let method_names: Vec<&'static str> = module.method_names().collect();
+    for method_name in method_names {
+        let Some(method) = method_name.strip_prefix("eth_") else {
+            continue;
+        };
+        // registered names must be static, the module is mounted for the lifetime of the node
+        let alias: &'static str = Box::leak(format!("{namespace}_{method}").into_boxed_str());
+        module.register_alias(alias, method_name)?;
+        module.remove_method(method_name);
+    }
+    Ok(module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_consensus::{SignableTransaction, TxEip7702};
+    use alloy_eips::eip2718::Decodable2718;
+    use alloy_eips::eip7702::Authorization;
+    use alloy_primitives::Signature;
+
+    #[test]
+    fn test_eip7702_fields_rendered() {
+        let authorization = Authorization {
+            chain_id: U256::from(8453),
+            address: Address::repeat_byte(0x1),
+            nonce: 7,
+        }
+        .into_signed(Signature::test_signature());
+        let tx = TxEip7702 {
+            chain_id: 8453,
+            nonce: 1,
+            gas_limit: 100000,
+            max_fee_per_gas: 2,
+            max_priority_fee_per_gas: 1,
+            to: Address::repeat_byte(0x2),
+            authorization_list: vec![authorization],
+            ..Default::default()
+        };
+        let envelope = OpTxEnvelope::Eip7702(tx.into_signed(Signature::test_signature()));
+
+        // decoded the same way as the transactions of a flashblock
+        let decoded =
+            OpTransactionSigned::decode_2718(&mut envelope.encoded_2718().as_slice()).unwrap();
+        let tx_info = TransactionInfo {
+            hash: Some(decoded.tx_hash()),
+            block_hash: None,
+            block_number: Some(1),
+            index: Some(0),
+            base_fee: Some(1),
+        };
+        let transaction = to_rpc_transaction(
+            Recovered::new_unchecked(decoded, Address::repeat_byte(0x3)),
+            tx_info,
+            None,
+        );
+
+        let json = serde_json::to_value(&transaction).unwrap();
+        assert_eq!(json["type"], "0x4");
+        let authorization_list = json["authorizationList"].as_array().unwrap();
+        assert_eq!(authorization_list.len(), 1);
+        assert_eq!(authorization_list[0]["nonce"], "0x7");
+        assert_eq!(json["from"], Address::repeat_byte(0x3).to_string());
+    }
+
+    #[test]
+    fn test_differences() {
+        assert!(differences(&U256::from(1), &U256::from(1)).is_empty());
+        assert_eq!(differences(&U256::from(1), &U256::from(2)), vec!["value"]);
+        assert_eq!(differences(&Some(U256::from(1)), &None), vec!["value"]);
+
+        let flashblocks = serde_json::json!({"number": "0x2", "hash": "0x1", "extra": true});
+        let standard = serde_json::json!({"number": "0x1", "hash": "0x1"});
+        assert_eq!(
+            differences(&flashblocks, &standard),
+            vec!["extra", "number"]
+        );
+    }
+
+    #[test]
+    fn test_serve_lookup() {
+        let chain_spec = reth_optimism_chainspec::BASE_SEPOLIA.clone();
+        let pending = Arc::new(PendingViewStore::default());
+        let eth_api = EthApiExt::new((), Arc::new(Cache::default()), pending, chain_spec);
+        let served = Counter::noop();
+
+        // canonical answers win and the flashblocks aren't looked up outside shadow mode
+        let lookup = eth_api.serve_lookup("eth_test", &served, Some(1), || unreachable!());
+        assert_eq!(lookup, Some(1));
+        assert_eq!(
+            eth_api.serve_lookup("eth_test", &served, None, || Some(2)),
+            Some(2)
+        );
+        assert_eq!(
+            eth_api.serve_lookup::<u64>("eth_test", &served, None, || None),
+            None
+        );
+
+        let eth_api = eth_api.with_shadow_mode(true);
+        assert_eq!(
+            eth_api.serve_lookup("eth_test", &served, None, || Some(2)),
+            None
+        );
+        let mut looked_up = false;
+        let lookup = eth_api.serve_lookup("eth_test", &served, Some(1), || {
+            looked_up = true;
+            Some(2)
+        });
+        assert_eq!(lookup, Some(1));
+        assert!(looked_up);
+    }
+
+    /// Canonical logs answering every request with a single log of its last block.
+    #[derive(Debug, Default)]
+    struct CanonicalLogs {
+        requested: std::sync::Mutex<Vec<Option<u64>>>,
+    }
+
+    #[async_trait]
+    impl CanonicalFilters for CanonicalLogs {
+        async fn logs(&self, filter: Filter) -> RpcResult<Vec<Log>> {
+            let to_block = filter.get_to_block();
+            self.requested.lock().unwrap().push(to_block);
+            Ok(vec![Log {
+                block_number: to_block,
+                ..Default::default()
+            }])
+        }
+
+        async fn new_filter(&self, _filter: Filter) -> RpcResult<FilterId> {
+            unimplemented!()
+        }
+
+        async fn new_block_filter(&self) -> RpcResult<FilterId> {
+            unimplemented!()
+        }
+
+        async fn filter_changes(&self, _id: FilterId) -> RpcResult<FilterChanges<Transaction>> {
+            unimplemented!()
+        }
+
+        async fn filter_logs(&self, _id: FilterId) -> RpcResult<Vec<Log>> {
+            unimplemented!()
+        }
+
+        async fn uninstall_filter(&self, _id: FilterId) -> RpcResult<bool> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pending_range_logs() {
+        let pending = PendingViewStore::default();
+        for block_number in [6, 7] {
+            let mut block = OpBlock::default();
+            block.header.number = block_number;
+            let mut view = PendingView::new(block, 0, Vec::new());
+            view.receipts
+                .push_chunk(vec![OpReceipt::Eip1559(alloy_consensus::Receipt {
+                    status: true.into(),
+                    cumulative_gas_used: 21000,
+                    logs: vec![alloy_primitives::Log::default()],
+                })]);
+            pending.publish(view);
+        }
+        let blocks = pending.load_blocks();
+        let filter = Filter::new().to_block(BlockNumberOrTag::Pending);
+        let block_numbers = |logs: Option<Vec<Log>>| {
+            logs.map(|logs| logs.iter().map(|log| log.block_number).collect::<Vec<_>>())
+        };
+
+        // the canonical logs end at the head, the pending blocks follow it
+        let canonical = CanonicalLogs::default();
+        let from = Some(BlockNumberOrTag::Number(3));
+        let logs = pending_range_logs(&canonical, &filter, from, 5, &blocks).await;
+        assert_eq!(
+            block_numbers(logs.unwrap()),
+            Some(vec![Some(5), Some(6), Some(7)])
+        );
+        assert_eq!(*canonical.requested.lock().unwrap(), vec![Some(5)]);
+
+        // ranges starting after the head don't read the canonical logs
+        let canonical = CanonicalLogs::default();
+        let from = Some(BlockNumberOrTag::Number(7));
+        let logs = pending_range_logs(&canonical, &filter, from, 5, &blocks).await;
+        assert_eq!(block_numbers(logs.unwrap()), Some(vec![Some(7)]));
+        let from = Some(BlockNumberOrTag::Pending);
+        let logs = pending_range_logs(&canonical, &filter, from, 5, &blocks).await;
+        assert_eq!(block_numbers(logs.unwrap()), Some(vec![Some(7)]));
+        assert!(canonical.requested.lock().unwrap().is_empty());
+
+        // nothing pending on top of the head
+        let logs = pending_range_logs(&canonical, &filter, None, 7, &blocks).await;
+        assert_eq!(logs.unwrap(), None);
+    }
+
+    #[test]
+    fn test_simulate_on_pending() {
+        let sender = Address::repeat_byte(0x1);
+        let overridden = Address::repeat_byte(0x2);
+        let mut block = OpBlock::default();
+        block.header.number = 5;
+        block.header.timestamp = 1710000000;
+        block.header.beneficiary = Address::repeat_byte(0xfe);
+        let mut view = PendingView::new(block, 0, Vec::new());
+        view.balances.insert(sender, U256::from(10));
+        view.balances.insert(overridden, U256::from(20));
+
+        let pending = Arc::new(PendingViewStore::default());
+        let view = pending.publish(view);
+        let chain_spec = reth_optimism_chainspec::BASE_SEPOLIA.clone();
+        let eth_api = EthApiExt::new((), Arc::new(Cache::default()), pending, chain_spec);
+
+        let payload: SimulatePayload = serde_json::from_value(serde_json::json!({
+            "blockStateCalls": [
+                {
+                    "blockOverrides": { "time": "0x1" },
+                    "stateOverrides": {
+                        "0x0202020202020202020202020202020202020202": { "nonce": "0x3" }
+                    },
+                    "calls": []
+                },
+                { "calls": [] }
+            ]
+        }))
+        .unwrap();
+        let payload = eth_api.simulate_on_pending(&view, payload);
+
+        let first = &payload.block_state_calls[0];
+        let state_overrides = first.state_overrides.as_ref().unwrap();
+        assert_eq!(state_overrides[&sender].balance, Some(U256::from(10)));
+        // fields the request overrides are kept, the others come from the flashblocks
+        assert_eq!(state_overrides[&overridden].nonce, Some(3));
+        assert_eq!(state_overrides[&overridden].balance, Some(U256::from(20)));
+        let block_overrides = first.block_overrides.as_ref().unwrap();
+        assert_eq!(block_overrides.time, Some(1));
+        assert_eq!(block_overrides.number, Some(U256::from(5)));
+        assert_eq!(block_overrides.coinbase, Some(Address::repeat_byte(0xfe)));
+        // later blocks build on the first one
+        assert!(payload.block_state_calls[1].state_overrides.is_none());
+    }
+
+    #[test]
+    fn test_pending_block_shape() {
+        let mut block = OpBlock::default();
+        block.header.number = 5;
+        block.header.withdrawals_root = Some(B256::ZERO);
+        block.header.blob_gas_used = Some(0);
+        block.header.excess_blob_gas = Some(0);
+        block.body.withdrawals = Some(Default::default());
+        let mut view = PendingView::new(block.clone(), 0, Vec::new());
+        view.block_hash = B256::repeat_byte(0x5);
+
+        let chain_spec = reth_optimism_chainspec::BASE_SEPOLIA.clone();
+        let pending = Arc::new(PendingViewStore::default());
+        let eth_api = EthApiExt::new((), Arc::new(Cache::default()), pending, chain_spec);
+        let rendered = eth_api.transform_block(&view, false);
+
+        // the builder's hash is kept rather than hashing the incomplete header
+        assert_eq!(rendered.header.hash, view.block_hash);
+        assert_eq!(rendered.withdrawals, Some(Default::default()));
+        let json = serde_json::to_value(&rendered).unwrap();
+        assert_eq!(json["blobGasUsed"], "0x0");
+        assert_eq!(json["excessBlobGas"], "0x0");
+        assert_eq!(json["withdrawals"], serde_json::json!([]));
+        let size = OpBlock::rlp_length_for(&block.header, &block.body);
+        assert_eq!(rendered.header.size, Some(U256::from(size)));
+    }
+
+    #[test]
+    fn test_into_namespace() {
+        let mut module = RpcModule::new(());
+        module
+            .register_method("eth_getBalance", |_, _, _| "0x0")
+            .unwrap();
+        module
+            .register_method("eth_getTransactionCount", |_, _, _| "0x0")
+            .unwrap();
+
+        let module = into_namespace(module, "baseeth").unwrap();
+        let mut method_names: Vec<_> = module.method_names().collect();
+        method_names.sort();
+        assert_eq!(
+            method_names,
+            vec!["baseeth_getBalance", "baseeth_getTransactionCount"]
+        );
+    }
+
+    #[test]
+    fn test_latest_as_pending_methods() {
+        for method in [
+            LatestAsPendingMethod::GetBlockByNumber,
+            LatestAsPendingMethod::GetBalance,
+            LatestAsPendingMethod::GetTransactionCount,
+        ] {
+            assert_eq!(method.as_str().parse::<LatestAsPendingMethod>(), Ok(method));
         }
+        assert!("eth_call".parse::<LatestAsPendingMethod>().is_err());
     }
 }
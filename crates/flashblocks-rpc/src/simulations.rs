@@ -0,0 +1,139 @@
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::metrics::SimulationMetrics;
+use jsonrpsee::core::RpcResult;
+use revm::interpreter::interpreter_types::LoopControl;
+use revm::interpreter::{InstructionResult, Interpreter, InterpreterTypes};
+use revm::Inspector;
+use tracing::debug;
+
+/// Why a simulation on the pending state was aborted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbortReason {
+    /// The request was dropped, the client disconnected or the server timed it out
+    Dropped,
+}
+
+impl Display for AbortReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Dropped => write!(f, "dropped"),
+        }
+    }
+}
+
+impl AbortReason {
+    /// Counts a simulation of `method` aborted for this reason.
+    pub fn record(self, method: &'static str) {
+        debug!("{} simulation aborted: {}", method, self);
+        SimulationMetrics::for_abort(method, self)
+            .aborted
+            .increment(1);
+    }
+}
+
+/// Set once the request running a simulation is gone, for the EVM to stop at its next step.
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation(Arc<AtomicBool>);
+
+impl Cancellation {
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Halts every frame of the execution once its [`Cancellation`] is set, so a simulation nobody
+/// waits for anymore doesn't keep a blocking thread busy until it runs out of gas.
+#[derive(Debug, Clone)]
+pub struct CancelInspector {
+    cancellation: Cancellation,
+}
+
+impl CancelInspector {
+    pub fn new(cancellation: Cancellation) -> Self {
+        Self { cancellation }
+    }
+}
+
+impl<CTX, INTR: InterpreterTypes> Inspector<CTX, INTR> for CancelInspector {
+    fn step(&mut self, interp: &mut Interpreter<INTR>, _context: &mut CTX) {
+        if self.cancellation.is_cancelled() {
+            interp
+                .control
+                .set_instruction_result(InstructionResult::OutOfGas);
+        }
+    }
+}
+
+/// Cancels the simulation and counts it as aborted when the request is dropped before it
+/// finished, which is how the server stops requests whose client went away or timed out.
+struct DropGuard {
+    method: &'static str,
+    cancellation: Cancellation,
+    finished: bool,
+}
+
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.cancellation.cancel();
+            AbortReason::Dropped.record(self.method);
+        }
+    }
+}
+
+/// Runs the simulation `simulate` starts for a request to `method`, cancelling it if the
+/// request is dropped first. Simulations that execute with a [`CancelInspector`] on the
+/// cancellation stop at their next EVM step. The node's gas estimation, bundle simulation and
+/// tracers don't take an inspector, dropping those only stops waiting on them.
+pub async fn cancellable<T, F, Fut>(method: &'static str, simulate: F) -> RpcResult<T>
+where
+    F: FnOnce(Cancellation) -> Fut,
+    Fut: Future<Output = RpcResult<T>>,
+{
+    let cancellation = Cancellation::default();
+    let mut guard = DropGuard {
+        method,
+        cancellation: cancellation.clone(),
+        finished: false,
+    };
+    let result = simulate(cancellation).await;
+    guard.finished = true;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_cancellable() {
+        let simulated = cancellable("eth_call", |_| async { Ok(1) }).await;
+        assert_eq!(simulated.unwrap(), 1);
+
+        // the request is dropped while a blocking simulation runs
+        let (stopped_tx, stopped_rx) = std::sync::mpsc::channel();
+        let simulation = cancellable("eth_call", |cancellation| async move {
+            tokio::task::spawn_blocking(move || {
+                while !cancellation.is_cancelled() {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                stopped_tx.send(()).unwrap();
+            })
+            .await
+            .unwrap();
+            Ok(())
+        });
+        let dropped = tokio::time::timeout(Duration::from_millis(20), simulation).await;
+        assert!(dropped.is_err());
+        stopped_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+    }
+}
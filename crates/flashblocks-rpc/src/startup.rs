@@ -0,0 +1,200 @@
+use std::fmt::{Display, Formatter};
+use std::sync::RwLock;
+
+use crate::clock::SharedClock;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+pub const WEBSOCKET_REACHABLE: &str = "websocket-reachable";
+pub const FIRST_PAYLOAD_PARSED: &str = "first-payload-parsed";
+pub const CHAIN_MATCHES: &str = "chain-matches";
+pub const CACHE_SIZED: &str = "cache-sized";
+pub const NAMESPACES_MOUNTED: &str = "namespaces-mounted";
+
+/// Checks run on every startup, in the order they are reported.
+const CHECKS: [&str; 5] = [
+    WEBSOCKET_REACHABLE,
+    FIRST_PAYLOAD_PARSED,
+    CHAIN_MATCHES,
+    CACHE_SIZED,
+    NAMESPACES_MOUNTED,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    /// Not resolved yet, e.g. no payload was received so far
+    Pending,
+    Passed,
+    Failed,
+    /// Not applicable to this node's configuration
+    Skipped,
+}
+
+impl Display for CheckStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckStatus::Pending => write!(f, "pending"),
+            CheckStatus::Passed => write!(f, "passed"),
+            CheckStatus::Failed => write!(f, "failed"),
+            CheckStatus::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartupCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// Unix timestamp in milliseconds at which the check was last resolved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved_at: Option<u64>,
+}
+
+/// Outcome of the checks that turn a misconfigured node into actionable output instead of
+/// silently serving canonical data. Checks resolve as the node starts and connects; each
+/// resolution is logged, and the full report once nothing is pending anymore.
+#[derive(Debug)]
+pub struct StartupReport {
+    checks: RwLock<Vec<StartupCheck>>,
+    clock: SharedClock,
+}
+
+impl Default for StartupReport {
+    fn default() -> Self {
+        let checks = CHECKS
+            .iter()
+            .map(|name| StartupCheck {
+                name: name.to_string(),
+                status: CheckStatus::Pending,
+                detail: None,
+                resolved_at: None,
+            })
+            .collect();
+        Self {
+            checks: RwLock::new(checks),
+            clock: SharedClock::default(),
+        }
+    }
+}
+
+impl StartupReport {
+    /// Clock the resolutions are timestamped with.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn pass(&self, name: &str, detail: impl Into<String>) {
+        self.record(name, CheckStatus::Passed, detail.into());
+    }
+
+    pub fn fail(&self, name: &str, detail: impl Into<String>) {
+        self.record(name, CheckStatus::Failed, detail.into());
+    }
+
+    pub fn skip(&self, name: &str, detail: impl Into<String>) {
+        self.record(name, CheckStatus::Skipped, detail.into());
+    }
+
+    /// Whether `name` hasn't passed yet, so callers on hot paths can stop reporting once it
+    /// did.
+    pub fn is_unresolved(&self, name: &str) -> bool {
+        self.checks
+            .read()
+            .unwrap()
+            .iter()
+            .any(|check| check.name == name && check.status != CheckStatus::Passed)
+    }
+
+    /// Status of check `name`, `None` for unknown checks.
+    pub fn status(&self, name: &str) -> Option<CheckStatus> {
+        self.checks
+            .read()
+            .unwrap()
+            .iter()
+            .find(|check| check.name == name)
+            .map(|check| check.status)
+    }
+
+    pub fn checks(&self) -> Vec<StartupCheck> {
+        self.checks.read().unwrap().clone()
+    }
+
+    fn record(&self, name: &str, status: CheckStatus, detail: String) {
+        let resolved_at = self.clock.unix_millis();
+        let mut checks = self.checks.write().unwrap();
+        let Some(check) = checks.iter_mut().find(|check| check.name == name) else {
+            return;
+        };
+        if check.status == status && check.detail.as_ref() == Some(&detail) {
+            return;
+        }
+        if status == CheckStatus::Failed {
+            warn!("Startup check {} failed: {}", name, detail);
+        } else {
+            info!("Startup check {} {}: {}", name, status, detail);
+        }
+        let was_pending = check.status == CheckStatus::Pending;
+        check.status = status;
+        check.detail = Some(detail);
+        check.resolved_at = Some(resolved_at);
+
+        // the full report is logged once, when the last pending check resolves
+        if was_pending
+            && checks
+                .iter()
+                .all(|check| check.status != CheckStatus::Pending)
+        {
+            for check in checks.iter() {
+                info!(
+                    "Startup report: {} {} ({})",
+                    check.name,
+                    check.status,
+                    check.detail.as_deref().unwrap_or_default()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_startup_report() {
+        let report = StartupReport::default();
+        assert!(report
+            .checks()
+            .iter()
+            .all(|check| check.status == CheckStatus::Pending));
+        assert!(report.is_unresolved(WEBSOCKET_REACHABLE));
+
+        report.fail(WEBSOCKET_REACHABLE, "connection refused");
+        assert!(report.is_unresolved(WEBSOCKET_REACHABLE));
+        report.pass(WEBSOCKET_REACHABLE, "connected");
+        assert!(!report.is_unresolved(WEBSOCKET_REACHABLE));
+
+        let check = report
+            .checks()
+            .into_iter()
+            .find(|check| check.name == WEBSOCKET_REACHABLE)
+            .unwrap();
+        assert_eq!(check.status, CheckStatus::Passed);
+        assert_eq!(check.detail.as_deref(), Some("connected"));
+        assert_eq!(
+            report.status(WEBSOCKET_REACHABLE),
+            Some(CheckStatus::Passed)
+        );
+        assert_eq!(report.status("unknown"), None);
+        assert!(check.resolved_at.is_some());
+
+        // unknown checks are ignored
+        report.pass("unknown", "");
+        assert_eq!(report.checks().len(), CHECKS.len());
+    }
+}
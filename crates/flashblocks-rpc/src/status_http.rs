@@ -0,0 +1,220 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::flashblocks_api::PendingSummary;
+use crate::pending::PendingViewStore;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, error, info};
+
+/// Path serving the same summary as `flashblocks_getLatest`.
+const PENDING_PATH: &str = "/flashblocks/pending";
+
+/// Path prefix serving the raw websocket frame of a flashblock, as
+/// `/flashblocks/raw/{block}/{index}`.
+const RAW_FRAME_PATH: &str = "/flashblocks/raw/";
+
+/// Largest request head read before giving up on a connection.
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+/// Time a client gets to send its request head.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Plain HTTP endpoint for consumers that don't speak JSON-RPC (monitoring scripts, CDNs,
+/// status pages). `GET /flashblocks/pending` returns the pending summary as bare JSON, or
+/// `null` when nothing is preconfirmed, and `GET /flashblocks/raw/{block}/{index}` downloads the
+/// websocket frame of a recent flashblock as received. Every response closes the connection.
+#[derive(Debug)]
+pub struct PendingHttpServer {
+    addr: SocketAddr,
+    pending: Arc<PendingViewStore>,
+}
+
+impl PendingHttpServer {
+    pub fn new(addr: SocketAddr, pending: Arc<PendingViewStore>) -> Self {
+        Self { addr, pending }
+    }
+
+    pub async fn run(self) {
+        let listener = match TcpListener::bind(self.addr).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!(
+                    "Failed to bind flashblocks HTTP endpoint {}: {}",
+                    self.addr, e
+                );
+                return;
+            }
+        };
+        info!("Serving flashblocks HTTP endpoint on {}", self.addr);
+
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    error!("Failed to accept flashblocks HTTP connection: {}", e);
+                    continue;
+                }
+            };
+            let pending = Arc::clone(&self.pending);
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(stream, &pending).await {
+                    debug!("Flashblocks HTTP connection from {} failed: {}", peer, e);
+                }
+            });
+        }
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    pending: &PendingViewStore,
+) -> std::io::Result<()> {
+    let head = match tokio::time::timeout(READ_TIMEOUT, read_head(&mut stream)).await {
+        Ok(head) => head?,
+        Err(_) => return Ok(()),
+    };
+
+    let response = match parse_request_line(&head) {
+        Some(("GET", PENDING_PATH)) => {
+            let summary = pending
+                .load_blocks()
+                .latest()
+                .map(|view| PendingSummary::from_view(view, pending.age(view)));
+            let body = serde_json::to_vec(&summary).unwrap_or_else(|_| b"null".to_vec());
+            response("200 OK", "application/json", &body)
+        }
+        Some((_, PENDING_PATH)) => response("405 Method Not Allowed", "text/plain", b""),
+        Some(("GET", path)) if path.starts_with(RAW_FRAME_PATH) => {
+            match raw_frame_key(path).and_then(|(block, index)| pending.raw_frame(block, index)) {
+                Some(frame) => response("200 OK", "application/octet-stream", &frame),
+                None => response("404 Not Found", "text/plain", b""),
+            }
+        }
+        Some(_) => response("404 Not Found", "text/plain", b""),
+        None => response("400 Bad Request", "text/plain", b""),
+    };
+    stream.write_all(&response).await?;
+    stream.shutdown().await
+}
+
+/// Reads until the end of the request head. Bodies are never needed, so anything after it
+/// is ignored.
+async fn read_head(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") && head.len() < MAX_REQUEST_HEAD {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..read]);
+    }
+    Ok(head)
+}
+
+/// Returns the method and path of the request line, without the query string.
+fn parse_request_line(head: &[u8]) -> Option<(&str, &str)> {
+    let line = head.split(|byte| *byte == b'\n').next()?;
+    let line = std::str::from_utf8(line).ok()?.trim_end_matches('\r');
+    let mut parts = line.split(' ');
+    let method = parts.next()?;
+    let target = parts.next()?;
+    if !parts.next()?.starts_with("HTTP/") {
+        return None;
+    }
+    let path = target.split('?').next().unwrap_or(target);
+    Some((method, path))
+}
+
+/// Returns the block number and flashblock index of a raw frame path.
+fn raw_frame_key(path: &str) -> Option<(u64, u64)> {
+    let (block, index) = path.strip_prefix(RAW_FRAME_PATH)?.split_once('/')?;
+    Some((block.parse().ok()?, index.parse().ok()?))
+}
+
+fn response(status: &str, content_type: &str, body: &[u8]) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::Bytes;
+
+    #[test]
+    fn test_parse_request_line() {
+        assert_eq!(
+            parse_request_line(b"GET /flashblocks/pending HTTP/1.1\r\nHost: x\r\n\r\n"),
+            Some(("GET", PENDING_PATH))
+        );
+        assert_eq!(
+            parse_request_line(b"GET /flashblocks/pending?t=1 HTTP/1.0\r\n\r\n"),
+            Some(("GET", PENDING_PATH))
+        );
+        assert_eq!(
+            parse_request_line(b"HEAD / HTTP/1.1\r\n\r\n"),
+            Some(("HEAD", "/"))
+        );
+        assert_eq!(
+            parse_request_line(b"GET /flashblocks/pending\r\n\r\n"),
+            None
+        );
+        assert_eq!(parse_request_line(b""), None);
+    }
+
+    #[test]
+    fn test_raw_frame_key() {
+        assert_eq!(raw_frame_key("/flashblocks/raw/12/3"), Some((12, 3)));
+        assert_eq!(raw_frame_key("/flashblocks/raw/12"), None);
+        assert_eq!(raw_frame_key("/flashblocks/raw/12/3/4"), None);
+        assert_eq!(raw_frame_key("/flashblocks/raw/latest/0"), None);
+    }
+
+    #[tokio::test]
+    async fn test_serves_pending_summary() {
+        let pending = Arc::new(PendingViewStore::default());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_pending = Arc::clone(&pending);
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                handle_connection(stream, &server_pending).await.unwrap();
+            }
+        });
+
+        let get = |path: &'static str| async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(format!("GET {path} HTTP/1.1\r\nHost: test\r\n\r\n").as_bytes())
+                .await
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        let response = get(PENDING_PATH).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("\r\n\r\nnull"));
+
+        let response = get("/other").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found"));
+
+        let sequence = pending.record_raw_frame(Bytes::from_static(b"frame"));
+        pending.raw_frame_decoded(sequence, 1, 0);
+        let response = get("/flashblocks/raw/1/0").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("\r\n\r\nframe"));
+        let response = get("/flashblocks/raw/1/1").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found"));
+    }
+}
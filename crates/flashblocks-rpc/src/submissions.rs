@@ -0,0 +1,315 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crate::clock::SharedClock;
+use crate::filters::{Cursor, PendingFilterKind};
+use crate::pending::{PendingView, PendingViewStore};
+use alloy_primitives::TxHash;
+use alloy_rpc_types_eth::PendingTransactionFilterKind;
+use reth::providers::{CanonStateNotification, CanonStateNotifications};
+use reth_optimism_primitives::OpPrimitives;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::debug;
+
+/// Number of submitted transactions tracked, the oldest ones are forgotten first.
+const MAX_TRACKED_SUBMISSIONS: usize = 10_000;
+
+/// Where a transaction submitted through this node is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SubmissionState {
+    Submitted,
+    /// A flashblock included the transaction
+    Preconfirmed,
+    /// A canonical block included the transaction
+    Confirmed,
+}
+
+/// A transaction submitted through `eth_sendRawTransaction` and the transitions it went
+/// through. Timestamps are Unix timestamps in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Submission {
+    pub state: SubmissionState,
+    /// Whether the node forwarded the transaction to the sequencer, along with adding it to
+    /// the local txpool
+    pub forwarded: bool,
+    pub submitted_at: u64,
+    pub preconfirmed_at: Option<u64>,
+    pub confirmed_at: Option<u64>,
+    /// Block that preconfirmed, then included, the transaction
+    pub block_number: Option<u64>,
+}
+
+impl Submission {
+    fn preconfirm(&mut self, block_number: u64, now: u64) {
+        if self.state == SubmissionState::Submitted {
+            self.state = SubmissionState::Preconfirmed;
+            self.preconfirmed_at = Some(now);
+            self.block_number = Some(block_number);
+        }
+    }
+
+    fn confirm(&mut self, block_number: u64, now: u64) {
+        if self.state != SubmissionState::Confirmed {
+            self.state = SubmissionState::Confirmed;
+            self.confirmed_at = Some(now);
+            self.block_number = Some(block_number);
+        }
+    }
+
+    /// Goes back to submitted, the block that included the transaction was reorged out.
+    fn reorged(&mut self) {
+        self.state = SubmissionState::Submitted;
+        self.preconfirmed_at = None;
+        self.confirmed_at = None;
+        self.block_number = None;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Submissions {
+    by_hash: HashMap<TxHash, Submission>,
+    /// Hashes in submission order, to forget the oldest ones first
+    order: VecDeque<TxHash>,
+}
+
+/// Transactions submitted through this node, so receipt and status queries can tell a
+/// transaction that is on its way to the sequencer from an unknown one.
+#[derive(Debug, Default)]
+pub struct SubmissionTracker {
+    submissions: Mutex<Submissions>,
+    clock: SharedClock,
+}
+
+impl SubmissionTracker {
+    /// Clock the transitions are timestamped with.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn submitted(&self, tx_hash: TxHash, forwarded: bool) {
+        let mut submissions = self.submissions.lock().unwrap();
+        // a resubmission keeps the transitions already seen
+        if submissions.by_hash.contains_key(&tx_hash) {
+            return;
+        }
+        let submission = Submission {
+            state: SubmissionState::Submitted,
+            forwarded,
+            submitted_at: self.clock.unix_millis(),
+            preconfirmed_at: None,
+            confirmed_at: None,
+            block_number: None,
+        };
+        submissions.by_hash.insert(tx_hash, submission);
+        submissions.order.push_back(tx_hash);
+        while submissions.order.len() > MAX_TRACKED_SUBMISSIONS {
+            if let Some(oldest) = submissions.order.pop_front() {
+                submissions.by_hash.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn preconfirmed(&self, tx_hash: TxHash, block_number: u64) {
+        let mut submissions = self.submissions.lock().unwrap();
+        if let Some(submission) = submissions.by_hash.get_mut(&tx_hash) {
+            submission.preconfirm(block_number, self.clock.unix_millis());
+        }
+    }
+
+    pub fn confirmed(&self, tx_hash: TxHash, block_number: u64) {
+        let mut submissions = self.submissions.lock().unwrap();
+        if let Some(submission) = submissions.by_hash.get_mut(&tx_hash) {
+            submission.confirm(block_number, self.clock.unix_millis());
+        }
+    }
+
+    pub fn get(&self, tx_hash: TxHash) -> Option<Submission> {
+        self.submissions
+            .lock()
+            .unwrap()
+            .by_hash
+            .get(&tx_hash)
+            .copied()
+    }
+
+    /// Marks the submitted transactions `view` added from `position` on as preconfirmed.
+    pub fn observe(&self, view: &PendingView, position: usize) {
+        let transactions = view
+            .block
+            .body
+            .transactions
+            .get(position..)
+            .unwrap_or_default();
+        let now = self.clock.unix_millis();
+        let mut submissions = self.submissions.lock().unwrap();
+        for transaction in transactions {
+            if let Some(submission) = submissions.by_hash.get_mut(&transaction.tx_hash()) {
+                submission.preconfirm(view.block_number(), now);
+            }
+        }
+    }
+
+    /// Marks the submitted transactions of the canonical blocks in `notification` as
+    /// confirmed, and those of the blocks it reorged out as submitted again.
+    pub fn observe_canonical(&self, notification: &CanonStateNotification<OpPrimitives>) {
+        let now = self.clock.unix_millis();
+        let mut submissions = self.submissions.lock().unwrap();
+        if let CanonStateNotification::Reorg { old, .. } = notification {
+            for block in old.blocks().values() {
+                for transaction in &block.body().transactions {
+                    if let Some(submission) = submissions.by_hash.get_mut(&transaction.tx_hash()) {
+                        submission.reorged();
+                    }
+                }
+            }
+        }
+        for block in notification.committed().blocks().values() {
+            for transaction in &block.body().transactions {
+                if let Some(submission) = submissions.by_hash.get_mut(&transaction.tx_hash()) {
+                    submission.confirm(block.header().number, now);
+                }
+            }
+        }
+    }
+
+    /// Follows the published views and the canonical chain, recording when the submitted
+    /// transactions are preconfirmed and confirmed.
+    pub async fn run(
+        self: Arc<Self>,
+        pending: Arc<PendingViewStore>,
+        mut canonical: CanonStateNotifications<OpPrimitives>,
+    ) {
+        let kind = PendingFilterKind::Transactions(PendingTransactionFilterKind::Hashes);
+        let mut views = pending.subscribe_views();
+        let mut cursor = Cursor::latest(&pending.load_blocks());
+        loop {
+            tokio::select! {
+                view = views.recv() => {
+                    // the views skipped by a lagging receiver are read from the blocks
+                    if matches!(view, Err(RecvError::Closed)) {
+                        return;
+                    }
+                    let blocks = pending.load_blocks();
+                    let advance = cursor.advance(&blocks, &kind);
+                    if advance.missed {
+                        debug!("Flashblocks were dropped before their submissions were tracked");
+                    }
+                    for (view, position) in advance.views {
+                        self.observe(view, position);
+                    }
+                }
+                notification = canonical.recv() => match notification {
+                    Ok(notification) => self.observe_canonical(&notification),
+                    // the skipped blocks are picked up as preconfirmed, not confirmed
+                    Err(RecvError::Lagged(skipped)) => {
+                        debug!("Submissions skipped {} canonical notifications", skipped);
+                    }
+                    Err(RecvError::Closed) => return,
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::signed_tx;
+    use alloy_primitives::{Address, U256};
+    use reth::providers::{Chain, ExecutionOutcome};
+    use reth_optimism_primitives::{OpBlock, OpTransactionSigned};
+    use reth_primitives::RecoveredBlock;
+
+    fn block(block_number: u64, transactions: Vec<OpTransactionSigned>) -> OpBlock {
+        let mut block = OpBlock::default();
+        block.header.number = block_number;
+        block.body.transactions = transactions;
+        block
+    }
+
+    fn chain(block: OpBlock) -> Arc<Chain<OpPrimitives>> {
+        let senders = vec![Address::ZERO; block.body.transactions.len()];
+        let block = RecoveredBlock::new_unhashed(block, senders);
+        Arc::new(Chain::new([block], ExecutionOutcome::default(), None))
+    }
+
+    #[test]
+    fn test_submission_transitions() {
+        let tracker = SubmissionTracker::default();
+        let tx_hash = TxHash::repeat_byte(0x1);
+        assert_eq!(tracker.get(tx_hash), None);
+
+        tracker.submitted(tx_hash, true);
+        let submission = tracker.get(tx_hash).unwrap();
+        assert_eq!(submission.state, SubmissionState::Submitted);
+        assert!(submission.forwarded);
+
+        tracker.preconfirmed(tx_hash, 5);
+        tracker.confirmed(tx_hash, 5);
+        let submission = tracker.get(tx_hash).unwrap();
+        assert_eq!(submission.state, SubmissionState::Confirmed);
+        assert_eq!(submission.block_number, Some(5));
+        assert!(submission.preconfirmed_at.is_some());
+
+        // confirmation is final
+        tracker.preconfirmed(tx_hash, 6);
+        assert_eq!(tracker.get(tx_hash).unwrap().block_number, Some(5));
+    }
+
+    #[test]
+    fn test_observe_new_transactions() {
+        let tracker = SubmissionTracker::default();
+        tracker.submitted(signed_tx(0).tx_hash(), false);
+        tracker.submitted(signed_tx(1).tx_hash(), false);
+
+        // the first transaction was there when the view was last read
+        let view = PendingView::new(
+            block(5, vec![signed_tx(0), signed_tx(1)]),
+            0,
+            vec![Address::ZERO; 2],
+        );
+        tracker.observe(&view, 1);
+        let state = |tx: OpTransactionSigned| tracker.get(tx.tx_hash()).unwrap().state;
+        assert_eq!(state(signed_tx(0)), SubmissionState::Submitted);
+        assert_eq!(state(signed_tx(1)), SubmissionState::Preconfirmed);
+    }
+
+    #[test]
+    fn test_observe_canonical() {
+        let tracker = SubmissionTracker::default();
+        let tx_hash = signed_tx(0).tx_hash();
+        tracker.submitted(tx_hash, true);
+
+        let included = chain(block(5, vec![signed_tx(0)]));
+        tracker.observe_canonical(&CanonStateNotification::Commit {
+            new: included.clone(),
+        });
+        let submission = tracker.get(tx_hash).unwrap();
+        assert_eq!(submission.state, SubmissionState::Confirmed);
+        assert_eq!(submission.block_number, Some(5));
+
+        // the block is reorged out for one without the transaction
+        tracker.observe_canonical(&CanonStateNotification::Reorg {
+            old: included,
+            new: chain(block(5, Vec::new())),
+        });
+        let submission = tracker.get(tx_hash).unwrap();
+        assert_eq!(submission.state, SubmissionState::Submitted);
+        assert_eq!(submission.block_number, None);
+    }
+
+    #[test]
+    fn test_oldest_submissions_are_forgotten() {
+        let tracker = SubmissionTracker::default();
+        let tx_hash = |index: usize| TxHash::from(U256::from(index));
+        for index in 0..=MAX_TRACKED_SUBMISSIONS {
+            tracker.submitted(tx_hash(index), false);
+        }
+        assert_eq!(tracker.get(tx_hash(0)), None);
+        assert!(tracker.get(tx_hash(1)).is_some());
+    }
+}
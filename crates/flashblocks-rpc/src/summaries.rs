@@ -0,0 +1,415 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::clock::SharedClock;
+use crate::pending::PendingView;
+use crate::reconciliation::Reconciliation;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+/// Default number of days block summaries are kept for.
+pub const DEFAULT_SUMMARY_RETENTION_DAYS: u64 = 14;
+
+/// How often expired summaries are dropped from the file, which rewrites it.
+const COMPACTION_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Largest number of blocks `base_getBlockSummaries` returns at once.
+pub const MAX_SUMMARY_RANGE: u64 = 10_000;
+
+/// Largest number of buckets `base_getBlockSummaryAggregates` returns at once.
+pub const MAX_SUMMARY_BUCKETS: u64 = 1_000;
+
+/// Compact record of how well a block was preconfirmed, kept long enough to compare quality
+/// week over week.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockSummary {
+    pub block_number: u64,
+    /// Number of flashblocks applied to the block
+    pub flashblocks: u64,
+    pub transactions: u64,
+    /// Time between the first and the last flashblock of the block
+    pub duration_ms: u64,
+    pub mean_interval_ms: u64,
+    pub max_interval_ms: u64,
+    /// Time from receiving the last flashblock to serving it
+    pub ingest_lag_micros: u64,
+    /// Whether the canonical block matched the preconfirmation
+    pub accurate: bool,
+    /// Preconfirmed transactions missing from the canonical block
+    pub missing: u64,
+    /// Canonical transactions that were never preconfirmed
+    pub unexpected: u64,
+    /// Unix timestamp in milliseconds at which the block was reconciled
+    pub reconciled_at: u64,
+}
+
+impl BlockSummary {
+    pub fn new(view: &PendingView, reconciliation: &Reconciliation) -> Self {
+        let intervals: Vec<u64> = view
+            .audit
+            .iter()
+            .zip(view.audit.iter().skip(1))
+            .map(|(previous, next)| next.received_at.saturating_sub(previous.received_at))
+            .collect();
+        Self {
+            block_number: reconciliation.block_number,
+            flashblocks: view.audit.len() as u64,
+            transactions: view.block.body.transactions.len() as u64,
+            duration_ms: intervals.iter().sum(),
+            mean_interval_ms: intervals.iter().sum::<u64>() / intervals.len().max(1) as u64,
+            max_interval_ms: intervals.iter().copied().max().unwrap_or_default(),
+            ingest_lag_micros: view.ingest_lag().as_micros() as u64,
+            accurate: reconciliation.is_consistent(),
+            missing: reconciliation.missing.len() as u64,
+            unexpected: reconciliation.unexpected.len() as u64,
+            reconciled_at: reconciliation.reconciled_at,
+        }
+    }
+}
+
+/// Block summaries of a range of blocks folded together, so weeks of blocks can be compared
+/// without fetching every summary.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SummaryAggregate {
+    pub from_block: u64,
+    pub to_block: u64,
+    /// Number of blocks of the range that were summarized
+    pub blocks: u64,
+    /// Number of them whose canonical block matched the preconfirmation
+    pub accurate: u64,
+    pub flashblocks: u64,
+    pub transactions: u64,
+    pub mean_interval_ms: u64,
+    pub max_interval_ms: u64,
+    pub mean_ingest_lag_micros: u64,
+    pub max_ingest_lag_micros: u64,
+    pub missing: u64,
+    pub unexpected: u64,
+}
+
+impl SummaryAggregate {
+    fn new(from_block: u64, to_block: u64) -> Self {
+        Self {
+            from_block,
+            to_block,
+            ..Default::default()
+        }
+    }
+
+    fn add(&mut self, summary: &BlockSummary) {
+        self.blocks += 1;
+        self.accurate += summary.accurate as u64;
+        self.flashblocks += summary.flashblocks;
+        self.transactions += summary.transactions;
+        self.max_interval_ms = self.max_interval_ms.max(summary.max_interval_ms);
+        self.max_ingest_lag_micros = self.max_ingest_lag_micros.max(summary.ingest_lag_micros);
+        self.missing += summary.missing;
+        self.unexpected += summary.unexpected;
+        // running sums until the bucket is complete
+        self.mean_interval_ms += summary.duration_ms;
+        self.mean_ingest_lag_micros += summary.ingest_lag_micros;
+    }
+
+    fn finish(mut self) -> Self {
+        let intervals = self.flashblocks.saturating_sub(self.blocks).max(1);
+        self.mean_interval_ms /= intervals;
+        self.mean_ingest_lag_micros /= self.blocks.max(1);
+        self
+    }
+}
+
+/// Block summaries of the retention period. They are served from memory and written behind to
+/// a JSON lines file, so they survive restarts without slowing down the reconciler.
+#[derive(Debug)]
+pub struct SummaryStore {
+    path: Option<PathBuf>,
+    retention: Duration,
+    summaries: RwLock<BTreeMap<u64, BlockSummary>>,
+    /// Summaries recorded since the last flush
+    unflushed: Mutex<Vec<BlockSummary>>,
+    /// Clock the retention is measured with
+    clock: SharedClock,
+}
+
+impl SummaryStore {
+    /// A store that only keeps the summaries in memory.
+    pub fn new(retention: Duration, clock: SharedClock) -> Self {
+        Self {
+            path: None,
+            retention,
+            summaries: RwLock::new(BTreeMap::new()),
+            unflushed: Mutex::new(Vec::new()),
+            clock,
+        }
+    }
+
+    /// Opens the store persisted at `path`, loading the summaries that haven't expired.
+    /// Unreadable lines are skipped.
+    pub fn open(path: PathBuf, retention: Duration, clock: SharedClock) -> io::Result<Self> {
+        let mut store = Self::new(retention, clock);
+        if path.exists() {
+            let mut summaries = store.summaries.write().unwrap();
+            for line in BufReader::new(File::open(&path)?).lines() {
+                match serde_json::from_str::<BlockSummary>(&line?) {
+                    Ok(summary) => {
+                        summaries.insert(summary.block_number, summary);
+                    }
+                    Err(e) => error!("Skipping unreadable block summary: {}", e),
+                }
+            }
+        }
+        store.path = Some(path);
+        store.compact()?;
+        Ok(store)
+    }
+
+    pub fn record(&self, summary: BlockSummary) {
+        self.summaries
+            .write()
+            .unwrap()
+            .insert(summary.block_number, summary.clone());
+        // a compaction in between writes it twice, loading keeps one
+        if self.path.is_some() {
+            self.unflushed.lock().unwrap().push(summary);
+        }
+    }
+
+    /// Summaries of the blocks from `from` to `to`, at most [`MAX_SUMMARY_RANGE`] of them.
+    pub fn range(&self, from: u64, to: u64) -> Vec<BlockSummary> {
+        let to = to.min(from.saturating_add(MAX_SUMMARY_RANGE - 1));
+        if from > to {
+            return Vec::new();
+        }
+        self.summaries
+            .read()
+            .unwrap()
+            .range(from..=to)
+            .map(|(_, summary)| summary.clone())
+            .collect()
+    }
+
+    /// Summaries of the blocks from `from` to `to` aggregated by `bucket_size` blocks, at most
+    /// [`MAX_SUMMARY_BUCKETS`] buckets. Buckets without any summary are left out.
+    pub fn aggregate(&self, from: u64, to: u64, bucket_size: u64) -> Vec<SummaryAggregate> {
+        let bucket_size = bucket_size.max(1);
+        let to = to.min(
+            from.saturating_add(bucket_size.saturating_mul(MAX_SUMMARY_BUCKETS))
+                .saturating_sub(1),
+        );
+        if from > to {
+            return Vec::new();
+        }
+        let mut aggregates: Vec<SummaryAggregate> = Vec::new();
+        for (block_number, summary) in self.summaries.read().unwrap().range(from..=to) {
+            let bucket_from = from + (block_number - from) / bucket_size * bucket_size;
+            match aggregates.last_mut() {
+                Some(aggregate) if aggregate.from_block == bucket_from => aggregate.add(summary),
+                _ => {
+                    let bucket_to = bucket_from.saturating_add(bucket_size - 1).min(to);
+                    let mut aggregate = SummaryAggregate::new(bucket_from, bucket_to);
+                    aggregate.add(summary);
+                    aggregates.push(aggregate);
+                }
+            }
+        }
+        aggregates
+            .into_iter()
+            .map(SummaryAggregate::finish)
+            .collect()
+    }
+
+    /// Appends the summaries recorded since the last flush to the file.
+    pub fn flush(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let unflushed = std::mem::take(&mut *self.unflushed.lock().unwrap());
+        if unflushed.is_empty() {
+            return Ok(());
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        write_summaries(file, &unflushed)
+    }
+
+    /// Drops the expired summaries and rewrites the file with the remaining ones.
+    pub fn compact(&self) -> io::Result<()> {
+        let cutoff = self
+            .clock
+            .unix_millis()
+            .saturating_sub(self.retention.as_millis() as u64);
+        {
+            let mut summaries = self.summaries.write().unwrap();
+            summaries.retain(|_, summary| summary.reconciled_at >= cutoff);
+            if self.path.is_some() {
+                // everything in memory is written out, including what wasn't flushed yet
+                self.unflushed.lock().unwrap().clear();
+            }
+        }
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        // rewriting under the read lock keeps the store readable, a summary recorded in
+        // between is written twice and loading keeps one
+        let rewritten = path.with_extension("tmp");
+        write_summaries(
+            File::create(&rewritten)?,
+            self.summaries.read().unwrap().values(),
+        )?;
+        fs::rename(rewritten, path)
+    }
+
+    /// Flushes the store every `interval` and compacts it every hour. The file is written on
+    /// the blocking pool, a compaction rewrites all of it.
+    pub async fn run(self: Arc<Self>, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        let mut compacted_at = Instant::now();
+        loop {
+            interval.tick().await;
+            let compact = compacted_at.elapsed() >= COMPACTION_INTERVAL;
+            if compact {
+                compacted_at = Instant::now();
+            }
+            let store = self.clone();
+            let written = tokio::task::spawn_blocking(move || {
+                if let Err(e) = store.flush() {
+                    error!("Failed to persist block summaries: {}", e);
+                }
+                if compact {
+                    if let Err(e) = store.compact() {
+                        error!("Failed to compact block summaries: {}", e);
+                    }
+                }
+            })
+            .await;
+            if let Err(e) = written {
+                error!("Block summaries writer failed: {}", e);
+            }
+        }
+    }
+}
+
+fn write_summaries<'a>(
+    file: File,
+    summaries: impl IntoIterator<Item = &'a BlockSummary>,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(file);
+    for summary in summaries {
+        serde_json::to_writer(&mut writer, summary)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(block_number: u64, reconciled_at: u64) -> BlockSummary {
+        BlockSummary {
+            block_number,
+            flashblocks: 10,
+            transactions: 100,
+            duration_ms: 1800,
+            mean_interval_ms: 200,
+            max_interval_ms: 250,
+            ingest_lag_micros: 500,
+            accurate: true,
+            missing: 0,
+            unexpected: 0,
+            reconciled_at,
+        }
+    }
+
+    #[test]
+    fn test_range_is_capped() {
+        let clock = SharedClock::default();
+        let store = SummaryStore::new(Duration::from_secs(3600), clock.clone());
+        for block_number in 1..=3 {
+            store.record(summary(block_number, clock.unix_millis()));
+        }
+
+        let blocks: Vec<_> = store
+            .range(2, 10)
+            .iter()
+            .map(|summary| summary.block_number)
+            .collect();
+        assert_eq!(blocks, vec![2, 3]);
+        assert!(store.range(3, 2).is_empty());
+        assert_eq!(store.range(0, u64::MAX).len(), 3);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let clock = SharedClock::default();
+        let store = SummaryStore::new(Duration::from_secs(3600), clock.clone());
+        for block_number in [1, 2, 3, 7] {
+            let mut summary = summary(block_number, clock.unix_millis());
+            summary.accurate = block_number != 2;
+            summary.ingest_lag_micros = block_number * 100;
+            store.record(summary);
+        }
+
+        let aggregates = store.aggregate(1, 10, 3);
+        // blocks 4 to 6 have no summary
+        assert_eq!(aggregates.len(), 2);
+        assert_eq!(
+            aggregates[0],
+            SummaryAggregate {
+                from_block: 1,
+                to_block: 3,
+                blocks: 3,
+                accurate: 2,
+                flashblocks: 30,
+                transactions: 300,
+                mean_interval_ms: 200,
+                max_interval_ms: 250,
+                mean_ingest_lag_micros: 200,
+                max_ingest_lag_micros: 300,
+                missing: 0,
+                unexpected: 0,
+            }
+        );
+        assert_eq!((aggregates[1].from_block, aggregates[1].to_block), (7, 9));
+        assert_eq!(aggregates[1].blocks, 1);
+
+        // the number of buckets is capped
+        assert_eq!(store.aggregate(0, u64::MAX, 1).len(), 4);
+        let capped = store.aggregate(0, u64::MAX, u64::MAX / 2);
+        assert_eq!(capped.len(), 1);
+        assert_eq!(capped[0].blocks, 4);
+        assert!(store.aggregate(3, 2, 1).is_empty());
+    }
+
+    #[test]
+    fn test_persisted_across_restarts() {
+        let path = std::env::temp_dir().join(format!("summaries-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let retention = Duration::from_secs(3600);
+
+        let clock = SharedClock::default();
+
+        let store = SummaryStore::open(path.clone(), retention, clock.clone()).unwrap();
+        store.record(summary(1, clock.unix_millis()));
+        // expired by the time the store is opened again
+        store.record(summary(2, clock.unix_millis() - 2 * 3600 * 1000));
+        store.flush().unwrap();
+        store.record(summary(3, clock.unix_millis()));
+        store.flush().unwrap();
+
+        let reopened = SummaryStore::open(path.clone(), retention, clock).unwrap();
+        let blocks: Vec<_> = reopened
+            .range(0, 10)
+            .iter()
+            .map(|summary| summary.block_number)
+            .collect();
+        assert_eq!(blocks, vec![1, 3]);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,313 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use alloy_eips::{BlockId, BlockNumberOrTag};
+use alloy_rpc_types::Filter;
+use alloy_rpc_types_eth::{StateContext, TransactionIndex};
+use jsonrpsee::types::ErrorObject;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+/// Tag naming the block the flashblocks are building, whatever `pending` is configured to mean.
+pub const PRECONFIRMED: &str = "preconfirmed";
+
+/// Error code of requests on `preconfirmed` while the flashblocks have no block to serve them
+/// from, the EIP-1474 "resource unavailable".
+pub const PRECONFIRMED_UNAVAILABLE_CODE: i32 = -32002;
+
+/// Error of requests on `preconfirmed` that the flashblocks can't serve. The node's own pending
+/// block isn't the preconfirmed one, so they don't fall back to it.
+pub fn preconfirmed_unavailable() -> ErrorObject<'static> {
+    ErrorObject::owned(
+        PRECONFIRMED_UNAVAILABLE_CODE,
+        "no preconfirmed block",
+        None::<()>,
+    )
+}
+
+/// What the `pending` tag is served from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PendingTagMode {
+    /// The flashblocks, like `preconfirmed`
+    #[default]
+    Flashblocks,
+    /// The node's own pending block, leaving the flashblocks to `preconfirmed`
+    Node,
+}
+
+impl FromStr for PendingTagMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flashblocks" => Ok(Self::Flashblocks),
+            "node" => Ok(Self::Node),
+            _ => Err(format!("invalid pending tag mode: {s}")),
+        }
+    }
+}
+
+impl Display for PendingTagMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Flashblocks => write!(f, "flashblocks"),
+            Self::Node => write!(f, "node"),
+        }
+    }
+}
+
+/// Block parameters that can name the pending block.
+pub trait PendingTag: Sized {
+    fn pending() -> Self;
+
+    fn is_pending(&self) -> bool;
+}
+
+impl PendingTag for BlockId {
+    fn pending() -> Self {
+        BlockId::pending()
+    }
+
+    fn is_pending(&self) -> bool {
+        BlockId::is_pending(self)
+    }
+}
+
+impl PendingTag for BlockNumberOrTag {
+    fn pending() -> Self {
+        BlockNumberOrTag::Pending
+    }
+
+    fn is_pending(&self) -> bool {
+        BlockNumberOrTag::is_pending(self)
+    }
+}
+
+/// A block parameter that also accepts the `preconfirmed` tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreconfirmedOr<T> {
+    Preconfirmed,
+    Block(T),
+}
+
+impl<T> PreconfirmedOr<T> {
+    pub fn is_preconfirmed(&self) -> bool {
+        matches!(self, Self::Preconfirmed)
+    }
+}
+
+impl<T: PendingTag> PreconfirmedOr<T> {
+    /// The block the node resolves the parameter to, `pending` for `preconfirmed`, and whether
+    /// the flashblocks serve it.
+    pub fn resolve(self, mode: PendingTagMode) -> (T, bool) {
+        match self {
+            Self::Preconfirmed => (T::pending(), true),
+            Self::Block(block) => {
+                let flashblocks = block.is_pending() && mode == PendingTagMode::Flashblocks;
+                (block, flashblocks)
+            }
+        }
+    }
+}
+
+impl<T: Default> Default for PreconfirmedOr<T> {
+    fn default() -> Self {
+        Self::Block(T::default())
+    }
+}
+
+impl<T> From<T> for PreconfirmedOr<T> {
+    fn from(block: T) -> Self {
+        Self::Block(block)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for PreconfirmedOr<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        if value.as_str() == Some(PRECONFIRMED) {
+            return Ok(Self::Preconfirmed);
+        }
+        T::deserialize(value)
+            .map(Self::Block)
+            .map_err(D::Error::custom)
+    }
+}
+
+impl<T: Serialize> Serialize for PreconfirmedOr<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Preconfirmed => serializer.serialize_str(PRECONFIRMED),
+            Self::Block(block) => block.serialize(serializer),
+        }
+    }
+}
+
+/// A [`StateContext`] whose block may be `preconfirmed`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreconfirmedStateContext {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<PreconfirmedOr<BlockId>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_index: Option<TransactionIndex>,
+}
+
+impl PreconfirmedStateContext {
+    /// The context the node resolves the parameter to and whether the flashblocks serve it,
+    /// like [`PreconfirmedOr::resolve`].
+    pub fn resolve(self, mode: PendingTagMode) -> (StateContext, bool) {
+        let (block_number, flashblocks) = self.block_number.unwrap_or_default().resolve(mode);
+        let context = StateContext {
+            block_number: Some(block_number),
+            transaction_index: self.transaction_index,
+        };
+        (context, flashblocks)
+    }
+}
+
+/// A log filter whose range may start or end at `preconfirmed`, read as `pending`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreconfirmedFilter {
+    pub filter: Filter,
+    /// Whether a bound of the range is `preconfirmed`
+    pub preconfirmed: bool,
+}
+
+/// Bounds of a filter's range that may name a block.
+const FILTER_BOUNDS: [&str; 2] = ["fromBlock", "toBlock"];
+
+impl PreconfirmedFilter {
+    /// The filter the node resolves the parameter to, and whether the flashblocks serve its
+    /// `pending` bounds.
+    pub fn resolve(self, mode: PendingTagMode) -> (Filter, bool) {
+        let flashblocks = self.preconfirmed || mode == PendingTagMode::Flashblocks;
+        (self.filter, flashblocks)
+    }
+}
+
+impl From<Filter> for PreconfirmedFilter {
+    fn from(filter: Filter) -> Self {
+        Self {
+            filter,
+            preconfirmed: false,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PreconfirmedFilter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut value = Value::deserialize(deserializer)?;
+        let mut preconfirmed = false;
+        for bound in FILTER_BOUNDS {
+            if let Some(tag) = value.get_mut(bound) {
+                if tag.as_str() == Some(PRECONFIRMED) {
+                    *tag = Value::from("pending");
+                    preconfirmed = true;
+                }
+            }
+        }
+        let filter = Filter::deserialize(value).map_err(D::Error::custom)?;
+        Ok(Self {
+            filter,
+            preconfirmed,
+        })
+    }
+}
+
+impl Serialize for PreconfirmedFilter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut value = serde_json::to_value(&self.filter).map_err(serde::ser::Error::custom)?;
+        if self.preconfirmed {
+            for bound in FILTER_BOUNDS {
+                if let Some(tag) = value.get_mut(bound) {
+                    if tag.as_str() == Some("pending") {
+                        *tag = Value::from(PRECONFIRMED);
+                    }
+                }
+            }
+        }
+        value.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_preconfirmed() {
+        let parse = |s| serde_json::from_str::<PreconfirmedOr<BlockId>>(s).unwrap();
+        assert_eq!(parse("\"preconfirmed\""), PreconfirmedOr::Preconfirmed);
+        assert_eq!(
+            parse("\"pending\""),
+            PreconfirmedOr::Block(BlockId::pending())
+        );
+        assert_eq!(
+            parse("\"0x10\""),
+            PreconfirmedOr::Block(BlockId::number(16))
+        );
+        assert!(serde_json::from_str::<PreconfirmedOr<BlockNumberOrTag>>("\"head\"").is_err());
+
+        let tag = serde_json::to_string(&PreconfirmedOr::<BlockId>::Preconfirmed).unwrap();
+        assert_eq!(tag, "\"preconfirmed\"");
+    }
+
+    #[test]
+    fn test_resolve() {
+        let pending = PreconfirmedOr::Block(BlockNumberOrTag::Pending);
+        let preconfirmed = PreconfirmedOr::<BlockNumberOrTag>::Preconfirmed;
+        assert_eq!(
+            pending.resolve(PendingTagMode::Flashblocks),
+            (BlockNumberOrTag::Pending, true)
+        );
+        assert_eq!(
+            pending.resolve(PendingTagMode::Node),
+            (BlockNumberOrTag::Pending, false)
+        );
+        assert_eq!(
+            preconfirmed.resolve(PendingTagMode::Node),
+            (BlockNumberOrTag::Pending, true)
+        );
+        let latest = PreconfirmedOr::Block(BlockNumberOrTag::Latest);
+        assert_eq!(
+            latest.resolve(PendingTagMode::Flashblocks),
+            (BlockNumberOrTag::Latest, false)
+        );
+    }
+
+    #[test]
+    fn test_parse_preconfirmed_filter() {
+        let filter: PreconfirmedFilter =
+            serde_json::from_str(r#"{"fromBlock":"0x1","toBlock":"preconfirmed"}"#).unwrap();
+        assert!(filter.preconfirmed);
+        assert_eq!(filter.filter.get_to_block(), None);
+        assert!(filter
+            .filter
+            .block_option
+            .get_to_block()
+            .unwrap()
+            .is_pending());
+        let json = serde_json::to_value(&filter).unwrap();
+        assert_eq!(json["toBlock"], PRECONFIRMED);
+
+        let filter: PreconfirmedFilter = serde_json::from_str(r#"{"toBlock":"pending"}"#).unwrap();
+        assert!(!filter.clone().resolve(PendingTagMode::Node).1);
+        assert!(filter.resolve(PendingTagMode::Flashblocks).1);
+
+        let context: PreconfirmedStateContext =
+            serde_json::from_str(r#"{"blockNumber":"preconfirmed"}"#).unwrap();
+        let (context, flashblocks) = context.resolve(PendingTagMode::Node);
+        assert_eq!(context.block_number, Some(BlockId::pending()));
+        assert!(flashblocks);
+    }
+
+    #[test]
+    fn test_pending_tag_mode() {
+        for mode in [PendingTagMode::Flashblocks, PendingTagMode::Node] {
+            assert_eq!(mode.to_string().parse::<PendingTagMode>(), Ok(mode));
+        }
+        assert!("mempool".parse::<PendingTagMode>().is_err());
+    }
+}
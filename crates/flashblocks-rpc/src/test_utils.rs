@@ -0,0 +1,41 @@
+use crate::pending::FlashblockAudit;
+use alloy_consensus::{SignableTransaction, TxEip1559};
+use alloy_eips::eip2718::{Decodable2718, Encodable2718};
+use alloy_primitives::{hex, Signature};
+use op_alloy_consensus::OpTxEnvelope;
+use reth_optimism_primitives::OpTransactionSigned;
+
+/// EIP-1559 transaction signed for chain id 84532, with nonce 382.
+pub const EIP1559_TX: &str = "0x02f87483014a3482017e8459682f0084596830a98301f1d094b01866f195533de16eb929b73f87280693ca0cb480844e71d92dc001a0a658c18bdba29dd4022ee6640fdd143691230c12b3c8c86cf5c1a1f1682cc1e2a0248a28763541ebed2b87ecea63a7024b5c2b7de58539fa64c887b08f5faf29c1";
+
+/// [`EIP1559_TX`] decoded.
+pub fn eip1559_tx() -> OpTransactionSigned {
+    OpTransactionSigned::decode_2718(&mut hex::decode(EIP1559_TX).unwrap().as_slice()).unwrap()
+}
+
+/// Transfer with `nonce` carrying the test signature, so transactions differing only in their
+/// nonce have distinct hashes.
+pub fn signed_tx(nonce: u64) -> OpTransactionSigned {
+    let tx = TxEip1559 {
+        chain_id: 8453,
+        nonce,
+        gas_limit: 21000,
+        ..Default::default()
+    };
+    let envelope = OpTxEnvelope::Eip1559(tx.into_signed(Signature::test_signature()));
+    OpTransactionSigned::decode_2718(&mut envelope.encoded_2718().as_slice()).unwrap()
+}
+
+/// Audit of flashblock `index` received at `received_at`, which added no transactions and ran
+/// no checks. Tests set the fields they need on top of it.
+pub fn flashblock_audit(index: u64, received_at: u64) -> FlashblockAudit {
+    FlashblockAudit {
+        index,
+        block_hash: Default::default(),
+        received_at,
+        transactions: Vec::new(),
+        metadata_receipts: Vec::new(),
+        missing_receipts: Vec::new(),
+        checks: Vec::new(),
+    }
+}
@@ -0,0 +1,593 @@
+use crate::clock::SharedClock;
+use base64::Engine;
+use native_tls::{Certificate, Identity, TlsConnector};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{lookup_host, TcpStream};
+use tokio_socks::tcp::Socks5Stream;
+use tokio_tungstenite::tungstenite::http::header::SERVER;
+use tokio_tungstenite::{client_async_with_config, MaybeTlsStream, WebSocketStream};
+use tracing::info;
+use url::Url;
+
+pub type UpstreamStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+type UpstreamError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Largest proxy response header we are willing to buffer while establishing a tunnel.
+const MAX_PROXY_RESPONSE_SIZE: usize = 8192;
+
+/// Flashblocks sources exported by name in the upstream metrics, any other is `other`.
+const KNOWN_SOFTWARE: &[&str] = &["rollup-boost", "op-rbuilder", "websocket-proxy"];
+
+/// Servers exported by name in the upstream metrics, any other is `other`.
+const KNOWN_SERVERS: &[&str] = &["nginx", "envoy", "cloudflare", "caddy", "haproxy"];
+
+/// Label of announced values that aren't exported as they are.
+const OTHER_LABEL: &str = "other";
+
+/// Connection options for the upstream flashblocks websocket.
+#[derive(Debug, Clone, Default)]
+pub struct UpstreamConfig {
+    proxy: Option<Url>,
+    tls: TlsConfig,
+    endpoints: Vec<SocketAddr>,
+}
+
+/// TLS options used when the upstream url is `wss://`.
+#[derive(Debug, Clone, Default)]
+struct TlsConfig {
+    ca_file: Option<PathBuf>,
+    client_cert: Option<PathBuf>,
+    client_key: Option<PathBuf>,
+    server_name: Option<String>,
+}
+
+impl TlsConfig {
+    /// Builds the connector, reading certificate files on every call so rotated
+    /// certificates are picked up on reconnect.
+    fn connector(&self) -> Result<TlsConnector, UpstreamError> {
+        let mut builder = TlsConnector::builder();
+
+        if let Some(ca_file) = &self.ca_file {
+            let pem = std::fs::read(ca_file)?;
+            builder.add_root_certificate(Certificate::from_pem(&pem)?);
+        }
+
+        match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => {
+                let cert = std::fs::read(cert)?;
+                let key = std::fs::read(key)?;
+                builder.identity(Identity::from_pkcs8(&cert, &key)?);
+            }
+            (None, None) => {}
+            _ => return Err("client certificate and key must be configured together".into()),
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+impl UpstreamConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Route the connection through a proxy, either `http://` (HTTP CONNECT) or `socks5://`.
+    /// Credentials are taken from the userinfo part of the url.
+    pub fn proxy(mut self, proxy: Url) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Trust the PEM encoded root certificate in `path` in addition to the system roots.
+    pub fn tls_ca_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.tls.ca_file = Some(path.into());
+        self
+    }
+
+    /// Present a client certificate (PEM) and PKCS#8 key (PEM) for mutual TLS.
+    pub fn tls_client_identity<P: Into<PathBuf>>(mut self, cert: P, key: P) -> Self {
+        self.tls.client_cert = Some(cert.into());
+        self.tls.client_key = Some(key.into());
+        self
+    }
+
+    /// Use `name` for SNI and certificate verification instead of the url host.
+    pub fn tls_server_name(mut self, name: String) -> Self {
+        self.tls.server_name = Some(name);
+        self
+    }
+
+    /// Connect to this static list of addresses instead of resolving the url host. The url
+    /// host is still used for TLS and the websocket handshake.
+    pub fn endpoints(mut self, endpoints: Vec<SocketAddr>) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+
+    /// Picks the address for a connection attempt. The host is resolved afresh on every
+    /// attempt and the results are rotated through, so reconnects follow DNS changes and
+    /// spread across the endpoints behind a load balancer.
+    async fn resolve_endpoint(
+        &self,
+        host: &str,
+        port: u16,
+        attempt: usize,
+    ) -> Result<SocketAddr, UpstreamError> {
+        let mut addrs: Vec<SocketAddr> = if self.endpoints.is_empty() {
+            lookup_host((host, port)).await?.collect()
+        } else {
+            self.endpoints.clone()
+        };
+        addrs.sort();
+        addrs.dedup();
+
+        if addrs.is_empty() {
+            return Err(format!("no addresses resolved for {host}").into());
+        }
+        Ok(addrs[attempt % addrs.len()])
+    }
+}
+
+/// What the upstream announced about itself: the `Server` header of the websocket handshake and
+/// the info frame some sources send before their first flashblock.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpstreamInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub software: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol_version: Option<String>,
+    /// Unix timestamp in milliseconds of the connection the info was captured on
+    pub connected_at: u64,
+}
+
+impl UpstreamInfo {
+    /// Takes the fields announced by a text frame, returning whether it was an info frame.
+    pub fn apply_frame(&mut self, text: &str) -> bool {
+        let Ok(Value::Object(frame)) = serde_json::from_str(text) else {
+            return false;
+        };
+        let field = |keys: &[&str]| {
+            keys.iter().find_map(|key| match frame.get(*key)? {
+                Value::String(value) => Some(value.clone()),
+                Value::Number(value) => Some(value.to_string()),
+                _ => None,
+            })
+        };
+        let software = field(&["software", "name", "client"]);
+        let version = field(&["version"]);
+        let protocol_version = field(&["protocolVersion", "protocol_version"]);
+        if software.is_none() && version.is_none() && protocol_version.is_none() {
+            return false;
+        }
+
+        self.software = software.or(self.software.take());
+        self.version = version.or(self.version.take());
+        self.protocol_version = protocol_version.or(self.protocol_version.take());
+        true
+    }
+}
+
+impl UpstreamInfo {
+    /// The announcement as metric labels. The upstream picks these strings, so only known
+    /// names and the `major.minor` of the versions are exported, keeping the number of series
+    /// bounded. The full strings are logged and served by `base_getUpstreamInfo`.
+    pub fn metric_labels(&self) -> [(&'static str, String); 4] {
+        let known = |value: Option<&str>, known: &[&str]| {
+            value.map_or(String::new(), |value| {
+                let value = value.to_ascii_lowercase();
+                known
+                    .iter()
+                    .find(|name| value.starts_with(**name))
+                    .map_or(OTHER_LABEL.to_string(), |name| name.to_string())
+            })
+        };
+        [
+            ("server", known(self.server.as_deref(), KNOWN_SERVERS)),
+            ("software", known(self.software.as_deref(), KNOWN_SOFTWARE)),
+            ("version", version_label(self.version.as_deref())),
+            (
+                "protocol_version",
+                version_label(self.protocol_version.as_deref()),
+            ),
+        ]
+    }
+}
+
+/// The `major.minor` of a version as a metric label, `other` if it isn't numeric.
+fn version_label(version: Option<&str>) -> String {
+    let Some(version) = version else {
+        return String::new();
+    };
+    let numbers: Vec<&str> = version
+        .trim_start_matches('v')
+        .split(['.', '-', '+'])
+        .take(2)
+        .collect();
+    let numeric = |number: &&str| {
+        !number.is_empty() && number.len() <= 4 && number.bytes().all(|b| b.is_ascii_digit())
+    };
+    if numbers.iter().all(numeric) {
+        numbers.join(".")
+    } else {
+        OTHER_LABEL.to_string()
+    }
+}
+
+impl Display for UpstreamInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let unknown = "unknown";
+        write!(
+            f,
+            "{} {} (protocol {}, server {})",
+            self.software.as_deref().unwrap_or(unknown),
+            self.version.as_deref().unwrap_or(unknown),
+            self.protocol_version.as_deref().unwrap_or(unknown),
+            self.server.as_deref().unwrap_or(unknown),
+        )
+    }
+}
+
+/// State of the upstream websocket connection.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConnectionState {
+    /// The websocket didn't connect yet
+    #[default]
+    Connecting,
+    Connected,
+    /// The connection was lost and is being reestablished
+    Reconnecting,
+}
+
+/// How the upstream connection has been doing since startup. Timestamps are Unix timestamps in
+/// milliseconds.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionStatus {
+    pub state: ConnectionState,
+    /// Connections established after the first one was lost
+    pub reconnects: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_payload_at: Option<u64>,
+}
+
+/// Info of the current upstream connection, shared with the RPC.
+#[derive(Debug, Default)]
+pub struct UpstreamInfoStore {
+    info: RwLock<Option<UpstreamInfo>>,
+    connection: RwLock<ConnectionStatus>,
+    clock: SharedClock,
+}
+
+impl UpstreamInfoStore {
+    /// Clock the connections and payloads are timestamped with.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn set(&self, info: UpstreamInfo) {
+        *self.info.write().unwrap() = Some(info);
+    }
+
+    /// Info of the current connection, none until the websocket connected once.
+    pub fn get(&self) -> Option<UpstreamInfo> {
+        self.info.read().unwrap().clone()
+    }
+
+    pub fn connected(&self) {
+        let mut connection = self.connection.write().unwrap();
+        if connection.state == ConnectionState::Reconnecting {
+            connection.reconnects += 1;
+        }
+        connection.state = ConnectionState::Connected;
+    }
+
+    pub fn disconnected(&self) {
+        let mut connection = self.connection.write().unwrap();
+        if connection.state == ConnectionState::Connected {
+            connection.state = ConnectionState::Reconnecting;
+        }
+    }
+
+    pub fn payload_received(&self) {
+        self.connection.write().unwrap().last_payload_at = Some(self.clock.unix_millis());
+    }
+
+    pub fn connection(&self) -> ConnectionStatus {
+        *self.connection.read().unwrap()
+    }
+}
+
+/// Opens the websocket connection to `url`, tunnelling through the configured proxy if any.
+/// `attempt` selects which of the upstream endpoints to use when there are several. The
+/// returned info holds what the handshake told about the upstream.
+pub async fn connect(
+    url: &Url,
+    config: &UpstreamConfig,
+    attempt: usize,
+) -> Result<(UpstreamStream, UpstreamInfo), UpstreamError> {
+    let host = url.host_str().ok_or("websocket url has no host")?;
+    let port = url
+        .port_or_known_default()
+        .ok_or("websocket url has no port")?;
+
+    let stream = match &config.proxy {
+        // the proxy resolves the host itself
+        Some(proxy) => connect_via_proxy(proxy, host, port).await?,
+        None => {
+            let addr = config.resolve_endpoint(host, port, attempt).await?;
+            info!("connecting to upstream {} at {}", host, addr);
+            TcpStream::connect(addr).await?
+        }
+    };
+
+    let stream = match url.scheme() {
+        "wss" => {
+            let server_name = config.tls.server_name.as_deref().unwrap_or(host);
+            let connector = tokio_native_tls::TlsConnector::from(config.tls.connector()?);
+            MaybeTlsStream::NativeTls(connector.connect(server_name, stream).await?)
+        }
+        _ => MaybeTlsStream::Plain(stream),
+    };
+
+    let (ws_stream, response) = client_async_with_config(url.as_str(), stream, None).await?;
+    let info = UpstreamInfo {
+        server: response
+            .headers()
+            .get(SERVER)
+            .and_then(|server| server.to_str().ok())
+            .map(str::to_string),
+        ..Default::default()
+    };
+    Ok((ws_stream, info))
+}
+
+async fn connect_via_proxy(proxy: &Url, host: &str, port: u16) -> Result<TcpStream, UpstreamError> {
+    let proxy_host = proxy.host_str().ok_or("proxy url has no host")?;
+    let proxy_port = proxy
+        .port_or_known_default()
+        .ok_or("proxy url has no port")?;
+
+    match proxy.scheme() {
+        "http" => {
+            let stream = TcpStream::connect((proxy_host, proxy_port)).await?;
+            http_connect(stream, proxy, host, port).await
+        }
+        "socks5" | "socks5h" => {
+            let stream = if proxy.username().is_empty() {
+                Socks5Stream::connect((proxy_host, proxy_port), (host, port)).await?
+            } else {
+                Socks5Stream::connect_with_password(
+                    (proxy_host, proxy_port),
+                    (host, port),
+                    proxy.username(),
+                    proxy.password().unwrap_or_default(),
+                )
+                .await?
+            };
+            Ok(stream.into_inner())
+        }
+        scheme => Err(format!("unsupported proxy scheme: {scheme}").into()),
+    }
+}
+
+/// Performs an HTTP CONNECT handshake on `stream`, returning it once the tunnel is established.
+async fn http_connect(
+    mut stream: TcpStream,
+    proxy: &Url,
+    host: &str,
+    port: u16,
+) -> Result<TcpStream, UpstreamError> {
+    let mut request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n");
+    if !proxy.username().is_empty() {
+        let credentials = format!(
+            "{}:{}",
+            proxy.username(),
+            proxy.password().unwrap_or_default()
+        );
+        let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+        request.push_str(&format!("Proxy-Authorization: Basic {encoded}\r\n"));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read byte by byte so nothing past the end of the proxy response is consumed
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_PROXY_RESPONSE_SIZE {
+            return Err("proxy response too large".into());
+        }
+        let byte = stream.read_u8().await?;
+        response.push(byte);
+    }
+
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(stream),
+        _ => Err(format!("proxy refused CONNECT: {status_line}").into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_connection_status() {
+        let store = UpstreamInfoStore::default();
+        assert_eq!(store.connection().state, ConnectionState::Connecting);
+
+        // failing to connect the first time isn't a reconnect
+        store.disconnected();
+        store.connected();
+        store.payload_received();
+        assert_eq!(store.connection().state, ConnectionState::Connected);
+        assert_eq!(store.connection().reconnects, 0);
+        assert!(store.connection().last_payload_at.is_some());
+
+        store.disconnected();
+        assert_eq!(store.connection().state, ConnectionState::Reconnecting);
+        store.connected();
+        assert_eq!(store.connection().reconnects, 1);
+    }
+
+    #[test]
+    fn test_info_frame() {
+        let mut info = UpstreamInfo {
+            server: Some("nginx".to_string()),
+            ..Default::default()
+        };
+        assert!(!info.apply_frame(r#"{"jsonrpc":"2.0","id":1}"#));
+        assert!(!info.apply_frame("hello"));
+
+        let frame = r#"{"name":"rollup-boost","version":"0.7.1","protocol_version":1}"#;
+        assert!(info.apply_frame(frame));
+        assert_eq!(info.software.as_deref(), Some("rollup-boost"));
+        assert_eq!(info.version.as_deref(), Some("0.7.1"));
+        assert_eq!(info.protocol_version.as_deref(), Some("1"));
+        assert_eq!(
+            info.to_string(),
+            "rollup-boost 0.7.1 (protocol 1, server nginx)"
+        );
+
+        // a later frame only overrides what it announces
+        assert!(info.apply_frame(r#"{"version":"0.7.2"}"#));
+        assert_eq!(info.software.as_deref(), Some("rollup-boost"));
+        assert_eq!(info.version.as_deref(), Some("0.7.2"));
+    }
+
+    #[test]
+    fn test_metric_labels() {
+        let info = UpstreamInfo {
+            server: Some("nginx/1.25.3".to_string()),
+            software: Some("rollup-boost".to_string()),
+            version: Some("v0.7.1-rc.2".to_string()),
+            protocol_version: Some("1".to_string()),
+            connected_at: 0,
+        };
+        assert_eq!(
+            info.metric_labels(),
+            [
+                ("server", "nginx".to_string()),
+                ("software", "rollup-boost".to_string()),
+                ("version", "0.7".to_string()),
+                ("protocol_version", "1".to_string()),
+            ]
+        );
+
+        // whatever else the upstream sends is folded into a single value
+        let info = UpstreamInfo {
+            server: Some("my-proxy 3".to_string()),
+            software: Some("builder-7f3a9c".to_string()),
+            version: Some("nightly-2026-10-16".to_string()),
+            ..Default::default()
+        };
+        let labels = info.metric_labels();
+        assert_eq!(labels[0].1, "other");
+        assert_eq!(labels[1].1, "other");
+        assert_eq!(labels[2].1, "other");
+        // nothing announced
+        assert_eq!(labels[3].1, "");
+    }
+
+    #[tokio::test]
+    async fn test_http_connect_handshake() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = listener.local_addr().unwrap();
+
+        let proxy = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 1024];
+            let n = socket.read(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_string();
+            socket
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                .await
+                .unwrap();
+            request
+        });
+
+        let proxy_url = Url::parse(&format!("http://user:pass@{proxy_addr}")).unwrap();
+        let stream = connect_via_proxy(&proxy_url, "example.com", 443).await;
+        assert!(stream.is_ok());
+
+        let request = proxy.await.unwrap();
+        assert!(request.starts_with("CONNECT example.com:443 HTTP/1.1\r\n"));
+        assert!(request.contains("Proxy-Authorization: Basic dXNlcjpwYXNz\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_http_connect_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 1024];
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let proxy_url = Url::parse(&format!("http://{proxy_addr}")).unwrap();
+        let stream = connect_via_proxy(&proxy_url, "example.com", 443).await;
+        assert!(stream.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_endpoint_rotates() {
+        let a: SocketAddr = "10.0.0.1:443".parse().unwrap();
+        let b: SocketAddr = "10.0.0.2:443".parse().unwrap();
+        let config = UpstreamConfig::new().endpoints(vec![b, a, b]);
+
+        let resolved = [
+            config
+                .resolve_endpoint("example.com", 443, 0)
+                .await
+                .unwrap(),
+            config
+                .resolve_endpoint("example.com", 443, 1)
+                .await
+                .unwrap(),
+            config
+                .resolve_endpoint("example.com", 443, 2)
+                .await
+                .unwrap(),
+        ];
+        assert_eq!(resolved, [a, b, a]);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_endpoint_uses_dns() {
+        let config = UpstreamConfig::new();
+        let addr = config.resolve_endpoint("localhost", 8545, 0).await.unwrap();
+        assert_eq!(addr.port(), 8545);
+        assert!(addr.ip().is_loopback());
+    }
+
+    #[test]
+    fn test_tls_client_identity_requires_key() {
+        let tls = TlsConfig {
+            client_cert: Some(PathBuf::from("client.pem")),
+            ..Default::default()
+        };
+        assert!(tls.connector().is_err());
+    }
+}
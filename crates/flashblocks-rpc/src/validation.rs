@@ -0,0 +1,825 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use crate::flashblocks::{assemble_block, Metadata};
+use crate::pending::RETAINED_BLOCKS;
+use alloy_consensus::proofs::ordered_trie_root_with_encoder;
+use alloy_consensus::transaction::SignerRecoverable;
+use alloy_consensus::{Transaction, TxReceipt};
+use alloy_eips::eip2718::{Decodable2718, Encodable2718};
+use alloy_primitives::{keccak256, Bytes, TxHash, B256};
+use reth_optimism_chainspec::OpChainSpec;
+use reth_optimism_forks::OpHardforks;
+use reth_optimism_primitives::{OpReceipt, OpTransactionSigned};
+use rollup_boost::primitives::{ExecutionPayloadBaseV1, FlashblocksPayloadV1};
+use tracing::{error, info, warn};
+use url::Url;
+
+/// What to do when the flashblocks source turns out to serve a different chain than the node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChainIdCheck {
+    /// Hold flashblocks until the chain id is verified and stop processing them on mismatch
+    #[default]
+    Enforce,
+    /// Log the mismatch but keep processing flashblocks
+    Warn,
+    /// Don't check the chain id
+    Disabled,
+}
+
+impl FromStr for ChainIdCheck {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "enforce" => Ok(Self::Enforce),
+            "warn" => Ok(Self::Warn),
+            "disabled" => Ok(Self::Disabled),
+            _ => Err(format!("invalid chain id check: {s}")),
+        }
+    }
+}
+
+impl Display for ChainIdCheck {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Enforce => write!(f, "enforce"),
+            Self::Warn => write!(f, "warn"),
+            Self::Disabled => write!(f, "disabled"),
+        }
+    }
+}
+
+/// What to do with a flashblock given what is known of the source's chain id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainIdDecision {
+    /// Apply the flashblock
+    Accept,
+    /// Keep the flashblock until the chain id is known, it may be from another chain
+    Hold,
+    /// Drop the flashblock
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChainIdState {
+    Unverified,
+    Verified,
+    Mismatch,
+}
+
+/// A check run on every flashblock before it is applied. Flashblocks failing an enabled
+/// validator are dropped, so forks can add chain specific checks without patching the
+/// pipeline.
+pub trait PayloadValidator: Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Returns why the flashblock must not be applied, if it mustn't.
+    fn validate(&self, payload: &FlashblocksPayloadV1, metadata: &Metadata) -> Result<(), String>;
+}
+
+/// Validators shipped with the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinValidator {
+    /// Only the first flashblock carries the base, and the base and metadata agree on the
+    /// height
+    Schema,
+    /// Receipts are keyed by transaction hash and their cumulative gas grows along the
+    /// flashblock's transactions without exceeding the gas used
+    Receipts,
+    /// Every transaction decodes and its signer can be recovered
+    Signatures,
+}
+
+/// Validators enabled unless configured otherwise. Signature checks duplicate the sender
+/// recovery done while applying the flashblock, so they are opt-in.
+pub const DEFAULT_VALIDATORS: [BuiltinValidator; 2] =
+    [BuiltinValidator::Schema, BuiltinValidator::Receipts];
+
+impl FromStr for BuiltinValidator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "schema" => Ok(Self::Schema),
+            "receipts" => Ok(Self::Receipts),
+            "signatures" => Ok(Self::Signatures),
+            _ => Err(format!("invalid validator: {s}")),
+        }
+    }
+}
+
+impl Display for BuiltinValidator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl PayloadValidator for BuiltinValidator {
+    fn name(&self) -> &str {
+        match self {
+            Self::Schema => "schema",
+            Self::Receipts => "receipts",
+            Self::Signatures => "signatures",
+        }
+    }
+
+    fn validate(&self, payload: &FlashblocksPayloadV1, metadata: &Metadata) -> Result<(), String> {
+        match self {
+            Self::Schema => validate_schema(payload, metadata),
+            Self::Receipts => validate_receipts(payload, metadata),
+            Self::Signatures => validate_signatures(payload),
+        }
+    }
+}
+
+fn validate_schema(payload: &FlashblocksPayloadV1, metadata: &Metadata) -> Result<(), String> {
+    match (&payload.base, payload.index) {
+        (None, 0) => Err("first flashblock without a base".to_string()),
+        (Some(_), index) if index != 0 => Err(format!("flashblock {index} carries a base")),
+        (Some(base), _) if base.block_number != metadata.block_number => Err(format!(
+            "base is for block {} but metadata for block {}",
+            base.block_number, metadata.block_number
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn validate_receipts(payload: &FlashblocksPayloadV1, metadata: &Metadata) -> Result<(), String> {
+    let mut receipts = HashMap::with_capacity(metadata.receipts.len());
+    for (tx_hash, receipt) in &metadata.receipts {
+        let tx_hash = TxHash::from_str(tx_hash)
+            .map_err(|e| format!("invalid receipt transaction hash {tx_hash}: {e}"))?;
+        receipts.insert(tx_hash, receipt);
+    }
+
+    let mut cumulative_gas_used = 0;
+    for transaction in &payload.diff.transactions {
+        let Some(receipt) = receipts.get(&keccak256(transaction)) else {
+            continue;
+        };
+        if receipt.cumulative_gas_used() < cumulative_gas_used {
+            return Err("receipt cumulative gas used decreases".to_string());
+        }
+        cumulative_gas_used = receipt.cumulative_gas_used();
+    }
+    if cumulative_gas_used > payload.diff.gas_used {
+        return Err(format!(
+            "receipts use {} gas but the block used {}",
+            cumulative_gas_used, payload.diff.gas_used
+        ));
+    }
+    Ok(())
+}
+
+fn validate_signatures(payload: &FlashblocksPayloadV1) -> Result<(), String> {
+    for (index, bytes) in payload.diff.transactions.iter().enumerate() {
+        let transaction = OpTransactionSigned::decode_2718(&mut bytes.as_ref())
+            .map_err(|e| format!("transaction {index} doesn't decode: {e}"))?;
+        transaction
+            .recover_signer()
+            .map_err(|e| format!("transaction {index} has an invalid signature: {e}"))?;
+    }
+    Ok(())
+}
+
+/// How much of a block the limit validator has let through.
+#[derive(Debug, Default)]
+struct BlockProgress {
+    /// Gas limit of the block, known once its first flashblock was seen
+    gas_limit: Option<u64>,
+    transactions: usize,
+    /// Whether a flashblock of the block was already rejected
+    exceeded: bool,
+}
+
+/// Keeps a misbehaving builder from ballooning memory and response sizes: flashblocks that would
+/// take their block past `max_transactions` transactions, or report more gas used than the
+/// block's gas limit, are dropped. Since both only grow along a block, every later flashblock of
+/// the block is dropped too, truncating it at the last flashblock within the limits.
+#[derive(Debug)]
+pub struct BlockLimitValidator {
+    max_transactions: usize,
+    blocks: Mutex<BTreeMap<u64, BlockProgress>>,
+    /// Blocks that exceeded a limit since the last call to `take_incidents`
+    incidents: Mutex<Vec<String>>,
+}
+
+impl BlockLimitValidator {
+    pub fn new(max_transactions: usize) -> Self {
+        Self {
+            max_transactions,
+            blocks: Mutex::new(BTreeMap::new()),
+            incidents: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns a description of every block that exceeded a limit since the last call, once per
+    /// block.
+    pub fn take_incidents(&self) -> Vec<String> {
+        std::mem::take(&mut *self.incidents.lock().unwrap())
+    }
+
+    fn check(
+        &self,
+        progress: &BlockProgress,
+        payload: &FlashblocksPayloadV1,
+    ) -> Result<(), String> {
+        let transactions = progress.transactions + payload.diff.transactions.len();
+        if transactions > self.max_transactions {
+            return Err(format!(
+                "{} transactions exceed the limit of {}",
+                transactions, self.max_transactions
+            ));
+        }
+        if let Some(gas_limit) = progress.gas_limit {
+            if payload.diff.gas_used > gas_limit {
+                return Err(format!(
+                    "{} gas used exceeds the gas limit of {}",
+                    payload.diff.gas_used, gas_limit
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PayloadValidator for BlockLimitValidator {
+    fn name(&self) -> &str {
+        "block_limits"
+    }
+
+    fn validate(&self, payload: &FlashblocksPayloadV1, metadata: &Metadata) -> Result<(), String> {
+        let block_number = metadata.block_number;
+        let mut blocks = self.blocks.lock().unwrap();
+        // a first flashblock starts the block over
+        if let Some(base) = &payload.base {
+            blocks.insert(
+                block_number,
+                BlockProgress {
+                    gas_limit: Some(base.gas_limit),
+                    ..Default::default()
+                },
+            );
+        }
+        blocks.retain(|&number, _| number + RETAINED_BLOCKS > block_number);
+
+        let progress = blocks.entry(block_number).or_default();
+        if let Err(reason) = self.check(progress, payload) {
+            if !progress.exceeded {
+                progress.exceeded = true;
+                self.incidents.lock().unwrap().push(format!(
+                    "Block {block_number} truncated at flashblock {}: {reason}",
+                    payload.index
+                ));
+            }
+            return Err(reason);
+        }
+        progress.transactions += payload.diff.transactions.len();
+        Ok(())
+    }
+}
+
+/// Transactions of a block applied so far, with the base they build on.
+#[derive(Debug)]
+struct BlockTransactions {
+    base: ExecutionPayloadBaseV1,
+    transactions: Vec<Bytes>,
+}
+
+/// Checks that every flashblock's block hash is the hash of the block it extends with its
+/// transactions, so a builder can't send a block other than the one it commits to. The block is
+/// reassembled on every flashblock, so it is opt-in.
+#[derive(Debug)]
+pub struct BlockHashValidator {
+    chain_spec: Arc<OpChainSpec>,
+    blocks: Mutex<BTreeMap<u64, BlockTransactions>>,
+}
+
+impl BlockHashValidator {
+    pub fn new(chain_spec: Arc<OpChainSpec>) -> Self {
+        Self {
+            chain_spec,
+            blocks: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl PayloadValidator for BlockHashValidator {
+    fn name(&self) -> &str {
+        "block_hash"
+    }
+
+    fn validate(&self, payload: &FlashblocksPayloadV1, metadata: &Metadata) -> Result<(), String> {
+        let block_number = metadata.block_number;
+        let mut blocks = self.blocks.lock().unwrap();
+        // a first flashblock starts the block over
+        if let Some(base) = &payload.base {
+            blocks.insert(
+                block_number,
+                BlockTransactions {
+                    base: base.clone(),
+                    transactions: Vec::new(),
+                },
+            );
+        }
+        blocks.retain(|&number, _| number + RETAINED_BLOCKS > block_number);
+
+        // the first flashblock was missed, the block isn't applied either
+        let Some(block) = blocks.get_mut(&block_number) else {
+            return Ok(());
+        };
+        let mut transactions = block.transactions.clone();
+        transactions.extend(payload.diff.transactions.iter().cloned());
+        let hash = assemble_block(&block.base, &payload.diff, transactions, &self.chain_spec)
+            .map_err(|e| format!("block doesn't assemble: {e}"))?
+            .header
+            .hash_slow();
+        if hash != payload.diff.block_hash {
+            return Err(format!(
+                "block hashes to {hash} but the flashblock commits to {}",
+                payload.diff.block_hash
+            ));
+        }
+        block
+            .transactions
+            .extend(payload.diff.transactions.iter().cloned());
+        Ok(())
+    }
+}
+
+/// Receipts of a block applied so far.
+#[derive(Debug)]
+struct BlockReceipts {
+    timestamp: u64,
+    receipts: Vec<OpReceipt>,
+}
+
+/// Checks that every flashblock's receipts root is the root of the block's receipts so far,
+/// the flashblock's own taken from its metadata. The root is recomputed over the whole block on
+/// every flashblock, so it is opt-in.
+#[derive(Debug)]
+pub struct ReceiptsRootValidator {
+    chain_spec: Arc<OpChainSpec>,
+    blocks: Mutex<BTreeMap<u64, BlockReceipts>>,
+}
+
+impl ReceiptsRootValidator {
+    pub fn new(chain_spec: Arc<OpChainSpec>) -> Self {
+        Self {
+            chain_spec,
+            blocks: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl PayloadValidator for ReceiptsRootValidator {
+    fn name(&self) -> &str {
+        "receipts_root"
+    }
+
+    fn validate(&self, payload: &FlashblocksPayloadV1, metadata: &Metadata) -> Result<(), String> {
+        let block_number = metadata.block_number;
+        let mut blocks = self.blocks.lock().unwrap();
+        // a first flashblock starts the block over
+        if let Some(base) = &payload.base {
+            blocks.insert(
+                block_number,
+                BlockReceipts {
+                    timestamp: base.timestamp,
+                    receipts: Vec::new(),
+                },
+            );
+        }
+        blocks.retain(|&number, _| number + RETAINED_BLOCKS > block_number);
+
+        // the first flashblock was missed, the block isn't applied either
+        let Some(block) = blocks.get_mut(&block_number) else {
+            return Ok(());
+        };
+        let mut receipts = HashMap::with_capacity(metadata.receipts.len());
+        for (tx_hash, receipt) in &metadata.receipts {
+            let tx_hash = TxHash::from_str(tx_hash)
+                .map_err(|e| format!("invalid receipt transaction hash {tx_hash}: {e}"))?;
+            receipts.insert(tx_hash, receipt);
+        }
+        let mut block_receipts = block.receipts.clone();
+        for transaction in &payload.diff.transactions {
+            let tx_hash = keccak256(transaction);
+            let receipt = receipts
+                .get(&tx_hash)
+                .ok_or_else(|| format!("transaction {tx_hash} has no receipt"))?;
+            block_receipts.push((*receipt).clone());
+        }
+
+        let root = receipts_root(&block_receipts, &self.chain_spec, block.timestamp);
+        if root != payload.diff.receipts_root {
+            return Err(format!(
+                "receipts have root {root} but the flashblock commits to {}",
+                payload.diff.receipts_root
+            ));
+        }
+        block.receipts = block_receipts;
+        Ok(())
+    }
+}
+
+/// Root of a block's receipts, as the node computes it.
+fn receipts_root(receipts: &[OpReceipt], chain_spec: &OpChainSpec, timestamp: u64) -> B256 {
+    // between regolith and canyon the deposit nonce was left out of the root
+    if chain_spec.is_regolith_active_at_timestamp(timestamp)
+        && !chain_spec.is_canyon_active_at_timestamp(timestamp)
+    {
+        let receipts: Vec<_> = receipts
+            .iter()
+            .cloned()
+            .map(|mut receipt| {
+                if let OpReceipt::Deposit(deposit) = &mut receipt {
+                    deposit.deposit_nonce = None;
+                }
+                receipt
+            })
+            .collect();
+        return ordered_trie_root_with_encoder(&receipts, |receipt, buf| {
+            receipt.with_bloom_ref().encode_2718(buf)
+        });
+    }
+    ordered_trie_root_with_encoder(receipts, |receipt, buf| {
+        receipt.with_bloom_ref().encode_2718(buf)
+    })
+}
+
+/// Verifies that the flashblocks source serves the node's chain, either by asking the
+/// upstream's RPC endpoint for its chain id or by reading it from the first transactions that
+/// carry one.
+#[derive(Debug, Clone)]
+pub struct ChainIdValidator {
+    expected: u64,
+    mode: ChainIdCheck,
+    state: ChainIdState,
+}
+
+impl ChainIdValidator {
+    pub fn new(expected: u64, mode: ChainIdCheck) -> Self {
+        Self {
+            expected,
+            mode,
+            state: ChainIdState::Unverified,
+        }
+    }
+
+    /// Queries `eth_chainId` from the upstream's info endpoint and records the result.
+    pub async fn check_upstream(&mut self, info_url: &Url) {
+        if self.mode == ChainIdCheck::Disabled {
+            return;
+        }
+        match query_chain_id(info_url).await {
+            Ok(chain_id) => self.record(chain_id),
+            Err(e) => warn!("Failed to query chain id from {}: {}", info_url, e),
+        }
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.mode == ChainIdCheck::Disabled
+    }
+
+    /// Returns whether the source was found to serve the expected chain, or `None` while no
+    /// chain id was seen yet.
+    pub fn verified(&self) -> Option<bool> {
+        match self.state {
+            ChainIdState::Unverified => None,
+            ChainIdState::Verified => Some(true),
+            ChainIdState::Mismatch => Some(false),
+        }
+    }
+
+    /// Returns what to do with the payload. In enforce mode nothing is applied before the
+    /// chain id is verified.
+    pub fn check(&mut self, payload: &FlashblocksPayloadV1) -> ChainIdDecision {
+        if self.mode == ChainIdCheck::Disabled {
+            return ChainIdDecision::Accept;
+        }
+        if self.state == ChainIdState::Unverified {
+            if let Some(chain_id) = payload_chain_id(payload) {
+                self.record(chain_id);
+            }
+        }
+        match (self.state, self.mode) {
+            (_, ChainIdCheck::Warn) | (ChainIdState::Verified, _) => ChainIdDecision::Accept,
+            (ChainIdState::Unverified, _) => ChainIdDecision::Hold,
+            (ChainIdState::Mismatch, _) => ChainIdDecision::Reject,
+        }
+    }
+
+    fn record(&mut self, chain_id: u64) {
+        if chain_id == self.expected {
+            info!("Flashblocks source chain id {} verified", chain_id);
+            self.state = ChainIdState::Verified;
+        } else {
+            error!(
+                "Flashblocks source serves chain id {} but the node runs chain id {} ({} mode)",
+                chain_id, self.expected, self.mode
+            );
+            self.state = ChainIdState::Mismatch;
+        }
+    }
+}
+
+/// Returns the chain id of the first transaction in the payload that commits to one. Deposits
+/// and pre EIP-155 legacy transactions don't.
+fn payload_chain_id(payload: &FlashblocksPayloadV1) -> Option<u64> {
+    payload.diff.transactions.iter().find_map(|bytes| {
+        OpTransactionSigned::decode_2718(&mut bytes.as_ref())
+            .ok()
+            .and_then(|tx| tx.chain_id())
+    })
+}
+
+async fn query_chain_id(url: &Url) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "eth_chainId",
+        "params": [],
+    });
+    let response: serde_json::Value = reqwest::Client::new()
+        .post(url.clone())
+        .json(&request)
+        .send()
+        .await?
+        .json()
+        .await?;
+
+    let chain_id = response["result"]
+        .as_str()
+        .ok_or("eth_chainId response has no result")?;
+    Ok(u64::from_str_radix(chain_id.trim_start_matches("0x"), 16)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::EIP1559_TX;
+    use alloy_consensus::proofs::calculate_receipt_root;
+    use alloy_consensus::{Receipt, ReceiptEnvelope};
+    use alloy_primitives::{Address, U256};
+    use alloy_rpc_types_engine::PayloadId;
+    use reth_optimism_chainspec::BASE_SEPOLIA;
+    use rollup_boost::primitives::ExecutionPayloadFlashblockDeltaV1;
+
+    fn payload_with_tx() -> FlashblocksPayloadV1 {
+        FlashblocksPayloadV1 {
+            payload_id: PayloadId::new([0; 8]),
+            index: 1,
+            base: None,
+            diff: ExecutionPayloadFlashblockDeltaV1 {
+                transactions: vec![Bytes::from_str(EIP1559_TX).unwrap()],
+                ..Default::default()
+            },
+            metadata: serde_json::Value::Null,
+        }
+    }
+
+    #[test]
+    fn test_chain_id_match() {
+        let mut validator = ChainIdValidator::new(84532, ChainIdCheck::Enforce);
+        assert_eq!(validator.check(&payload_with_tx()), ChainIdDecision::Accept);
+        assert_eq!(validator.state, ChainIdState::Verified);
+    }
+
+    #[test]
+    fn test_chain_id_mismatch() {
+        let mut validator = ChainIdValidator::new(8453, ChainIdCheck::Enforce);
+        assert_eq!(validator.check(&payload_with_tx()), ChainIdDecision::Reject);
+
+        let mut validator = ChainIdValidator::new(8453, ChainIdCheck::Warn);
+        assert_eq!(validator.check(&payload_with_tx()), ChainIdDecision::Accept);
+        assert_eq!(validator.state, ChainIdState::Mismatch);
+    }
+
+    #[test]
+    fn test_chain_id_unverified_without_transactions() {
+        let mut validator = ChainIdValidator::new(84532, ChainIdCheck::Enforce);
+        let mut payload = payload_with_tx();
+        payload.diff.transactions.clear();
+        assert_eq!(validator.check(&payload), ChainIdDecision::Hold);
+        assert_eq!(validator.state, ChainIdState::Unverified);
+        assert_eq!(validator.check(&payload_with_tx()), ChainIdDecision::Accept);
+
+        let mut validator = ChainIdValidator::new(84532, ChainIdCheck::Warn);
+        assert_eq!(validator.check(&payload), ChainIdDecision::Accept);
+        assert_eq!(validator.state, ChainIdState::Unverified);
+    }
+
+    fn metadata(block_number: u64) -> Metadata {
+        Metadata {
+            receipts: Default::default(),
+            new_account_balances: Default::default(),
+            block_number,
+        }
+    }
+
+    #[test]
+    fn test_schema_validator() {
+        let mut payload = payload_with_tx();
+        assert!(BuiltinValidator::Schema
+            .validate(&payload, &metadata(1))
+            .is_ok());
+
+        payload.index = 0;
+        assert!(BuiltinValidator::Schema
+            .validate(&payload, &metadata(1))
+            .is_err());
+
+        payload.base = Some(ExecutionPayloadBaseV1 {
+            parent_hash: Default::default(),
+            parent_beacon_block_root: Default::default(),
+            fee_recipient: Address::ZERO,
+            block_number: 1,
+            gas_limit: 1000000,
+            timestamp: 1234567890,
+            prev_randao: Default::default(),
+            extra_data: Default::default(),
+            base_fee_per_gas: U256::from(1000),
+        });
+        assert!(BuiltinValidator::Schema
+            .validate(&payload, &metadata(1))
+            .is_ok());
+        assert!(BuiltinValidator::Schema
+            .validate(&payload, &metadata(2))
+            .is_err());
+    }
+
+    #[test]
+    fn test_receipts_validator() {
+        let mut payload = payload_with_tx();
+        let mut metadata = metadata(1);
+        metadata.receipts.insert(
+            keccak256(&payload.diff.transactions[0]).to_string(),
+            OpReceipt::Eip1559(Receipt {
+                status: true.into(),
+                cumulative_gas_used: 21000,
+                logs: vec![],
+            }),
+        );
+
+        assert!(BuiltinValidator::Receipts
+            .validate(&payload, &metadata)
+            .is_err());
+        payload.diff.gas_used = 21000;
+        assert!(BuiltinValidator::Receipts
+            .validate(&payload, &metadata)
+            .is_ok());
+        assert!(BuiltinValidator::Signatures
+            .validate(&payload, &metadata)
+            .is_ok());
+
+        let receipt = metadata.receipts.values().next().unwrap().clone();
+        metadata.receipts.insert("not a hash".to_string(), receipt);
+        assert!(BuiltinValidator::Receipts
+            .validate(&payload, &metadata)
+            .is_err());
+    }
+
+    #[test]
+    fn test_block_limit_validator() {
+        let validator = BlockLimitValidator::new(2);
+        let mut first = payload_with_tx();
+        first.index = 0;
+        first.base = Some(ExecutionPayloadBaseV1 {
+            parent_hash: Default::default(),
+            parent_beacon_block_root: Default::default(),
+            fee_recipient: Address::ZERO,
+            block_number: 1,
+            gas_limit: 100000,
+            timestamp: 1234567890,
+            prev_randao: Default::default(),
+            extra_data: Default::default(),
+            base_fee_per_gas: U256::from(1000),
+        });
+        first.diff.gas_used = 21000;
+        assert!(validator.validate(&first, &metadata(1)).is_ok());
+
+        let mut second = payload_with_tx();
+        second.diff.gas_used = 200000;
+        assert!(validator.validate(&second, &metadata(1)).is_err());
+        second.diff.gas_used = 42000;
+        assert!(validator.validate(&second, &metadata(1)).is_ok());
+
+        // a third transaction exceeds the limit, and keeps exceeding it
+        second.index = 2;
+        assert!(validator.validate(&second, &metadata(1)).is_err());
+        second.diff.transactions.clear();
+        second.index = 3;
+        assert!(validator.validate(&second, &metadata(1)).is_ok());
+        second.diff.transactions = payload_with_tx().diff.transactions;
+        assert!(validator.validate(&second, &metadata(1)).is_err());
+        // every block is reported once
+        assert_eq!(validator.take_incidents().len(), 1);
+        assert!(validator.take_incidents().is_empty());
+
+        // a new first flashblock starts over
+        assert!(validator.validate(&first, &metadata(1)).is_ok());
+    }
+
+    fn first_payload_with_tx() -> FlashblocksPayloadV1 {
+        let mut payload = payload_with_tx();
+        payload.index = 0;
+        payload.base = Some(ExecutionPayloadBaseV1 {
+            parent_hash: Default::default(),
+            parent_beacon_block_root: Default::default(),
+            fee_recipient: Address::ZERO,
+            block_number: 1,
+            gas_limit: 100000,
+            timestamp: 1750000000,
+            prev_randao: Default::default(),
+            extra_data: Default::default(),
+            base_fee_per_gas: U256::from(1000),
+        });
+        payload
+    }
+
+    #[test]
+    fn test_block_hash_validator() {
+        let validator = BlockHashValidator::new(BASE_SEPOLIA.clone());
+        let mut first = first_payload_with_tx();
+        let base = first.base.clone().unwrap();
+        let block_hash = |diff: &ExecutionPayloadFlashblockDeltaV1, transactions: usize| {
+            let transactions = vec![Bytes::from_str(EIP1559_TX).unwrap(); transactions];
+            assemble_block(&base, diff, transactions, &BASE_SEPOLIA)
+                .unwrap()
+                .header
+                .hash_slow()
+        };
+        assert!(validator.validate(&first, &metadata(1)).is_err());
+        first.diff.block_hash = block_hash(&first.diff, 1);
+        assert!(validator.validate(&first, &metadata(1)).is_ok());
+
+        // the second flashblock commits to both transactions, not only its own
+        let mut second = payload_with_tx();
+        second.diff.block_hash = block_hash(&second.diff, 1);
+        assert!(validator.validate(&second, &metadata(1)).is_err());
+        second.diff.block_hash = block_hash(&second.diff, 2);
+        assert!(validator.validate(&second, &metadata(1)).is_ok());
+
+        // blocks whose first flashblock was missed aren't checked
+        assert!(validator.validate(&second, &metadata(2)).is_ok());
+    }
+
+    #[test]
+    fn test_receipts_root_validator() {
+        let validator = ReceiptsRootValidator::new(BASE_SEPOLIA.clone());
+        let mut first = first_payload_with_tx();
+        let tx_hash = keccak256(&first.diff.transactions[0]);
+        let receipt = |cumulative_gas_used| {
+            Receipt {
+                status: true.into(),
+                cumulative_gas_used,
+                logs: vec![],
+            }
+            .with_bloom()
+        };
+        let mut metadata = metadata(1);
+        assert!(validator.validate(&first, &metadata).is_err());
+        metadata.receipts.insert(
+            tx_hash.to_string(),
+            OpReceipt::Eip1559(receipt(21000).receipt),
+        );
+        assert!(validator.validate(&first, &metadata).is_err());
+        first.diff.receipts_root =
+            calculate_receipt_root(&[ReceiptEnvelope::Eip1559(receipt(21000))]);
+        assert!(validator.validate(&first, &metadata).is_ok());
+
+        // the second flashblock commits to the receipts of both transactions
+        let mut second = payload_with_tx();
+        metadata.receipts.insert(
+            tx_hash.to_string(),
+            OpReceipt::Eip1559(receipt(42000).receipt),
+        );
+        second.diff.receipts_root =
+            calculate_receipt_root(&[ReceiptEnvelope::Eip1559(receipt(42000))]);
+        assert!(validator.validate(&second, &metadata).is_err());
+        second.diff.receipts_root = calculate_receipt_root(&[
+            ReceiptEnvelope::Eip1559(receipt(21000)),
+            ReceiptEnvelope::Eip1559(receipt(42000)),
+        ]);
+        assert!(validator.validate(&second, &metadata).is_ok());
+    }
+
+    #[test]
+    fn test_builtin_validator_from_str() {
+        for validator in [
+            BuiltinValidator::Schema,
+            BuiltinValidator::Receipts,
+            BuiltinValidator::Signatures,
+        ] {
+            assert_eq!(
+                validator.to_string().parse::<BuiltinValidator>(),
+                Ok(validator)
+            );
+        }
+        assert!("hash".parse::<BuiltinValidator>().is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::alerts::{AlertKind, Webhook};
+use crate::metrics::WatchedAccountMetrics;
+use crate::pending::{PendingView, PendingViewStore};
+use alloy_primitives::utils::format_ether;
+use alloy_primitives::{Address, U256};
+use tracing::info;
+
+/// A preconfirmed balance change of a watched account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceChange {
+    pub address: Address,
+    pub block_number: u64,
+    pub flashblock_index: u64,
+    /// Balance before the change, unknown for the first change seen
+    pub previous: Option<U256>,
+    pub balance: U256,
+}
+
+impl BalanceChange {
+    /// Absolute size of the change, zero for the first change seen.
+    fn amount(&self) -> U256 {
+        self.previous
+            .map_or(U256::ZERO, |previous| previous.abs_diff(self.balance))
+    }
+}
+
+/// Exports the preconfirmed balances of a configured list of accounts (hot wallets, bridges)
+/// as labeled metrics, and optionally alerts on large changes.
+#[derive(Debug)]
+pub struct BalanceWatcher {
+    pending: Arc<PendingViewStore>,
+    balances: HashMap<Address, Option<U256>>,
+    metrics: HashMap<Address, WatchedAccountMetrics>,
+    last_generation: u64,
+    alert: Option<(Webhook, U256)>,
+}
+
+impl BalanceWatcher {
+    pub fn new(pending: Arc<PendingViewStore>, addresses: Vec<Address>) -> Self {
+        Self {
+            pending,
+            balances: addresses.iter().map(|address| (*address, None)).collect(),
+            metrics: addresses
+                .iter()
+                .map(|address| (*address, WatchedAccountMetrics::for_address(*address)))
+                .collect(),
+            last_generation: 0,
+            alert: None,
+        }
+    }
+
+    /// Posts an alert for every change of at least `min_change` wei.
+    pub fn with_alerts(mut self, webhook: Webhook, min_change: U256) -> Self {
+        self.alert = Some((webhook, min_change));
+        self
+    }
+
+    /// Records the balances of the watched accounts changed by `view`.
+    fn observe(&mut self, view: &PendingView) -> Vec<BalanceChange> {
+        let mut changes = Vec::new();
+        for (address, last) in self.balances.iter_mut() {
+            let Some(balance) = view.balance(*address) else {
+                continue;
+            };
+            if *last == Some(balance) {
+                continue;
+            }
+            changes.push(BalanceChange {
+                address: *address,
+                block_number: view.block_number(),
+                flashblock_index: view.flashblock_index,
+                previous: *last,
+                balance,
+            });
+            *last = Some(balance);
+        }
+        changes
+    }
+
+    async fn check(&mut self) {
+        let Some(view) = self.pending.load() else {
+            return;
+        };
+        if view.generation == self.last_generation {
+            return;
+        }
+        self.last_generation = view.generation;
+
+        for change in self.observe(&view) {
+            let metrics = &self.metrics[&change.address];
+            metrics.balance.set(
+                format_ether(change.balance)
+                    .parse::<f64>()
+                    .unwrap_or_default(),
+            );
+            metrics.balance_changes.increment(1);
+
+            let Some((webhook, min_change)) = &self.alert else {
+                continue;
+            };
+            if change.previous.is_some() && change.amount() >= *min_change {
+                let text = format!(
+                    "Balance of {} changed from {} to {} ETH in block {} flashblock {}",
+                    change.address,
+                    format_ether(change.previous.unwrap_or_default()),
+                    format_ether(change.balance),
+                    change.block_number,
+                    change.flashblock_index
+                );
+                webhook.send(AlertKind::BalanceChanged, text).await;
+            }
+        }
+    }
+
+    /// Records the balances of the latest view whenever views are published.
+    pub async fn run(mut self) {
+        info!("Watching the balances of {} accounts", self.balances.len());
+        let mut updates = self.pending.view_updates();
+        while updates.changed().await {
+            self.check().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_optimism_primitives::OpBlock;
+
+    #[test]
+    fn test_observe() {
+        let watched = Address::repeat_byte(0x1);
+        let mut watcher = BalanceWatcher::new(Arc::new(PendingViewStore::default()), vec![watched]);
+
+        let mut view = PendingView::new(OpBlock::default(), 0, Vec::new());
+        view.balances
+            .insert(Address::repeat_byte(0x2), U256::from(5));
+        assert!(watcher.observe(&view).is_empty());
+
+        view.balances.insert(watched, U256::from(10));
+        let changes = watcher.observe(&view);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].previous, None);
+        assert_eq!(changes[0].amount(), U256::ZERO);
+
+        // unchanged balances are not reported again
+        assert!(watcher.observe(&view).is_empty());
+
+        view.balances.insert(watched, U256::from(4));
+        let changes = watcher.observe(&view);
+        assert_eq!(changes[0].previous, Some(U256::from(10)));
+        assert_eq!(changes[0].amount(), U256::from(6));
+    }
+}
@@ -1,18 +1,55 @@
-use base_reth_flashblocks_rpc::{cache::Cache, flashblocks::FlashblocksClient, rpc::EthApiExt};
+use base_reth_flashblocks_rpc::{
+    admin_api::{AdminApiExt, AdminApiServer},
+    alerts::{AlertNotifier, Webhook, DEFAULT_STREAM_DOWN_AFTER},
+    base_api::{BaseApiExt, BaseApiServer},
+    cache::Cache,
+    canonical::{self, warm_up},
+    clock::ClockSource,
+    debug_api::{DebugApiExt, DebugApiServer},
+    debug_overrides::{DebugOverrideExt, DebugOverrideServer},
+    eth_pubsub::{EthPubSubExt, EthPubSubOverrideServer},
+    filters::BlockFilterMode,
+    flashblocks::{FlashblocksClient, DEFAULT_PAYLOAD_WORKERS},
+    flashblocks_api::{FlashblocksApiExt, FlashblocksApiServer, ServingConfig},
+    modules_api::{RpcModulesApiServer, RpcModulesExt},
+    origins::{OriginTagger, OriginTracker},
+    pending::PendingViewStore,
+    pending_block::PendingBlockSync,
+    reconciliation::{Reconciler, ReconciliationHistory, DEFAULT_RECONCILIATION_HISTORY},
+    replacements::{ReplacementDetector, ReplacementTracker},
+    rpc::{into_namespace, EthApiExt, LatestAsPendingMethod, ResponseFormat},
+    startup::{StartupReport, CACHE_SIZED, NAMESPACES_MOUNTED},
+    status_http::PendingHttpServer,
+    submissions::SubmissionTracker,
+    summaries::{SummaryStore, DEFAULT_SUMMARY_RETENTION_DAYS},
+    tags::PendingTagMode,
+    upstream::{UpstreamConfig, UpstreamInfoStore},
+    validation::{
+        BlockHashValidator, BlockLimitValidator, BuiltinValidator, ChainIdCheck, PayloadValidator,
+        ReceiptsRootValidator,
+    },
+    watchlist::BalanceWatcher,
+};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
+use alloy_primitives::{Address, U256};
 use base_reth_flashblocks_rpc::rpc::EthApiOverrideServer;
 use clap::Parser;
 use reth::builder::Node;
+use reth::chainspec::EthChainSpec;
+use reth::rpc::server_types::{RethRpcModule, RpcModuleSelection};
 use reth::{
     builder::{EngineNodeLauncher, TreeConfig},
-    providers::providers::BlockchainProvider,
+    providers::{providers::BlockchainProvider, CanonStateSubscriptions},
 };
 use reth_optimism_cli::{chainspec::OpChainSpecParser, Cli};
 use reth_optimism_node::args::RollupArgs;
 use reth_optimism_node::OpNode;
-use tracing::info;
+use tracing::{info, warn};
+use url::Url;
 
 #[derive(Debug, Clone, PartialEq, Eq, clap::Args)]
 #[command(next_help_heading = "Rollup")]
@@ -22,30 +59,537 @@ struct FlashblocksRollupArgs {
 
     #[arg(long = "websocket-url", value_name = "WEBSOCKET_URL")]
     pub websocket_url: String,
+
+    /// Proxy to route the websocket connection through (`http://` or `socks5://`)
+    #[arg(long = "websocket-proxy", value_name = "PROXY_URL")]
+    pub websocket_proxy: Option<Url>,
+
+    /// PEM encoded root certificate to trust for the websocket connection
+    #[arg(long = "websocket-tls-ca", value_name = "PATH")]
+    pub websocket_tls_ca: Option<PathBuf>,
+
+    /// PEM encoded client certificate for mutual TLS, requires `--websocket-tls-key`
+    #[arg(
+        long = "websocket-tls-cert",
+        value_name = "PATH",
+        requires = "websocket_tls_key"
+    )]
+    pub websocket_tls_cert: Option<PathBuf>,
+
+    /// PEM encoded PKCS#8 client key for mutual TLS, requires `--websocket-tls-cert`
+    #[arg(
+        long = "websocket-tls-key",
+        value_name = "PATH",
+        requires = "websocket_tls_cert"
+    )]
+    pub websocket_tls_key: Option<PathBuf>,
+
+    /// Server name to use for SNI and certificate verification instead of the url host
+    #[arg(long = "websocket-tls-server-name", value_name = "NAME")]
+    pub websocket_tls_server_name: Option<String>,
+
+    /// Static list of addresses to rotate through instead of resolving the websocket host
+    #[arg(
+        long = "websocket-endpoints",
+        value_name = "ADDR",
+        value_delimiter = ','
+    )]
+    pub websocket_endpoints: Vec<SocketAddr>,
+
+    /// JSON-RPC endpoint of the flashblocks source, queried for its chain id on startup
+    #[arg(long = "websocket-info-url", value_name = "URL")]
+    pub websocket_info_url: Option<Url>,
+
+    /// How to handle a flashblocks source serving a different chain (enforce, warn, disabled)
+    #[arg(
+        long = "websocket-chain-check",
+        value_name = "MODE",
+        default_value = "enforce"
+    )]
+    pub websocket_chain_check: ChainIdCheck,
+
+    /// Validators run on every flashblock before it is applied (schema, receipts, signatures)
+    #[arg(
+        long = "flashblocks-validators",
+        value_name = "VALIDATORS",
+        value_delimiter = ',',
+        default_value = "schema,receipts"
+    )]
+    pub flashblocks_validators: Vec<BuiltinValidator>,
+
+    /// Drop flashblocks that would take their block past this many transactions or report more
+    /// gas used than its gas limit, truncating the block, and alert on it
+    #[arg(long = "flashblocks-max-transactions", value_name = "COUNT")]
+    pub flashblocks_max_transactions: Option<usize>,
+
+    /// Drop flashblocks whose block hash isn't the hash of the block they extend, reassembling
+    /// the block on every flashblock
+    #[arg(long = "flashblocks-verify-block-hash", default_value_t = false)]
+    pub flashblocks_verify_block_hash: bool,
+
+    /// Drop flashblocks whose receipts root isn't the root of their block's receipts,
+    /// recomputing it on every flashblock
+    #[arg(long = "flashblocks-verify-receipts-root", default_value_t = false)]
+    pub flashblocks_verify_receipts_root: bool,
+
+    /// Log the first bytes of websocket frames that aren't flashblocks
+    #[arg(long = "websocket-log-unexpected-frames", default_value_t = false)]
+    pub websocket_log_unexpected_frames: bool,
+
+    /// Methods whose `latest` requests are served from the flashblocks state when it is
+    /// exactly one block ahead of the canonical head (e.g. `eth_getBlockByNumber,eth_getBalance`)
+    #[arg(
+        long = "latest-as-pending",
+        value_name = "METHOD",
+        value_delimiter = ','
+    )]
+    pub latest_as_pending: Vec<LatestAsPendingMethod>,
+
+    /// Namespace the overridden `eth` methods are served under. Anything other than `eth` leaves
+    /// the node's own `eth` methods in place and serves the overrides next to them.
+    #[arg(
+        long = "flashblocks-rpc-namespace",
+        value_name = "NAMESPACE",
+        default_value = "eth"
+    )]
+    pub flashblocks_rpc_namespace: String,
+
+    /// Add `flashblockIndex` and `preconfirmedAt` fields to pending transaction receipts
+    #[arg(long = "receipt-flashblock-fields", default_value_t = false)]
+    pub receipt_flashblock_fields: bool,
+
+    /// Add the `payloadId` the builder sent the flashblocks under to pending blocks
+    #[arg(long = "block-payload-id", default_value_t = false)]
+    pub block_payload_id: bool,
+
+    /// Render pending blocks the way strict client libraries (ethers, viem, web3.js) expect,
+    /// filling in fields such as `totalDifficulty` and the signature of deposit transactions
+    #[arg(long = "pending-compat", default_value_t = false)]
+    pub pending_compat: bool,
+
+    /// Network types the overridden block, transaction and receipt methods render their answers
+    /// with (optimism, ethereum). `ethereum` drops the L1 fee and deposit fields for tooling
+    /// that only knows the Ethereum types.
+    #[arg(
+        long = "flashblocks-response-format",
+        value_name = "FORMAT",
+        default_value = "optimism"
+    )]
+    pub flashblocks_response_format: ResponseFormat,
+
+    /// What `eth_newBlockFilter` reports (canonical, flashblock, block). `flashblock` reports the
+    /// block hash sent with every flashblock and `block` the final hash of every preconfirmed
+    /// block, so polling clients can react before the blocks are canonical.
+    #[arg(
+        long = "flashblocks-block-filter",
+        value_name = "MODE",
+        default_value = "canonical"
+    )]
+    pub flashblocks_block_filter: BlockFilterMode,
+
+    /// What the `pending` block tag is served from (flashblocks, node). With `node` the
+    /// overridden methods leave `pending` to the node and only serve the flashblocks under the
+    /// `preconfirmed` tag, so clients can tell preconfirmed data from the txpool's.
+    #[arg(
+        long = "flashblocks-pending-tag",
+        value_name = "MODE",
+        default_value = "flashblocks"
+    )]
+    pub flashblocks_pending_tag: PendingTagMode,
+
+    /// Clock the cache TTLs and the staleness of the pending views are measured with (wall,
+    /// block). `block` follows the timestamps of the preconfirmed blocks and runs with the host's
+    /// monotonic time between them, so expiry keeps up with blocks replayed faster than real
+    /// time.
+    #[arg(
+        long = "flashblocks-clock",
+        value_name = "CLOCK",
+        default_value = "wall"
+    )]
+    pub flashblocks_clock: ClockSource,
+
+    /// Number of canonical blocks whose comparison with their preconfirmation is kept for
+    /// `base_getReconciliationHistory`
+    #[arg(
+        long = "reconciliation-history",
+        value_name = "BLOCKS",
+        default_value_t = DEFAULT_RECONCILIATION_HISTORY
+    )]
+    pub reconciliation_history: usize,
+
+    /// File the per-block preconfirmation summaries served by `base_getBlockSummaries` are
+    /// persisted to. Summaries are only kept when set.
+    #[arg(long = "preconfirmation-summaries-path", value_name = "PATH")]
+    pub preconfirmation_summaries_path: Option<PathBuf>,
+
+    /// Number of days the per-block preconfirmation summaries are kept for
+    #[arg(
+        long = "preconfirmation-summaries-retention-days",
+        value_name = "DAYS",
+        default_value_t = DEFAULT_SUMMARY_RETENTION_DAYS
+    )]
+    pub preconfirmation_summaries_retention_days: u64,
+
+    /// Compute the flashblocks answer of the overridden methods but serve the standard one,
+    /// logging and counting the differences, to evaluate preconfirmed serving before enabling it
+    #[arg(long = "flashblocks-shadow-mode", default_value_t = false)]
+    pub flashblocks_shadow_mode: bool,
+
+    /// Install the flashblocks pending block as the node's pending block, so the node's methods
+    /// replaying it (e.g. `eth_callMany` on `pending`) see the storage the flashblocks wrote
+    #[arg(long = "flashblocks-pending-block", default_value_t = false)]
+    pub flashblocks_pending_block: bool,
+
+    /// Only ingest and validate flashblocks, without mounting the `eth` overrides or the `base`
+    /// namespace. Only the `flashblocks` namespace and metrics are exposed.
+    #[arg(long = "flashblocks-mirror", default_value_t = false)]
+    pub flashblocks_mirror: bool,
+
+    /// Serve the pending summary as plain JSON on `GET /flashblocks/pending` at this address,
+    /// for consumers that don't speak JSON-RPC
+    #[arg(long = "flashblocks-http-addr", value_name = "ADDR")]
+    pub flashblocks_http_addr: Option<SocketAddr>,
+
+    /// Number of workers applying flashblocks, each owning a share of the block heights
+    #[arg(
+        long = "flashblocks-payload-workers",
+        value_name = "COUNT",
+        default_value_t = DEFAULT_PAYLOAD_WORKERS
+    )]
+    pub flashblocks_payload_workers: usize,
+
+    /// Webhook that receives a JSON alert when the flashblocks stream goes down or comes back,
+    /// or a startup validation fails
+    #[arg(long = "alert-webhook-url", value_name = "URL")]
+    pub alert_webhook_url: Option<Url>,
+
+    /// Seconds without a flashblock before the stream is reported down
+    #[arg(
+        long = "alert-stream-down-secs",
+        value_name = "SECS",
+        default_value_t = DEFAULT_STREAM_DOWN_AFTER.as_secs()
+    )]
+    pub alert_stream_down_secs: u64,
+
+    /// Accounts (e.g. hot wallets, bridges) whose preconfirmed balances are exported as metrics
+    #[arg(
+        long = "watch-addresses",
+        value_name = "ADDRESS",
+        value_delimiter = ','
+    )]
+    pub watch_addresses: Vec<Address>,
+
+    /// Post an alert to the alert webhook when a watched balance changes by at least this many
+    /// wei in a flashblock
+    #[arg(
+        long = "watch-alert-min-change",
+        value_name = "WEI",
+        requires = "alert_webhook_url"
+    )]
+    pub watch_alert_min_change: Option<U256>,
+}
+
+impl FlashblocksRollupArgs {
+    fn upstream_config(&self) -> UpstreamConfig {
+        let mut upstream_config = UpstreamConfig::new().endpoints(self.websocket_endpoints.clone());
+        if let Some(proxy) = self.websocket_proxy.clone() {
+            upstream_config = upstream_config.proxy(proxy);
+        }
+        if let Some(ca_file) = self.websocket_tls_ca.clone() {
+            upstream_config = upstream_config.tls_ca_file(ca_file);
+        }
+        if let (Some(cert), Some(key)) = (
+            self.websocket_tls_cert.clone(),
+            self.websocket_tls_key.clone(),
+        ) {
+            upstream_config = upstream_config.tls_client_identity(cert, key);
+        }
+        if let Some(server_name) = self.websocket_tls_server_name.clone() {
+            upstream_config = upstream_config.tls_server_name(server_name);
+        }
+        upstream_config
+    }
 }
 
 fn main() {
     Cli::<OpChainSpecParser, FlashblocksRollupArgs>::parse()
         .run(|builder, flashblocks_rollup_args| async move {
             info!("Starting custom Base node");
-            let cache = Arc::new(Cache::default());
+            let clock = flashblocks_rollup_args.flashblocks_clock.clock();
+            let cache = Arc::new(Cache::default().with_clock(clock.clone()));
+            let pending = Arc::new(PendingViewStore::default().with_clock(clock.clone()));
+            let startup_report = Arc::new(StartupReport::default().with_clock(clock.clone()));
+            let upstream_info = Arc::new(UpstreamInfoStore::default().with_clock(clock.clone()));
+            startup_report.skip(
+                CACHE_SIZED,
+                "the cache is unbounded, entries expire after their ttl",
+            );
             let op_node = OpNode::new(flashblocks_rollup_args.rollup_args.clone());
-            let mut flashblocks_client = FlashblocksClient::new(Arc::clone(&cache));
+            let chain_id = builder.config().chain.chain().id();
+            let mut flashblocks_client =
+                FlashblocksClient::new(Arc::clone(&cache), Arc::clone(&pending))
+                    .with_upstream_config(flashblocks_rollup_args.upstream_config())
+                    .with_chain_id_check(chain_id, flashblocks_rollup_args.websocket_chain_check)
+                    .with_chain_spec(builder.config().chain.clone())
+                    .with_payload_workers(flashblocks_rollup_args.flashblocks_payload_workers)
+                    .with_log_unexpected_frames(
+                        flashblocks_rollup_args.websocket_log_unexpected_frames,
+                    )
+                    .with_startup_report(Arc::clone(&startup_report))
+                    .with_upstream_info(Arc::clone(&upstream_info))
+                    .with_payload_validators(
+                        flashblocks_rollup_args
+                            .flashblocks_validators
+                            .iter()
+                            .map(|validator| Arc::new(*validator) as Arc<dyn PayloadValidator>)
+                            .collect(),
+                    );
+            if let Some(info_url) = flashblocks_rollup_args.websocket_info_url.clone() {
+                flashblocks_client = flashblocks_client.with_upstream_info_url(info_url);
+            }
+            let block_limits = flashblocks_rollup_args
+                .flashblocks_max_transactions
+                .map(|max_transactions| Arc::new(BlockLimitValidator::new(max_transactions)));
+            if let Some(block_limits) = block_limits.clone() {
+                flashblocks_client = flashblocks_client.with_payload_validator(block_limits);
+            }
+            if flashblocks_rollup_args.flashblocks_verify_block_hash {
+                flashblocks_client = flashblocks_client.with_payload_validator(Arc::new(
+                    BlockHashValidator::new(builder.config().chain.clone()),
+                ));
+            }
+            if flashblocks_rollup_args.flashblocks_verify_receipts_root {
+                flashblocks_client = flashblocks_client.with_payload_validator(Arc::new(
+                    ReceiptsRootValidator::new(builder.config().chain.clone()),
+                ));
+            }
 
             let cache_clone = Arc::clone(&cache);
+            let pending_clone = Arc::clone(&pending);
+            let replacements = Arc::new(ReplacementTracker::default());
+            let origins = Arc::new(OriginTracker::default());
+            let reconciliations = Arc::new(ReconciliationHistory::new(
+                flashblocks_rollup_args.reconciliation_history,
+            ));
+            let summaries = match flashblocks_rollup_args
+                .preconfirmation_summaries_path
+                .clone()
+            {
+                Some(path) => {
+                    let retention = Duration::from_secs(
+                        flashblocks_rollup_args.preconfirmation_summaries_retention_days * 86400,
+                    );
+                    Some(Arc::new(SummaryStore::open(
+                        path,
+                        retention,
+                        clock.clone(),
+                    )?))
+                }
+                None => None,
+            };
+            let startup_report_clone = Arc::clone(&startup_report);
             let chain_spec = builder.config().chain.clone();
+            let latest_as_pending = flashblocks_rollup_args.latest_as_pending.clone();
+            let receipt_flashblock_fields = flashblocks_rollup_args.receipt_flashblock_fields;
+            let flashblocks_shadow_mode = flashblocks_rollup_args.flashblocks_shadow_mode;
+            let block_payload_id = flashblocks_rollup_args.block_payload_id;
+            let pending_compat = flashblocks_rollup_args.pending_compat;
+            let response_format = flashblocks_rollup_args.flashblocks_response_format;
+            let block_filter_mode = flashblocks_rollup_args.flashblocks_block_filter;
+            let pending_tag_mode = flashblocks_rollup_args.flashblocks_pending_tag;
+            // transactions are forwarded by the node itself, with `--rollup.sequencer-http`
+            let sequencer_forwarding = flashblocks_rollup_args.rollup_args.sequencer_http.is_some();
+            let submissions = Arc::new(SubmissionTracker::default().with_clock(clock.clone()));
+            let flashblocks_mirror = flashblocks_rollup_args.flashblocks_mirror;
+            let flashblocks_pending_block = flashblocks_rollup_args.flashblocks_pending_block;
+            let chain_id_check = flashblocks_rollup_args.websocket_chain_check;
+            let flashblocks_rpc_namespace =
+                flashblocks_rollup_args.flashblocks_rpc_namespace.clone();
             let handle = builder
                 .with_types_and_provider::<OpNode, BlockchainProvider<_>>()
                 .with_components(op_node.components())
                 .with_add_ons(op_node.add_ons())
                 .on_component_initialized(move |_ctx| Ok(()))
                 .extend_rpc_modules(move |ctx| {
+                    let flashblocks_ext = FlashblocksApiExt::new(
+                        ctx.provider().clone(),
+                        Arc::clone(&pending_clone),
+                        chain_spec.clone(),
+                    )
+                    .with_upstream_info(Arc::clone(&upstream_info))
+                    .with_reconciliations(Arc::clone(&reconciliations))
+                    .with_submissions(Arc::clone(&submissions))
+                    .with_startup_report(Arc::clone(&startup_report_clone))
+                    .with_serving(ServingConfig {
+                        overrides: !flashblocks_mirror && flashblocks_rpc_namespace == "eth",
+                        shadow_mode: flashblocks_shadow_mode,
+                        pending_tag_mode,
+                        chain_id_check,
+                    });
+                    if flashblocks_mirror {
+                        info!("Running as a flashblocks mirror, only the flashblocks namespace is mounted");
+                        for methods in ctx.registry.module_for(&RpcModuleSelection::All) {
+                            for method in methods.method_names() {
+                                ctx.modules.remove_method_from_configured(method);
+                            }
+                        }
+                        ctx.modules
+                            .merge_configured(flashblocks_ext.into_mirror_module())?;
+                        startup_report_clone.pass(NAMESPACES_MOUNTED, "flashblocks (mirror)");
+                        return Ok(());
+                    }
+                    ctx.modules.merge_configured(flashblocks_ext.into_rpc())?;
+                    let admin_ext =
+                        AdminApiExt::new(Arc::clone(&cache_clone), Arc::clone(&pending_clone));
+                    ctx.modules
+                        .merge_if_module_configured(RethRpcModule::Admin, admin_ext.into_rpc())?;
+                    let debug_ext = DebugApiExt::new(Arc::clone(&pending_clone));
+                    ctx.modules
+                        .merge_if_module_configured(RethRpcModule::Debug, debug_ext.into_rpc())?;
+                    let module_config = ctx.modules.module_config();
+                    let modules_ext = RpcModulesExt::new(
+                        [
+                            module_config.http(),
+                            module_config.ws(),
+                            module_config.ipc(),
+                        ]
+                        .into_iter()
+                        .flatten()
+                        .flat_map(|selection| selection.clone().into_selection()),
+                    )
+                    .with_namespace("flashblocks");
+
+                    let eth_api = ctx.registry.eth_api().clone();
+                    let warm_up_cache = Arc::clone(&cache_clone);
+                    ctx.node().task_executor().spawn(async move {
+                        match warm_up(&eth_api, &warm_up_cache).await {
+                            Ok(nonces) => info!("Warmed up the cache with {} nonces", nonces),
+                            Err(e) => warn!("Failed to warm up the cache: {}", e),
+                        }
+                    });
+                    ctx.node().task_executor().spawn(canonical::run(
+                        Arc::clone(&cache_clone),
+                        ctx.provider().subscribe_to_canonical_state(),
+                    ));
+
                     let api_ext = EthApiExt::new(
                         ctx.registry.eth_api().clone(),
                         Arc::clone(&cache_clone),
+                        Arc::clone(&pending_clone),
                         chain_spec.clone(),
+                    )
+                    .with_latest_as_pending(latest_as_pending.clone())
+                    .with_receipt_flashblock_fields(receipt_flashblock_fields)
+                    .with_shadow_mode(flashblocks_shadow_mode)
+                    .with_block_payload_id(block_payload_id)
+                    .with_pending_compat(pending_compat)
+                    .with_response_format(response_format)
+                    .with_block_filter_mode(block_filter_mode)
+                    .with_pending_tag_mode(pending_tag_mode)
+                    .with_submissions(Arc::clone(&submissions))
+                    .with_sequencer_forwarding(sequencer_forwarding)
+                    .with_canonical_filters(Arc::new(ctx.registry.eth_handlers().filter.clone()));
+                    ctx.node().task_executor().spawn(Arc::clone(&submissions).run(
+                        Arc::clone(&pending_clone),
+                        ctx.provider().subscribe_to_canonical_state(),
+                    ));
+                    let overrides = if flashblocks_rpc_namespace == "eth" {
+                        let overrides = api_ext.into_module();
+                        ctx.modules.replace_configured(overrides.clone())?;
+                        let debug_ext = DebugOverrideExt::new(
+                            ctx.provider().clone(),
+                            Arc::clone(&pending_clone),
+                            Arc::new(ctx.registry.debug_api()),
+                        )
+                        .with_pending_tag_mode(pending_tag_mode);
+                        ctx.modules.add_or_replace_if_module_configured(
+                            RethRpcModule::Debug,
+                            debug_ext.into_rpc(),
+                        )?;
+                        let pubsub_ext = EthPubSubExt::new(
+                            ctx.provider().clone(),
+                            Arc::clone(&pending_clone),
+                            Arc::new(ctx.registry.eth_handlers().pubsub.clone()),
+                        );
+                        ctx.modules.replace_configured(pubsub_ext.into_rpc())?;
+                        overrides
+                    } else {
+                        info!(
+                            "Serving the flashblocks overrides under the {} namespace",
+                            flashblocks_rpc_namespace
+                        );
+                        let overrides =
+                            into_namespace(api_ext.into_module(), &flashblocks_rpc_namespace)?;
+                        ctx.modules.merge_configured(overrides.clone())?;
+                        overrides
+                    };
+
+                    if flashblocks_pending_block {
+                        let sync = PendingBlockSync::new(
+                            ctx.registry.eth_api().clone(),
+                            Arc::clone(&pending_clone),
+                        );
+                        ctx.node()
+                            .task_executor()
+                            .spawn(sync.run());
+                    }
+
+                    let detector = ReplacementDetector::new(
+                        ctx.pool().clone(),
+                        Arc::clone(&pending_clone),
+                        Arc::clone(&replacements),
+                    );
+                    ctx.node()
+                        .task_executor()
+                        .spawn(detector.run());
+
+                    let tagger = OriginTagger::new(
+                        ctx.pool().clone(),
+                        Arc::clone(&pending_clone),
+                        Arc::clone(&origins),
+                    );
+                    ctx.node()
+                        .task_executor()
+                        .spawn(tagger.run());
+
+                    let mut reconciler = Reconciler::new(
+                        ctx.provider().clone(),
+                        Arc::clone(&pending_clone),
+                        Arc::clone(&reconciliations),
+                    );
+                    if let Some(summaries) = summaries.clone() {
+                        reconciler = reconciler.with_summaries(Arc::clone(&summaries));
+                        ctx.node()
+                            .task_executor()
+                            .spawn(summaries.run(Duration::from_secs(5)));
+                    }
+                    ctx.node()
+                        .task_executor()
+                        .spawn(reconciler.run());
+
+                    let mut base_ext = BaseApiExt::new(
+                        ctx.registry.eth_api().clone(),
+                        Arc::clone(&cache_clone),
+                        Arc::clone(&pending_clone),
+                        Arc::clone(&replacements),
+                        Arc::clone(&reconciliations),
+                        Arc::clone(&origins),
+                        Arc::clone(&startup_report_clone),
+                    )
+                    .with_upstream_info(Arc::clone(&upstream_info))
+                    .with_submissions(Arc::clone(&submissions));
+                    if let Some(summaries) = summaries.clone() {
+                        base_ext = base_ext.with_summaries(summaries);
+                    }
+                    ctx.modules.merge_configured(base_ext.into_rpc())?;
+                    let modules_ext = modules_ext
+                        .with_namespace("base")
+                        .with_overrides(overrides.method_names());
+                    ctx.modules.replace_configured(modules_ext.into_rpc())?;
+                    startup_report_clone.pass(
+                        NAMESPACES_MOUNTED,
+                        format!("flashblocks, {flashblocks_rpc_namespace} overrides, base"),
                     );
-                    ctx.modules.replace_configured(api_ext.into_rpc())?;
                     Ok(())
                 })
                 .launch_with_fn(|builder| {
@@ -65,6 +609,44 @@ fn main() {
                             .init(flashblocks_rollup_args.websocket_url.clone())
                             .unwrap();
                     });
+                    let webhook = flashblocks_rollup_args
+                        .alert_webhook_url
+                        .clone()
+                        .map(|url| Webhook::new(url).with_clock(clock.clone()));
+                    if let Some(webhook) = webhook.clone() {
+                        let mut notifier = AlertNotifier::new(
+                            webhook,
+                            Arc::clone(&pending),
+                            Arc::clone(&startup_report),
+                        )
+                        .with_stream_down_after(Duration::from_secs(
+                            flashblocks_rollup_args.alert_stream_down_secs,
+                        ));
+                        if let Some(block_limits) = block_limits {
+                            notifier = notifier.with_block_limits(block_limits);
+                        }
+                        builder
+                            .task_executor()
+                            .spawn(notifier.run(Duration::from_secs(1)));
+                    }
+                    if !flashblocks_rollup_args.watch_addresses.is_empty() {
+                        let mut watcher = BalanceWatcher::new(
+                            Arc::clone(&pending),
+                            flashblocks_rollup_args.watch_addresses.clone(),
+                        );
+                        if let (Some(webhook), Some(min_change)) =
+                            (webhook, flashblocks_rollup_args.watch_alert_min_change)
+                        {
+                            watcher = watcher.with_alerts(webhook, min_change);
+                        }
+                        builder
+                            .task_executor()
+                            .spawn(watcher.run());
+                    }
+                    if let Some(addr) = flashblocks_rollup_args.flashblocks_http_addr {
+                        let server = PendingHttpServer::new(addr, Arc::clone(&pending));
+                        builder.task_executor().spawn(server.run());
+                    }
                     builder.task_executor().spawn(async move {
                         let mut interval = tokio::time::interval(Duration::from_secs(2));
                         loop {